@@ -0,0 +1,15 @@
+-- Migration: Column profiling statistics
+-- Files are profiled in the background after upload; per-column statistics
+-- are stored alongside the column metadata in file_columns.
+
+ALTER TABLE files ADD COLUMN IF NOT EXISTS profile_status VARCHAR(20) NOT NULL DEFAULT 'pending';
+ALTER TABLE files ADD COLUMN IF NOT EXISTS profile_error TEXT;
+ALTER TABLE files ADD COLUMN IF NOT EXISTS profiled_at TIMESTAMPTZ;
+
+-- Arrow type names (e.g. nested structs) can exceed 50 characters
+ALTER TABLE file_columns ALTER COLUMN data_type TYPE VARCHAR(255);
+ALTER TABLE file_columns ADD COLUMN IF NOT EXISTS stats JSONB;
+
+-- Comments
+COMMENT ON COLUMN files.profile_status IS 'Profiling state: pending, ready, failed';
+COMMENT ON COLUMN file_columns.stats IS 'Column statistics: null count, distinct estimate, min/max, mean/stddev, top values, histogram';
@@ -0,0 +1,7 @@
+-- Migration: JSON read options per file
+-- Records path and array handling chosen at upload, applied whenever a JSON
+-- or NDJSON file is read (preview, export, charts, profiling).
+
+ALTER TABLE files ADD COLUMN IF NOT EXISTS json_options JSONB;
+
+COMMENT ON COLUMN files.json_options IS 'JSON reader options: {"records_path": "data.items", "arrays": "stringify" | "explode"}';
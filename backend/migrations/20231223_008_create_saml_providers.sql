@@ -0,0 +1,44 @@
+-- Migration: SAML identity providers
+-- Per-team SAML 2.0 identity providers, optionally routed by email domain,
+-- and pending SP-initiated logins between AuthnRequest and ACS.
+
+-- Identity providers (one per team)
+CREATE TABLE IF NOT EXISTS saml_providers (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    team_id UUID NOT NULL UNIQUE REFERENCES teams(id) ON DELETE CASCADE,
+    idp_entity_id VARCHAR(1024) NOT NULL,
+    sso_url VARCHAR(2048) NOT NULL,
+    certificate TEXT NOT NULL,
+    domains TEXT[] NOT NULL DEFAULT '{}',
+    attribute_mapping JSONB NOT NULL DEFAULT '{}',
+    allow_signup BOOLEAN NOT NULL DEFAULT TRUE,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_saml_providers_domains ON saml_providers USING GIN (domains);
+
+DROP TRIGGER IF EXISTS update_saml_providers_updated_at ON saml_providers;
+CREATE TRIGGER update_saml_providers_updated_at
+    BEFORE UPDATE ON saml_providers
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Pending SAML logins, keyed by RelayState
+CREATE TABLE IF NOT EXISTS saml_login_states (
+    relay_state VARCHAR(128) PRIMARY KEY,
+    request_id VARCHAR(128) NOT NULL,
+    provider_id UUID NOT NULL REFERENCES saml_providers(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_saml_login_states_expires ON saml_login_states(expires_at);
+
+-- Comments
+COMMENT ON TABLE saml_providers IS 'SAML 2.0 identity providers configured by teams';
+COMMENT ON COLUMN saml_providers.certificate IS 'PEM signing certificate of the IdP; only signatures from this key are trusted';
+COMMENT ON COLUMN saml_providers.domains IS 'Lower-case email domains routed to this IdP; set by system administrators';
+COMMENT ON COLUMN saml_providers.attribute_mapping IS 'Assertion attribute names for email, name and role, and role value mapping';
+COMMENT ON COLUMN saml_login_states.request_id IS 'AuthnRequest ID the response must answer (InResponseTo)';
@@ -0,0 +1,41 @@
+-- Migration: Server-side sessions and refresh token families
+-- Every login starts a session (one per device) holding a family of refresh
+-- tokens. Tokens are single-use: refreshing marks the presented token used
+-- and issues its successor. Presenting a used token again means it was
+-- copied, so the whole session is revoked.
+
+-- Sessions (refresh token families)
+CREATE TABLE IF NOT EXISTS auth_sessions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    user_agent TEXT,
+    ip_address VARCHAR(45),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    revoked_reason VARCHAR(50)
+);
+
+CREATE INDEX IF NOT EXISTS idx_auth_sessions_user ON auth_sessions(user_id);
+CREATE INDEX IF NOT EXISTS idx_auth_sessions_expires ON auth_sessions(expires_at);
+
+-- Refresh tokens, stored as SHA-256 hashes
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    token_hash CHAR(64) PRIMARY KEY,
+    session_id UUID NOT NULL REFERENCES auth_sessions(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_session ON refresh_tokens(session_id);
+
+-- Refresh tokens are no longer self-contained JWTs, so the blocklist is unused
+DROP TABLE IF EXISTS revoked_tokens;
+
+-- Comments
+COMMENT ON TABLE auth_sessions IS 'Per-device login sessions; each owns a family of rotating refresh tokens';
+COMMENT ON COLUMN auth_sessions.revoked_reason IS 'logout or reuse_detected';
+COMMENT ON TABLE refresh_tokens IS 'Single-use refresh tokens (SHA-256 of the opaque token)';
+COMMENT ON COLUMN refresh_tokens.used_at IS 'Set when the token was rotated; reuse after this revokes the session';
@@ -0,0 +1,9 @@
+-- Migration: Session management indexes
+-- Supports listing a user's active sessions and "sign out everywhere".
+
+CREATE INDEX IF NOT EXISTS idx_auth_sessions_user_active
+    ON auth_sessions(user_id, last_used_at DESC)
+    WHERE revoked_at IS NULL;
+
+-- Comments
+COMMENT ON COLUMN auth_sessions.revoked_reason IS 'logout, reuse_detected, or revoked (by the user or an administrator)';
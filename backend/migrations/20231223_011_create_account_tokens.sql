@@ -0,0 +1,32 @@
+-- Migration: Email verification and password reset
+-- Tokens are emailed to the user, single-use, short-lived, and stored only as
+-- SHA-256 hashes. Each token is tied to the address it was sent to, so a link
+-- stops working if the account's email changes.
+
+-- When the user proved ownership of their email address
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email_verified_at TIMESTAMPTZ;
+
+-- Addresses asserted by an identity provider are already verified
+UPDATE users SET email_verified_at = NOW()
+WHERE email_verified_at IS NULL
+  AND id IN (SELECT user_id FROM user_identities);
+
+CREATE TABLE IF NOT EXISTS account_tokens (
+    token_hash CHAR(64) PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    purpose VARCHAR(30) NOT NULL CHECK (purpose IN ('email_verification', 'password_reset')),
+    email VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_account_tokens_user ON account_tokens(user_id, purpose);
+CREATE INDEX IF NOT EXISTS idx_account_tokens_expires ON account_tokens(expires_at);
+
+-- Comments
+COMMENT ON COLUMN users.email_verified_at IS 'Set when the user confirmed their email address; NULL if unverified';
+COMMENT ON TABLE account_tokens IS 'Single-use emailed tokens (SHA-256 of the opaque token) for email verification and password reset';
+COMMENT ON COLUMN account_tokens.email IS 'Address the token was sent to; the token is void if the account email changes';
+COMMENT ON COLUMN account_tokens.used_at IS 'Set when the token was redeemed';
+COMMENT ON COLUMN auth_sessions.revoked_reason IS 'logout, reuse_detected, revoked (by the user or an administrator), or password_reset';
@@ -0,0 +1,43 @@
+-- Migration: TOTP multi-factor authentication
+-- Users enroll an authenticator app (pending until a first code is
+-- confirmed) and receive single-use recovery codes. Password logins for
+-- enrolled users return a short-lived challenge that must be answered with a
+-- code before tokens are issued.
+
+-- Authenticator enrollment, one per user
+CREATE TABLE IF NOT EXISTS user_mfa (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    totp_secret VARCHAR(64) NOT NULL,
+    enabled_at TIMESTAMPTZ,
+    last_used_step BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Recovery codes, stored as SHA-256 hashes
+CREATE TABLE IF NOT EXISTS mfa_recovery_codes (
+    code_hash CHAR(64) PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_mfa_recovery_codes_user ON mfa_recovery_codes(user_id);
+
+-- Pending second-factor challenges issued by password login
+CREATE TABLE IF NOT EXISTS mfa_challenges (
+    token_hash CHAR(64) PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_mfa_challenges_expires ON mfa_challenges(expires_at);
+
+-- Comments
+COMMENT ON TABLE user_mfa IS 'TOTP authenticator per user; enabled_at is NULL until enrollment is confirmed';
+COMMENT ON COLUMN user_mfa.totp_secret IS 'Base32 TOTP secret';
+COMMENT ON COLUMN user_mfa.last_used_step IS 'Time step of the last accepted code; older or equal steps are rejected as replays';
+COMMENT ON TABLE mfa_recovery_codes IS 'Single-use recovery codes (SHA-256 of the normalized code)';
+COMMENT ON TABLE mfa_challenges IS 'Short-lived second-factor challenges (SHA-256 of the opaque token) with an attempt limit';
+COMMENT ON COLUMN teams.settings IS 'Team settings; require_mfa (boolean) denies team access to members without MFA';
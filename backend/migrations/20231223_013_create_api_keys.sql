@@ -0,0 +1,30 @@
+-- Migration: API keys for scripts and CI
+-- Long-lived credentials accepted by the API in place of a JWT. A key belongs
+-- to the user who created it, or to a team (managed by its admins and acting
+-- with its creator's permissions in that team). Keys may be restricted to a
+-- subset of permissions and may expire.
+
+CREATE TABLE IF NOT EXISTS api_keys (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    team_id UUID REFERENCES teams(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    key_prefix VARCHAR(16) NOT NULL,
+    key_hash CHAR(64) NOT NULL UNIQUE,
+    permissions TEXT[],
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+    last_used_ip VARCHAR(45),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_keys_user ON api_keys(user_id) WHERE team_id IS NULL;
+CREATE INDEX IF NOT EXISTS idx_api_keys_team ON api_keys(team_id) WHERE team_id IS NOT NULL;
+
+-- Comments
+COMMENT ON TABLE api_keys IS 'User- and team-owned API keys (SHA-256 of the secret)';
+COMMENT ON COLUMN api_keys.user_id IS 'Owner of a personal key, or creator of a team key (whose team role bounds it)';
+COMMENT ON COLUMN api_keys.key_prefix IS 'Leading characters of the key, shown to identify it';
+COMMENT ON COLUMN api_keys.permissions IS 'Permission strings the key is limited to (e.g. dataset:upload); NULL for all of its owner''s';
+COMMENT ON COLUMN api_keys.last_used_at IS 'Last authenticated request, updated at most once a minute';
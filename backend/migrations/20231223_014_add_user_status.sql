@@ -0,0 +1,21 @@
+-- Migration: Account status for admin user management
+-- Administrators can disable an account without deleting it. Disabled users
+-- cannot sign in, their sessions are revoked, and their API keys stop
+-- working until the account is re-enabled.
+
+DO $$ BEGIN
+    CREATE TYPE user_status AS ENUM ('active', 'disabled');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS status user_status NOT NULL DEFAULT 'active';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS disabled_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_users_status ON users(status);
+
+-- Comments
+COMMENT ON COLUMN users.status IS 'active, or disabled by an administrator (sign-in and API access refused)';
+COMMENT ON COLUMN users.disabled_at IS 'When the account was last disabled';
+COMMENT ON COLUMN users.password_hash IS 'Argon2 hash; !sso for single sign-on accounts, !reset while a password must be set through a reset link';
+COMMENT ON COLUMN auth_sessions.revoked_reason IS 'logout, reuse_detected, revoked, password_reset or account_disabled';
@@ -0,0 +1,102 @@
+-- Migration: Database-backed roles
+-- Built-in system and team roles are seeded from the permission sets that
+-- were previously hardcoded. A team may override the permissions of its
+-- built-in roles (except owner) and define custom roles from any subset of
+-- permissions; a member assigned a custom role is granted exactly its
+-- permissions in that team.
+
+DO $$ BEGIN
+    CREATE TYPE role_scope AS ENUM ('system', 'team');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+CREATE TABLE IF NOT EXISTS roles (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    scope role_scope NOT NULL,
+    team_id UUID REFERENCES teams(id) ON DELETE CASCADE,
+    builtin VARCHAR(20),
+    name VARCHAR(100) NOT NULL,
+    description TEXT,
+    permissions TEXT[] NOT NULL DEFAULT '{}',
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CHECK (scope = 'team' OR team_id IS NULL),
+    CHECK (team_id IS NOT NULL OR builtin IS NOT NULL)
+);
+
+-- One default per built-in role, one override per team and built-in role,
+-- and custom role names unique within a team
+CREATE UNIQUE INDEX IF NOT EXISTS idx_roles_builtin_default
+    ON roles(scope, builtin) WHERE team_id IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_roles_builtin_override
+    ON roles(team_id, builtin) WHERE team_id IS NOT NULL AND builtin IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_roles_team_name
+    ON roles(team_id, LOWER(name)) WHERE team_id IS NOT NULL AND builtin IS NULL;
+
+DROP TRIGGER IF EXISTS update_roles_updated_at ON roles;
+CREATE TRIGGER update_roles_updated_at
+    BEFORE UPDATE ON roles
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Custom role assigned to a member; NULL for their built-in role
+ALTER TABLE team_members ADD COLUMN IF NOT EXISTS role_id UUID REFERENCES roles(id);
+CREATE INDEX IF NOT EXISTS idx_team_members_role ON team_members(role_id) WHERE role_id IS NOT NULL;
+
+-- Built-in system roles
+INSERT INTO roles (scope, builtin, name, description, permissions) VALUES
+    ('system', 'admin', 'Admin', 'Can manage users and teams at system level', ARRAY[
+        'dashboard:create', 'dashboard:read', 'dashboard:update', 'dashboard:delete', 'dashboard:share',
+        'dataset:upload', 'dataset:read', 'dataset:update', 'dataset:delete', 'dataset:share',
+        'query:create', 'query:read', 'query:execute', 'query:delete',
+        'chart:create', 'chart:read', 'chart:update', 'chart:delete', 'chart:export',
+        'team:manage_members', 'team:manage_settings',
+        'admin:manage_users', 'admin:manage_teams'
+    ]),
+    ('system', 'user', 'User', 'Standard user - can create and manage own content', ARRAY[
+        'dashboard:create', 'dashboard:read', 'dashboard:update', 'dashboard:delete', 'dashboard:share',
+        'dataset:upload', 'dataset:read', 'dataset:update', 'dataset:delete',
+        'query:create', 'query:read', 'query:execute', 'query:delete',
+        'chart:create', 'chart:read', 'chart:update', 'chart:delete', 'chart:export'
+    ]),
+    ('system', 'readonly', 'Read only', 'View-only access', ARRAY[
+        'dashboard:read', 'dataset:read', 'query:read', 'chart:read', 'chart:export'
+    ])
+ON CONFLICT (scope, builtin) WHERE team_id IS NULL DO NOTHING;
+
+-- Built-in team roles
+INSERT INTO roles (scope, builtin, name, description, permissions) VALUES
+    ('team', 'owner', 'Owner', 'Team owner - full control', ARRAY[
+        'dashboard:create', 'dashboard:read', 'dashboard:update', 'dashboard:delete', 'dashboard:share',
+        'dataset:upload', 'dataset:read', 'dataset:update', 'dataset:delete', 'dataset:share',
+        'query:create', 'query:read', 'query:execute', 'query:delete',
+        'chart:create', 'chart:read', 'chart:update', 'chart:delete', 'chart:export',
+        'team:manage_members', 'team:manage_settings', 'team:manage_roles', 'team:view_audit_log'
+    ]),
+    ('team', 'admin', 'Admin', 'Team admin - can manage members and settings', ARRAY[
+        'dashboard:create', 'dashboard:read', 'dashboard:update', 'dashboard:share',
+        'dataset:upload', 'dataset:read', 'dataset:update', 'dataset:share',
+        'query:create', 'query:read', 'query:execute',
+        'chart:create', 'chart:read', 'chart:update', 'chart:export',
+        'team:manage_members'
+    ]),
+    ('team', 'member', 'Member', 'Regular member - can create and edit content', ARRAY[
+        'dashboard:create', 'dashboard:read', 'dashboard:update',
+        'dataset:upload', 'dataset:read',
+        'query:create', 'query:read', 'query:execute',
+        'chart:create', 'chart:read', 'chart:update', 'chart:export'
+    ]),
+    ('team', 'viewer', 'Viewer', 'View-only access to team resources', ARRAY[
+        'dashboard:read', 'dataset:read', 'query:read', 'chart:read', 'chart:export'
+    ])
+ON CONFLICT (scope, builtin) WHERE team_id IS NULL DO NOTHING;
+
+-- Comments
+COMMENT ON TABLE roles IS 'Built-in role defaults, per-team overrides of them, and team custom roles';
+COMMENT ON COLUMN roles.team_id IS 'Team owning an override or custom role; NULL for built-in defaults';
+COMMENT ON COLUMN roles.builtin IS 'Built-in role (users.role or team_members.role value) this row defines or overrides; NULL for custom roles';
+COMMENT ON COLUMN roles.permissions IS 'Permission strings, e.g. dataset:upload; unknown strings grant nothing';
+COMMENT ON COLUMN team_members.role_id IS 'Custom role granting the member''s permissions in place of their built-in role';
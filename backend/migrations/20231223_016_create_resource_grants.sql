@@ -0,0 +1,54 @@
+-- Migration: Resource-level access grants
+-- Share a single file, dashboard or saved query with a user or a team at a
+-- given level, optionally until an expiry. Grants are checked in addition
+-- to ownership and team membership, and never lift a user above what their
+-- own role allows.
+
+DO $$ BEGIN
+    CREATE TYPE grant_resource_type AS ENUM ('file', 'dashboard', 'query');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+-- Ordered: each level includes the ones before it
+DO $$ BEGIN
+    CREATE TYPE access_level AS ENUM ('view', 'edit', 'manage');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+CREATE TABLE IF NOT EXISTS resource_grants (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    resource_type grant_resource_type NOT NULL,
+    resource_id UUID NOT NULL,
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    team_id UUID REFERENCES teams(id) ON DELETE CASCADE,
+    level access_level NOT NULL,
+    granted_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    expires_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CHECK ((user_id IS NULL) <> (team_id IS NULL))
+);
+
+-- One grant per resource and grantee
+CREATE UNIQUE INDEX IF NOT EXISTS idx_resource_grants_user
+    ON resource_grants(resource_type, resource_id, user_id) WHERE user_id IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_resource_grants_team
+    ON resource_grants(resource_type, resource_id, team_id) WHERE team_id IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_resource_grants_grantee_user ON resource_grants(user_id) WHERE user_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_resource_grants_grantee_team ON resource_grants(team_id) WHERE team_id IS NOT NULL;
+
+DROP TRIGGER IF EXISTS update_resource_grants_updated_at ON resource_grants;
+CREATE TRIGGER update_resource_grants_updated_at
+    BEFORE UPDATE ON resource_grants
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Comments
+COMMENT ON TABLE resource_grants IS 'Per-resource access granted to a user or team';
+COMMENT ON COLUMN resource_grants.resource_id IS 'File, dashboard or saved query id; grants are removed with the resource';
+COMMENT ON COLUMN resource_grants.level IS 'view (read, query, export), edit (also modify) or manage (also delete and share)';
+COMMENT ON COLUMN resource_grants.expires_at IS 'When the grant stops applying; NULL for no expiry';
@@ -0,0 +1,37 @@
+-- Migration: Row-level security policies on datasets
+-- A policy is a list of filters whose values may reference attributes of the
+-- user running a query (e.g. {{user.attributes.region}}). Matching policies
+-- are injected into every preview, export and chart query on the file, so the
+-- same dataset can be shared while each user only sees their own rows.
+
+-- Custom attributes referenced by policies, set by administrators
+ALTER TABLE users ADD COLUMN IF NOT EXISTS attributes JSONB NOT NULL DEFAULT '{}';
+
+CREATE TABLE IF NOT EXISTS row_policies (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    description TEXT,
+    filters JSONB NOT NULL,
+    applies_to_roles TEXT[],
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE (file_id, name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_row_policies_file ON row_policies(file_id) WHERE enabled;
+
+DROP TRIGGER IF EXISTS update_row_policies_updated_at ON row_policies;
+CREATE TRIGGER update_row_policies_updated_at
+    BEFORE UPDATE ON row_policies
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Comments
+COMMENT ON COLUMN users.attributes IS 'Custom attributes available to row-level security policies as {{user.attributes.<key>}}';
+COMMENT ON TABLE row_policies IS 'Row filters applied to every query on a file by users other than its owner';
+COMMENT ON COLUMN row_policies.filters IS 'Query filters, ANDed; values may contain {{...}} placeholders resolved per user';
+COMMENT ON COLUMN row_policies.applies_to_roles IS 'Team roles (in the file''s team) the policy applies to; NULL for every user but the owner';
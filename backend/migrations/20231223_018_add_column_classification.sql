@@ -0,0 +1,30 @@
+-- Migration: Column classification and masking
+-- Columns can be classified as PII or sensitive, by hand or by detection at
+-- upload. Classified columns are masked in previews, queries and exports for
+-- everyone but the file's owner and users holding dataset:unmask.
+
+DO $$ BEGIN
+    CREATE TYPE data_classification AS ENUM ('pii', 'sensitive');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+DO $$ BEGIN
+    CREATE TYPE mask_rule AS ENUM ('hide', 'hash', 'partial', 'null');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+ALTER TABLE file_columns ADD COLUMN IF NOT EXISTS classification data_classification;
+ALTER TABLE file_columns ADD COLUMN IF NOT EXISTS mask mask_rule;
+ALTER TABLE file_columns ADD COLUMN IF NOT EXISTS classification_detected BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Team owners may see their team's data unmasked and delegate it via custom roles
+UPDATE roles
+SET permissions = array_append(permissions, 'dataset:unmask')
+WHERE scope = 'team' AND builtin = 'owner' AND NOT ('dataset:unmask' = ANY(permissions));
+
+-- Comments
+COMMENT ON COLUMN file_columns.classification IS 'pii or sensitive; NULL for unclassified columns';
+COMMENT ON COLUMN file_columns.mask IS 'How classified values are shown without dataset:unmask: hide, hash, partial or null';
+COMMENT ON COLUMN file_columns.classification_detected IS 'Whether the classification was detected at upload rather than set by a user';
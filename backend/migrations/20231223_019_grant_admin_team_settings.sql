@@ -0,0 +1,8 @@
+-- Migration: Team settings for built-in team admins
+-- Team admins are meant to manage members and settings, and the team settings
+-- endpoint used to admit them by role. It now checks team:manage_settings like
+-- every other team route, so the built-in admin role has to grant it.
+
+UPDATE roles
+SET permissions = array_append(permissions, 'team:manage_settings')
+WHERE scope = 'team' AND builtin = 'admin' AND NOT ('team:manage_settings' = ANY(permissions));
@@ -0,0 +1,46 @@
+-- Migration: Team invitations
+-- Members are invited by email rather than added outright: the invitee
+-- accepts or declines through an emailed link, which also works for addresses
+-- with no account yet. Invitation tokens are stored only as SHA-256 hashes
+-- and expire; resending issues a new token and voids the old one.
+
+DO $$ BEGIN
+    CREATE TYPE invitation_status AS ENUM ('pending', 'accepted', 'declined', 'revoked');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+CREATE TABLE IF NOT EXISTS team_invitations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    team_id UUID NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    role team_role NOT NULL DEFAULT 'member',
+    token_hash CHAR(64) NOT NULL UNIQUE,
+    invited_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    status invitation_status NOT NULL DEFAULT 'pending',
+    expires_at TIMESTAMPTZ NOT NULL,
+    responded_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CHECK (role <> 'owner')
+);
+
+-- One open invitation per team and address
+CREATE UNIQUE INDEX IF NOT EXISTS idx_team_invitations_pending
+    ON team_invitations(team_id, email) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_team_invitations_email
+    ON team_invitations(email) WHERE status = 'pending';
+
+-- Trigger for updated_at
+DROP TRIGGER IF EXISTS update_team_invitations_updated_at ON team_invitations;
+CREATE TRIGGER update_team_invitations_updated_at
+    BEFORE UPDATE ON team_invitations
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Comments
+COMMENT ON TABLE team_invitations IS 'Emailed invitations to join a team, answered by the invitee';
+COMMENT ON COLUMN team_invitations.email IS 'Lower-cased address the invitation was sent to; only an account with this address may accept';
+COMMENT ON COLUMN team_invitations.token_hash IS 'SHA-256 of the opaque token in the emailed link';
+COMMENT ON COLUMN team_invitations.status IS 'pending until accepted, declined or revoked; a pending invitation past expires_at cannot be answered';
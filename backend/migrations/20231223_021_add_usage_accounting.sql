@@ -0,0 +1,38 @@
+-- Migration: Usage accounting and storage quotas
+-- Storage use is summed from files as needed; query compute time is counted
+-- per user, team and day as queries run. Personal files count against the
+-- uploader's quota and team files against the team's (teams.settings
+-- storage_quota_bytes). A user's quota defaults to the server-wide one.
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS storage_quota_bytes BIGINT CHECK (storage_quota_bytes > 0);
+ALTER TABLE users ADD COLUMN IF NOT EXISTS file_quota INTEGER CHECK (file_quota > 0);
+
+CREATE TABLE IF NOT EXISTS query_usage (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    team_id UUID REFERENCES teams(id) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    query_count BIGINT NOT NULL DEFAULT 0,
+    compute_ms BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- One row per user, team (or none) and day
+CREATE UNIQUE INDEX IF NOT EXISTS idx_query_usage_bucket
+    ON query_usage(user_id, COALESCE(team_id, '00000000-0000-0000-0000-000000000000'::uuid), day);
+CREATE INDEX IF NOT EXISTS idx_query_usage_team ON query_usage(team_id, day) WHERE team_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_query_usage_day ON query_usage(day);
+
+-- Trigger for updated_at
+DROP TRIGGER IF EXISTS update_query_usage_updated_at ON query_usage;
+CREATE TRIGGER update_query_usage_updated_at
+    BEFORE UPDATE ON query_usage
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Comments
+COMMENT ON COLUMN users.storage_quota_bytes IS 'Bytes of personal files the user may store; NULL for the server default';
+COMMENT ON COLUMN users.file_quota IS 'Personal files the user may store; NULL for the server default';
+COMMENT ON TABLE query_usage IS 'Queries run and their compute time, per user, team and day';
+COMMENT ON COLUMN query_usage.team_id IS 'Team owning the queried file; NULL for personal files';
@@ -0,0 +1,17 @@
+-- Migration: SAML login codes
+-- The Assertion Consumer Service redirects the browser to the frontend with a
+-- short-lived, single-use code, which the frontend exchanges for tokens, so
+-- tokens never appear in the page answering the IdP's form post. Codes are
+-- stored only as SHA-256 hashes.
+
+CREATE TABLE IF NOT EXISTS saml_login_codes (
+    code_hash CHAR(64) PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_saml_login_codes_expires ON saml_login_codes(expires_at);
+
+-- Comments
+COMMENT ON TABLE saml_login_codes IS 'Single-use codes (SHA-256 of the opaque code) exchanged for tokens after a SAML login';
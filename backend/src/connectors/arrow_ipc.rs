@@ -0,0 +1,27 @@
+//! Arrow IPC connector
+//!
+//! Reads Arrow IPC files (Feather v2) into record batches.
+
+use arrow::ipc::reader::FileReader;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use super::{collect_window, projection_indices, ConnectorResult, Dataset, ReadOptions};
+
+/// Read an Arrow IPC file
+pub fn read(path: &Path, options: &ReadOptions) -> ConnectorResult<Dataset> {
+    let projection = match &options.columns {
+        Some(columns) => {
+            let schema = FileReader::try_new(BufReader::new(File::open(path)?), None)?.schema();
+            Some(projection_indices(&schema, columns)?)
+        }
+        None => None,
+    };
+
+    let reader = FileReader::try_new(BufReader::new(File::open(path)?), projection)?;
+    let schema = reader.schema();
+    let batches = collect_window(reader, options.offset, options.limit)?;
+
+    Ok(Dataset { schema, batches })
+}
@@ -0,0 +1,322 @@
+//! JSON connector
+//!
+//! Reads JSON arrays, single objects and newline-delimited JSON (JSON Lines)
+//! into Arrow record batches. Records can be located inside a document with a
+//! dotted path (e.g. `data.items`), nested objects are flattened into dotted
+//! column names, and arrays are either stringified or exploded into one row
+//! per element. The schema is inferred across all records.
+
+use arrow::json::reader::infer_json_schema_from_iterator;
+use arrow::json::ReaderBuilder;
+use arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::{
+    collect_window, projection_indices, ConnectorError, ConnectorResult, Dataset, ReadOptions,
+};
+
+/// Column name used for records that are not objects
+const SCALAR_COLUMN: &str = "value";
+
+/// Most rows a single record may expand to when exploding arrays. Several
+/// arrays in one record multiply, so a small document could otherwise turn
+/// into millions of rows.
+pub const MAX_EXPLODED_ROWS: usize = 10_000;
+
+/// How array values are turned into columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArrayMode {
+    /// Store the array as a JSON string in a single column
+    #[default]
+    Stringify,
+    /// Emit one row per array element (an empty array yields a null)
+    Explode,
+}
+
+/// Options for reading JSON sources
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JsonOptions {
+    /// Dotted path to the records inside each document, e.g. `data.items`
+    #[serde(default)]
+    pub records_path: Option<String>,
+    #[serde(default)]
+    pub arrays: ArrayMode,
+}
+
+/// Read a JSON, NDJSON or JSON Lines file
+pub fn read(path: &Path, options: &ReadOptions) -> ConnectorResult<Dataset> {
+    let content = std::fs::read(path)?;
+    let records: Vec<Value> = flatten_records(&content, &options.json)?
+        .into_iter()
+        .map(Value::Object)
+        .collect();
+
+    let schema = Arc::new(infer_json_schema_from_iterator(records.iter().map(Ok))?);
+
+    // Types are unified across records, so a column may hold both strings and
+    // numbers; coerce primitives rather than rejecting those records
+    let mut decoder = ReaderBuilder::new(schema.clone())
+        .with_batch_size(options.batch_size)
+        .with_coerce_primitive(true)
+        .build_decoder()?;
+
+    let mut batches = Vec::new();
+    for chunk in records.chunks(options.batch_size.max(1)) {
+        decoder.serialize(chunk)?;
+        if let Some(batch) = decoder.flush()? {
+            batches.push(Ok(batch));
+        }
+    }
+
+    let mut batches = collect_window(batches, options.offset, options.limit)?;
+
+    let schema = match &options.columns {
+        Some(columns) => {
+            let indices = projection_indices(&schema, columns)?;
+            batches = batches
+                .iter()
+                .map(|batch| batch.project(&indices))
+                .collect::<Result<Vec<RecordBatch>, _>>()?;
+            Arc::new(schema.project(&indices)?)
+        }
+        None => schema,
+    };
+
+    Ok(Dataset { schema, batches })
+}
+
+/// Parse JSON content into flat records with dotted column names.
+///
+/// The content may hold a single document or a sequence of documents (NDJSON
+/// / JSON Lines). In each document the records path, when set, is resolved;
+/// an array yields one record per element and anything else a single record.
+pub fn flatten_records(
+    content: &[u8],
+    options: &JsonOptions,
+) -> ConnectorResult<Vec<Map<String, Value>>> {
+    let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(content);
+
+    let mut rows = Vec::new();
+    for document in serde_json::Deserializer::from_slice(content).into_iter::<Value>() {
+        let document = document
+            .map_err(|e| ConnectorError::InvalidData(format!("Invalid JSON: {}", e)))?;
+
+        let target = match &options.records_path {
+            Some(path) => resolve_path(document, path)?,
+            None => document,
+        };
+
+        match target {
+            Value::Array(records) => {
+                for record in records {
+                    rows.extend(flatten_record(record, options.arrays)?);
+                }
+            }
+            record => rows.extend(flatten_record(record, options.arrays)?),
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Follow a dotted path through objects (and array indices)
+fn resolve_path(document: Value, path: &str) -> ConnectorResult<Value> {
+    let mut current = document;
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        let next = match &mut current {
+            Value::Object(object) => object.remove(segment),
+            Value::Array(items) => segment
+                .parse::<usize>()
+                .ok()
+                .filter(|index| *index < items.len())
+                .map(|index| items.swap_remove(index)),
+            _ => None,
+        };
+        current = next.ok_or_else(|| {
+            ConnectorError::InvalidData(format!("Records path '{}' not found", path))
+        })?;
+    }
+    Ok(current)
+}
+
+/// Flatten one record into one or more rows
+fn flatten_record(record: Value, arrays: ArrayMode) -> ConnectorResult<Vec<Map<String, Value>>> {
+    match record {
+        Value::Object(object) => flatten_object(vec![Map::new()], "", object, arrays),
+        other => flatten_value(vec![Map::new()], SCALAR_COLUMN.to_string(), other, arrays),
+    }
+}
+
+fn flatten_object(
+    mut rows: Vec<Map<String, Value>>,
+    prefix: &str,
+    object: Map<String, Value>,
+    arrays: ArrayMode,
+) -> ConnectorResult<Vec<Map<String, Value>>> {
+    for (key, value) in object {
+        let name = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        rows = flatten_value(rows, name, value, arrays)?;
+    }
+    Ok(rows)
+}
+
+fn flatten_value(
+    mut rows: Vec<Map<String, Value>>,
+    name: String,
+    value: Value,
+    arrays: ArrayMode,
+) -> ConnectorResult<Vec<Map<String, Value>>> {
+    Ok(match value {
+        Value::Object(object) if !object.is_empty() => {
+            return flatten_object(rows, &name, object, arrays)
+        }
+        Value::Object(_) => set_all(rows, name, Value::Null),
+        Value::Array(items) if arrays == ArrayMode::Explode && !items.is_empty() => {
+            if rows.len().saturating_mul(items.len()) > MAX_EXPLODED_ROWS {
+                return Err(too_many_rows(&name));
+            }
+            let mut exploded = Vec::with_capacity(rows.len() * items.len());
+            for row in rows.drain(..) {
+                for item in &items {
+                    exploded.extend(flatten_value(vec![row.clone()], name.clone(), item.clone(), arrays)?);
+                    if exploded.len() > MAX_EXPLODED_ROWS {
+                        return Err(too_many_rows(&name));
+                    }
+                }
+            }
+            exploded
+        }
+        Value::Array(items) if arrays == ArrayMode::Explode || items.is_empty() => {
+            set_all(rows, name, Value::Null)
+        }
+        Value::Array(items) => set_all(rows, name, Value::String(Value::Array(items).to_string())),
+        scalar => set_all(rows, name, scalar),
+    })
+}
+
+fn too_many_rows(name: &str) -> ConnectorError {
+    ConnectorError::InvalidData(format!(
+        "Exploding '{}' would produce more than {} rows from one record; read arrays as strings instead",
+        name, MAX_EXPLODED_ROWS
+    ))
+}
+
+fn set_all(mut rows: Vec<Map<String, Value>>, name: String, value: Value) -> Vec<Map<String, Value>> {
+    for row in &mut rows {
+        row.insert(name.clone(), value.clone());
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Float64Array, StringArray};
+    use serde_json::json;
+    use std::io::Write;
+
+    fn rows(content: &str, options: &JsonOptions) -> Vec<Value> {
+        flatten_records(content.as_bytes(), options)
+            .unwrap()
+            .into_iter()
+            .map(Value::Object)
+            .collect()
+    }
+
+    #[test]
+    fn test_ndjson_with_nested_objects() {
+        let content = "{\"id\":1,\"user\":{\"name\":\"a\",\"geo\":{\"lat\":1.5}}}\n\n{\"id\":2,\"user\":{\"name\":\"b\"}}\n";
+        assert_eq!(
+            rows(content, &JsonOptions::default()),
+            vec![
+                json!({"id": 1, "user.name": "a", "user.geo.lat": 1.5}),
+                json!({"id": 2, "user.name": "b"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_records_path_and_array_modes() {
+        let content = r#"{"data": {"items": [{"id": 1, "tags": ["x", "y"]}, {"id": 2, "tags": []}]}}"#;
+
+        let stringified = JsonOptions {
+            records_path: Some("data.items".to_string()),
+            arrays: ArrayMode::Stringify,
+        };
+        assert_eq!(
+            rows(content, &stringified),
+            vec![json!({"id": 1, "tags": "[\"x\",\"y\"]"}), json!({"id": 2, "tags": null})]
+        );
+
+        let exploded = JsonOptions {
+            arrays: ArrayMode::Explode,
+            ..stringified
+        };
+        assert_eq!(
+            rows(content, &exploded),
+            vec![
+                json!({"id": 1, "tags": "x"}),
+                json!({"id": 1, "tags": "y"}),
+                json!({"id": 2, "tags": null}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explode_is_capped() {
+        let options = JsonOptions {
+            records_path: None,
+            arrays: ArrayMode::Explode,
+        };
+        let items: Vec<u32> = (0..200).collect();
+        let content = json!({"a": items, "b": items}).to_string();
+        assert!(matches!(
+            flatten_records(content.as_bytes(), &options),
+            Err(ConnectorError::InvalidData(_))
+        ));
+
+        let content = json!({"a": items, "b": [1, 2]}).to_string();
+        assert_eq!(flatten_records(content.as_bytes(), &options).unwrap().len(), 400);
+    }
+
+    #[test]
+    fn test_missing_records_path() {
+        let options = JsonOptions {
+            records_path: Some("data.rows".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            flatten_records(br#"{"data": {}}"#, &options),
+            Err(ConnectorError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_unifies_schema() {
+        let path = std::env::temp_dir().join(format!("pilotba-json-{}.ndjson", uuid::Uuid::new_v4()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        file.write_all(b"{\"id\": 1, \"score\": 2}\n{\"id\": \"b\", \"score\": 2.5, \"extra\": {\"flag\": true}}\n")
+            .unwrap();
+
+        let dataset = read(&path, &ReadOptions::default()).unwrap();
+        std::fs::remove_file(&path).ok();
+        let batch = &dataset.batches[0];
+        assert_eq!(dataset.num_rows(), 2);
+        assert_eq!(dataset.schema.fields().len(), 3);
+
+        let column = |name: &str| batch.column(dataset.schema.index_of(name).unwrap());
+        let ids = column("id");
+        assert_eq!(ids.as_any().downcast_ref::<StringArray>().unwrap().value(0), "1");
+        let scores = column("score");
+        assert_eq!(scores.as_any().downcast_ref::<Float64Array>().unwrap().value(1), 2.5);
+        assert!(column("extra.flag").is_null(0));
+    }
+}
@@ -1,16 +1,216 @@
 // Data connectors for various sources
+pub mod arrow_ipc;
 pub mod csv;
 pub mod database;
+pub mod json;
 pub mod parquet;
 
+use arrow::datatypes::SchemaRef;
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
+use thiserror::Error;
+
+/// Default number of rows per Arrow record batch
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DataSource {
     Csv { path: String },
+    Json { path: String },
     Parquet { path: String },
+    Arrow { path: String },
     Postgres { connection_string: String },
     MySQL { connection_string: String },
 }
 
+/// Errors raised while reading data from a source
+#[derive(Error, Debug)]
+pub enum ConnectorError {
+    #[error("Unknown column: {0}")]
+    UnknownColumn(String),
+
+    #[error("Invalid data: {0}")]
+    InvalidData(String),
+
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+
+    #[error("Parquet error: {0}")]
+    Parquet(#[from] ::parquet::errors::ParquetError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type ConnectorResult<T> = Result<T, ConnectorError>;
+
+/// File formats that can be read into Arrow record batches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFormat {
+    Csv,
+    Json,
+    Parquet,
+    Arrow,
+}
 
+impl FileFormat {
+    /// Resolve a format from a file extension (case-insensitive)
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "csv" => Some(FileFormat::Csv),
+            "json" | "ndjson" | "jsonl" => Some(FileFormat::Json),
+            "parquet" => Some(FileFormat::Parquet),
+            "arrow" => Some(FileFormat::Arrow),
+            _ => None,
+        }
+    }
+
+    /// Resolve a format from a file path
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_extension)
+    }
+}
+
+/// Options controlling which part of a source is read
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+    /// Columns to read, in output order (all columns when `None`)
+    pub columns: Option<Vec<String>>,
+    /// Number of data rows to skip
+    pub offset: usize,
+    /// Maximum number of rows to return
+    pub limit: Option<usize>,
+    /// Rows per record batch
+    pub batch_size: usize,
+    /// Options for JSON sources
+    pub json: json::JsonOptions,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            columns: None,
+            offset: 0,
+            limit: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            json: json::JsonOptions::default(),
+        }
+    }
+}
+
+/// Data read from a source as Arrow record batches
+#[derive(Debug, Clone)]
+pub struct Dataset {
+    pub schema: SchemaRef,
+    pub batches: Vec<RecordBatch>,
+}
+
+impl Dataset {
+    /// Total number of rows across all batches
+    pub fn num_rows(&self) -> usize {
+        self.batches.iter().map(|b| b.num_rows()).sum()
+    }
+}
+
+/// Read a file into Arrow record batches using the connector for its format
+pub fn read_file(path: &Path, format: FileFormat, options: &ReadOptions) -> ConnectorResult<Dataset> {
+    match format {
+        FileFormat::Csv => csv::read(path, options),
+        FileFormat::Json => json::read(path, options),
+        FileFormat::Parquet => parquet::read(path, options),
+        FileFormat::Arrow => arrow_ipc::read(path, options),
+    }
+}
+
+/// Resolve column names to indices in `schema`, preserving the requested order
+pub(crate) fn projection_indices(
+    schema: &arrow::datatypes::Schema,
+    columns: &[String],
+) -> ConnectorResult<Vec<usize>> {
+    columns
+        .iter()
+        .map(|name| {
+            schema
+                .index_of(name)
+                .map_err(|_| ConnectorError::UnknownColumn(name.clone()))
+        })
+        .collect()
+}
+
+/// Apply offset and limit to a stream of record batches, stopping early once
+/// the limit has been reached so the rest of the source is never decoded.
+pub(crate) fn collect_window<I>(
+    batches: I,
+    offset: usize,
+    limit: Option<usize>,
+) -> ConnectorResult<Vec<RecordBatch>>
+where
+    I: IntoIterator<Item = Result<RecordBatch, ArrowError>>,
+{
+    let mut skip = offset;
+    let mut remaining = limit.unwrap_or(usize::MAX);
+    let mut result = Vec::new();
+
+    for batch in batches {
+        if remaining == 0 {
+            break;
+        }
+        let batch = batch?;
+        let rows = batch.num_rows();
+        if skip >= rows {
+            skip -= rows;
+            continue;
+        }
+        let take = (rows - skip).min(remaining);
+        result.push(batch.slice(skip, take));
+        remaining -= take;
+        skip = 0;
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int32Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    fn batch(values: Vec<i32>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new("v", DataType::Int32, false)]));
+        RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(values))]).unwrap()
+    }
+
+    #[test]
+    fn test_format_from_extension() {
+        assert_eq!(FileFormat::from_extension("CSV"), Some(FileFormat::Csv));
+        assert_eq!(FileFormat::from_extension("parquet"), Some(FileFormat::Parquet));
+        assert_eq!(FileFormat::from_extension("jsonl"), Some(FileFormat::Json));
+        assert_eq!(FileFormat::from_extension("xlsx"), None);
+    }
+
+    #[test]
+    fn test_collect_window_spans_batches() {
+        let batches = vec![Ok(batch(vec![1, 2, 3])), Ok(batch(vec![4, 5, 6]))];
+        let result = collect_window(batches, 2, Some(3)).unwrap();
+
+        let values: Vec<i32> = result
+            .iter()
+            .flat_map(|b| {
+                b.column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap()
+                    .values()
+                    .to_vec()
+            })
+            .collect();
+        assert_eq!(values, vec![3, 4, 5]);
+    }
+}
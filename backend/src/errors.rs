@@ -8,6 +8,14 @@ use serde_json::json;
 use std::fmt;
 use thiserror::Error;
 
+use crate::connectors::ConnectorError;
+use crate::services::auth::oidc::OidcError;
+use crate::services::auth::saml::SamlError;
+use crate::services::export::ExportError;
+use crate::services::mail::MailError;
+use crate::services::query_engine::QueryError;
+use crate::services::usage::QuotaExceeded;
+
 /// API Error types with associated HTTP status codes
 #[derive(Error, Debug)]
 pub enum ApiError {
@@ -35,13 +43,17 @@ pub enum ApiError {
     #[error("File too large: maximum size is {0} bytes")]
     FileTooLarge(u64),
 
+    /// Upload would exceed a storage or file quota (413)
+    #[error("{0}")]
+    QuotaExceeded(QuotaExceeded),
+
     /// Unsupported file type (415)
     #[error("Unsupported file type: {0}")]
     UnsupportedMediaType(String),
 
-    /// Rate limit exceeded (429)
+    /// Rate limit exceeded (429), with seconds until the client may retry
     #[error("Rate limit exceeded")]
-    RateLimitExceeded,
+    RateLimitExceeded(u64),
 
     /// Internal server error (500)
     #[error("Internal error: {0}")]
@@ -91,8 +103,9 @@ impl ApiError {
             ApiError::BadRequest(_) => "bad_request",
             ApiError::ValidationError(_) => "validation_error",
             ApiError::FileTooLarge(_) => "file_too_large",
+            ApiError::QuotaExceeded(_) => "quota_exceeded",
             ApiError::UnsupportedMediaType(_) => "unsupported_media_type",
-            ApiError::RateLimitExceeded => "rate_limit_exceeded",
+            ApiError::RateLimitExceeded(_) => "rate_limit_exceeded",
             ApiError::Internal(_) => "internal_error",
             ApiError::DatabaseError(_) => "database_error",
             ApiError::IoError(_) => "io_error",
@@ -122,10 +135,13 @@ impl ResponseError for ApiError {
                 actix_web::http::StatusCode::PAYLOAD_TOO_LARGE,
                 format!("File exceeds maximum size of {} bytes", max_size),
             ),
+            ApiError::QuotaExceeded(exceeded) => {
+                (actix_web::http::StatusCode::PAYLOAD_TOO_LARGE, exceeded.to_string())
+            }
             ApiError::UnsupportedMediaType(msg) => {
                 (actix_web::http::StatusCode::UNSUPPORTED_MEDIA_TYPE, msg.clone())
             }
-            ApiError::RateLimitExceeded => (
+            ApiError::RateLimitExceeded(_) => (
                 actix_web::http::StatusCode::TOO_MANY_REQUESTS,
                 "Rate limit exceeded. Please try again later.".to_string(),
             ),
@@ -153,11 +169,83 @@ impl ResponseError for ApiError {
             }
         };
 
-        HttpResponse::build(status).json(json!({
+        let mut response = HttpResponse::build(status);
+        if let ApiError::RateLimitExceeded(retry_after) = self {
+            response.insert_header(("Retry-After", (*retry_after).max(1).to_string()));
+        }
+
+        let mut body = json!({
             "error": self.error_code(),
             "message": message,
             "status": status.as_u16()
-        }))
+        });
+        // Say which quota was hit and how much of it is left
+        if let ApiError::QuotaExceeded(exceeded) = self {
+            body["quota"] = json!(exceeded);
+        }
+
+        response.json(body)
+    }
+}
+
+impl From<ConnectorError> for ApiError {
+    fn from(err: ConnectorError) -> Self {
+        match err {
+            ConnectorError::UnknownColumn(column) => {
+                ApiError::BadRequest(format!("Unknown column: {}", column))
+            }
+            ConnectorError::InvalidData(msg) => ApiError::ValidationError(msg),
+            ConnectorError::Io(e) => ApiError::IoError(e),
+            other => ApiError::Internal(format!("Failed to read file: {}", other)),
+        }
+    }
+}
+
+impl From<QueryError> for ApiError {
+    fn from(err: QueryError) -> Self {
+        match err {
+            QueryError::Arrow(e) => ApiError::Internal(format!("Query execution failed: {}", e)),
+            other => ApiError::BadRequest(other.to_string()),
+        }
+    }
+}
+
+impl From<ExportError> for ApiError {
+    fn from(err: ExportError) -> Self {
+        match err {
+            ExportError::TooManyRows { .. } => ApiError::BadRequest(err.to_string()),
+            ExportError::Io(e) => ApiError::IoError(e),
+            other => ApiError::Internal(format!("Export failed: {}", other)),
+        }
+    }
+}
+
+impl From<OidcError> for ApiError {
+    fn from(err: OidcError) -> Self {
+        match err {
+            OidcError::TokenExchange(_) | OidcError::InvalidIdToken(_) => {
+                ApiError::Unauthorized(err.to_string())
+            }
+            other => ApiError::Internal(format!("OIDC provider error: {}", other)),
+        }
+    }
+}
+
+impl From<SamlError> for ApiError {
+    fn from(err: SamlError) -> Self {
+        match err {
+            SamlError::Config(msg) => ApiError::BadRequest(msg),
+            other => ApiError::Unauthorized(other.to_string()),
+        }
+    }
+}
+
+impl From<MailError> for ApiError {
+    fn from(err: MailError) -> Self {
+        match err {
+            MailError::Address(_) => ApiError::BadRequest(err.to_string()),
+            other => ApiError::Internal(format!("Failed to send email: {}", other)),
+        }
     }
 }
 
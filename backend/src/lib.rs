@@ -10,3 +10,6 @@ pub mod routes;
 pub mod services;
 pub mod utils;
 
+#[cfg(test)]
+mod test_db;
+
@@ -15,6 +15,9 @@ mod routes;
 mod services;
 mod utils;
 
+#[cfg(test)]
+mod test_db;
+
 #[actix_web::main]
 async fn main() -> io::Result<()> {
     // Initialize logger
@@ -29,11 +32,10 @@ async fn main() -> io::Result<()> {
     let bind_address = std::env::var("BIND_ADDRESS")
         .unwrap_or_else(|_| "0.0.0.0:8080".to_string());
     
-    let jwt_secret = std::env::var("JWT_SECRET")
-        .unwrap_or_else(|_| {
-            log::warn!("JWT_SECRET not set, using development default. DO NOT USE IN PRODUCTION!");
-            "development-secret-change-in-production".to_string()
-        });
+    // JWT signing keys
+    let keyring = services::auth::keys::Keyring::from_env().expect("Failed to load JWT signing keys");
+    log::info!("Signing access tokens with key {}", keyring.signing_key_id());
+    services::auth::keys::Keyring::install(keyring);
 
     // Database connection
     let database_url = std::env::var("DATABASE_URL")
@@ -55,6 +57,54 @@ async fn main() -> io::Result<()> {
     std::fs::create_dir_all(&upload_dir).ok();
     log::info!("Upload directory: {}", upload_dir);
     
+    // Outgoing email (verification, password reset)
+    let mailer = web::Data::new(
+        services::mail::Mailer::from_env().expect("Failed to configure mail transport"),
+    );
+
+    // Request rate limits and login lockout
+    let rate_limiter = web::Data::new(
+        services::rate_limit::RateLimiter::from_env()
+            .await
+            .expect("Failed to configure rate limiting"),
+    );
+
+    // Session revocations, shared between instances through Redis if configured
+    services::auth::sessions::SessionService::configure_from_env()
+        .await
+        .expect("Failed to configure session store");
+
+    // Default storage quotas
+    let usage_limits = web::Data::new(
+        services::usage::UsageLimits::from_env().expect("Failed to configure usage quotas"),
+    );
+
+    // Optional OpenID Connect login
+    let oidc_client = services::auth::oidc::OidcConfig::from_env().map(|config| {
+        log::info!("OIDC login enabled for issuer {}", config.issuer);
+        web::Data::new(services::auth::oidc::OidcClient::new(config))
+    });
+
+    // Optional SAML single sign-on
+    let saml_sp = services::auth::saml::ServiceProvider::from_env().map(|sp| {
+        log::info!("SAML login enabled for SP entity {}", sp.entity_id);
+        web::Data::new(sp)
+    });
+
+    // Delete team files past their retention period
+    let retention_pool = pool.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            match services::team_settings::TeamSettingsService::purge_expired_files(&retention_pool).await {
+                Ok(0) => {}
+                Ok(purged) => log::info!("Deleted {} files past their team's retention period", purged),
+                Err(e) => log::warn!("Failed to apply team retention periods: {}", e),
+            }
+        }
+    });
+
     log::info!("Server binding to: {}", bind_address);
     
     HttpServer::new(move || {
@@ -73,6 +123,19 @@ async fn main() -> io::Result<()> {
             .wrap(cors)
             // App state
             .app_data(web::Data::new(pool.clone()))
+            .app_data(mailer.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(usage_limits.clone())
+            .configure(|cfg| {
+                if let Some(client) = &oidc_client {
+                    cfg.app_data(client.clone());
+                }
+                if let Some(sp) = &saml_sp {
+                    cfg.app_data(sp.clone());
+                }
+            })
+            // JWKS for services verifying our tokens
+            .configure(routes::auth::well_known_config)
             // Public API routes
             .service(
                 web::scope("/api")
@@ -83,9 +146,15 @@ async fn main() -> io::Result<()> {
                     // Protected routes
                     .service(
                         web::scope("")
+                            .wrap(middleware::RateLimitMiddleware::new("api"))
                             .wrap(middleware::AuthMiddleware)
+                            .configure(routes::admin::config)
+                            .configure(routes::api_keys::config)
                             .configure(routes::files::config)
+                            .configure(routes::invitations::config)
+                            .configure(routes::permissions::config)
                             .configure(routes::teams::config)
+                            .configure(routes::usage::config)
                     )
             )
     })
@@ -1,17 +1,28 @@
 //! JWT Authentication Middleware
 //!
 //! Provides JWT token validation and user extraction for protected routes.
+//! Tokens bound to a session (`sid`) are rejected once that session has been
+//! revoked, e.g. by logout or "sign out everywhere".
+//!
+//! API keys are accepted in place of a JWT, either as the bearer token or in
+//! an `X-API-Key` header. Their claims carry the key's scope, which handlers
+//! enforce with [`Claims::require`] and [`Claims::require_team`].
 
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    Error, HttpMessage,
+    web, Error, HttpMessage,
 };
 use futures_util::future::{ok, LocalBoxFuture, Ready};
-use jsonwebtoken::{decode, DecodingKey, Validation, Algorithm};
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 use std::rc::Rc;
+use uuid::Uuid;
 
 use crate::errors::ApiError;
+use crate::services::auth::api_keys::{is_api_key, ApiKeyPrincipal, ApiKeyScope, ApiKeyService};
+use crate::services::auth::keys::Keyring;
+use crate::services::auth::sessions::SessionService;
+use crate::services::permissions::Permission;
 
 /// JWT Claims structure
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -26,6 +37,15 @@ pub struct Claims {
     pub exp: usize,
     /// Issued at timestamp
     pub iat: usize,
+    /// Unique token ID
+    #[serde(default)]
+    pub jti: String,
+    /// Login session the token belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    /// Scope of the API key the request was authenticated with
+    #[serde(skip)]
+    pub api_key: Option<ApiKeyScope>,
 }
 
 impl Claims {
@@ -40,8 +60,85 @@ impl Claims {
             name: name.to_string(),
             exp,
             iat: now.timestamp() as usize,
+            jti: Uuid::new_v4().to_string(),
+            sid: None,
+            api_key: None,
         }
     }
+
+    /// Claims for a request authenticated with an API key
+    pub fn for_api_key(principal: ApiKeyPrincipal) -> Self {
+        let now = chrono::Utc::now();
+        let exp = principal.scope.expires_at.map_or(usize::MAX, |at| at.timestamp() as usize);
+
+        Claims {
+            sub: principal.user_id.to_string(),
+            email: principal.email,
+            name: principal.name,
+            exp,
+            iat: now.timestamp() as usize,
+            jti: principal.scope.key_id.to_string(),
+            sid: None,
+            api_key: Some(principal.scope),
+        }
+    }
+
+    /// Bind the token to a login session
+    pub fn with_session(mut self, session_id: Uuid) -> Self {
+        self.sid = Some(session_id.to_string());
+        self
+    }
+
+    /// Session ID, if the token is bound to one
+    pub fn session_id(&self) -> Option<Uuid> {
+        self.sid.as_deref().and_then(|sid| Uuid::parse_str(sid).ok())
+    }
+
+    /// Whether the credential allows `permission`. Signed-in users are
+    /// limited only by their roles; API keys may be restricted further.
+    pub fn allows(&self, permission: Permission) -> bool {
+        self.api_key.as_ref().is_none_or(|key| key.allows(permission))
+    }
+
+    /// Fail unless the credential allows `permission`
+    pub fn require(&self, permission: Permission) -> Result<(), ApiError> {
+        if !self.allows(permission) {
+            return Err(ApiError::forbidden(format!("This API key does not grant {}", permission.as_str())));
+        }
+        Ok(())
+    }
+
+    /// Fail if the credential is a team API key for another team
+    pub fn require_team(&self, team_id: Uuid) -> Result<(), ApiError> {
+        match self.key_team() {
+            Some(key_team) if key_team != team_id => Err(ApiError::forbidden("This API key belongs to another team")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Team a team API key is confined to
+    pub fn key_team(&self) -> Option<Uuid> {
+        self.api_key.as_ref().and_then(|key| key.team_id)
+    }
+
+    /// Whether the credential reaches a resource of `team_id` (`None` for a
+    /// personal one). A team API key acts as its creator but only within its
+    /// team, so it cannot reach personal resources or other teams'.
+    pub fn reaches(&self, team_id: Option<Uuid>) -> bool {
+        match self.key_team() {
+            Some(key_team) => team_id == Some(key_team),
+            None => true,
+        }
+    }
+
+    /// Fail if the credential is an API key; account security (sessions,
+    /// MFA, API keys themselves) needs the user to be signed in
+    pub fn require_interactive(&self) -> Result<(), ApiError> {
+        if self.api_key.is_some() {
+            return Err(ApiError::forbidden("API keys cannot be used for this operation; sign in instead"));
+        }
+        Ok(())
+    }
 }
 
 /// Authentication middleware
@@ -86,76 +183,70 @@ where
         let service = self.service.clone();
 
         Box::pin(async move {
-            // Extract Authorization header
-            let auth_header = req
+            // Extract the bearer token, or an API key header
+            let credential = req
                 .headers()
                 .get("Authorization")
-                .and_then(|h| h.to_str().ok());
-
-            match auth_header {
-                Some(header) if header.starts_with("Bearer ") => {
-                    let token = &header[7..]; // Skip "Bearer "
-
-                    // Get JWT secret from environment
-                    let jwt_secret = std::env::var("JWT_SECRET")
-                        .unwrap_or_else(|_| "development-secret-change-in-production".to_string());
-
-                    // Validate token
-                    match validate_jwt(token, &jwt_secret) {
-                        Ok(claims) => {
-                            // Store claims in request extensions
-                            req.extensions_mut().insert(claims);
-                            service.call(req).await
-                        }
-                        Err(e) => {
-                            log::warn!("JWT validation failed: {:?}", e);
-                            Err(ApiError::unauthorized("Invalid or expired token").into())
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .or_else(|| req.headers().get("X-API-Key").and_then(|h| h.to_str().ok()))
+                .map(|token| token.trim().to_string());
+
+            let Some(token) = credential else {
+                return Err(ApiError::unauthorized("Missing or invalid Authorization header").into());
+            };
+
+            let claims = if is_api_key(&token) {
+                let pool = req
+                    .app_data::<web::Data<PgPool>>()
+                    .ok_or_else(|| ApiError::internal("Database pool not configured"))?;
+                let ip_address = req.peer_addr().map(|addr| addr.ip());
+                match ApiKeyService::authenticate(pool.get_ref(), &token, ip_address)
+                    .await
+                    .map_err(ApiError::from)?
+                {
+                    Some(principal) => Claims::for_api_key(principal),
+                    None => return Err(ApiError::unauthorized("Invalid, expired or revoked API key").into()),
+                }
+            } else {
+                match validate_jwt(&token) {
+                    Ok(claims) => {
+                        // Reject tokens whose session has ended
+                        if let Some(session_id) = claims.session_id() {
+                            let pool = req
+                                .app_data::<web::Data<PgPool>>()
+                                .ok_or_else(|| ApiError::internal("Database pool not configured"))?;
+                            let active = SessionService::is_active(pool.get_ref(), session_id)
+                                .await
+                                .map_err(ApiError::from)?;
+                            if !active {
+                                return Err(ApiError::unauthorized("Session has been revoked").into());
+                            }
                         }
+                        claims
+                    }
+                    Err(e) => {
+                        log::warn!("JWT validation failed: {:?}", e);
+                        return Err(ApiError::unauthorized("Invalid or expired token").into());
                     }
                 }
-                _ => {
-                    Err(ApiError::unauthorized("Missing or invalid Authorization header").into())
-                }
-            }
+            };
+
+            // Store claims in request extensions
+            req.extensions_mut().insert(claims);
+            service.call(req).await
         })
     }
 }
 
-/// Validate JWT token and extract claims
-fn validate_jwt(token: &str, secret: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let validation = Validation::new(Algorithm::HS256);
-    let key = DecodingKey::from_secret(secret.as_bytes());
-
-    let token_data = decode::<Claims>(token, &key, &validation)?;
-    Ok(token_data.claims)
-}
-
-/// Generate a new JWT access token
-pub fn generate_jwt(claims: &Claims, secret: &str) -> Result<String, jsonwebtoken::errors::Error> {
-    use jsonwebtoken::{encode, EncodingKey, Header};
-
-    let key = EncodingKey::from_secret(secret.as_bytes());
-    encode(&Header::default(), claims, &key)
-}
-
-/// Generate a refresh token (uses same JWT format but different purpose)
-pub fn generate_refresh_token(claims: &Claims, secret: &str) -> Result<String, jsonwebtoken::errors::Error> {
-    use jsonwebtoken::{encode, EncodingKey, Header};
-
-    // Use a different secret suffix for refresh tokens for additional security
-    let refresh_secret = format!("{}-refresh", secret);
-    let key = EncodingKey::from_secret(refresh_secret.as_bytes());
-    encode(&Header::default(), claims, &key)
+/// Validate JWT token against the keyring and extract claims
+fn validate_jwt(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    Keyring::global().verify(token)
 }
 
-/// Validate a refresh token
-pub fn validate_refresh_token(token: &str, secret: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let refresh_secret = format!("{}-refresh", secret);
-    let validation = Validation::new(Algorithm::HS256);
-    let key = DecodingKey::from_secret(refresh_secret.as_bytes());
-
-    let token_data = decode::<Claims>(token, &key, &validation)?;
-    Ok(token_data.claims)
+/// Generate a new JWT access token signed with the current signing key
+pub fn generate_jwt(claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
+    Keyring::global().sign(claims)
 }
 
 /// Extract claims from request extensions
@@ -175,23 +266,70 @@ mod tests {
         assert_eq!(claims.email, "test@example.com");
         assert_eq!(claims.name, "Test User");
         assert!(claims.exp > claims.iat);
+        assert_ne!(claims.jti, Claims::new("user123", "test@example.com", "Test User", 24).jti);
+        assert_eq!(claims.session_id(), None);
     }
 
     #[test]
     fn test_jwt_roundtrip() {
         let claims = Claims::new("user123", "test@example.com", "Test User", 24);
-        let secret = "test-secret";
 
-        let token = generate_jwt(&claims, secret).unwrap();
-        let decoded = validate_jwt(&token, secret).unwrap();
+        let token = generate_jwt(&claims).unwrap();
+        let decoded = validate_jwt(&token).unwrap();
 
         assert_eq!(decoded.sub, claims.sub);
         assert_eq!(decoded.email, claims.email);
+        assert_eq!(decoded.jti, claims.jti);
+    }
+
+    #[test]
+    fn test_session_bound_claims_roundtrip() {
+        let session_id = Uuid::new_v4();
+        let claims = Claims::new("user123", "test@example.com", "Test User", 1).with_session(session_id);
+
+        let token = generate_jwt(&claims).unwrap();
+        let decoded = validate_jwt(&token).unwrap();
+        assert_eq!(decoded.session_id(), Some(session_id));
+    }
+
+    #[test]
+    fn test_api_key_scope_checks() {
+        let team_id = Uuid::new_v4();
+        let claims = Claims::for_api_key(ApiKeyPrincipal {
+            user_id: Uuid::new_v4(),
+            email: "etl@example.com".to_string(),
+            name: "ETL".to_string(),
+            scope: ApiKeyScope {
+                key_id: Uuid::new_v4(),
+                team_id: Some(team_id),
+                permissions: Some([Permission::DatasetUpload].into_iter().collect()),
+                expires_at: None,
+            },
+        });
+
+        assert!(claims.require(Permission::DatasetUpload).is_ok());
+        assert!(claims.require(Permission::DatasetDelete).is_err());
+        assert!(claims.require_team(team_id).is_ok());
+        assert!(claims.require_team(Uuid::new_v4()).is_err());
+        assert!(claims.require_interactive().is_err());
+
+        // A team key reaches its team's resources only, not its creator's
+        // personal ones
+        assert!(claims.reaches(Some(team_id)));
+        assert!(!claims.reaches(None));
+        assert!(!claims.reaches(Some(Uuid::new_v4())));
+
+        let user = Claims::new("user123", "test@example.com", "Test User", 1);
+        assert!(user.require(Permission::DatasetDelete).is_ok());
+        assert!(user.require_team(team_id).is_ok());
+        assert!(user.require_interactive().is_ok());
+        assert!(user.reaches(None));
+        assert!(user.reaches(Some(team_id)));
     }
 
     #[test]
     fn test_invalid_token() {
-        let result = validate_jwt("invalid-token", "secret");
+        let result = validate_jwt("invalid-token");
         assert!(result.is_err());
     }
 }
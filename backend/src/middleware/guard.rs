@@ -0,0 +1,333 @@
+//! Permission guards
+//!
+//! Extractors that authorize a request before its handler runs, so handlers
+//! declare what they need instead of checking roles by hand:
+//!
+//! ```ignore
+//! async fn add_member(guard: RequireTeamPermission<perm::TeamManageMembers>, ...) -> ApiResult<HttpResponse>
+//! ```
+//!
+//! Team guards take the team from the `{id}` path segment and require the
+//! user to be a member, the credential to be allowed in the team (API key
+//! scope, MFA requirement) and, for [`RequireTeamPermission`], the permission
+//! granted by the member's role through [`PermissionService`]. Decisions are
+//! cached in the request, so several checks cost one lookup each.
+
+use actix_web::{dev::Payload, web, FromRequest, HttpMessage, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use uuid::Uuid;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::auth::{get_claims, Claims};
+use crate::models::TeamMember;
+use crate::services::auth::mfa::MfaService;
+use crate::services::permissions::{Permission, PermissionService};
+
+/// Type-level name of a [`Permission`], for use in guard types
+pub trait RequiredPermission {
+    const PERMISSION: Permission;
+}
+
+macro_rules! permission_markers {
+    ($($name:ident),* $(,)?) => {
+        $(
+            #[doc = concat!("[`Permission::", stringify!($name), "`]")]
+            pub enum $name {}
+
+            impl RequiredPermission for $name {
+                const PERMISSION: Permission = Permission::$name;
+            }
+        )*
+
+        /// Permissions with a marker, in declaration order
+        #[cfg(test)]
+        pub(crate) const ALL: &[Permission] = &[$(Permission::$name),*];
+    };
+}
+
+/// Permission markers, e.g. `RequireTeamPermission<perm::TeamManageMembers>`
+///
+/// One per permission whether or not a route checks it yet.
+#[allow(dead_code)]
+pub mod perm {
+    use super::RequiredPermission;
+    use crate::services::permissions::Permission;
+
+    permission_markers!(
+        DashboardCreate,
+        DashboardRead,
+        DashboardUpdate,
+        DashboardDelete,
+        DashboardShare,
+        DatasetUpload,
+        DatasetRead,
+        DatasetUpdate,
+        DatasetDelete,
+        DatasetShare,
+        DatasetUnmask,
+        QueryCreate,
+        QueryRead,
+        QueryExecute,
+        QueryDelete,
+        ChartCreate,
+        ChartRead,
+        ChartUpdate,
+        ChartDelete,
+        ChartExport,
+        TeamManageMembers,
+        TeamManageSettings,
+        TeamManageRoles,
+        TeamViewAuditLog,
+        AdminManageUsers,
+        AdminManageTeams,
+        AdminManageSystem,
+        AdminViewAllAuditLogs,
+    );
+}
+
+/// Permission decisions and memberships looked up during a request
+#[derive(Default)]
+struct PermissionCache {
+    decisions: HashMap<(Uuid, Option<Uuid>, Permission), bool>,
+    memberships: HashMap<(Uuid, Uuid), Option<TeamMember>>,
+}
+
+/// Whether a user holds a system permission, cached for the request
+pub async fn check_permission(
+    req: &HttpRequest,
+    pool: &PgPool,
+    user_id: Uuid,
+    permission: Permission,
+) -> ApiResult<bool> {
+    let key = (user_id, None, permission);
+    if let Some(allowed) = cached(req, |cache| cache.decisions.get(&key).copied()) {
+        return Ok(allowed);
+    }
+
+    let allowed = PermissionService::has_permission(pool, user_id, permission).await?;
+    cache(req, |cache| cache.decisions.insert(key, allowed));
+    Ok(allowed)
+}
+
+/// Whether a user holds a permission in a team, cached for the request
+pub async fn check_team_permission(
+    req: &HttpRequest,
+    pool: &PgPool,
+    user_id: Uuid,
+    team_id: Uuid,
+    permission: Permission,
+) -> ApiResult<bool> {
+    let key = (user_id, Some(team_id), permission);
+    if let Some(allowed) = cached(req, |cache| cache.decisions.get(&key).copied()) {
+        return Ok(allowed);
+    }
+
+    let allowed = PermissionService::has_team_permission(pool, user_id, team_id, permission).await?;
+    cache(req, |cache| cache.decisions.insert(key, allowed));
+    Ok(allowed)
+}
+
+/// A user's membership of a team, cached for the request
+pub async fn team_membership(
+    req: &HttpRequest,
+    pool: &PgPool,
+    team_id: Uuid,
+    user_id: Uuid,
+) -> ApiResult<Option<TeamMember>> {
+    let key = (team_id, user_id);
+    if let Some(membership) = cached(req, |cache| cache.memberships.get(&key).cloned()) {
+        return Ok(membership);
+    }
+
+    let membership: Option<TeamMember> =
+        sqlx::query_as("SELECT * FROM team_members WHERE team_id = $1 AND user_id = $2")
+            .bind(team_id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+    cache(req, |cache| cache.memberships.insert(key, membership.clone()));
+    Ok(membership)
+}
+
+/// Forget cached lookups after a handler changes memberships or roles
+pub fn invalidate(req: &HttpRequest) {
+    req.extensions_mut().remove::<PermissionCache>();
+}
+
+fn cached<T>(req: &HttpRequest, lookup: impl FnOnce(&PermissionCache) -> Option<T>) -> Option<T> {
+    req.extensions().get::<PermissionCache>().and_then(lookup)
+}
+
+fn cache<T>(req: &HttpRequest, update: impl FnOnce(&mut PermissionCache) -> T) {
+    let mut extensions = req.extensions_mut();
+    if extensions.get::<PermissionCache>().is_none() {
+        extensions.insert(PermissionCache::default());
+    }
+    update(extensions.get_mut::<PermissionCache>().unwrap());
+}
+
+/// Authenticated user holding a system permission
+pub struct RequirePermission<P> {
+    pub user_id: Uuid,
+    _permission: PhantomData<P>,
+}
+
+impl<P: RequiredPermission + 'static> FromRequest for RequirePermission<P> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let (claims, user_id) = authenticated(&req)?;
+            let pool = pool(&req)?;
+
+            claims.require(P::PERMISSION)?;
+            if !check_permission(&req, &pool, user_id, P::PERMISSION).await? {
+                return Err(forbidden(P::PERMISSION).into());
+            }
+
+            Ok(RequirePermission {
+                user_id,
+                _permission: PhantomData,
+            })
+        })
+    }
+}
+
+/// Member of the team in the `{id}` path segment
+pub struct RequireTeamMember {
+    pub claims: Claims,
+    pub user_id: Uuid,
+    pub team_id: Uuid,
+    pub membership: TeamMember,
+}
+
+impl RequireTeamMember {
+    /// Authorize a request for a team member, optionally holding `permission`
+    pub async fn authorize(req: &HttpRequest, permission: Option<Permission>) -> ApiResult<Self> {
+        let (claims, user_id) = authenticated(req)?;
+        let pool = pool(req)?;
+        let team_id = req
+            .match_info()
+            .get("id")
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or_else(|| ApiError::bad_request("Invalid team ID"))?;
+
+        let membership = team_membership(req, &pool, team_id, user_id)
+            .await?
+            .ok_or_else(|| ApiError::forbidden("You are not a member of this team"))?;
+
+        claims.require_team(team_id)?;
+        if !MfaService::meets_team_requirement(&pool, user_id, team_id).await? {
+            return Err(ApiError::forbidden(
+                "This team requires multi-factor authentication. Enable it in your account settings to continue.",
+            ));
+        }
+
+        if let Some(permission) = permission {
+            if !check_team_permission(req, &pool, user_id, team_id, permission).await? {
+                return Err(forbidden(permission));
+            }
+            claims.require(permission)?;
+        }
+
+        Ok(RequireTeamMember {
+            claims,
+            user_id,
+            team_id,
+            membership,
+        })
+    }
+}
+
+impl FromRequest for RequireTeamMember {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { Ok(RequireTeamMember::authorize(&req, None).await?) })
+    }
+}
+
+/// Member of the team in the `{id}` path segment holding a team permission
+pub struct RequireTeamPermission<P> {
+    member: RequireTeamMember,
+    _permission: PhantomData<P>,
+}
+
+impl<P> Deref for RequireTeamPermission<P> {
+    type Target = RequireTeamMember;
+
+    fn deref(&self) -> &RequireTeamMember {
+        &self.member
+    }
+}
+
+impl<P: RequiredPermission + 'static> FromRequest for RequireTeamPermission<P> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            Ok(RequireTeamPermission {
+                member: RequireTeamMember::authorize(&req, Some(P::PERMISSION)).await?,
+                _permission: PhantomData,
+            })
+        })
+    }
+}
+
+fn authenticated(req: &HttpRequest) -> ApiResult<(Claims, Uuid)> {
+    let claims = get_claims(req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
+
+    Ok((claims, user_id))
+}
+
+fn pool(req: &HttpRequest) -> ApiResult<web::Data<PgPool>> {
+    req.app_data::<web::Data<PgPool>>()
+        .cloned()
+        .ok_or_else(|| ApiError::internal("Database pool not configured"))
+}
+
+fn forbidden(permission: Permission) -> ApiError {
+    ApiError::forbidden(format!("You do not have the {} permission", permission.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_markers_cover_every_permission() {
+        assert_eq!(perm::ALL, Permission::all().as_slice());
+        assert_eq!(<perm::TeamManageMembers as RequiredPermission>::PERMISSION, Permission::TeamManageMembers);
+    }
+
+    #[test]
+    fn test_cache_is_per_request() {
+        let req = TestRequest::default().to_http_request();
+        let key = (Uuid::nil(), None, Permission::DatasetRead);
+
+        assert_eq!(cached(&req, |c| c.decisions.get(&key).copied()), None);
+        cache(&req, |c| c.decisions.insert(key, true));
+        assert_eq!(cached(&req, |c| c.decisions.get(&key).copied()), Some(true));
+
+        invalidate(&req);
+        assert_eq!(cached(&req, |c| c.decisions.get(&key).copied()), None);
+
+        let other = TestRequest::default().to_http_request();
+        assert_eq!(cached(&other, |c| c.decisions.get(&key).copied()), None);
+    }
+}
@@ -1,6 +1,8 @@
 //! Middleware module
 
 pub mod auth;
+pub mod guard;
+pub mod rate_limit;
 
 pub use auth::AuthMiddleware;
-
+pub use rate_limit::RateLimitMiddleware;
@@ -0,0 +1,157 @@
+//! Rate Limiting Middleware
+//!
+//! Meters requests to a route group with the [`RateLimiter`] registered as
+//! app data, keyed by client IP and, behind [`super::AuthMiddleware`], by
+//! user. Requests over the limit get `429 Too Many Requests` with a
+//! `Retry-After` header. Without a registered limiter requests pass through.
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web, Error, HttpMessage,
+};
+use futures_util::future::{ok, LocalBoxFuture, Ready};
+use std::rc::Rc;
+
+use super::auth::Claims;
+use crate::errors::ApiError;
+use crate::services::rate_limit::RateLimiter;
+
+/// Rate limiting middleware for one route group
+pub struct RateLimitMiddleware {
+    group: Rc<str>,
+}
+
+impl RateLimitMiddleware {
+    /// Limit requests with the limits configured for `group`
+    pub fn new(group: &str) -> Self {
+        RateLimitMiddleware { group: group.into() }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimitMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimitMiddlewareService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimitMiddlewareService {
+            service: Rc::new(service),
+            group: self.group.clone(),
+        })
+    }
+}
+
+pub struct RateLimitMiddlewareService<S> {
+    service: Rc<S>,
+    group: Rc<str>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let group = self.group.clone();
+
+        Box::pin(async move {
+            let Some(limiter) = req.app_data::<web::Data<RateLimiter>>().cloned() else {
+                return service.call(req).await;
+            };
+
+            let mut keys = Vec::with_capacity(2);
+            if let Some(ip) = client_ip(&req, limiter.trust_proxy) {
+                keys.push(format!("ip:{}", ip));
+            }
+            if let Some(claims) = req.extensions().get::<Claims>() {
+                keys.push(format!("user:{}", claims.sub));
+            }
+
+            if let Some(wait) = limiter.check(&group, &keys).await {
+                log::warn!("Rate limit exceeded for {} ({})", keys.join(", "), group);
+                return Err(ApiError::RateLimitExceeded(wait.as_secs_f64().ceil() as u64).into());
+            }
+
+            service.call(req).await
+        })
+    }
+}
+
+/// Client IP: the socket peer, or the forwarded client when behind a trusted proxy
+pub fn client_ip(req: &ServiceRequest, trust_proxy: bool) -> Option<String> {
+    if trust_proxy {
+        req.connection_info().realip_remote_addr().map(strip_port)
+    } else {
+        req.peer_addr().map(|addr| addr.ip().to_string())
+    }
+}
+
+fn strip_port(addr: &str) -> String {
+    match addr.parse::<std::net::SocketAddr>() {
+        Ok(socket) => socket.ip().to_string(),
+        Err(_) => addr.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::rate_limit::{LockoutPolicy, MemoryStore, RateLimit};
+    use actix_web::{http::StatusCode, test as actix_test, App, HttpResponse};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[actix_rt::test]
+    async fn test_rejects_over_limit_with_retry_after() {
+        let limits = HashMap::from([("auth".to_string(), RateLimit::new(2, Duration::from_secs(60)))]);
+        let limiter = web::Data::new(RateLimiter::new(Arc::new(MemoryStore::new()), limits, LockoutPolicy::default()));
+
+        let app = actix_test::init_service(
+            App::new().app_data(limiter).service(
+                web::scope("/auth")
+                    .wrap(RateLimitMiddleware::new("auth"))
+                    .route("/login", web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let peer = "10.0.0.1:4000".parse().unwrap();
+        for _ in 0..2 {
+            let req = actix_test::TestRequest::post().uri("/auth/login").peer_addr(peer).to_request();
+            assert_eq!(actix_test::call_service(&app, req).await.status(), StatusCode::OK);
+        }
+
+        let req = actix_test::TestRequest::post().uri("/auth/login").peer_addr(peer).to_request();
+        let err = actix_test::try_call_service(&app, req).await.unwrap_err();
+        let response = err.error_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "30");
+
+        let other = "10.0.0.2:4000".parse().unwrap();
+        let req = actix_test::TestRequest::post().uri("/auth/login").peer_addr(other).to_request();
+        assert_eq!(actix_test::call_service(&app, req).await.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_strip_port() {
+        assert_eq!(strip_port("203.0.113.7:51234"), "203.0.113.7");
+        assert_eq!(strip_port("[2001:db8::1]:443"), "2001:db8::1");
+        assert_eq!(strip_port("203.0.113.7"), "203.0.113.7");
+    }
+}
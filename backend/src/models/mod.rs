@@ -21,6 +21,15 @@ impl Default for UserRole {
     }
 }
 
+/// Account status, set by administrators
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "user_status", rename_all = "lowercase")]
+pub enum UserStatus {
+    #[default]
+    Active,
+    Disabled,
+}
+
 /// User entity stored in database
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
@@ -32,6 +41,9 @@ pub struct User {
     pub role: UserRole,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub status: UserStatus,
+    pub disabled_at: Option<DateTime<Utc>>,
 }
 
 /// Public user info (safe to return in API responses)
@@ -41,6 +53,7 @@ pub struct UserInfo {
     pub email: String,
     pub name: String,
     pub role: UserRole,
+    pub email_verified: bool,
 }
 
 impl From<User> for UserInfo {
@@ -50,6 +63,7 @@ impl From<User> for UserInfo {
             email: user.email,
             name: user.name,
             role: user.role,
+            email_verified: user.email_verified_at.is_some(),
         }
     }
 }
@@ -60,6 +74,10 @@ pub struct RegisterRequest {
     pub email: String,
     pub password: String,
     pub name: String,
+    /// Token from a team invitation sent to this address; joins its teams
+    /// at once
+    #[serde(default)]
+    pub invite_token: Option<String>,
 }
 
 /// Request body for user login
@@ -75,6 +93,25 @@ pub struct RefreshRequest {
     pub refresh_token: String,
 }
 
+/// Request body naming an account by email (password reset, resend verification)
+#[derive(Debug, Deserialize)]
+pub struct EmailRequest {
+    pub email: String,
+}
+
+/// Request body carrying an emailed token
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+/// Request body for completing a password reset
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
 /// Auth response with tokens
 #[derive(Debug, Serialize)]
 pub struct AuthResponse {
@@ -125,6 +162,8 @@ pub struct TeamMember {
     pub team_id: Uuid,
     pub user_id: Uuid,
     pub role: TeamRole,
+    /// Custom role granting the member's permissions, if assigned one
+    pub role_id: Option<Uuid>,
     pub joined_at: DateTime<Utc>,
 }
 
@@ -151,19 +190,38 @@ pub struct CreateTeamRequest {
 pub struct UpdateTeamRequest {
     pub name: Option<String>,
     pub description: Option<String>,
+    /// Deny team access to members without multi-factor authentication
+    pub require_mfa: Option<bool>,
 }
 
 /// Request to invite a user to a team
 #[derive(Debug, Deserialize)]
 pub struct InviteUserRequest {
     pub email: String,
-    pub role: TeamRole,
+    /// Role to join with; omit for the team's default member role
+    #[serde(default)]
+    pub role: Option<TeamRole>,
 }
 
 /// Request to update member role
 #[derive(Debug, Deserialize)]
 pub struct UpdateMemberRoleRequest {
     pub role: TeamRole,
+    /// Custom team role to grant the member's permissions; omit for the
+    /// built-in role's
+    #[serde(default)]
+    pub role_id: Option<Uuid>,
+}
+
+/// Request to hand a team to another member
+#[derive(Debug, Deserialize)]
+pub struct TransferOwnershipRequest {
+    pub new_owner_id: Uuid,
+    /// The team's slug, typed to confirm the transfer
+    pub confirm: String,
+    /// Role the previous owner keeps; defaults to admin
+    #[serde(default)]
+    pub previous_owner_role: Option<TeamRole>,
 }
 
 /// Team member with user details
@@ -174,6 +232,7 @@ pub struct TeamMemberInfo {
     pub email: String,
     pub name: String,
     pub role: TeamRole,
+    pub role_id: Option<Uuid>,
     pub joined_at: DateTime<Utc>,
 }
 
@@ -0,0 +1,211 @@
+//! Email verification and password reset routes
+//!
+//! Public endpoints under `/api/auth`. Requests that name an account by email
+//! return the same response whether or not the account exists, and send mail
+//! in the background so response times do not reveal it either.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::json;
+use sqlx::PgPool;
+
+use super::auth::{hash_password, validate_password, SSO_PASSWORD_HASH};
+use crate::errors::{ApiError, ApiResult};
+use crate::models::{EmailRequest, ResetPasswordRequest, User, VerifyEmailRequest};
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::auth::account_tokens::{AccountTokenService, TokenPurpose};
+use crate::services::auth::sessions::{DeviceInfo, RevocationReason, SessionService};
+use crate::services::invitations::InvitationService;
+use crate::services::mail::Mailer;
+
+/// Configure account recovery routes (nested in the /auth scope)
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/verify-email", web::post().to(verify_email))
+        .route("/verify-email/resend", web::post().to(resend_verification))
+        .route("/password/forgot", web::post().to(forgot_password))
+        .route("/password/reset", web::post().to(reset_password));
+}
+
+/// Confirm an email address
+///
+/// POST /api/auth/verify-email
+async fn verify_email(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<VerifyEmailRequest>,
+) -> ApiResult<HttpResponse> {
+    let mut tx = pool.begin().await?;
+    let user_id = AccountTokenService::redeem(&mut tx, &body.token, TokenPurpose::EmailVerification)
+        .await?
+        .ok_or_else(|| ApiError::bad_request("Invalid or expired verification link"))?;
+
+    let (email, created_at): (String, chrono::DateTime<chrono::Utc>) = sqlx::query_as(
+        "UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()) WHERE id = $1 RETURNING email, created_at"
+    )
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    // A new account joins the teams that invited its address before it signed up
+    let accepted = InvitationService::accept_all(&mut tx, user_id, &email, created_at).await?;
+    tx.commit().await?;
+
+    audit(pool.get_ref(), &req, user_id, AuditAction::UserEmailVerify, None).await;
+    super::invitations::audit_accepted(pool.get_ref(), &req, user_id, &accepted).await;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "message": "Email address verified"
+    })))
+}
+
+/// Send a new verification email to an unverified account
+///
+/// POST /api/auth/verify-email/resend
+async fn resend_verification(
+    pool: web::Data<PgPool>,
+    mailer: web::Data<Mailer>,
+    body: web::Json<EmailRequest>,
+) -> ApiResult<HttpResponse> {
+    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE email = $1")
+        .bind(body.email.trim().to_lowercase())
+        .fetch_optional(pool.get_ref())
+        .await?;
+
+    if let Some(user) = user.filter(|u| u.email_verified_at.is_none()) {
+        let pool = pool.get_ref().clone();
+        actix_web::rt::spawn(async move {
+            if let Err(e) = send_email_verification(&pool, &mailer, &user).await {
+                log::warn!("Failed to send verification email to user {}: {}", user.id, e);
+            }
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "message": "If the account exists and is unverified, a verification email has been sent"
+    })))
+}
+
+/// Request a password reset link
+///
+/// POST /api/auth/password/forgot
+async fn forgot_password(
+    pool: web::Data<PgPool>,
+    mailer: web::Data<Mailer>,
+    body: web::Json<EmailRequest>,
+) -> ApiResult<HttpResponse> {
+    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE email = $1")
+        .bind(body.email.trim().to_lowercase())
+        .fetch_optional(pool.get_ref())
+        .await?;
+
+    // Single sign-on accounts have no password to reset
+    if let Some(user) = user.filter(|u| u.password_hash != SSO_PASSWORD_HASH) {
+        let pool = pool.get_ref().clone();
+        actix_web::rt::spawn(async move {
+            if let Err(e) = send_password_reset(&pool, &mailer, &user).await {
+                log::warn!("Failed to send password reset email to user {}: {}", user.id, e);
+            }
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "message": "If an account exists for this address, a password reset email has been sent"
+    })))
+}
+
+/// Set a new password with a reset token
+///
+/// POST /api/auth/password/reset
+/// Signs the user out of every session
+async fn reset_password(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<ResetPasswordRequest>,
+) -> ApiResult<HttpResponse> {
+    // Validate before redeeming so a weak password does not burn the token
+    validate_password(&body.password)?;
+    let password_hash = hash_password(&body.password)?;
+
+    let mut tx = pool.begin().await?;
+    let user_id = AccountTokenService::redeem(&mut tx, &body.token, TokenPurpose::PasswordReset)
+        .await?
+        .ok_or_else(|| ApiError::bad_request("Invalid or expired password reset link"))?;
+
+    // Receiving the reset email also proves ownership of the address
+    sqlx::query(
+        r#"
+        UPDATE users SET password_hash = $2, email_verified_at = COALESCE(email_verified_at, NOW())
+        WHERE id = $1
+        "#
+    )
+    .bind(user_id)
+    .bind(&password_hash)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let revoked = SessionService::revoke_all(pool.get_ref(), user_id, RevocationReason::PasswordReset).await?;
+
+    audit(
+        pool.get_ref(),
+        &req,
+        user_id,
+        AuditAction::UserPasswordReset,
+        Some(json!({ "sessions_revoked": revoked })),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "message": "Password has been reset. Please sign in again."
+    })))
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Issue a verification token for the user's current address and email it
+pub(crate) async fn send_email_verification(pool: &PgPool, mailer: &Mailer, user: &User) -> ApiResult<()> {
+    let mut tx = pool.begin().await?;
+    let token = AccountTokenService::issue(&mut tx, user.id, &user.email, TokenPurpose::EmailVerification).await?;
+    tx.commit().await?;
+
+    mailer.send_email_verification(&user.email, &user.name, &token).await?;
+    Ok(())
+}
+
+/// Issue a password reset token and email it
+pub(crate) async fn send_password_reset(pool: &PgPool, mailer: &Mailer, user: &User) -> ApiResult<()> {
+    let mut tx = pool.begin().await?;
+    let token = AccountTokenService::issue(&mut tx, user.id, &user.email, TokenPurpose::PasswordReset).await?;
+    tx.commit().await?;
+
+    mailer.send_password_reset(&user.email, &user.name, &token).await?;
+    Ok(())
+}
+
+async fn audit(
+    pool: &PgPool,
+    req: &HttpRequest,
+    user_id: uuid::Uuid,
+    action: AuditAction,
+    details: Option<serde_json::Value>,
+) {
+    if let Err(e) = AuditService::log(pool, AuditEntry {
+        user_id: Some(user_id),
+        team_id: None,
+        action: action.clone(),
+        resource_type: Some(ResourceType::User),
+        resource_id: Some(user_id),
+        details,
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: DeviceInfo::from_request(req).user_agent,
+    })
+    .await
+    {
+        log::warn!("Failed to audit {} for {}: {}", action.as_str(), user_id, e);
+    }
+}
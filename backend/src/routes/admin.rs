@@ -0,0 +1,916 @@
+//! Admin user management routes
+//!
+//! Everything under `/api/admin/users` requires `admin:manage_users`.
+//! Administrators cannot change their own role, status or account here, so
+//! at least one administrator always remains.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::auth::{hash_password, validate_email, validate_password, RESET_REQUIRED_PASSWORD_HASH, SSO_PASSWORD_HASH};
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::guard::{perm, RequirePermission};
+use crate::models::{User, UserRole, UserStatus};
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::auth::sessions::{DeviceInfo, RevocationReason, SessionService};
+use crate::services::mail::Mailer;
+use crate::services::usage::{period_start, UsageLimits, UsageService};
+
+/// Configure admin routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/users")
+            .route("", web::get().to(list_users))
+            .route("", web::post().to(create_user))
+            .route("/{id}", web::get().to(get_user))
+            .route("/{id}", web::delete().to(delete_user))
+            .route("/{id}/role", web::put().to(update_role))
+            .route("/{id}/attributes", web::put().to(update_attributes))
+            .route("/{id}/quota", web::put().to(update_quota))
+            .route("/{id}/disable", web::post().to(disable_user))
+            .route("/{id}/enable", web::post().to(enable_user))
+            .route("/{id}/password-reset", web::post().to(force_password_reset)),
+    );
+}
+
+// ============================================================================
+// MODELS
+// ============================================================================
+
+/// User as shown to administrators
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AdminUserInfo {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub role: UserRole,
+    pub status: UserStatus,
+    pub email_verified: bool,
+    pub mfa_enabled: bool,
+    /// Custom attributes used by row-level security policies
+    pub attributes: sqlx::types::Json<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+    pub disabled_at: Option<DateTime<Utc>>,
+}
+
+/// Query parameters for listing users
+#[derive(Debug, Deserialize)]
+pub struct ListUsersQuery {
+    pub page: Option<i64>,
+    pub limit: Option<i64>,
+    /// Matches email or name
+    pub search: Option<String>,
+    pub role: Option<UserRole>,
+    pub status: Option<UserStatus>,
+}
+
+/// New user created by an administrator
+#[derive(Debug, Deserialize)]
+pub struct CreateUserRequest {
+    pub email: String,
+    pub name: String,
+    /// Omit to email the user a link to choose their own
+    pub password: Option<String>,
+    pub role: Option<UserRole>,
+}
+
+/// Role change
+#[derive(Debug, Deserialize)]
+pub struct UpdateRoleRequest {
+    pub role: UserRole,
+}
+
+/// Replacement custom attributes
+#[derive(Debug, Deserialize)]
+pub struct UpdateAttributesRequest {
+    pub attributes: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Replacement personal quotas; an omitted quota follows the server default
+#[derive(Debug, Deserialize)]
+pub struct UpdateQuotaRequest {
+    #[serde(default)]
+    pub storage_quota_bytes: Option<i64>,
+    #[serde(default)]
+    pub file_quota: Option<i32>,
+}
+
+/// Query parameters for deleting a user
+#[derive(Debug, Deserialize)]
+pub struct DeleteUserQuery {
+    /// User to receive the deleted user's files and teams
+    pub transfer_to: Option<Uuid>,
+}
+
+const USER_COLUMNS: &str = r#"
+    u.id, u.email, u.name, u.role, u.status,
+    u.email_verified_at IS NOT NULL AS email_verified,
+    EXISTS (SELECT 1 FROM user_mfa m WHERE m.user_id = u.id AND m.enabled_at IS NOT NULL) AS mfa_enabled,
+    u.attributes, u.created_at, u.disabled_at
+"#;
+
+/// Custom attributes a user may have
+const MAX_ATTRIBUTES: usize = 50;
+
+// ============================================================================
+// HANDLERS
+// ============================================================================
+
+/// List and search users
+///
+/// GET /api/admin/users
+async fn list_users(
+    _admin: RequirePermission<perm::AdminManageUsers>,
+    pool: web::Data<PgPool>,
+    query: web::Query<ListUsersQuery>,
+) -> ApiResult<HttpResponse> {
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let offset = (page - 1) * limit;
+    let search = query.search.as_deref().and_then(search_pattern);
+
+    let filter = r#"
+        ($1::text IS NULL OR u.email ILIKE $1 OR u.name ILIKE $1)
+        AND ($2::user_role IS NULL OR u.role = $2)
+        AND ($3::user_status IS NULL OR u.status = $3)
+    "#;
+
+    let (total,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM users u WHERE {}", filter))
+        .bind(&search)
+        .bind(&query.role)
+        .bind(query.status)
+        .fetch_one(pool.get_ref())
+        .await?;
+
+    let users: Vec<AdminUserInfo> = sqlx::query_as(&format!(
+        "SELECT {} FROM users u WHERE {} ORDER BY u.created_at DESC LIMIT $4 OFFSET $5",
+        USER_COLUMNS, filter
+    ))
+    .bind(&search)
+    .bind(&query.role)
+    .bind(query.status)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool.get_ref())
+    .await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "users": users,
+        "total": total,
+        "page": page,
+        "limit": limit,
+        "pages": (total as f64 / limit as f64).ceil() as i64
+    })))
+}
+
+/// Get one user
+///
+/// GET /api/admin/users/{id}
+async fn get_user(
+    _admin: RequirePermission<perm::AdminManageUsers>,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(fetch_user_info(pool.get_ref(), path.into_inner()).await?))
+}
+
+/// Create a user; without a password they are emailed a link to set one
+///
+/// POST /api/admin/users
+async fn create_user(
+    req: HttpRequest,
+    admin: RequirePermission<perm::AdminManageUsers>,
+    pool: web::Data<PgPool>,
+    mailer: web::Data<Mailer>,
+    body: web::Json<CreateUserRequest>,
+) -> ApiResult<HttpResponse> {
+    let admin_id = admin.user_id;
+
+    let email = body.email.trim().to_lowercase();
+    let name = body.name.trim();
+    validate_email(&email)?;
+    if name.is_empty() || name.len() > 255 {
+        return Err(ApiError::bad_request("Name must be 1-255 characters"));
+    }
+    let password_hash = match &body.password {
+        Some(password) => {
+            validate_password(password)?;
+            hash_password(password)?
+        }
+        None => RESET_REQUIRED_PASSWORD_HASH.to_string(),
+    };
+
+    let existing: Option<(Uuid,)> = sqlx::query_as("SELECT id FROM users WHERE email = $1")
+        .bind(&email)
+        .fetch_optional(pool.get_ref())
+        .await?;
+    if existing.is_some() {
+        return Err(ApiError::bad_request("Email already registered"));
+    }
+
+    let role = body.role.clone().unwrap_or_default();
+    let user: User = sqlx::query_as(
+        r#"
+        INSERT INTO users (email, password_hash, name, role)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *
+        "#
+    )
+    .bind(&email)
+    .bind(&password_hash)
+    .bind(name)
+    .bind(&role)
+    .fetch_one(pool.get_ref())
+    .await?;
+
+    // Setting a password from the emailed link also verifies the address;
+    // users given a password verify it as if they had registered
+    let mailer = mailer.into_inner();
+    let pool_ref = pool.get_ref().clone();
+    let new_user = user.clone();
+    let invited = body.password.is_none();
+    actix_web::rt::spawn(async move {
+        let sent = if invited {
+            super::account::send_password_reset(&pool_ref, &mailer, &new_user).await
+        } else {
+            super::account::send_email_verification(&pool_ref, &mailer, &new_user).await
+        };
+        if let Err(e) = sent {
+            log::warn!("Failed to send welcome email to user {}: {}", new_user.id, e);
+        }
+    });
+
+    audit(
+        pool.get_ref(),
+        &req,
+        admin_id,
+        AuditAction::AdminUserCreate,
+        user.id,
+        json!({ "email": user.email, "role": role, "password_set": !invited }),
+    )
+    .await;
+
+    Ok(HttpResponse::Created().json(fetch_user_info(pool.get_ref(), user.id).await?))
+}
+
+/// Change a user's system role
+///
+/// PUT /api/admin/users/{id}/role
+async fn update_role(
+    req: HttpRequest,
+    admin: RequirePermission<perm::AdminManageUsers>,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<UpdateRoleRequest>,
+) -> ApiResult<HttpResponse> {
+    let admin_id = admin.user_id;
+    let user_id = path.into_inner();
+    ensure_not_self(admin_id, user_id)?;
+
+    let previous: Option<(UserRole,)> = sqlx::query_as("SELECT role FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool.get_ref())
+        .await?;
+    let (previous,) = previous.ok_or_else(|| ApiError::not_found("User not found"))?;
+
+    sqlx::query("UPDATE users SET role = $2 WHERE id = $1")
+        .bind(user_id)
+        .bind(&body.role)
+        .execute(pool.get_ref())
+        .await?;
+
+    audit(
+        pool.get_ref(),
+        &req,
+        admin_id,
+        AuditAction::AdminUserUpdate,
+        user_id,
+        json!({ "role": { "from": previous, "to": body.role } }),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(fetch_user_info(pool.get_ref(), user_id).await?))
+}
+
+/// Replace a user's custom attributes, which row-level security policies
+/// reference as `{{user.attributes.<key>}}`
+///
+/// PUT /api/admin/users/{id}/attributes
+async fn update_attributes(
+    req: HttpRequest,
+    admin: RequirePermission<perm::AdminManageUsers>,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<UpdateAttributesRequest>,
+) -> ApiResult<HttpResponse> {
+    let admin_id = admin.user_id;
+    let user_id = path.into_inner();
+    // Attributes decide which rows a user sees, so admins cannot widen their own
+    ensure_not_self(admin_id, user_id)?;
+
+    let attributes = body.into_inner().attributes;
+    validate_attributes(&attributes)?;
+
+    let previous: Option<(sqlx::types::Json<serde_json::Value>,)> =
+        sqlx::query_as("SELECT attributes FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(pool.get_ref())
+            .await?;
+    let (previous,) = previous.ok_or_else(|| ApiError::not_found("User not found"))?;
+
+    let attributes = serde_json::Value::Object(attributes);
+    sqlx::query("UPDATE users SET attributes = $2 WHERE id = $1")
+        .bind(user_id)
+        .bind(sqlx::types::Json(&attributes))
+        .execute(pool.get_ref())
+        .await?;
+
+    audit(
+        pool.get_ref(),
+        &req,
+        admin_id,
+        AuditAction::AdminUserUpdate,
+        user_id,
+        json!({ "attributes": { "from": previous.0, "to": attributes } }),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(fetch_user_info(pool.get_ref(), user_id).await?))
+}
+
+/// Set a user's personal storage and file quotas, returning their usage
+/// against them
+///
+/// PUT /api/admin/users/{id}/quota
+async fn update_quota(
+    req: HttpRequest,
+    admin: RequirePermission<perm::AdminManageUsers>,
+    pool: web::Data<PgPool>,
+    limits: web::Data<UsageLimits>,
+    path: web::Path<Uuid>,
+    body: web::Json<UpdateQuotaRequest>,
+) -> ApiResult<HttpResponse> {
+    let user_id = path.into_inner();
+    if body.storage_quota_bytes.is_some_and(|q| q <= 0) || body.file_quota.is_some_and(|q| q <= 0) {
+        return Err(ApiError::bad_request("Quotas must be positive; omit one to use the server default"));
+    }
+
+    let previous: Option<(Option<i64>, Option<i32>)> =
+        sqlx::query_as("SELECT storage_quota_bytes, file_quota FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(pool.get_ref())
+            .await?;
+    let (previous_storage, previous_files) = previous.ok_or_else(|| ApiError::not_found("User not found"))?;
+
+    sqlx::query("UPDATE users SET storage_quota_bytes = $2, file_quota = $3 WHERE id = $1")
+        .bind(user_id)
+        .bind(body.storage_quota_bytes)
+        .bind(body.file_quota)
+        .execute(pool.get_ref())
+        .await?;
+
+    audit(
+        pool.get_ref(),
+        &req,
+        admin.user_id,
+        AuditAction::AdminUserUpdate,
+        user_id,
+        json!({
+            "storage_quota_bytes": { "from": previous_storage, "to": body.storage_quota_bytes },
+            "file_quota": { "from": previous_files, "to": body.file_quota }
+        }),
+    )
+    .await;
+
+    let usage = UsageService::user_usage(pool.get_ref(), &limits, user_id, period_start(None)).await?;
+    Ok(HttpResponse::Ok().json(usage))
+}
+
+/// Disable an account, signing it out everywhere
+///
+/// POST /api/admin/users/{id}/disable
+async fn disable_user(
+    req: HttpRequest,
+    admin: RequirePermission<perm::AdminManageUsers>,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    let admin_id = admin.user_id;
+    let user_id = path.into_inner();
+    ensure_not_self(admin_id, user_id)?;
+
+    let updated = sqlx::query(
+        "UPDATE users SET status = 'disabled', disabled_at = NOW() WHERE id = $1 AND status = 'active'"
+    )
+    .bind(user_id)
+    .execute(pool.get_ref())
+    .await?
+    .rows_affected();
+
+    if updated == 0 {
+        // Missing, or already disabled
+        fetch_user_info(pool.get_ref(), user_id).await?;
+    } else {
+        let revoked = SessionService::revoke_all(pool.get_ref(), user_id, RevocationReason::AccountDisabled).await?;
+        audit(
+            pool.get_ref(),
+            &req,
+            admin_id,
+            AuditAction::AdminUserUpdate,
+            user_id,
+            json!({ "status": "disabled", "sessions_revoked": revoked }),
+        )
+        .await;
+    }
+
+    Ok(HttpResponse::Ok().json(fetch_user_info(pool.get_ref(), user_id).await?))
+}
+
+/// Re-enable a disabled account
+///
+/// POST /api/admin/users/{id}/enable
+async fn enable_user(
+    req: HttpRequest,
+    admin: RequirePermission<perm::AdminManageUsers>,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    let admin_id = admin.user_id;
+    let user_id = path.into_inner();
+
+    let updated = sqlx::query("UPDATE users SET status = 'active' WHERE id = $1 AND status = 'disabled'")
+        .bind(user_id)
+        .execute(pool.get_ref())
+        .await?
+        .rows_affected();
+
+    if updated > 0 {
+        audit(
+            pool.get_ref(),
+            &req,
+            admin_id,
+            AuditAction::AdminUserUpdate,
+            user_id,
+            json!({ "status": "active" }),
+        )
+        .await;
+    }
+
+    Ok(HttpResponse::Ok().json(fetch_user_info(pool.get_ref(), user_id).await?))
+}
+
+/// Invalidate a user's password and sessions and email them a reset link
+///
+/// POST /api/admin/users/{id}/password-reset
+async fn force_password_reset(
+    req: HttpRequest,
+    admin: RequirePermission<perm::AdminManageUsers>,
+    pool: web::Data<PgPool>,
+    mailer: web::Data<Mailer>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    let admin_id = admin.user_id;
+    let user_id = path.into_inner();
+    ensure_not_self(admin_id, user_id)?;
+
+    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool.get_ref())
+        .await?;
+    let user = user.ok_or_else(|| ApiError::not_found("User not found"))?;
+
+    if user.password_hash == SSO_PASSWORD_HASH {
+        return Err(ApiError::bad_request("This account signs in through single sign-on and has no password"));
+    }
+
+    sqlx::query("UPDATE users SET password_hash = $2 WHERE id = $1")
+        .bind(user_id)
+        .bind(RESET_REQUIRED_PASSWORD_HASH)
+        .execute(pool.get_ref())
+        .await?;
+    let revoked = SessionService::revoke_all(pool.get_ref(), user_id, RevocationReason::PasswordReset).await?;
+
+    super::account::send_password_reset(pool.get_ref(), &mailer, &user).await?;
+
+    audit(
+        pool.get_ref(),
+        &req,
+        admin_id,
+        AuditAction::AdminUserUpdate,
+        user_id,
+        json!({ "password_reset": true, "sessions_revoked": revoked }),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "message": "Password cleared and reset link sent",
+        "sessions_revoked": revoked
+    })))
+}
+
+/// Delete a user, transferring their files and owned teams to another user
+///
+/// DELETE /api/admin/users/{id}?transfer_to={user_id}
+async fn delete_user(
+    req: HttpRequest,
+    admin: RequirePermission<perm::AdminManageUsers>,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    query: web::Query<DeleteUserQuery>,
+) -> ApiResult<HttpResponse> {
+    let admin_id = admin.user_id;
+    let user_id = path.into_inner();
+    ensure_not_self(admin_id, user_id)?;
+
+    let mut tx = pool.begin().await?;
+
+    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = $1 FOR UPDATE")
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let user = user.ok_or_else(|| ApiError::not_found("User not found"))?;
+
+    let (files_transferred, teams_transferred) = match query.transfer_to {
+        Some(target_id) => {
+            if target_id == user_id {
+                return Err(ApiError::bad_request("Cannot transfer data to the user being deleted"));
+            }
+            let target: Option<(UserStatus,)> = sqlx::query_as("SELECT status FROM users WHERE id = $1")
+                .bind(target_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+            match target {
+                Some((UserStatus::Active,)) => {}
+                Some(_) => return Err(ApiError::bad_request("Data can only be transferred to an active user")),
+                None => return Err(ApiError::not_found("Transfer target not found")),
+            }
+            transfer_ownership(&mut tx, user_id, target_id).await?
+        }
+        None => {
+            let (files, teams): (i64, i64) = sqlx::query_as(
+                r#"
+                SELECT (SELECT COUNT(*) FROM files WHERE user_id = $1),
+                       (SELECT COUNT(*) FROM teams WHERE owner_id = $1)
+                "#
+            )
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if files > 0 || teams > 0 {
+                return Err(ApiError::bad_request(format!(
+                    "User owns {} file(s) and {} team(s); pass transfer_to to hand them to another user",
+                    files, teams
+                )));
+            }
+            (0, 0)
+        }
+    };
+
+    // Sessions, keys and memberships go with the user
+    sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    audit(
+        pool.get_ref(),
+        &req,
+        admin_id,
+        AuditAction::AdminUserDelete,
+        user_id,
+        json!({
+            "email": user.email,
+            "transfer_to": query.transfer_to,
+            "files_transferred": files_transferred,
+            "teams_transferred": teams_transferred
+        }),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "files_transferred": files_transferred,
+        "teams_transferred": teams_transferred
+    })))
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// ILIKE pattern matching the term anywhere, with wildcards escaped
+fn search_pattern(term: &str) -> Option<String> {
+    let term = term.trim();
+    if term.is_empty() {
+        return None;
+    }
+    let escaped = term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    Some(format!("%{}%", escaped))
+}
+
+fn ensure_not_self(admin_id: Uuid, user_id: Uuid) -> ApiResult<()> {
+    if admin_id == user_id {
+        return Err(ApiError::bad_request("Administrators cannot change their own account here"));
+    }
+    Ok(())
+}
+
+/// Attribute keys are identifiers; values are scalars or arrays of scalars
+fn validate_attributes(attributes: &serde_json::Map<String, serde_json::Value>) -> ApiResult<()> {
+    use serde_json::Value;
+
+    if attributes.len() > MAX_ATTRIBUTES {
+        return Err(ApiError::bad_request(format!("A user may have at most {} attributes", MAX_ATTRIBUTES)));
+    }
+    for (key, value) in attributes {
+        let valid_key = !key.is_empty()
+            && key.len() <= 64
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_key {
+            return Err(ApiError::bad_request(format!(
+                "Invalid attribute name '{}': use 1-64 letters, digits or underscores",
+                key
+            )));
+        }
+        let scalar = |v: &Value| matches!(v, Value::String(_) | Value::Number(_) | Value::Bool(_));
+        let valid_value = match value {
+            Value::Array(items) => items.iter().all(scalar),
+            other => scalar(other),
+        };
+        if !valid_value {
+            return Err(ApiError::bad_request(format!(
+                "Attribute '{}' must be a string, number, boolean or an array of them",
+                key
+            )));
+        }
+    }
+    Ok(())
+}
+
+async fn fetch_user_info(pool: &PgPool, user_id: Uuid) -> ApiResult<AdminUserInfo> {
+    sqlx::query_as(&format!("SELECT {} FROM users u WHERE u.id = $1", USER_COLUMNS))
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("User not found"))
+}
+
+/// Move files and owned teams from one user to another; returns the counts
+async fn transfer_ownership(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    from: Uuid,
+    to: Uuid,
+) -> Result<(u64, u64), sqlx::Error> {
+    let files = sqlx::query("UPDATE files SET user_id = $2 WHERE user_id = $1")
+        .bind(from)
+        .bind(to)
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+
+    let teams: Vec<(Uuid,)> = sqlx::query_as("UPDATE teams SET owner_id = $2 WHERE owner_id = $1 RETURNING id")
+        .bind(from)
+        .bind(to)
+        .fetch_all(&mut **tx)
+        .await?;
+
+    for (team_id,) in &teams {
+        sqlx::query(
+            r#"
+            INSERT INTO team_members (team_id, user_id, role)
+            VALUES ($1, $2, 'owner')
+            ON CONFLICT (team_id, user_id) DO UPDATE SET role = 'owner'
+            "#
+        )
+        .bind(team_id)
+        .bind(to)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    Ok((files, teams.len() as u64))
+}
+
+async fn audit(
+    pool: &PgPool,
+    req: &HttpRequest,
+    admin_id: Uuid,
+    action: AuditAction,
+    user_id: Uuid,
+    details: serde_json::Value,
+) {
+    if let Err(e) = AuditService::log(pool, AuditEntry {
+        user_id: Some(admin_id),
+        team_id: None,
+        action: action.clone(),
+        resource_type: Some(ResourceType::User),
+        resource_id: Some(user_id),
+        details: Some(details),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: DeviceInfo::from_request(req).user_agent,
+    })
+    .await
+    {
+        log::warn!("Failed to audit {} of {}: {}", action.as_str(), user_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::AuthMiddleware;
+    use crate::routes::auth::generate_tokens;
+    use crate::test_db::TestDb;
+    use actix_web::{test as actix_test, App};
+
+    const PASSWORD: &str = "SecureP@ss123";
+
+    async fn insert_user(pool: &PgPool, email: &str, role: UserRole) -> User {
+        sqlx::query_as("INSERT INTO users (email, password_hash, name, role) VALUES ($1, $2, $3, $4) RETURNING *")
+            .bind(email)
+            .bind(hash_password(PASSWORD).unwrap())
+            .bind(email.split('@').next().unwrap())
+            .bind(role)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    /// Access token for a new session of `user`
+    async fn sign_in(pool: &PgPool, user: &User) -> String {
+        let (access_token, _, _) = generate_tokens(pool, user, &DeviceInfo::default()).await.unwrap();
+        format!("Bearer {}", access_token)
+    }
+
+    macro_rules! test_app {
+        ($pool:expr) => {
+            actix_test::init_service(
+                App::new().app_data(web::Data::new($pool.clone())).service(
+                    web::scope("/api")
+                        .configure(crate::routes::auth::config)
+                        .service(web::scope("").wrap(AuthMiddleware).configure(config)),
+                ),
+            )
+            .await
+        };
+    }
+
+    #[test]
+    fn test_search_pattern() {
+        assert_eq!(search_pattern("  ada "), Some("%ada%".to_string()));
+        assert_eq!(search_pattern("100%_a\\b"), Some("%100\\%\\_a\\\\b%".to_string()));
+        assert_eq!(search_pattern("   "), None);
+    }
+
+    #[test]
+    fn test_validate_attributes() {
+        let attributes = |value: serde_json::Value| value.as_object().cloned().unwrap();
+
+        assert!(validate_attributes(&attributes(json!({ "region": "EMEA", "accounts": ["a1", 2], "level": 3 }))).is_ok());
+        assert!(validate_attributes(&attributes(json!({ "bad key": "x" }))).is_err());
+        assert!(validate_attributes(&attributes(json!({ "nested": { "a": 1 } }))).is_err());
+        assert!(validate_attributes(&attributes(json!({ "empty": null }))).is_err());
+    }
+
+    #[test]
+    fn test_ensure_not_self() {
+        let id = Uuid::new_v4();
+        assert!(ensure_not_self(id, id).is_err());
+        assert!(ensure_not_self(id, Uuid::new_v4()).is_ok());
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_disabled_account_cannot_log_in_or_refresh() {
+        let db = TestDb::create().await;
+        let pool = &db.pool;
+        let app = test_app!(pool);
+
+        let admin = insert_user(pool, "admin@example.com", UserRole::Admin).await;
+        let user = insert_user(pool, "jane@example.com", UserRole::User).await;
+        let login = || {
+            actix_test::TestRequest::post()
+                .uri("/api/auth/login")
+                .set_json(json!({ "email": "jane@example.com", "password": PASSWORD }))
+                .to_request()
+        };
+
+        let body: serde_json::Value = actix_test::call_and_read_body_json(&app, login()).await;
+        let access_token = format!("Bearer {}", body["access_token"].as_str().unwrap());
+        let refresh_token = body["refresh_token"].as_str().unwrap().to_string();
+
+        let disable = actix_test::TestRequest::post()
+            .uri(&format!("/api/admin/users/{}/disable", user.id))
+            .insert_header(("Authorization", sign_in(pool, &admin).await))
+            .to_request();
+        let disabled: serde_json::Value = actix_test::call_and_read_body_json(&app, disable).await;
+        assert_eq!(disabled["status"], "Disabled");
+
+        assert_eq!(actix_test::call_service(&app, login()).await.status(), 403);
+
+        let refresh = actix_test::TestRequest::post()
+            .uri("/api/auth/refresh")
+            .set_json(json!({ "refresh_token": refresh_token }))
+            .to_request();
+        assert_eq!(actix_test::call_service(&app, refresh).await.status(), 401);
+
+        // Access tokens issued before the account was disabled stop working too
+        let me = actix_test::TestRequest::get()
+            .uri("/api/auth/me")
+            .insert_header(("Authorization", access_token))
+            .to_request();
+        assert_eq!(actix_test::call_service(&app, me).await.status(), 401);
+
+        db.drop().await;
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_delete_transfers_ownership() {
+        let db = TestDb::create().await;
+        let pool = &db.pool;
+        let app = test_app!(pool);
+
+        let admin = insert_user(pool, "admin@example.com", UserRole::Admin).await;
+        let leaving = insert_user(pool, "leaving@example.com", UserRole::User).await;
+        let heir = insert_user(pool, "heir@example.com", UserRole::User).await;
+        let authorization = sign_in(pool, &admin).await;
+
+        let (file_id,): (Uuid,) = sqlx::query_as(
+            r#"
+            INSERT INTO files (user_id, name, original_name, mime_type, size_bytes, storage_path)
+            VALUES ($1, 'sales.csv', 'sales.csv', 'text/csv', 10, '/tmp/sales.csv')
+            RETURNING id
+            "#
+        )
+        .bind(leaving.id)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        let (team_id,): (Uuid,) =
+            sqlx::query_as("INSERT INTO teams (name, slug, owner_id) VALUES ('Sales', 'sales', $1) RETURNING id")
+                .bind(leaving.id)
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        sqlx::query("INSERT INTO team_members (team_id, user_id, role) VALUES ($1, $2, 'owner'), ($1, $3, 'member')")
+            .bind(team_id)
+            .bind(leaving.id)
+            .bind(heir.id)
+            .execute(pool)
+            .await
+            .unwrap();
+
+        // Owned data must go somewhere
+        let delete = actix_test::TestRequest::delete()
+            .uri(&format!("/api/admin/users/{}", leaving.id))
+            .insert_header(("Authorization", authorization.clone()))
+            .to_request();
+        assert_eq!(actix_test::call_service(&app, delete).await.status(), 400);
+
+        let delete = actix_test::TestRequest::delete()
+            .uri(&format!("/api/admin/users/{}?transfer_to={}", leaving.id, heir.id))
+            .insert_header(("Authorization", authorization))
+            .to_request();
+        let body: serde_json::Value = actix_test::call_and_read_body_json(&app, delete).await;
+        assert_eq!(body["files_transferred"], 1);
+        assert_eq!(body["teams_transferred"], 1);
+
+        let (file_owner,): (Uuid,) = sqlx::query_as("SELECT user_id FROM files WHERE id = $1")
+            .bind(file_id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(file_owner, heir.id);
+
+        let (team_owner, heir_role): (Uuid, String) = sqlx::query_as(
+            r#"
+            SELECT t.owner_id, tm.role::text FROM teams t
+            JOIN team_members tm ON tm.team_id = t.id AND tm.user_id = $2
+            WHERE t.id = $1
+            "#
+        )
+        .bind(team_id)
+        .bind(heir.id)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert_eq!(team_owner, heir.id);
+        assert_eq!(heir_role, "owner");
+
+        let (remaining,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users WHERE id = $1")
+            .bind(leaving.id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(remaining, 0);
+
+        db.drop().await;
+    }
+}
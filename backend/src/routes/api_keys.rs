@@ -0,0 +1,305 @@
+//! API key routes
+//!
+//! Personal keys under `/api/api-keys` and team keys under
+//! `/api/teams/{id}/api-keys`. Keys can only be managed by a signed-in user,
+//! never with another key, and the secret is returned once, on creation.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::auth::get_claims;
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::auth::api_keys::{ApiKey, ApiKeyService, NewApiKey, MAX_KEYS_PER_OWNER};
+use crate::services::auth::sessions::DeviceInfo;
+use crate::services::permissions::{Permission, PermissionService};
+
+/// Configure personal API key routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api-keys")
+            .route("", web::get().to(list_keys))
+            .route("", web::post().to(create_key))
+            .route("/{key_id}", web::delete().to(revoke_key)),
+    );
+}
+
+/// New API key
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    /// Permission strings (e.g. `dataset:upload`); omit for all of the owner's
+    pub permissions: Option<Vec<String>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// List the current user's API keys
+///
+/// GET /api/api-keys
+async fn list_keys(req: HttpRequest, pool: web::Data<PgPool>) -> ApiResult<HttpResponse> {
+    let user_id = key_manager(&req)?;
+
+    let keys = ApiKeyService::list(pool.get_ref(), user_id, None).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "api_keys": keys,
+        "total": keys.len()
+    })))
+}
+
+/// Create a personal API key
+///
+/// POST /api/api-keys
+async fn create_key(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<CreateApiKeyRequest>,
+) -> ApiResult<HttpResponse> {
+    let user_id = key_manager(&req)?;
+
+    let held = PermissionService::get_user_permissions(pool.get_ref(), user_id).await?;
+    create(&req, pool.get_ref(), user_id, None, &held, body.into_inner()).await
+}
+
+/// Revoke a personal API key
+///
+/// DELETE /api/api-keys/{key_id}
+async fn revoke_key(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    let user_id = key_manager(&req)?;
+
+    revoke(&req, pool.get_ref(), user_id, None, path.into_inner()).await
+}
+
+/// List a team's API keys
+///
+/// GET /api/teams/{id}/api-keys
+pub async fn list_team_keys(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    let team_id = path.into_inner();
+    let user_id = team_key_manager(&req, pool.get_ref(), team_id).await?;
+
+    let keys = ApiKeyService::list(pool.get_ref(), user_id, Some(team_id)).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "api_keys": keys,
+        "total": keys.len()
+    })))
+}
+
+/// Create a team API key, acting with the creator's permissions in the team
+///
+/// POST /api/teams/{id}/api-keys
+pub async fn create_team_key(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<CreateApiKeyRequest>,
+) -> ApiResult<HttpResponse> {
+    let team_id = path.into_inner();
+    let user_id = team_key_manager(&req, pool.get_ref(), team_id).await?;
+
+    let held = PermissionService::get_team_permissions(pool.get_ref(), user_id, team_id).await?;
+    create(&req, pool.get_ref(), user_id, Some(team_id), &held, body.into_inner()).await
+}
+
+/// Revoke a team API key
+///
+/// DELETE /api/teams/{id}/api-keys/{key_id}
+pub async fn revoke_team_key(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> ApiResult<HttpResponse> {
+    let (team_id, key_id) = path.into_inner();
+    let user_id = team_key_manager(&req, pool.get_ref(), team_id).await?;
+
+    revoke(&req, pool.get_ref(), user_id, Some(team_id), key_id).await
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+async fn create(
+    req: &HttpRequest,
+    pool: &PgPool,
+    user_id: Uuid,
+    team_id: Option<Uuid>,
+    held: &HashSet<Permission>,
+    body: CreateApiKeyRequest,
+) -> ApiResult<HttpResponse> {
+    let key = validate_new_key(body, team_id, held)?;
+
+    if ApiKeyService::count(pool, user_id, team_id).await? >= MAX_KEYS_PER_OWNER {
+        return Err(ApiError::bad_request(format!(
+            "At most {} API keys may be active; revoke one first",
+            MAX_KEYS_PER_OWNER
+        )));
+    }
+
+    let (record, secret) = ApiKeyService::create(pool, user_id, &key).await?;
+
+    audit(pool, req, user_id, AuditAction::ApiKeyCreate, &record).await;
+
+    Ok(HttpResponse::Created().json(json!({
+        "api_key": record,
+        "key": secret
+    })))
+}
+
+async fn revoke(
+    req: &HttpRequest,
+    pool: &PgPool,
+    user_id: Uuid,
+    team_id: Option<Uuid>,
+    key_id: Uuid,
+) -> ApiResult<HttpResponse> {
+    let keys = ApiKeyService::list(pool, user_id, team_id).await?;
+    let record = keys
+        .into_iter()
+        .find(|key| key.id == key_id)
+        .ok_or_else(|| ApiError::not_found("API key not found"))?;
+
+    if !ApiKeyService::revoke(pool, key_id, user_id, team_id).await? {
+        return Err(ApiError::not_found("API key not found"));
+    }
+
+    audit(pool, req, user_id, AuditAction::ApiKeyRevoke, &record).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Validate a key request against the permissions its owner holds
+fn validate_new_key(
+    body: CreateApiKeyRequest,
+    team_id: Option<Uuid>,
+    held: &HashSet<Permission>,
+) -> ApiResult<NewApiKey> {
+    let name = body.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > 100 {
+        return Err(ApiError::bad_request("API key name must be 1-100 characters"));
+    }
+
+    if body.expires_at.is_some_and(|at| at <= Utc::now()) {
+        return Err(ApiError::bad_request("expires_at must be in the future"));
+    }
+
+    let permissions = match body.permissions {
+        None => None,
+        Some(requested) => {
+            let mut permissions = Vec::with_capacity(requested.len());
+            for s in &requested {
+                let permission = Permission::parse(s.trim())
+                    .ok_or_else(|| ApiError::bad_request(format!("Unknown permission: {}", s)))?;
+                if !held.contains(&permission) {
+                    return Err(ApiError::forbidden(format!(
+                        "You cannot grant {}, which you do not have",
+                        permission.as_str()
+                    )));
+                }
+                if !permissions.contains(&permission) {
+                    permissions.push(permission);
+                }
+            }
+            if permissions.is_empty() {
+                return Err(ApiError::bad_request("Grant at least one permission, or omit permissions for all"));
+            }
+            Some(permissions)
+        }
+    };
+
+    Ok(NewApiKey {
+        name,
+        team_id,
+        permissions,
+        expires_at: body.expires_at,
+    })
+}
+
+/// Signed-in user managing their own keys
+fn key_manager(req: &HttpRequest) -> ApiResult<Uuid> {
+    let claims = get_claims(req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+    claims.require_interactive()?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))
+}
+
+/// Signed-in user allowed to manage the team's keys
+async fn team_key_manager(req: &HttpRequest, pool: &PgPool, team_id: Uuid) -> ApiResult<Uuid> {
+    let user_id = key_manager(req)?;
+
+    if !PermissionService::has_team_permission(pool, user_id, team_id, Permission::TeamManageSettings).await? {
+        return Err(ApiError::forbidden("Only team owners can manage team API keys"));
+    }
+
+    Ok(user_id)
+}
+
+async fn audit(pool: &PgPool, req: &HttpRequest, user_id: Uuid, action: AuditAction, key: &ApiKey) {
+    if let Err(e) = AuditService::log(pool, AuditEntry {
+        user_id: Some(user_id),
+        team_id: key.team_id,
+        action: action.clone(),
+        resource_type: Some(ResourceType::ApiKey),
+        resource_id: Some(key.id),
+        details: Some(json!({
+            "name": key.name,
+            "key_prefix": key.key_prefix,
+            "permissions": key.permissions,
+            "expires_at": key.expires_at
+        })),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: DeviceInfo::from_request(req).user_agent,
+    })
+    .await
+    {
+        log::warn!("Failed to audit {} for {}: {}", action.as_str(), key.id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(permissions: Option<Vec<&str>>) -> CreateApiKeyRequest {
+        CreateApiKeyRequest {
+            name: " nightly-etl ".to_string(),
+            permissions: permissions.map(|p| p.into_iter().map(String::from).collect()),
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_new_key() {
+        let held: HashSet<Permission> = [Permission::DatasetUpload, Permission::QueryExecute].into_iter().collect();
+
+        let key = validate_new_key(request(Some(vec!["dataset:upload", "dataset:upload"])), None, &held).unwrap();
+        assert_eq!(key.name, "nightly-etl");
+        assert_eq!(key.permissions, Some(vec![Permission::DatasetUpload]));
+
+        assert!(validate_new_key(request(None), None, &held).unwrap().permissions.is_none());
+        assert!(validate_new_key(request(Some(vec![])), None, &held).is_err());
+        assert!(validate_new_key(request(Some(vec!["dataset:teleport"])), None, &held).is_err());
+        assert!(validate_new_key(request(Some(vec!["dataset:delete"])), None, &held).is_err());
+
+        let expired = CreateApiKeyRequest {
+            expires_at: Some(Utc::now() - chrono::Duration::hours(1)),
+            ..request(None)
+        };
+        assert!(validate_new_key(expired, None, &held).is_err());
+    }
+}
@@ -1,38 +1,102 @@
 //! Authentication Routes
 //!
 //! Provides registration, login, logout, refresh, and user info endpoints.
-//! Uses Argon2 for password hashing and JWT for stateless authentication.
+//! Uses Argon2 for password hashing and short-lived JWT access tokens. Refresh
+//! tokens are opaque, single-use and tracked server-side per device session
+//! (see `services::auth::sessions`); users can list their sessions and revoke
+//! one or all of them, which also invalidates the session's access tokens.
+//! Single sign-on providers (see `routes::oidc` and `routes::saml`) provision
+//! users through [`find_or_provision_user`] and receive the same token pair.
 
 use actix_web::{web, HttpRequest, HttpResponse};
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
+use serde::Serialize;
 use serde_json::json;
 use sqlx::PgPool;
 use uuid::Uuid;
 
 use crate::errors::{ApiError, ApiResult};
-use crate::middleware::auth::{generate_jwt, generate_refresh_token, get_claims, Claims};
-use crate::models::{AuthResponse, LoginRequest, RefreshRequest, RegisterRequest, User, UserInfo, UserRole};
+use crate::middleware::auth::{generate_jwt, get_claims, Claims};
+use crate::middleware::{AuthMiddleware, RateLimitMiddleware};
+use crate::models::{AuthResponse, LoginRequest, RefreshRequest, RegisterRequest, User, UserInfo, UserRole, UserStatus};
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::auth::keys::Keyring;
+use crate::services::auth::mfa::{MfaService, CHALLENGE_TTL_MINUTES};
+use crate::services::auth::sessions::{
+    DeviceInfo, RefreshOutcome, RevocationReason, Session, SessionService,
+};
+use crate::services::invitations::InvitationService;
+use crate::services::mail::Mailer;
+use crate::services::rate_limit::RateLimiter;
+
+/// Password hash stored for accounts created through single sign-on. It is not
+/// a valid Argon2 hash, so these accounts cannot log in with a password.
+pub(crate) const SSO_PASSWORD_HASH: &str = "!sso";
+
+/// Password hash stored while an account has no usable password, because an
+/// administrator created it without one or forced a reset. The user sets a
+/// password through a reset link.
+pub(crate) const RESET_REQUIRED_PASSWORD_HASH: &str = "!reset";
+
+/// Identity asserted by an external provider
+#[derive(Debug, Clone)]
+pub(crate) struct ExternalIdentity {
+    pub provider: String,
+    /// Stable user identifier at the provider
+    pub subject: String,
+    /// Email address, only when verified by the provider
+    pub email: Option<String>,
+    /// Whether the provider is trusted to claim existing accounts by email
+    pub link_by_email: bool,
+    pub name: Option<String>,
+    /// Role asserted by the provider, applied on every login
+    pub role: Option<UserRole>,
+}
 
 /// Configure auth routes
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/auth")
+            .wrap(RateLimitMiddleware::new("auth"))
             .route("/register", web::post().to(register))
             .route("/login", web::post().to(login))
             .route("/logout", web::post().to(logout))
             .route("/me", web::get().to(me))
-            .route("/refresh", web::post().to(refresh_token)),
+            .route("/refresh", web::post().to(refresh_token))
+            .service(
+                web::resource("/permissions")
+                    .wrap(AuthMiddleware)
+                    .route(web::get().to(super::permissions::my_permissions)),
+            )
+            .service(
+                web::scope("/sessions")
+                    .wrap(AuthMiddleware)
+                    .route("", web::get().to(list_sessions))
+                    .route("/{id}", web::delete().to(revoke_session)),
+            )
+            .configure(super::account::config)
+            .configure(super::invitations::public_config)
+            .configure(super::mfa::config)
+            .configure(super::oidc::config)
+            .configure(super::saml::config),
     );
 }
 
+/// Configure root-level discovery routes
+pub fn well_known_config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/.well-known/jwks.json", web::get().to(jwks));
+}
+
 /// Register endpoint
 ///
 /// POST /api/auth/register
 async fn register(
+    req: HttpRequest,
     pool: web::Data<PgPool>,
+    mailer: web::Data<Mailer>,
     body: web::Json<RegisterRequest>,
 ) -> ApiResult<HttpResponse> {
     // Validate input
@@ -51,6 +115,16 @@ async fn register(
         return Err(ApiError::bad_request("Email already registered"));
     }
 
+    // An invitation link proves the address, as long as it was sent to it
+    if let Some(token) = &body.invite_token {
+        let invitation = InvitationService::find_by_token(pool.get_ref(), token)
+            .await?
+            .ok_or_else(|| ApiError::bad_request("Invalid or expired invitation"))?;
+        if !invitation.email.eq_ignore_ascii_case(body.email.trim()) {
+            return Err(ApiError::bad_request("This invitation was sent to another address"));
+        }
+    }
+
     // Hash password with Argon2
     let password_hash = hash_password(&body.password)?;
 
@@ -70,8 +144,29 @@ async fn register(
     .await
     .map_err(|e| ApiError::internal(format!("Failed to create user: {}", e)))?;
 
+    let user = if body.invite_token.is_some() {
+        // Join every team that invited the address
+        let mut tx = pool.begin().await?;
+        let user: User = sqlx::query_as("UPDATE users SET email_verified_at = NOW() WHERE id = $1 RETURNING *")
+            .bind(user.id)
+            .fetch_one(&mut *tx)
+            .await?;
+        let accepted = InvitationService::accept_all(&mut tx, user.id, &user.email, user.created_at).await?;
+        tx.commit().await?;
+
+        super::invitations::audit_accepted(pool.get_ref(), &req, user.id, &accepted).await;
+        user
+    } else {
+        // Ask the user to confirm their address; the account works meanwhile
+        if let Err(e) = super::account::send_email_verification(pool.get_ref(), &mailer, &user).await {
+            log::warn!("Failed to send verification email to user {}: {}", user.id, e);
+        }
+        user
+    };
+
     // Generate tokens
-    let (access_token, refresh_token, expires_in) = generate_tokens(&user)?;
+    let (access_token, refresh_token, expires_in) =
+        generate_tokens(pool.get_ref(), &user, &DeviceInfo::from_request(&req)).await?;
 
     Ok(HttpResponse::Created().json(AuthResponse {
         access_token,
@@ -85,8 +180,11 @@ async fn register(
 /// Login endpoint
 ///
 /// POST /api/auth/login
+/// Users with MFA get an `mfa_token` to answer at /api/auth/mfa/challenge
 async fn login(
+    req: HttpRequest,
     pool: web::Data<PgPool>,
+    limiter: Option<web::Data<RateLimiter>>,
     body: web::Json<LoginRequest>,
 ) -> ApiResult<HttpResponse> {
     // Validate input
@@ -94,6 +192,13 @@ async fn login(
         return Err(ApiError::bad_request("Email and password are required"));
     }
 
+    // Refuse accounts locked out by repeated failures
+    if let Some(limiter) = &limiter {
+        if let Some(remaining) = limiter.login_locked(&body.email).await {
+            return Err(ApiError::RateLimitExceeded(remaining.as_secs_f64().ceil() as u64));
+        }
+    }
+
     // Find user by email
     let user: Option<User> = sqlx::query_as(
         "SELECT * FROM users WHERE email = $1"
@@ -103,18 +208,44 @@ async fn login(
     .await
     .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
+    // Unknown accounts, accounts without a password (SSO-only, or awaiting a
+    // reset) and wrong passwords all count towards the lockout
+    let authenticated = match &user {
+        Some(u) if !u.password_hash.starts_with('!') => verify_password(&body.password, &u.password_hash)?,
+        _ => false,
+    };
     let user = match user {
-        Some(u) => u,
-        None => return Err(ApiError::unauthorized("Invalid email or password")),
+        Some(u) if authenticated => u,
+        _ => {
+            if let Some(limiter) = &limiter {
+                limiter.login_failed(&body.email).await;
+            }
+            return Err(ApiError::unauthorized("Invalid email or password"));
+        }
     };
 
-    // Verify password
-    if !verify_password(&body.password, &user.password_hash)? {
-        return Err(ApiError::unauthorized("Invalid email or password"));
+    // Disabled accounts are refused only after the password checks out, so
+    // the response does not reveal the account's status to anyone else
+    ensure_active(&user)?;
+
+    // Enrolled users must also answer a second-factor challenge
+    if MfaService::is_enabled(pool.get_ref(), user.id).await? {
+        let mfa_token = MfaService::create_challenge(pool.get_ref(), user.id).await?;
+        return Ok(HttpResponse::Ok().json(json!({
+            "mfa_required": true,
+            "mfa_token": mfa_token,
+            "expires_in": CHALLENGE_TTL_MINUTES * 60
+        })));
+    }
+
+    // Failures are cleared only once every factor has been verified
+    if let Some(limiter) = &limiter {
+        limiter.login_succeeded(&body.email).await;
     }
 
     // Generate tokens
-    let (access_token, refresh_token, expires_in) = generate_tokens(&user)?;
+    let (access_token, refresh_token, expires_in) =
+        generate_tokens(pool.get_ref(), &user, &DeviceInfo::from_request(&req)).await?;
 
     Ok(HttpResponse::Ok().json(AuthResponse {
         access_token,
@@ -128,40 +259,16 @@ async fn login(
 /// Logout endpoint
 ///
 /// POST /api/auth/logout
-/// Adds refresh token to blacklist
+/// Ends the session the refresh token belongs to
 async fn logout(
-    req: HttpRequest,
     pool: web::Data<PgPool>,
     body: Option<web::Json<RefreshRequest>>,
 ) -> ApiResult<HttpResponse> {
-    let claims = get_claims(&req);
-
-    // If refresh token provided, blacklist it
     if let Some(refresh_body) = body {
-        let token_hash = sha256_hash(&refresh_body.refresh_token);
-        
-        // Calculate expiration (7 days from now to match refresh token expiry)
-        let expires_at = chrono::Utc::now() + chrono::Duration::days(7);
-        
-        let user_id = claims.as_ref()
-            .map(|c| Uuid::parse_str(&c.sub).ok())
-            .flatten()
-            .unwrap_or_else(Uuid::nil);
-
-        // Add to blacklist
-        sqlx::query(
-            r#"
-            INSERT INTO revoked_tokens (token_hash, user_id, expires_at)
-            VALUES ($1, $2, $3)
-            ON CONFLICT (token_hash) DO NOTHING
-            "#
-        )
-        .bind(&token_hash)
-        .bind(&user_id)
-        .bind(&expires_at)
-        .execute(pool.get_ref())
-        .await
-        .ok(); // Ignore errors - logout should succeed anyway
+        // Ignore errors - logout should succeed anyway
+        SessionService::revoke_by_token(pool.get_ref(), &refresh_body.refresh_token, RevocationReason::Logout)
+            .await
+            .ok();
     }
 
     Ok(HttpResponse::Ok().json(json!({
@@ -201,37 +308,41 @@ async fn me(
 /// Refresh token endpoint
 ///
 /// POST /api/auth/refresh
+/// Rotates the refresh token; replaying a rotated token revokes its session
 async fn refresh_token(
+    req: HttpRequest,
     pool: web::Data<PgPool>,
     body: web::Json<RefreshRequest>,
 ) -> ApiResult<HttpResponse> {
-    // Validate refresh token format (basic check)
-    if body.refresh_token.len() < 32 {
-        return Err(ApiError::unauthorized("Invalid refresh token"));
-    }
-
-    let token_hash = sha256_hash(&body.refresh_token);
-
-    // Check if token is blacklisted
-    let is_revoked: Option<(String,)> = sqlx::query_as(
-        "SELECT token_hash FROM revoked_tokens WHERE token_hash = $1"
-    )
-    .bind(&token_hash)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    if is_revoked.is_some() {
-        return Err(ApiError::unauthorized("Token has been revoked"));
-    }
-
-    // Decode the refresh token to get user info
-    let jwt_secret = get_jwt_secret();
-    let claims = crate::middleware::auth::validate_refresh_token(&body.refresh_token, &jwt_secret)
-        .map_err(|_| ApiError::unauthorized("Invalid or expired refresh token"))?;
-
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
+    let (session_id, user_id, new_refresh_token) =
+        match SessionService::rotate(pool.get_ref(), &body.refresh_token).await? {
+            RefreshOutcome::Rotated { session_id, user_id, refresh_token } => {
+                (session_id, user_id, refresh_token)
+            }
+            RefreshOutcome::ReuseDetected { session_id, user_id } => {
+                log::warn!("Refresh token reuse detected for user {}; session {} revoked", user_id, session_id);
+
+                if let Err(e) = AuditService::log(pool.get_ref(), AuditEntry {
+                    user_id: Some(user_id),
+                    team_id: None,
+                    action: AuditAction::TokenRefresh,
+                    resource_type: Some(ResourceType::User),
+                    resource_id: Some(user_id),
+                    details: Some(json!({ "reuse_detected": true, "session_id": session_id })),
+                    ip_address: req.peer_addr().map(|addr| addr.ip()),
+                    user_agent: DeviceInfo::from_request(&req).user_agent,
+                })
+                .await
+                {
+                    log::warn!("Failed to audit refresh token reuse for {}: {}", user_id, e);
+                }
+
+                return Err(ApiError::unauthorized("Refresh token reuse detected; session revoked"));
+            }
+            RefreshOutcome::Invalid => {
+                return Err(ApiError::unauthorized("Invalid or expired refresh token"));
+            }
+        };
 
     // Fetch user to ensure they still exist and get current data
     let user: Option<User> = sqlx::query_as(
@@ -246,21 +357,9 @@ async fn refresh_token(
         Some(u) => u,
         None => return Err(ApiError::unauthorized("User not found")),
     };
+    ensure_active(&user)?;
 
-    // Generate new tokens
-    let (access_token, new_refresh_token, expires_in) = generate_tokens(&user)?;
-
-    // Optionally blacklist the old refresh token (rotation)
-    let expires_at = chrono::Utc::now() + chrono::Duration::days(7);
-    sqlx::query(
-        "INSERT INTO revoked_tokens (token_hash, user_id, expires_at) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING"
-    )
-    .bind(&token_hash)
-    .bind(&user.id)
-    .bind(&expires_at)
-    .execute(pool.get_ref())
-    .await
-    .ok();
+    let (access_token, expires_in) = generate_access_token(&user, session_id)?;
 
     Ok(HttpResponse::Ok().json(AuthResponse {
         access_token,
@@ -271,6 +370,102 @@ async fn refresh_token(
     }))
 }
 
+/// Public keys for verifying access tokens
+///
+/// GET /.well-known/jwks.json
+async fn jwks() -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=300"))
+        .json(Keyring::global().jwks())
+}
+
+/// Active session, flagged when it is the caller's own
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    #[serde(flatten)]
+    pub session: Session,
+    pub current: bool,
+}
+
+/// List the current user's active sessions (devices)
+///
+/// GET /api/auth/sessions
+async fn list_sessions(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> ApiResult<HttpResponse> {
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
+    claims.require_interactive()?;
+
+    let current = claims.session_id();
+    let sessions: Vec<SessionInfo> = SessionService::list_active(pool.get_ref(), user_id)
+        .await?
+        .into_iter()
+        .map(|session| SessionInfo {
+            current: Some(session.id) == current,
+            session,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "sessions": sessions,
+        "total": sessions.len()
+    })))
+}
+
+/// Revoke one session, or every session with `all` ("sign out everywhere")
+///
+/// DELETE /api/auth/sessions/{id}
+async fn revoke_session(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
+    claims.require_interactive()?;
+
+    let target = path.into_inner();
+    let (revoked, details) = if target == "all" {
+        let revoked = SessionService::revoke_all(pool.get_ref(), user_id, RevocationReason::Revoked).await?;
+        (revoked, json!({ "all_sessions": true, "revoked": revoked }))
+    } else {
+        let session_id = Uuid::parse_str(&target)
+            .map_err(|_| ApiError::bad_request("Session ID must be a UUID or 'all'"))?;
+        if !SessionService::revoke(pool.get_ref(), user_id, session_id, RevocationReason::Revoked).await? {
+            return Err(ApiError::not_found("Session not found"));
+        }
+        (1, json!({ "session_id": session_id }))
+    };
+
+    if let Err(e) = AuditService::log(pool.get_ref(), AuditEntry {
+        user_id: Some(user_id),
+        team_id: None,
+        action: AuditAction::UserLogout,
+        resource_type: Some(ResourceType::User),
+        resource_id: Some(user_id),
+        details: Some(details),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: DeviceInfo::from_request(&req).user_agent,
+    })
+    .await
+    {
+        log::warn!("Failed to audit session revocation for {}: {}", user_id, e);
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "revoked": revoked
+    })))
+}
+
 // ============================================================================
 // HELPER FUNCTIONS
 // ============================================================================
@@ -288,20 +483,41 @@ fn validate_registration(req: &RegisterRequest) -> ApiResult<()> {
         return Err(ApiError::bad_request("Name is required"));
     }
 
-    // Validate email format
-    if !req.email.contains('@') || !req.email.contains('.') {
+    validate_email(&req.email)?;
+    validate_password(&req.password)?;
+
+    // Validate name length
+    if req.name.len() < 2 || req.name.len() > 100 {
+        return Err(ApiError::bad_request("Name must be between 2 and 100 characters"));
+    }
+
+    Ok(())
+}
+
+/// Validate email format: a deliverable `local@domain` address whose domain
+/// has at least one dot
+pub(crate) fn validate_email(email: &str) -> ApiResult<()> {
+    let address: lettre::Address = email
+        .parse()
+        .map_err(|_| ApiError::bad_request("Invalid email format"))?;
+
+    if email.len() > 255 || !address.domain().contains('.') || address.domain().ends_with('.') {
         return Err(ApiError::bad_request("Invalid email format"));
     }
 
-    // Validate password strength
-    if req.password.len() < 8 {
+    Ok(())
+}
+
+/// Validate password strength
+pub(crate) fn validate_password(password: &str) -> ApiResult<()> {
+    if password.len() < 8 {
         return Err(ApiError::bad_request("Password must be at least 8 characters"));
     }
 
     // Check for mixed character types
-    let has_lowercase = req.password.chars().any(|c| c.is_lowercase());
-    let has_uppercase = req.password.chars().any(|c| c.is_uppercase());
-    let has_digit = req.password.chars().any(|c| c.is_ascii_digit());
+    let has_lowercase = password.chars().any(|c| c.is_lowercase());
+    let has_uppercase = password.chars().any(|c| c.is_uppercase());
+    let has_digit = password.chars().any(|c| c.is_ascii_digit());
     
     if !has_lowercase || !has_uppercase || !has_digit {
         return Err(ApiError::bad_request(
@@ -309,16 +525,11 @@ fn validate_registration(req: &RegisterRequest) -> ApiResult<()> {
         ));
     }
 
-    // Validate name length
-    if req.name.len() < 2 || req.name.len() > 100 {
-        return Err(ApiError::bad_request("Name must be between 2 and 100 characters"));
-    }
-
     Ok(())
 }
 
 /// Hash password using Argon2
-fn hash_password(password: &str) -> ApiResult<String> {
+pub(crate) fn hash_password(password: &str) -> ApiResult<String> {
     let salt = SaltString::generate(&mut OsRng);
     let argon2 = Argon2::default();
     
@@ -339,10 +550,168 @@ fn verify_password(password: &str, hash: &str) -> ApiResult<bool> {
         .is_ok())
 }
 
-/// Generate access and refresh tokens
-fn generate_tokens(user: &User) -> ApiResult<(String, String, i64)> {
-    let jwt_secret = get_jwt_secret();
-    
+/// Find the user linked to an external identity, linking by verified email
+/// (when the provider is trusted to) or creating the user (when
+/// `allow_signup`) on first login.
+///
+/// Returns the user and whether it was created.
+pub(crate) async fn find_or_provision_user(
+    pool: &PgPool,
+    identity: &ExternalIdentity,
+    allow_signup: bool,
+) -> ApiResult<(User, bool)> {
+    let mut tx = pool.begin().await?;
+    // Sessions of a reclaimed account end once the link is committed
+    let mut reclaimed = false;
+
+    let linked: Option<User> = sqlx::query_as(
+        r#"
+        SELECT u.* FROM users u
+        JOIN user_identities i ON i.user_id = u.id
+        WHERE i.provider = $1 AND i.subject = $2
+        "#
+    )
+    .bind(&identity.provider)
+    .bind(&identity.subject)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let (user, created) = match linked {
+        Some(user) => {
+            sqlx::query(
+                "UPDATE user_identities SET last_login_at = NOW() WHERE provider = $1 AND subject = $2"
+            )
+            .bind(&identity.provider)
+            .bind(&identity.subject)
+            .execute(&mut *tx)
+            .await?;
+            (user, false)
+        }
+        None => {
+            let email = identity.email.clone().ok_or_else(|| {
+                ApiError::unauthorized("The identity provider did not supply a verified email address")
+            })?;
+
+            let existing: Option<User> = sqlx::query_as("SELECT * FROM users WHERE email = $1")
+                .bind(&email)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+            let (user, created) = match existing {
+                Some(user) if identity.link_by_email && user.email_verified_at.is_none() => {
+                    // Whoever registered this address never proved they own
+                    // it, so their password must not survive the link
+                    let user: User = sqlx::query_as(
+                        r#"
+                        UPDATE users SET email_verified_at = NOW(), password_hash = $2
+                        WHERE id = $1
+                        RETURNING *
+                        "#
+                    )
+                    .bind(user.id)
+                    .bind(SSO_PASSWORD_HASH)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                    reclaimed = true;
+                    (user, false)
+                }
+                Some(user) if identity.link_by_email => (user, false),
+                Some(_) => {
+                    return Err(ApiError::forbidden(
+                        "An account with this email already exists and cannot be linked to this identity provider",
+                    ))
+                }
+                None if allow_signup => {
+                    let name = identity
+                        .name
+                        .clone()
+                        .filter(|name| !name.trim().is_empty())
+                        .unwrap_or_else(|| email.split('@').next().unwrap_or(&email).to_string());
+
+                    let user: User = sqlx::query_as(
+                        r#"
+                        INSERT INTO users (email, password_hash, name, role, email_verified_at)
+                        VALUES ($1, $2, $3, $4, NOW())
+                        RETURNING *
+                        "#
+                    )
+                    .bind(&email)
+                    .bind(SSO_PASSWORD_HASH)
+                    .bind(&name)
+                    .bind(identity.role.clone().unwrap_or_default())
+                    .fetch_one(&mut *tx)
+                    .await?;
+
+                    // The provider vouches for the address, so its invitations stand
+                    let accepted = InvitationService::accept_all(&mut tx, user.id, &user.email, user.created_at).await?;
+                    if !accepted.is_empty() {
+                        log::info!("New SSO user {} joined {} team(s) they were invited to", user.id, accepted.len());
+                    }
+                    (user, true)
+                }
+                None => return Err(ApiError::forbidden("No account exists for this email address")),
+            };
+
+            sqlx::query(
+                r#"
+                INSERT INTO user_identities (user_id, provider, subject, email, last_login_at)
+                VALUES ($1, $2, $3, $4, NOW())
+                "#
+            )
+            .bind(user.id)
+            .bind(&identity.provider)
+            .bind(&identity.subject)
+            .bind(&email)
+            .execute(&mut *tx)
+            .await?;
+
+            (user, created)
+        }
+    };
+
+    // Keep the role in sync with the provider when it asserts one
+    let user = match &identity.role {
+        Some(role) if *role != user.role => {
+            sqlx::query_as("UPDATE users SET role = $2 WHERE id = $1 RETURNING *")
+                .bind(user.id)
+                .bind(role.clone())
+                .fetch_one(&mut *tx)
+                .await?
+        }
+        _ => user,
+    };
+
+    tx.commit().await?;
+    if reclaimed {
+        SessionService::revoke_all(pool, user.id, RevocationReason::Revoked).await?;
+    }
+    Ok((user, created))
+}
+
+/// Generate an access token and start a session with its first refresh token
+pub(crate) async fn generate_tokens(
+    pool: &PgPool,
+    user: &User,
+    device: &DeviceInfo,
+) -> ApiResult<(String, String, i64)> {
+    ensure_active(user)?;
+
+    let (session_id, refresh_token) = SessionService::start(pool, user.id, device).await?;
+    let (access_token, expires_in) = generate_access_token(user, session_id)?;
+
+    Ok((access_token, refresh_token, expires_in))
+}
+
+/// Refuse accounts disabled by an administrator
+pub(crate) fn ensure_active(user: &User) -> ApiResult<()> {
+    if user.status == UserStatus::Disabled {
+        return Err(ApiError::forbidden("This account has been disabled"));
+    }
+    Ok(())
+}
+
+/// Generate a short-lived access token bound to a session
+fn generate_access_token(user: &User, session_id: Uuid) -> ApiResult<(String, i64)> {
     // Access token: 1 hour
     let access_expires_hours = 1;
     let access_claims = Claims::new(
@@ -350,38 +719,12 @@ fn generate_tokens(user: &User) -> ApiResult<(String, String, i64)> {
         &user.email,
         &user.name,
         access_expires_hours,
-    );
-    let access_token = generate_jwt(&access_claims, &jwt_secret)
+    )
+    .with_session(session_id);
+    let access_token = generate_jwt(&access_claims)
         .map_err(|e| ApiError::internal(format!("Failed to generate access token: {}", e)))?;
 
-    // Refresh token: 7 days
-    let refresh_expires_hours = 7 * 24;
-    let refresh_claims = Claims::new(
-        &user.id.to_string(),
-        &user.email,
-        &user.name,
-        refresh_expires_hours,
-    );
-    let refresh_token = generate_refresh_token(&refresh_claims, &jwt_secret)
-        .map_err(|e| ApiError::internal(format!("Failed to generate refresh token: {}", e)))?;
-
-    Ok((access_token, refresh_token, access_expires_hours * 3600))
-}
-
-/// Get JWT secret from environment
-fn get_jwt_secret() -> String {
-    std::env::var("JWT_SECRET")
-        .unwrap_or_else(|_| "development-secret-change-in-production".to_string())
-}
-
-/// SHA256 hash for token blacklisting
-fn sha256_hash(input: &str) -> String {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-    
-    let mut hasher = DefaultHasher::new();
-    input.hash(&mut hasher);
-    format!("{:016x}", hasher.finish())
+    Ok((access_token, access_expires_hours * 3600))
 }
 
 // ============================================================================
@@ -408,6 +751,7 @@ mod tests {
             email: "".to_string(),
             password: "SecureP@ss123".to_string(),
             name: "Test User".to_string(),
+            invite_token: None,
         };
         assert!(validate_registration(&req).is_err());
     }
@@ -418,6 +762,7 @@ mod tests {
             email: "test@example.com".to_string(),
             password: "weak".to_string(),
             name: "Test User".to_string(),
+            invite_token: None,
         };
         assert!(validate_registration(&req).is_err());
     }
@@ -428,27 +773,30 @@ mod tests {
             email: "test@example.com".to_string(),
             password: "nouppercase123".to_string(),
             name: "Test User".to_string(),
+            invite_token: None,
         };
         assert!(validate_registration(&req).is_err());
     }
 
+    #[test]
+    fn test_email_validation() {
+        assert!(validate_email("jane.doe+bi@example.co.uk").is_ok());
+        assert!(validate_email("a.b@c").is_err());
+        assert!(validate_email("@example.com").is_err());
+        assert!(validate_email("jane@@example.com").is_err());
+        assert!(validate_email("jane doe@example.com").is_err());
+        assert!(validate_email("jane@example.").is_err());
+        assert!(validate_email("jane.example.com").is_err());
+    }
+
     #[test]
     fn test_validation_valid_request() {
         let req = RegisterRequest {
             email: "test@example.com".to_string(),
             password: "SecureP@ss123".to_string(),
             name: "Test User".to_string(),
+            invite_token: None,
         };
         assert!(validate_registration(&req).is_ok());
     }
-
-    #[test]
-    fn test_sha256_hash() {
-        let hash1 = sha256_hash("test-token");
-        let hash2 = sha256_hash("test-token");
-        let hash3 = sha256_hash("different-token");
-        
-        assert_eq!(hash1, hash2);
-        assert_ne!(hash1, hash3);
-    }
 }
@@ -0,0 +1,102 @@
+//! Column classification routes
+//!
+//! PII and sensitivity tags on a file's columns under
+//! `/api/files/{id}/columns`. Anyone who can read the file may see how its
+//! columns are classified; changing a classification is limited to those
+//! who manage the file's access policies.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::files::{accessible_file, data_view, managed_file};
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::auth::get_claims;
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::auth::sessions::DeviceInfo;
+use crate::services::masking::{Classification, MaskRule, MaskingService};
+use crate::services::permissions::Permission;
+
+/// New classification of a column; a `null` classification clears it
+#[derive(Debug, Deserialize)]
+pub struct ClassifyColumnRequest {
+    pub classification: Option<Classification>,
+    /// Defaults to the classification's usual rule
+    pub mask: Option<MaskRule>,
+}
+
+/// List a file's columns with their classification
+///
+/// GET /api/files/{id}/columns
+pub async fn list_columns(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID"))?;
+    claims.require(Permission::DatasetRead)?;
+
+    let record = accessible_file(pool.get_ref(), &claims, user_id, path.into_inner(), Permission::DatasetRead).await?;
+    let view = data_view(pool.get_ref(), &claims, &record, user_id).await?;
+    let columns = MaskingService::list(pool.get_ref(), record.id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "columns": columns,
+        // Whether classified columns are shown to the caller unmasked
+        "unmasked": view.masks.is_empty() && !view.unclassified
+    })))
+}
+
+/// Classify a column as PII or sensitive, or clear its classification
+///
+/// PUT /api/files/{id}/columns/{column}/classification
+pub async fn classify_column(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, String)>,
+    body: web::Json<ClassifyColumnRequest>,
+) -> ApiResult<HttpResponse> {
+    let (file_id, column) = path.into_inner();
+    let (user_id, record) = managed_file(&req, pool.get_ref(), file_id).await?;
+
+    if body.classification.is_none() && body.mask.is_some() {
+        return Err(ApiError::bad_request("A mask needs a classification"));
+    }
+
+    let previous = MaskingService::list(pool.get_ref(), record.id)
+        .await?
+        .into_iter()
+        .find(|c| c.name == column)
+        .ok_or_else(|| ApiError::not_found("Column not found; columns are known once the file is profiled"))?;
+
+    let updated = MaskingService::classify(pool.get_ref(), record.id, &column, body.classification, body.mask)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Column not found"))?;
+
+    if let Err(e) = AuditService::log(pool.get_ref(), AuditEntry {
+        user_id: Some(user_id),
+        team_id: record.team_id,
+        action: AuditAction::FileColumnClassify,
+        resource_type: Some(ResourceType::File),
+        resource_id: Some(record.id),
+        details: Some(json!({
+            "column": column,
+            "from": { "classification": previous.classification, "mask": previous.mask },
+            "to": { "classification": updated.classification, "mask": updated.mask }
+        })),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: DeviceInfo::from_request(&req).user_agent,
+    })
+    .await
+    {
+        log::warn!("Failed to audit classification of {} on file {}: {}", column, record.id, e);
+    }
+
+    Ok(HttpResponse::Ok().json(updated))
+}
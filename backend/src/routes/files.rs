@@ -1,6 +1,7 @@
 //! File Management Routes
 //!
-//! Provides file upload, download, list, and delete endpoints.
+//! Provides file upload, download, preview, profile, export, chart, list, and
+//! delete endpoints.
 //! Files are stored on local filesystem with metadata in PostgreSQL.
 
 use actix_web::{web, HttpRequest, HttpResponse};
@@ -13,15 +14,32 @@ use tokio::fs;
 use tokio::io::AsyncWriteExt;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use arrow::error::ArrowError;
 
+use crate::connectors::json::{flatten_records, ArrayMode, JsonOptions};
+use crate::connectors::{read_file, Dataset, FileFormat, ReadOptions};
 use crate::errors::{ApiError, ApiResult};
-use crate::middleware::auth::get_claims;
-
-/// Maximum file size (100MB)
-const MAX_FILE_SIZE: usize = 100 * 1024 * 1024;
-
-/// Allowed file extensions
-const ALLOWED_EXTENSIONS: &[&str] = &["csv", "json", "parquet", "arrow"];
+use crate::middleware::auth::{get_claims, Claims};
+use crate::middleware::guard::check_team_permission;
+use crate::services::acl::{AclService, ResourceKind};
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::export::{ChannelWriter, ExportFormat, ExportService};
+use crate::services::masking::{ColumnMask, MaskRule, MaskingService};
+use crate::services::permissions::{Permission, PermissionService};
+use crate::services::profiler::{ProfileStatus, ProfilerService};
+use crate::services::query_engine::{ChartSeries, QueryEngine, QuerySpec, Reduction};
+use crate::services::row_security::{RowAccess, RowSecurityService};
+use crate::services::team_settings::{TeamSettingsService, MAX_FILE_SIZE, SUPPORTED_FILE_TYPES};
+use crate::services::usage::{UsageLimits, UsageOwner, UsageService};
+
+/// Default number of rows returned by a preview
+const DEFAULT_PREVIEW_ROWS: usize = 50;
+
+/// Maximum number of rows returned by a preview
+const MAX_PREVIEW_ROWS: usize = 10_000;
+
+/// Content type of Arrow IPC stream responses
+const ARROW_STREAM_CONTENT_TYPE: &str = "application/vnd.apache.arrow.stream";
 
 /// Configure file routes
 pub fn config(cfg: &mut web::ServiceConfig) {
@@ -29,9 +47,24 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         web::scope("/files")
             .route("", web::post().to(upload_file))
             .route("", web::get().to(list_files))
+            .route("/shared", web::get().to(list_shared_files))
             .route("/{id}", web::get().to(get_file))
             .route("/{id}", web::delete().to(delete_file))
-            .route("/{id}/metadata", web::get().to(get_file_metadata)),
+            .route("/{id}/metadata", web::get().to(get_file_metadata))
+            .route("/{id}/preview", web::get().to(preview_file))
+            .route("/{id}/profile", web::get().to(get_file_profile))
+            .route("/{id}/export", web::post().to(export_file))
+            .route("/{id}/chart", web::post().to(chart_file))
+            .route("/{id}/access", web::get().to(super::sharing::list_file_access))
+            .route("/{id}/access", web::post().to(super::sharing::share_file))
+            .route("/{id}/access/{grant_id}", web::delete().to(super::sharing::unshare_file))
+            .route("/{id}/row-policies", web::get().to(super::row_policies::list_policies))
+            .route("/{id}/row-policies", web::post().to(super::row_policies::create_policy))
+            .route("/{id}/row-policies/test", web::post().to(super::row_policies::test_policies))
+            .route("/{id}/row-policies/{policy_id}", web::put().to(super::row_policies::update_policy))
+            .route("/{id}/row-policies/{policy_id}", web::delete().to(super::row_policies::delete_policy))
+            .route("/{id}/columns", web::get().to(super::classification::list_columns))
+            .route("/{id}/columns/{column}/classification", web::put().to(super::classification::classify_column)),
     );
 }
 
@@ -44,6 +77,7 @@ pub fn config(cfg: &mut web::ServiceConfig) {
 pub struct FileRecord {
     pub id: Uuid,
     pub user_id: Uuid,
+    pub team_id: Option<Uuid>,
     pub name: String,
     pub original_name: String,
     pub mime_type: String,
@@ -51,10 +85,28 @@ pub struct FileRecord {
     pub row_count: Option<i32>,
     pub column_count: Option<i32>,
     pub storage_path: String,
+    pub profile_status: String,
+    pub json_options: Option<sqlx::types::Json<JsonOptions>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+impl FileRecord {
+    /// Reader options for this file (all rows and columns)
+    pub fn read_options(&self) -> ReadOptions {
+        ReadOptions {
+            json: self.json_options.as_ref().map(|o| o.0.clone()).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether profiling has classified the file's columns; until it has,
+    /// there are no masks to apply to them
+    pub fn is_classified(&self) -> bool {
+        ProfileStatus::parse(&self.profile_status) == Some(ProfileStatus::Ready)
+    }
+}
+
 /// File metadata response (for API)
 #[derive(Debug, Serialize, Clone)]
 pub struct FileMetadata {
@@ -65,6 +117,7 @@ pub struct FileMetadata {
     pub content_type: String,
     pub row_count: Option<i32>,
     pub column_count: Option<i32>,
+    pub profile_status: String,
     pub created_at: DateTime<Utc>,
 }
 
@@ -78,11 +131,26 @@ impl From<FileRecord> for FileMetadata {
             content_type: record.mime_type,
             row_count: record.row_count,
             column_count: record.column_count,
+            profile_status: record.profile_status,
             created_at: record.created_at,
         }
     }
 }
 
+/// Query parameters for uploading a file
+#[derive(Debug, Deserialize)]
+pub struct UploadQuery {
+    /// Team to upload the file to, under its settings; omit for a personal
+    /// file, or for the key's team with a team API key
+    pub team_id: Option<Uuid>,
+    /// Dotted path to the records in a JSON document, e.g. `data.items`
+    pub records_path: Option<String>,
+    /// How JSON arrays are turned into columns
+    pub arrays: Option<ArrayMode>,
+    /// Classify columns that look like PII (default true)
+    pub detect_pii: Option<bool>,
+}
+
 /// Query parameters for listing files
 #[derive(Debug, Deserialize)]
 pub struct ListFilesQuery {
@@ -91,6 +159,75 @@ pub struct ListFilesQuery {
     pub search: Option<String>,
 }
 
+/// Query parameters for previewing a file
+#[derive(Debug, Deserialize)]
+pub struct PreviewQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    /// Comma-separated column names
+    pub columns: Option<String>,
+    /// `json` (default) or `arrow`
+    pub format: Option<PreviewFormat>,
+}
+
+/// Preview response encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewFormat {
+    Json,
+    Arrow,
+}
+
+/// Column description in a JSON preview
+#[derive(Debug, Serialize)]
+pub struct PreviewColumn {
+    pub name: String,
+    pub data_type: String,
+    pub nullable: bool,
+}
+
+/// JSON preview response
+#[derive(Debug, Serialize)]
+pub struct PreviewResponse {
+    pub columns: Vec<PreviewColumn>,
+    pub rows: Vec<serde_json::Map<String, serde_json::Value>>,
+    pub offset: usize,
+    pub limit: usize,
+    pub row_count: usize,
+    /// Total data rows in the file, when known
+    pub total_rows: Option<i32>,
+}
+
+/// Request body for exporting a file
+#[derive(Debug, Deserialize)]
+pub struct ExportRequest {
+    pub format: ExportFormat,
+    /// Download filename (extension is set from the format)
+    pub filename: Option<String>,
+    /// Column selection, filters, sorting and row limit
+    #[serde(flatten)]
+    pub query: QuerySpec,
+}
+
+/// Request body for a reduced chart series
+#[derive(Debug, Deserialize)]
+pub struct ChartRequest {
+    /// Reduction producing the chart series
+    pub reduce: Reduction,
+    /// Filters and sorting applied before the reduction
+    #[serde(flatten)]
+    pub query: QuerySpec,
+}
+
+/// Reduced chart series response
+#[derive(Debug, Serialize)]
+pub struct ChartResponse {
+    pub series: ChartSeries,
+    /// Rows matched by the query before reduction
+    pub row_count: usize,
+    pub execution_time_ms: u128,
+}
+
 // ============================================================================
 // HANDLERS
 // ============================================================================
@@ -101,6 +238,8 @@ pub struct ListFilesQuery {
 async fn upload_file(
     req: HttpRequest,
     pool: web::Data<PgPool>,
+    limits: web::Data<UsageLimits>,
+    query: web::Query<UploadQuery>,
     mut payload: actix_web::web::Payload,
 ) -> ApiResult<HttpResponse> {
     let claims = get_claims(&req)
@@ -108,6 +247,26 @@ async fn upload_file(
 
     let user_id = Uuid::parse_str(&claims.sub)
         .map_err(|_| ApiError::unauthorized("Invalid user ID"))?;
+    claims.require(Permission::DatasetUpload)?;
+
+    // Uploads to a team follow its settings, within the system-wide limits.
+    // A team API key cannot reach personal files, so it uploads to its team.
+    let team = match query.team_id.or(claims.key_team()) {
+        Some(team_id) => {
+            claims.require_team(team_id)?;
+            if !check_team_permission(&req, pool.get_ref(), user_id, team_id, Permission::DatasetUpload).await? {
+                return Err(ApiError::forbidden("You cannot upload files to this team"));
+            }
+            let settings = TeamSettingsService::get(pool.get_ref(), team_id)
+                .await?
+                .ok_or_else(|| ApiError::not_found("Team not found"))?;
+            Some((team_id, settings))
+        }
+        None => None,
+    };
+    let max_file_size = team
+        .as_ref()
+        .map_or(MAX_FILE_SIZE, |(_, settings)| settings.max_file_size_bytes.min(MAX_FILE_SIZE));
 
     // Get upload directory
     let upload_dir = get_upload_dir()?;
@@ -119,8 +278,8 @@ async fn upload_file(
         let chunk = chunk.map_err(|e| ApiError::bad_request(format!("Failed to read payload: {}", e)))?;
         
         // Check size limit
-        if body.len() + chunk.len() > MAX_FILE_SIZE {
-            return Err(ApiError::FileTooLarge(MAX_FILE_SIZE as u64));
+        if (body.len() + chunk.len()) as u64 > max_file_size {
+            return Err(ApiError::FileTooLarge(max_file_size));
         }
         
         body.extend_from_slice(&chunk);
@@ -147,10 +306,18 @@ async fn upload_file(
         .map(|s| s.to_lowercase())
         .unwrap_or_default();
 
-    if !ALLOWED_EXTENSIONS.contains(&extension.as_str()) {
+    let allowed_types: Vec<&str> = match &team {
+        Some((_, settings)) => SUPPORTED_FILE_TYPES
+            .iter()
+            .copied()
+            .filter(|t| settings.allows_file_type(t))
+            .collect(),
+        None => SUPPORTED_FILE_TYPES.to_vec(),
+    };
+    if !allowed_types.contains(&extension.as_str()) {
         return Err(ApiError::UnsupportedMediaType(format!(
             "File type '{}' not allowed. Allowed types: {:?}",
-            extension, ALLOWED_EXTENSIONS
+            extension, allowed_types
         )));
     }
 
@@ -168,36 +335,75 @@ async fn upload_file(
     // Get content type
     let mime_type = get_content_type(&extension);
 
-    // Analyze file to get row/column counts (basic implementation)
-    let (row_count, column_count) = analyze_file(&body, &extension).await;
+    let format = FileFormat::from_extension(&extension);
+    let query = query.into_inner();
+    let json_options = (format == Some(FileFormat::Json)).then(|| JsonOptions {
+        records_path: query.records_path.filter(|path| !path.trim().is_empty()),
+        arrays: query.arrays.unwrap_or_default(),
+    });
+
+    // Quick row/column counts; exact counts are set once profiling completes
+    let (row_count, column_count) = analyze_file(&body, &extension, json_options.as_ref()).await;
+
+    // Team files count against the team's quota, others against the uploader's.
+    // The check and the insert share a transaction so concurrent uploads
+    // cannot both fit in the same remaining space.
+    let owner = match &team {
+        Some((team_id, _)) => UsageOwner::Team(*team_id),
+        None => UsageOwner::User(user_id),
+    };
+    let saved: ApiResult<FileRecord> = async {
+        let mut tx = pool.begin().await?;
+        if let Some(exceeded) = UsageService::check_upload(&mut tx, &limits, owner, body.len() as u64).await? {
+            return Err(ApiError::QuotaExceeded(exceeded));
+        }
 
-    // Store metadata in database
-    let record: FileRecord = sqlx::query_as(
-        r#"
-        INSERT INTO files (id, user_id, name, original_name, mime_type, size_bytes, row_count, column_count, storage_path)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
-        RETURNING *
-        "#
-    )
-    .bind(&file_id)
-    .bind(&user_id)
-    .bind(&file_name)
-    .bind(sanitize_filename(&original_name))
-    .bind(&mime_type)
-    .bind(body.len() as i64)
-    .bind(row_count)
-    .bind(column_count)
-    .bind(&storage_path)
-    .fetch_one(pool.get_ref())
-    .await
-    .map_err(|e| {
-        // Clean up file if database insert fails
+        // Store metadata in database
+        let record: FileRecord = sqlx::query_as(
+            r#"
+            INSERT INTO files (id, user_id, team_id, name, original_name, mime_type, size_bytes, row_count, column_count, storage_path, json_options)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            RETURNING *
+            "#
+        )
+        .bind(file_id)
+        .bind(user_id)
+        .bind(team.as_ref().map(|(team_id, _)| *team_id))
+        .bind(&file_name)
+        .bind(sanitize_filename(&original_name))
+        .bind(&mime_type)
+        .bind(body.len() as i64)
+        .bind(row_count)
+        .bind(column_count)
+        .bind(&storage_path)
+        .bind(json_options.map(sqlx::types::Json))
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| ApiError::internal(format!("Failed to save file metadata: {}", e)))?;
+
+        tx.commit().await?;
+        Ok(record)
+    }
+    .await;
+    // Clean up file if it was not saved
+    let record = saved.inspect_err(|_| {
         let _ = std::fs::remove_file(&file_path);
-        ApiError::internal(format!("Failed to save file metadata: {}", e))
     })?;
 
     log::info!("File uploaded: {} ({} bytes) by user {}", record.id, record.size_bytes, user_id);
 
+    // Profile columns in the background
+    if let Some(format) = format {
+        tokio::spawn(ProfilerService::profile_file(
+            pool.get_ref().clone(),
+            record.id,
+            file_path.clone(),
+            format,
+            record.read_options(),
+            query.detect_pii.unwrap_or(true),
+        ));
+    }
+
     Ok(HttpResponse::Created().json(FileMetadata::from(record)))
 }
 
@@ -214,59 +420,50 @@ async fn list_files(
 
     let user_id = Uuid::parse_str(&claims.sub)
         .map_err(|_| ApiError::unauthorized("Invalid user ID"))?;
+    claims.require(Permission::DatasetRead)?;
 
     let page = query.page.unwrap_or(1).max(1);
     let limit = query.limit.unwrap_or(20).clamp(1, 100);
     let offset = (page - 1) * limit;
 
+    let search = query.search.as_ref().map(|search| format!("%{}%", search));
+    // Team API keys only see their team's files
+    let team_id = claims.key_team();
+
     // Get total count
-    let (total,): (i64,) = if let Some(ref search) = query.search {
-        sqlx::query_as(
-            "SELECT COUNT(*) FROM files WHERE user_id = $1 AND (original_name ILIKE $2 OR name ILIKE $2)"
-        )
-        .bind(&user_id)
-        .bind(format!("%{}%", search))
-        .fetch_one(pool.get_ref())
-        .await
-    } else {
-        sqlx::query_as("SELECT COUNT(*) FROM files WHERE user_id = $1")
-            .bind(&user_id)
-            .fetch_one(pool.get_ref())
-            .await
-    }
+    let (total,): (i64,) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*) FROM files
+        WHERE user_id = $1
+          AND ($2::text IS NULL OR original_name ILIKE $2 OR name ILIKE $2)
+          AND ($3::uuid IS NULL OR team_id = $3)
+        "#
+    )
+    .bind(user_id)
+    .bind(&search)
+    .bind(team_id)
+    .fetch_one(pool.get_ref())
+    .await
     .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
     // Get files
-    let files: Vec<FileRecord> = if let Some(ref search) = query.search {
-        sqlx::query_as(
-            r#"
-            SELECT * FROM files 
-            WHERE user_id = $1 AND (original_name ILIKE $2 OR name ILIKE $2)
-            ORDER BY created_at DESC
-            LIMIT $3 OFFSET $4
-            "#
-        )
-        .bind(&user_id)
-        .bind(format!("%{}%", search))
-        .bind(limit)
-        .bind(offset)
-        .fetch_all(pool.get_ref())
-        .await
-    } else {
-        sqlx::query_as(
-            r#"
-            SELECT * FROM files 
-            WHERE user_id = $1
-            ORDER BY created_at DESC
-            LIMIT $2 OFFSET $3
-            "#
-        )
-        .bind(&user_id)
-        .bind(limit)
-        .bind(offset)
-        .fetch_all(pool.get_ref())
-        .await
-    }
+    let files: Vec<FileRecord> = sqlx::query_as(
+        r#"
+        SELECT * FROM files
+        WHERE user_id = $1
+          AND ($2::text IS NULL OR original_name ILIKE $2 OR name ILIKE $2)
+          AND ($3::uuid IS NULL OR team_id = $3)
+        ORDER BY created_at DESC
+        LIMIT $4 OFFSET $5
+        "#
+    )
+    .bind(user_id)
+    .bind(&search)
+    .bind(team_id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool.get_ref())
+    .await
     .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
     let file_metadata: Vec<FileMetadata> = files.into_iter().map(FileMetadata::from).collect();
@@ -280,6 +477,71 @@ async fn list_files(
     })))
 }
 
+/// List files shared with the current user, directly or through a team
+///
+/// GET /api/files/shared
+async fn list_shared_files(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    query: web::Query<ListFilesQuery>,
+) -> ApiResult<HttpResponse> {
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID"))?;
+    claims.require(Permission::DatasetRead)?;
+
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let offset = (page - 1) * limit;
+    let search = query.search.as_ref().map(|search| format!("%{}%", search));
+
+    let shared = AclService::shared_with(pool.get_ref(), ResourceKind::File, user_id).await?;
+    // Team API keys only see their team's files
+    let team_id = claims.key_team();
+
+    let (total,): (i64,) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*) FROM files
+        WHERE id = ANY($1) AND ($2::text IS NULL OR original_name ILIKE $2 OR name ILIKE $2)
+          AND ($3::uuid IS NULL OR team_id = $3)
+        "#
+    )
+    .bind(&shared)
+    .bind(&search)
+    .bind(team_id)
+    .fetch_one(pool.get_ref())
+    .await?;
+
+    let files: Vec<FileRecord> = sqlx::query_as(
+        r#"
+        SELECT * FROM files
+        WHERE id = ANY($1) AND ($2::text IS NULL OR original_name ILIKE $2 OR name ILIKE $2)
+          AND ($3::uuid IS NULL OR team_id = $3)
+        ORDER BY created_at DESC
+        LIMIT $4 OFFSET $5
+        "#
+    )
+    .bind(&shared)
+    .bind(&search)
+    .bind(team_id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool.get_ref())
+    .await?;
+
+    let file_metadata: Vec<FileMetadata> = files.into_iter().map(FileMetadata::from).collect();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "files": file_metadata,
+        "total": total,
+        "page": page,
+        "limit": limit,
+        "pages": (total as f64 / limit as f64).ceil() as i64
+    })))
+}
+
 /// Get file by ID (download)
 ///
 /// GET /api/files/{id}
@@ -293,20 +555,17 @@ async fn get_file(
 
     let user_id = Uuid::parse_str(&claims.sub)
         .map_err(|_| ApiError::unauthorized("Invalid user ID"))?;
+    claims.require(Permission::DatasetRead)?;
 
     let file_id = path.into_inner();
 
-    // Get file record and verify ownership
-    let record: Option<FileRecord> = sqlx::query_as(
-        "SELECT * FROM files WHERE id = $1 AND user_id = $2"
-    )
-    .bind(&file_id)
-    .bind(&user_id)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    // Get file record and verify access
+    let record = accessible_file(pool.get_ref(), &claims, user_id, file_id, Permission::DatasetRead).await?;
 
-    let record = record.ok_or_else(|| ApiError::not_found("File not found"))?;
+    // The raw file would bypass row-level security and column masking
+    if data_view(pool.get_ref(), &claims, &record, user_id).await?.is_restricted() {
+        return Err(ApiError::forbidden("Access to this file is restricted; use preview or export instead"));
+    }
 
     // Read file from disk
     let contents = fs::read(&record.storage_path).await
@@ -331,20 +590,12 @@ async fn delete_file(
 
     let user_id = Uuid::parse_str(&claims.sub)
         .map_err(|_| ApiError::unauthorized("Invalid user ID"))?;
+    claims.require(Permission::DatasetDelete)?;
 
     let file_id = path.into_inner();
 
-    // Get file record and verify ownership
-    let record: Option<FileRecord> = sqlx::query_as(
-        "SELECT * FROM files WHERE id = $1 AND user_id = $2"
-    )
-    .bind(&file_id)
-    .bind(&user_id)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    let record = record.ok_or_else(|| ApiError::not_found("File not found"))?;
+    // Get file record and verify access
+    let record = accessible_file(pool.get_ref(), &claims, user_id, file_id, Permission::DatasetDelete).await?;
 
     // Delete from database first
     sqlx::query("DELETE FROM files WHERE id = $1")
@@ -353,6 +604,10 @@ async fn delete_file(
         .await
         .map_err(|e| ApiError::internal(format!("Failed to delete file record: {}", e)))?;
 
+    if let Err(e) = AclService::revoke_all(pool.get_ref(), ResourceKind::File, file_id).await {
+        log::warn!("Failed to remove grants on deleted file {}: {}", file_id, e);
+    }
+
     // Delete file from disk (don't fail if file doesn't exist)
     let _ = fs::remove_file(&record.storage_path).await;
 
@@ -377,28 +632,456 @@ async fn get_file_metadata(
 
     let user_id = Uuid::parse_str(&claims.sub)
         .map_err(|_| ApiError::unauthorized("Invalid user ID"))?;
+    claims.require(Permission::DatasetRead)?;
 
     let file_id = path.into_inner();
 
-    // Get file record and verify ownership
-    let record: Option<FileRecord> = sqlx::query_as(
-        "SELECT * FROM files WHERE id = $1 AND user_id = $2"
-    )
-    .bind(&file_id)
-    .bind(&user_id)
-    .fetch_optional(pool.get_ref())
+    // Get file record and verify access
+    let record = accessible_file(pool.get_ref(), &claims, user_id, file_id, Permission::DatasetRead).await?;
+    let restricted = data_view(pool.get_ref(), &claims, &record, user_id).await?.rows.filter.is_restricted();
+
+    let mut metadata = FileMetadata::from(record);
+    if restricted {
+        // The total would reveal how many rows are hidden
+        metadata.row_count = None;
+    }
+
+    Ok(HttpResponse::Ok().json(metadata))
+}
+
+/// Get column profile (statistics) for a file
+///
+/// GET /api/files/{id}/profile
+async fn get_file_profile(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID"))?;
+    claims.require(Permission::DatasetRead)?;
+
+    let file_id = path.into_inner();
+
+    // Verify access
+    let record = accessible_file(pool.get_ref(), &claims, user_id, file_id, Permission::DatasetRead).await?;
+
+    // Statistics are computed over every row
+    let view = data_view(pool.get_ref(), &claims, &record, user_id).await?;
+    view.check_readable()?;
+    if view.rows.filter.is_restricted() {
+        return Err(ApiError::forbidden("Row-level security applies to this file; profile is unavailable"));
+    }
+
+    let mut profile = ProfilerService::get_profile(pool.get_ref(), file_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("File not found"))?;
+
+    // Top values and ranges would reveal masked columns
+    for mask in &view.masks {
+        if let Some(column) = profile.columns.iter_mut().find(|c| c.name == mask.column) {
+            column.stats = None;
+        }
+    }
+    profile.columns.retain(|column| {
+        !view.masks.iter().any(|m| m.column == column.name && m.rule == MaskRule::Hide)
+    });
+
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+/// Preview a slice of a file as typed rows
+///
+/// GET /api/files/{id}/preview?offset=&limit=&columns=&format=
+async fn preview_file(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    query: web::Query<PreviewQuery>,
+) -> ApiResult<HttpResponse> {
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID"))?;
+    claims.require(Permission::DatasetRead)?;
+
+    let file_id = path.into_inner();
+    let query = query.into_inner();
+
+    // Get file record and verify access
+    let record = accessible_file(pool.get_ref(), &claims, user_id, file_id, Permission::DatasetRead).await?;
+
+    let source_format = FileFormat::from_path(std::path::Path::new(&record.name))
+        .ok_or_else(|| ApiError::UnsupportedMediaType("File format cannot be previewed".to_string()))?;
+
+    let storage_path = PathBuf::from(&record.storage_path);
+    if !storage_path.exists() {
+        return Err(ApiError::not_found("File data not found"));
+    }
+
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(DEFAULT_PREVIEW_ROWS).clamp(1, MAX_PREVIEW_ROWS);
+    let columns = query.columns.as_deref().and_then(parse_columns);
+    let view = data_view(pool.get_ref(), &claims, &record, user_id).await?;
+    view.check_readable()?;
+
+    let format = match query.format {
+        Some(format) => format,
+        None if accepts_arrow(&req) => PreviewFormat::Arrow,
+        None => PreviewFormat::Json,
+    };
+
+    let restricted = view.rows.filter.is_restricted();
+    let started = std::time::Instant::now();
+    let dataset = if restricted {
+        // Policies may filter on columns that are not previewed, so the whole
+        // file is read and the window taken after filtering
+        let read_options = record.read_options();
+        let mut spec = QuerySpec {
+            columns,
+            offset: Some(offset),
+            limit: Some(limit),
+            ..Default::default()
+        };
+        view.rows.filter.restrict(&mut spec);
+        web::block(move || -> ApiResult<_> {
+            let dataset = read_file(&storage_path, source_format, &read_options)?;
+            Ok(QueryEngine::execute(&dataset, &spec)?)
+        })
+        .await
+        .map_err(|e| ApiError::internal(format!("Preview task failed: {}", e)))??
+    } else {
+        // Only the requested window is decoded
+        let options = ReadOptions {
+            columns,
+            offset,
+            limit: Some(limit),
+            ..record.read_options()
+        };
+        web::block(move || read_file(&storage_path, source_format, &options))
+            .await
+            .map_err(|e| ApiError::internal(format!("Preview task failed: {}", e)))??
+    };
+    record_query(pool.get_ref(), user_id, &record, started.elapsed()).await;
+    let dataset = MaskingService::apply(&dataset, &view.masks).map_err(mask_error)?;
+
+    let encode_error = |e: ArrowError| ApiError::internal(format!("Failed to encode preview: {}", e));
+
+    match format {
+        PreviewFormat::Arrow => Ok(HttpResponse::Ok()
+            .content_type(ARROW_STREAM_CONTENT_TYPE)
+            .body(encode_arrow_stream(&dataset).map_err(encode_error)?)),
+        PreviewFormat::Json => Ok(HttpResponse::Ok().json(PreviewResponse {
+            columns: dataset
+                .schema
+                .fields()
+                .iter()
+                .map(|field| PreviewColumn {
+                    name: field.name().clone(),
+                    data_type: field.data_type().to_string(),
+                    nullable: field.is_nullable(),
+                })
+                .collect(),
+            row_count: dataset.num_rows(),
+            rows: encode_json_rows(&dataset).map_err(encode_error)?,
+            offset,
+            limit,
+            total_rows: if restricted { None } else { record.row_count },
+        })),
+    }
+}
+
+/// Export a filtered view of a file
+///
+/// POST /api/files/{id}/export
+async fn export_file(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<ExportRequest>,
+) -> ApiResult<HttpResponse> {
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID"))?;
+    claims.require(Permission::ChartExport)?;
+
+    let file_id = path.into_inner();
+    let body = body.into_inner();
+
+    if !PermissionService::has_permission(pool.get_ref(), user_id, Permission::ChartExport).await? {
+        return Err(ApiError::forbidden("You do not have permission to export data"));
+    }
+
+    // Get file record and verify access
+    let record = accessible_file(pool.get_ref(), &claims, user_id, file_id, Permission::ChartExport).await?;
+    let view = data_view(pool.get_ref(), &claims, &record, user_id).await?;
+
+    let source_format = FileFormat::from_path(std::path::Path::new(&record.name))
+        .ok_or_else(|| ApiError::UnsupportedMediaType("File format cannot be exported".to_string()))?;
+
+    // Read and filter on a blocking thread
+    let storage_path = PathBuf::from(&record.storage_path);
+    let read_options = record.read_options();
+    let query = body.query.clone();
+    let masked_columns: Vec<String> = view.masks.iter().map(|m| m.column.clone()).collect();
+    let policies: Vec<String> = view.rows.applied.iter().map(|p| p.name.clone()).collect();
+    let started = std::time::Instant::now();
+    let dataset = web::block(move || -> ApiResult<_> {
+        let dataset = view.apply(&read_file(&storage_path, source_format, &read_options)?)?;
+        Ok(QueryEngine::execute(&dataset, &query)?)
+    })
     .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    .map_err(|e| ApiError::internal(format!("Export task failed: {}", e)))??;
+    record_query(pool.get_ref(), user_id, &record, started.elapsed()).await;
+
+    ExportService::validate(&dataset, body.format)?;
+
+    let row_count = dataset.num_rows();
+    let columns: Vec<String> = dataset.schema.fields().iter().map(|f| f.name().clone()).collect();
+
+    AuditService::log(pool.get_ref(), AuditEntry {
+        user_id: Some(user_id),
+        team_id: None,
+        action: AuditAction::FileExport,
+        resource_type: Some(ResourceType::File),
+        resource_id: Some(file_id),
+        details: Some(json!({
+            "format": body.format,
+            "rows": row_count,
+            "columns": columns,
+            "filters": body.query.filters,
+            "row_policies": policies,
+            "masked_columns": masked_columns,
+        })),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: req
+            .headers()
+            .get("User-Agent")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.to_string()),
+    })
+    .await
+    .map_err(|e| ApiError::internal(format!("Failed to record export: {}", e)))?;
+
+    // Encode on a blocking thread and stream chunks as they are produced
+    let format = body.format;
+    let (sender, receiver) = tokio::sync::mpsc::channel(8);
+    tokio::task::spawn_blocking(move || {
+        let mut writer = ChannelWriter::new(sender);
+        if let Err(e) = ExportService::write(&dataset, format, &mut writer) {
+            log::error!("Export of file {} failed: {}", file_id, e);
+            writer.abort(std::io::Error::other(e.to_string()));
+        }
+    });
+
+    let stream = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    let filename = export_filename(body.filename.as_deref(), &record.original_name, format);
 
-    let record = record.ok_or_else(|| ApiError::not_found("File not found"))?;
+    log::info!("File exported: {} as {:?} ({} rows) by user {}", file_id, format, row_count, user_id);
 
-    Ok(HttpResponse::Ok().json(FileMetadata::from(record)))
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .streaming(stream))
+}
+
+/// Reduce a file to a render-ready chart series
+///
+/// POST /api/files/{id}/chart
+async fn chart_file(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<ChartRequest>,
+) -> ApiResult<HttpResponse> {
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID"))?;
+    claims.require(Permission::QueryExecute)?;
+
+    let file_id = path.into_inner();
+    let body = body.into_inner();
+
+    if !PermissionService::has_permission(pool.get_ref(), user_id, Permission::QueryExecute).await? {
+        return Err(ApiError::forbidden("You do not have permission to query data"));
+    }
+
+    // Get file record and verify access
+    let record = accessible_file(pool.get_ref(), &claims, user_id, file_id, Permission::QueryExecute).await?;
+    let view = data_view(pool.get_ref(), &claims, &record, user_id).await?;
+
+    let source_format = FileFormat::from_path(std::path::Path::new(&record.name))
+        .ok_or_else(|| ApiError::UnsupportedMediaType("File format cannot be queried".to_string()))?;
+
+    // Read, filter and reduce on a blocking thread
+    let started = std::time::Instant::now();
+    let storage_path = PathBuf::from(&record.storage_path);
+    let read_options = record.read_options();
+    let (series, row_count) = web::block(move || -> ApiResult<_> {
+        let dataset = view.apply(&read_file(&storage_path, source_format, &read_options)?)?;
+        let result = QueryEngine::execute(&dataset, &body.query)?;
+        let row_count = result.num_rows();
+        let series = body.reduce.apply(&result.batches[0])?;
+        Ok((series, row_count))
+    })
+    .await
+    .map_err(|e| ApiError::internal(format!("Chart task failed: {}", e)))??;
+    let elapsed = started.elapsed();
+    record_query(pool.get_ref(), user_id, &record, elapsed).await;
+
+    Ok(HttpResponse::Ok().json(ChartResponse {
+        series,
+        row_count,
+        execution_time_ms: elapsed.as_millis(),
+    }))
 }
 
 // ============================================================================
 // HELPER FUNCTIONS
 // ============================================================================
 
+/// Count a query's compute time towards usage; a failure is logged rather
+/// than failing the query
+async fn record_query(pool: &PgPool, user_id: Uuid, record: &FileRecord, elapsed: std::time::Duration) {
+    if let Err(e) = UsageService::record_query(pool, user_id, record.team_id, elapsed).await {
+        log::warn!("Failed to record query usage on file {}: {}", record.id, e);
+    }
+}
+
+/// File the user may act on with `permission`: their own, one shared with
+/// them, or one of a team they belong to. Team API keys reach only their
+/// team's files. Others are reported as missing.
+pub(crate) async fn accessible_file(
+    pool: &PgPool,
+    claims: &Claims,
+    user_id: Uuid,
+    file_id: Uuid,
+    permission: Permission,
+) -> ApiResult<FileRecord> {
+    let record: Option<FileRecord> = sqlx::query_as("SELECT * FROM files WHERE id = $1")
+        .bind(file_id)
+        .fetch_optional(pool)
+        .await?;
+
+    match record {
+        Some(record) if !claims.reaches(record.team_id) => Err(ApiError::not_found("File not found")),
+        Some(record) if record.user_id == user_id => Ok(record),
+        Some(record)
+            if PermissionService::can_access_resource(pool, user_id, ResourceKind::File, file_id, permission).await? =>
+        {
+            Ok(record)
+        }
+        _ => Err(ApiError::not_found("File not found")),
+    }
+}
+
+/// What of a file a user may see: the rows its policies allow, with
+/// classified columns masked unless the user may unmask them
+pub(crate) struct DataView {
+    pub rows: RowAccess,
+    pub masks: Vec<ColumnMask>,
+    /// The user would see masked columns, but the file's columns are not
+    /// classified yet, so none of its data is shown
+    pub unclassified: bool,
+}
+
+impl DataView {
+    /// Whether the user sees less than the whole file
+    pub fn is_restricted(&self) -> bool {
+        self.rows.filter.is_restricted() || !self.masks.is_empty() || self.unclassified
+    }
+
+    /// Fail unless the file's data may be read, masked as needed
+    pub fn check_readable(&self) -> ApiResult<()> {
+        if self.unclassified {
+            return Err(ApiError::forbidden(
+                "This file's columns have not been classified yet; its data is available once profiling succeeds",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Narrow a dataset to the visible rows and mask its columns, before any
+    /// query of the user's runs on it
+    pub fn apply(&self, dataset: &Dataset) -> ApiResult<Dataset> {
+        self.check_readable()?;
+        if !self.rows.filter.is_restricted() {
+            return MaskingService::apply(dataset, &self.masks).map_err(mask_error);
+        }
+
+        let mut spec = QuerySpec::default();
+        self.rows.filter.restrict(&mut spec);
+        let visible = QueryEngine::execute(dataset, &spec)?;
+        MaskingService::apply(&visible, &self.masks).map_err(mask_error)
+    }
+}
+
+/// Data view of a file for a user; `claims` limit unmasking to credentials
+/// allowing it
+pub(crate) async fn data_view(
+    pool: &PgPool,
+    claims: &Claims,
+    record: &FileRecord,
+    user_id: Uuid,
+) -> ApiResult<DataView> {
+    let rows = RowSecurityService::access(pool, record.id, record.user_id, record.team_id, user_id).await?;
+    let unmasked = claims.allows(Permission::DatasetUnmask)
+        && MaskingService::can_unmask(pool, user_id, record.user_id, record.team_id).await?;
+    let masks = if unmasked {
+        Vec::new()
+    } else {
+        MaskingService::masks(pool, record.id).await?
+    };
+
+    Ok(DataView {
+        rows,
+        masks,
+        unclassified: !unmasked && !record.is_classified(),
+    })
+}
+
+/// File whose access policies (row policies, column classification) the
+/// user may manage: as its owner or, for a team file, holding
+/// `dataset:share` in the team. A share grant is not enough, so users cannot
+/// lift the policies that restrict them.
+pub(crate) async fn managed_file(req: &HttpRequest, pool: &PgPool, file_id: Uuid) -> ApiResult<(Uuid, FileRecord)> {
+    let claims = get_claims(req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID"))?;
+    claims.require(Permission::DatasetShare)?;
+
+    let record = accessible_file(pool, &claims, user_id, file_id, Permission::DatasetRead).await?;
+
+    let allowed = record.user_id == user_id
+        || match record.team_id {
+            Some(team_id) => {
+                PermissionService::has_team_permission(pool, user_id, team_id, Permission::DatasetShare).await?
+            }
+            None => false,
+        };
+    if !allowed {
+        return Err(ApiError::forbidden("Only the file's owner or team can manage its access policies"));
+    }
+
+    Ok((user_id, record))
+}
+
+fn mask_error(e: ArrowError) -> ApiError {
+    ApiError::internal(format!("Failed to mask columns: {}", e))
+}
+
 fn get_upload_dir() -> ApiResult<PathBuf> {
     let dir = std::env::var("UPLOAD_DIR")
         .unwrap_or_else(|_| "./uploads".to_string());
@@ -429,10 +1112,79 @@ fn sanitize_filename(name: &str) -> String {
         .replace("..", "_")
 }
 
+/// Build the download filename for an export, forcing the format's extension
+fn export_filename(requested: Option<&str>, original_name: &str, format: ExportFormat) -> String {
+    let base = requested
+        .map(sanitize_filename)
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| sanitize_filename(original_name));
+
+    let stem = match base.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem.to_string(),
+        _ => base,
+    };
+
+    let stem = if stem.trim().is_empty() { "export".to_string() } else { stem };
+    format!("{}.{}", stem, format.extension())
+}
+
+/// Split a comma-separated column list, ignoring blanks; an empty list
+/// selects every column
+fn parse_columns(columns: &str) -> Option<Vec<String>> {
+    let columns: Vec<String> = columns
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+    (!columns.is_empty()).then_some(columns)
+}
+
+/// Whether the client asked for an Arrow IPC stream via the Accept header
+fn accepts_arrow(req: &HttpRequest) -> bool {
+    req.headers()
+        .get("Accept")
+        .and_then(|h| h.to_str().ok())
+        .map(|accept| accept.contains(ARROW_STREAM_CONTENT_TYPE))
+        .unwrap_or(false)
+}
+
+/// Encode a dataset as JSON objects, keeping nulls so every row has every column
+pub(crate) fn encode_json_rows(
+    dataset: &Dataset,
+) -> Result<Vec<serde_json::Map<String, serde_json::Value>>, ArrowError> {
+    let mut buffer = Vec::new();
+    {
+        let mut writer = arrow::json::WriterBuilder::new()
+            .with_explicit_nulls(true)
+            .build::<_, arrow::json::writer::JsonArray>(&mut buffer);
+        for batch in &dataset.batches {
+            writer.write(batch)?;
+        }
+        writer.finish()?;
+    }
+
+    if buffer.is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_slice(&buffer).map_err(|e| ArrowError::JsonError(e.to_string()))
+}
+
+/// Encode a dataset as an Arrow IPC stream
+fn encode_arrow_stream(dataset: &Dataset) -> Result<Vec<u8>, ArrowError> {
+    let mut writer = arrow::ipc::writer::StreamWriter::try_new(Vec::new(), &dataset.schema)?;
+    for batch in &dataset.batches {
+        writer.write(batch)?;
+    }
+    writer.finish()?;
+    writer.into_inner()
+}
+
 fn get_content_type(extension: &str) -> String {
     match extension.to_lowercase().as_str() {
         "csv" => "text/csv".to_string(),
         "json" => "application/json".to_string(),
+        "ndjson" | "jsonl" => "application/x-ndjson".to_string(),
         "parquet" => "application/vnd.apache.parquet".to_string(),
         "arrow" => "application/vnd.apache.arrow.file".to_string(),
         _ => "application/octet-stream".to_string(),
@@ -440,10 +1192,14 @@ fn get_content_type(extension: &str) -> String {
 }
 
 /// Analyze file to extract row and column counts
-async fn analyze_file(data: &[u8], extension: &str) -> (Option<i32>, Option<i32>) {
+async fn analyze_file(
+    data: &[u8],
+    extension: &str,
+    json_options: Option<&JsonOptions>,
+) -> (Option<i32>, Option<i32>) {
     match extension {
         "csv" => analyze_csv(data),
-        "json" => analyze_json(data),
+        "json" | "ndjson" | "jsonl" => analyze_json(data, json_options.cloned().unwrap_or_default()),
         _ => (None, None),
     }
 }
@@ -454,42 +1210,60 @@ fn analyze_csv(data: &[u8]) -> (Option<i32>, Option<i32>) {
         Err(_) => return (None, None),
     };
 
-    let lines: Vec<&str> = content.lines().collect();
-    if lines.is_empty() {
+    // Count records and header fields, ignoring delimiters and line breaks
+    // inside quoted fields. Escaped quotes ("") toggle twice, which is a no-op.
+    let mut in_quotes = false;
+    let mut in_header = true;
+    let mut record_has_content = false;
+    let mut records = 0usize;
+    let mut column_count = 1usize;
+
+    for c in content.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                record_has_content = true;
+            }
+            ',' if !in_quotes => {
+                if in_header {
+                    column_count += 1;
+                }
+                record_has_content = true;
+            }
+            '\n' if !in_quotes => {
+                if record_has_content {
+                    records += 1;
+                    in_header = false;
+                }
+                record_has_content = false;
+            }
+            '\r' => {}
+            _ => record_has_content = true,
+        }
+    }
+    if record_has_content {
+        records += 1;
+    }
+
+    if records == 0 {
         return (None, None);
     }
 
-    // Count columns from header
-    let column_count = lines[0].split(',').count() as i32;
-    
     // Row count (excluding header)
-    let row_count = (lines.len().saturating_sub(1)) as i32;
-
-    (Some(row_count), Some(column_count))
+    (Some((records - 1) as i32), Some(column_count as i32))
 }
 
-fn analyze_json(data: &[u8]) -> (Option<i32>, Option<i32>) {
-    let content = match std::str::from_utf8(data) {
-        Ok(s) => s,
+fn analyze_json(data: &[u8], options: JsonOptions) -> (Option<i32>, Option<i32>) {
+    let records = match flatten_records(data, &options) {
+        Ok(records) => records,
         Err(_) => return (None, None),
     };
 
-    let json: serde_json::Value = match serde_json::from_str(content) {
-        Ok(v) => v,
-        Err(_) => return (None, None),
-    };
+    // Columns are the union of flattened keys across all records
+    let columns: std::collections::HashSet<&String> =
+        records.iter().flat_map(|record| record.keys()).collect();
 
-    if let Some(array) = json.as_array() {
-        let row_count = array.len() as i32;
-        let column_count = array
-            .first()
-            .and_then(|obj| obj.as_object())
-            .map(|obj| obj.len() as i32);
-        
-        (Some(row_count), column_count)
-    } else {
-        (None, None)
-    }
+    (Some(records.len() as i32), Some(columns.len() as i32))
 }
 
 // ============================================================================
@@ -521,6 +1295,57 @@ mod tests {
         assert_eq!(extract_filename("attachment"), None);
     }
 
+    #[test]
+    fn test_export_filename() {
+        assert_eq!(export_filename(None, "sales.csv", ExportFormat::Xlsx), "sales.xlsx");
+        assert_eq!(
+            export_filename(Some("Q3 report"), "sales.csv", ExportFormat::Parquet),
+            "Q3 report.parquet"
+        );
+        assert_eq!(
+            export_filename(Some("../../etc/passwd.csv"), "sales.csv", ExportFormat::Json),
+            "__etcpasswd.ndjson"
+        );
+        assert_eq!(export_filename(Some("\"\""), "", ExportFormat::Csv), "export.csv");
+    }
+
+    #[test]
+    fn test_parse_columns() {
+        assert_eq!(parse_columns("id, name,,value "), Some(vec!["id".to_string(), "name".to_string(), "value".to_string()]));
+        assert_eq!(parse_columns(" , "), None);
+        assert_eq!(parse_columns(""), None);
+    }
+
+    #[test]
+    fn test_encode_json_rows_keeps_nulls() {
+        use arrow::array::{Int64Array, StringArray};
+        use arrow::datatypes::{DataType, Field, Schema};
+        use arrow::record_batch::RecordBatch;
+        use std::sync::Arc;
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec![Some("a"), None])),
+            ],
+        )
+        .unwrap();
+        let dataset = Dataset { schema, batches: vec![batch] };
+
+        let rows = encode_json_rows(&dataset).unwrap();
+        assert_eq!(serde_json::Value::Object(rows[1].clone()), json!({"id": 2, "name": null}));
+
+        let stream = encode_arrow_stream(&dataset).unwrap();
+        let reader = arrow::ipc::reader::StreamReader::try_new(stream.as_slice(), None).unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        assert_eq!(batches[0].num_rows(), 2);
+    }
+
     #[test]
     fn test_get_content_type() {
         assert_eq!(get_content_type("csv"), "text/csv");
@@ -537,11 +1362,48 @@ mod tests {
         assert_eq!(cols, Some(3));
     }
 
+    #[test]
+    fn test_analyze_csv_quoted_fields() {
+        let csv_data = b"name,\"address, city\",notes\r\n\"Smith, J\",\"1 Main St, NYC\",\"line one\nline two\"\r\nBob,LA,\"say \"\"hi\"\"\"\r\n\r\n";
+        let (rows, cols) = analyze_csv(csv_data);
+        assert_eq!(rows, Some(2));
+        assert_eq!(cols, Some(3));
+    }
+
     #[test]
     fn test_analyze_json() {
         let json_data = b"[{\"name\":\"Alice\",\"age\":30},{\"name\":\"Bob\",\"age\":25}]";
-        let (rows, cols) = analyze_json(json_data);
+        let (rows, cols) = analyze_json(json_data, JsonOptions::default());
         assert_eq!(rows, Some(2));
         assert_eq!(cols, Some(2));
     }
+
+    #[test]
+    fn test_analyze_ndjson() {
+        let ndjson_data = b"{\"name\":\"Alice\"}\n{\"name\":\"Bob\",\"address\":{\"city\":\"LA\"}}\n";
+        let (rows, cols) = analyze_json(ndjson_data, JsonOptions::default());
+        assert_eq!(rows, Some(2));
+        assert_eq!(cols, Some(2));
+    }
+
+    #[test]
+    fn test_unclassified_view_refuses_reads() {
+        use crate::services::row_security::RowFilter;
+        use arrow::datatypes::Schema;
+        use std::sync::Arc;
+
+        let dataset = Dataset { schema: Arc::new(Schema::empty()), batches: Vec::new() };
+        let view = DataView {
+            rows: RowAccess { filter: RowFilter::Unrestricted, applied: Vec::new() },
+            masks: Vec::new(),
+            unclassified: true,
+        };
+        assert!(view.is_restricted());
+        assert!(view.check_readable().is_err());
+        assert!(view.apply(&dataset).is_err());
+
+        let view = DataView { unclassified: false, ..view };
+        assert!(!view.is_restricted());
+        assert!(view.apply(&dataset).is_ok());
+    }
 }
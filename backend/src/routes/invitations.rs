@@ -0,0 +1,422 @@
+//! Team invitation routes
+//!
+//! Team managers invite by email under `/api/teams/{id}/members` and manage
+//! pending invitations under `/api/teams/{id}/invitations`. Invitees answer
+//! under `/api/invitations`, or before signing in under
+//! `/api/auth/invitations`; someone without an account registers with the
+//! invitation's token and joins on the spot.
+//!
+//! An invitation can only be accepted by the account with the invited
+//! address, and only once that address is proven: by the emailed token
+//! itself, or by the account's verified email. A team that restricts its
+//! email domains can neither invite nor admit addresses elsewhere.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::auth::validate_email;
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::auth::get_claims;
+use crate::middleware::guard::{perm, team_membership, RequireTeamPermission};
+use crate::models::{InviteUserRequest, TeamRole, User};
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::auth::sessions::DeviceInfo;
+use crate::services::invitations::{Invitation, InvitationService};
+use crate::services::mail::Mailer;
+use crate::services::team_settings::{TeamSettings, TeamSettingsService};
+
+/// Configure invitee routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/invitations")
+            .route("", web::get().to(list_my_invitations))
+            .route("/accept", web::post().to(accept_with_token))
+            .route("/{id}/accept", web::post().to(accept_invitation))
+            .route("/{id}/decline", web::post().to(decline_invitation)),
+    );
+}
+
+/// Configure routes for invitees who are not signed in (nested in the /auth scope)
+pub fn public_config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/invitations/preview", web::post().to(preview_invitation))
+        .route("/invitations/decline", web::post().to(decline_with_token));
+}
+
+/// Token from an invitation link
+#[derive(Debug, Deserialize)]
+pub struct InvitationTokenRequest {
+    pub token: String,
+}
+
+// ============================================================================
+// TEAM MANAGEMENT
+// ============================================================================
+
+/// Invite someone to the team by email, whether or not they have an account
+///
+/// POST /api/teams/{id}/members
+pub async fn invite_member(
+    req: HttpRequest,
+    guard: RequireTeamPermission<perm::TeamManageMembers>,
+    pool: web::Data<PgPool>,
+    mailer: web::Data<Mailer>,
+    body: web::Json<InviteUserRequest>,
+) -> ApiResult<HttpResponse> {
+    let email = body.email.trim().to_lowercase();
+    validate_email(&email)?;
+
+    let settings = team_settings(pool.get_ref(), guard.team_id).await?;
+    ensure_domain_allowed(&settings, &email)?;
+
+    // Cannot assign owner role through invite
+    let role = match body.role.clone().unwrap_or(settings.default_member_role) {
+        TeamRole::Owner => TeamRole::Admin,
+        role => role,
+    };
+
+    let existing: Option<(Uuid,)> = sqlx::query_as("SELECT id FROM users WHERE email = $1")
+        .bind(&email)
+        .fetch_optional(pool.get_ref())
+        .await?;
+    if let Some((user_id,)) = existing {
+        if team_membership(&req, pool.get_ref(), guard.team_id, user_id).await?.is_some() {
+            return Err(ApiError::bad_request("User is already a member of this team"));
+        }
+    }
+
+    let mut tx = pool.begin().await?;
+    let (invitation, token) = InvitationService::create(&mut tx, guard.team_id, &email, &role, guard.user_id)
+        .await?
+        .ok_or_else(|| ApiError::bad_request("This address already has a pending invitation; resend it instead"))?;
+    tx.commit().await?;
+
+    send_invitation(&mailer, &invitation, &token).await;
+    audit(pool.get_ref(), &req, guard.user_id, AuditAction::TeamInvitationCreate, &invitation).await;
+
+    Ok(HttpResponse::Created().json(invitation))
+}
+
+/// List the team's pending invitations, including expired ones
+///
+/// GET /api/teams/{id}/invitations
+pub async fn list_invitations(
+    guard: RequireTeamPermission<perm::TeamManageMembers>,
+    pool: web::Data<PgPool>,
+) -> ApiResult<HttpResponse> {
+    let invitations = InvitationService::list_pending(pool.get_ref(), guard.team_id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "invitations": invitations,
+        "total": invitations.len()
+    })))
+}
+
+/// Email a pending invitation again with a new link and a fresh expiry
+///
+/// POST /api/teams/{id}/invitations/{invitation_id}/resend
+pub async fn resend_invitation(
+    req: HttpRequest,
+    guard: RequireTeamPermission<perm::TeamManageMembers>,
+    pool: web::Data<PgPool>,
+    mailer: web::Data<Mailer>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> ApiResult<HttpResponse> {
+    let (team_id, invitation_id) = path.into_inner();
+
+    let pending = InvitationService::get(pool.get_ref(), invitation_id)
+        .await?
+        .filter(|invitation| invitation.team_id == team_id)
+        .ok_or_else(|| ApiError::not_found("Pending invitation not found"))?;
+    ensure_domain_allowed(&team_settings(pool.get_ref(), team_id).await?, &pending.email)?;
+
+    let mut tx = pool.begin().await?;
+    let (invitation, token) = InvitationService::reissue(&mut tx, team_id, invitation_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Pending invitation not found"))?;
+    tx.commit().await?;
+
+    send_invitation(&mailer, &invitation, &token).await;
+    audit(pool.get_ref(), &req, guard.user_id, AuditAction::TeamInvitationResend, &invitation).await;
+
+    Ok(HttpResponse::Ok().json(invitation))
+}
+
+/// Withdraw a pending invitation
+///
+/// DELETE /api/teams/{id}/invitations/{invitation_id}
+pub async fn revoke_invitation(
+    req: HttpRequest,
+    guard: RequireTeamPermission<perm::TeamManageMembers>,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> ApiResult<HttpResponse> {
+    let (team_id, invitation_id) = path.into_inner();
+
+    let invitation = InvitationService::revoke(pool.get_ref(), team_id, invitation_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Pending invitation not found"))?;
+
+    audit(pool.get_ref(), &req, guard.user_id, AuditAction::TeamInvitationRevoke, &invitation).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// ============================================================================
+// INVITEES
+// ============================================================================
+
+/// Show an invitation before answering it: the team, who sent it, and
+/// whether the address already has an account
+///
+/// POST /api/auth/invitations/preview
+async fn preview_invitation(
+    pool: web::Data<PgPool>,
+    body: web::Json<InvitationTokenRequest>,
+) -> ApiResult<HttpResponse> {
+    let invitation = open_invitation(pool.get_ref(), &body.token).await?;
+
+    let (account_exists,): (bool,) = sqlx::query_as("SELECT EXISTS (SELECT 1 FROM users WHERE email = $1)")
+        .bind(&invitation.email)
+        .fetch_one(pool.get_ref())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "team_name": invitation.team_name,
+        "email": invitation.email,
+        "role": invitation.role,
+        "invited_by_name": invitation.invited_by_name,
+        "expires_at": invitation.expires_at,
+        "account_exists": account_exists
+    })))
+}
+
+/// Decline an invitation from its link, without signing in
+///
+/// POST /api/auth/invitations/decline
+async fn decline_with_token(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<InvitationTokenRequest>,
+) -> ApiResult<HttpResponse> {
+    let invitation = open_invitation(pool.get_ref(), &body.token).await?;
+    let declined = InvitationService::decline(pool.get_ref(), invitation.id)
+        .await?
+        .ok_or_else(|| ApiError::bad_request("Invalid or expired invitation"))?;
+
+    let invitee: Option<(Uuid,)> = sqlx::query_as("SELECT id FROM users WHERE email = $1")
+        .bind(&declined.email)
+        .fetch_optional(pool.get_ref())
+        .await?;
+    if let Some((user_id,)) = invitee {
+        audit(pool.get_ref(), &req, user_id, AuditAction::TeamInvitationDecline, &declined).await;
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "message": "Invitation declined"
+    })))
+}
+
+/// List open invitations to the current user's verified address
+///
+/// GET /api/invitations
+async fn list_my_invitations(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> ApiResult<HttpResponse> {
+    let user = current_user(&req, pool.get_ref()).await?;
+
+    // Until the address is proven, invitations are answered from their link
+    let invitations = if user.email_verified_at.is_some() {
+        InvitationService::open_for_email(pool.get_ref(), &user.email).await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(HttpResponse::Ok().json(json!({
+        "invitations": invitations,
+        "total": invitations.len(),
+        "email_verified": user.email_verified_at.is_some()
+    })))
+}
+
+/// Accept an invitation from its link as the signed-in user
+///
+/// POST /api/invitations/accept
+async fn accept_with_token(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<InvitationTokenRequest>,
+) -> ApiResult<HttpResponse> {
+    let user = current_user(&req, pool.get_ref()).await?;
+    let invitation = open_invitation(pool.get_ref(), &body.token).await?;
+    ensure_invitee(&user, &invitation)?;
+    ensure_domain_allowed(&team_settings(pool.get_ref(), invitation.team_id).await?, &invitation.email)?;
+
+    let mut tx = pool.begin().await?;
+    let accepted = InvitationService::accept(&mut tx, invitation.id, user.id)
+        .await?
+        .ok_or_else(|| ApiError::bad_request("Invalid or expired invitation"))?;
+
+    // Receiving the invitation also proves ownership of the address
+    sqlx::query("UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()) WHERE id = $1")
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    audit(pool.get_ref(), &req, user.id, AuditAction::TeamInvitationAccept, &accepted).await;
+
+    Ok(HttpResponse::Ok().json(accepted))
+}
+
+/// Accept an invitation to the current user's verified address
+///
+/// POST /api/invitations/{id}/accept
+async fn accept_invitation(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    let user = current_user(&req, pool.get_ref()).await?;
+    let invitation = invitation_for(pool.get_ref(), &user, path.into_inner()).await?;
+    ensure_domain_allowed(&team_settings(pool.get_ref(), invitation.team_id).await?, &invitation.email)?;
+
+    let mut tx = pool.begin().await?;
+    let accepted = InvitationService::accept(&mut tx, invitation.id, user.id)
+        .await?
+        .ok_or_else(|| ApiError::bad_request("Invitation has expired or was already answered"))?;
+    tx.commit().await?;
+
+    audit(pool.get_ref(), &req, user.id, AuditAction::TeamInvitationAccept, &accepted).await;
+
+    Ok(HttpResponse::Ok().json(accepted))
+}
+
+/// Decline an invitation to the current user's verified address
+///
+/// POST /api/invitations/{id}/decline
+async fn decline_invitation(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    let user = current_user(&req, pool.get_ref()).await?;
+    let invitation = invitation_for(pool.get_ref(), &user, path.into_inner()).await?;
+
+    let declined = InvitationService::decline(pool.get_ref(), invitation.id)
+        .await?
+        .ok_or_else(|| ApiError::bad_request("Invitation has expired or was already answered"))?;
+
+    audit(pool.get_ref(), &req, user.id, AuditAction::TeamInvitationDecline, &declined).await;
+
+    Ok(HttpResponse::Ok().json(declined))
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Record invitations accepted on the user's behalf when they proved their
+/// address, e.g. by registering from an invitation link
+pub(crate) async fn audit_accepted(pool: &PgPool, req: &HttpRequest, user_id: Uuid, accepted: &[Invitation]) {
+    for invitation in accepted {
+        audit(pool, req, user_id, AuditAction::TeamInvitationAccept, invitation).await;
+    }
+}
+
+async fn current_user(req: &HttpRequest, pool: &PgPool) -> ApiResult<User> {
+    let claims = get_claims(req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
+    claims.require_interactive()?;
+
+    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    user.ok_or_else(|| ApiError::unauthorized("User not found"))
+}
+
+async fn open_invitation(pool: &PgPool, token: &str) -> ApiResult<Invitation> {
+    InvitationService::find_by_token(pool, token)
+        .await?
+        .ok_or_else(|| ApiError::bad_request("Invalid or expired invitation"))
+}
+
+/// Open invitation `id` to the user's address, once they have proven it
+async fn invitation_for(pool: &PgPool, user: &User, id: Uuid) -> ApiResult<Invitation> {
+    if user.email_verified_at.is_none() {
+        return Err(ApiError::forbidden("Verify your email address, or open the invitation link, to answer invitations"));
+    }
+
+    let invitation = InvitationService::get(pool, id)
+        .await?
+        .filter(|invitation| invitation.email == user.email)
+        .ok_or_else(|| ApiError::not_found("Invitation not found"))?;
+    if !invitation.is_open() {
+        return Err(ApiError::bad_request("Invitation has expired or was already answered"));
+    }
+    Ok(invitation)
+}
+
+async fn team_settings(pool: &PgPool, team_id: Uuid) -> ApiResult<TeamSettings> {
+    TeamSettingsService::get(pool, team_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Team not found"))
+}
+
+/// Check the address against the team's allowed email domains, which may
+/// have changed since the invitation was sent
+fn ensure_domain_allowed(settings: &TeamSettings, email: &str) -> ApiResult<()> {
+    if !settings.allows_email(email) {
+        return Err(ApiError::forbidden(format!(
+            "This team only admits addresses at {}",
+            settings.allowed_email_domains.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+fn ensure_invitee(user: &User, invitation: &Invitation) -> ApiResult<()> {
+    if !user.email.eq_ignore_ascii_case(&invitation.email) {
+        return Err(ApiError::forbidden(format!(
+            "This invitation was sent to {}; sign in with that address to accept it",
+            invitation.email
+        )));
+    }
+    Ok(())
+}
+
+/// Email an invitation; a failure is logged and the invitation can be resent
+async fn send_invitation(mailer: &Mailer, invitation: &Invitation, token: &str) {
+    let inviter = invitation.invited_by_name.as_deref().unwrap_or("A teammate");
+    if let Err(e) = mailer.send_team_invitation(&invitation.email, &invitation.team_name, inviter, token).await {
+        log::warn!("Failed to send invitation {}: {}", invitation.id, e);
+    }
+}
+
+async fn audit(pool: &PgPool, req: &HttpRequest, user_id: Uuid, action: AuditAction, invitation: &Invitation) {
+    if let Err(e) = AuditService::log(pool, AuditEntry {
+        user_id: Some(user_id),
+        team_id: Some(invitation.team_id),
+        action: action.clone(),
+        resource_type: Some(ResourceType::Invitation),
+        resource_id: Some(invitation.id),
+        details: Some(json!({
+            "email": invitation.email,
+            "role": invitation.role
+        })),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: DeviceInfo::from_request(req).user_agent,
+    })
+    .await
+    {
+        log::warn!("Failed to audit {} for invitation {}: {}", action.as_str(), invitation.id, e);
+    }
+}
@@ -0,0 +1,287 @@
+//! Multi-factor authentication routes
+//!
+//! Enrollment and management of a TOTP authenticator for the signed-in user,
+//! and the second step of password login: `POST /api/auth/login` answers
+//! enrolled users with an MFA challenge token, which is exchanged here
+//! together with a code for the usual token pair.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::auth::generate_tokens;
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::auth::get_claims;
+use crate::middleware::AuthMiddleware;
+use crate::models::{AuthResponse, User};
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::auth::mfa::{MfaMethod, MfaService, TOTP_ISSUER};
+use crate::services::auth::sessions::DeviceInfo;
+use crate::services::auth::totp;
+use crate::services::rate_limit::RateLimiter;
+
+/// Configure MFA routes (mounted under `/auth`)
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/mfa")
+            .route("/challenge", web::post().to(answer_challenge))
+            .service(web::resource("").wrap(AuthMiddleware).route(web::get().to(status)))
+            .service(web::resource("/totp/enroll").wrap(AuthMiddleware).route(web::post().to(enroll)))
+            .service(web::resource("/totp/activate").wrap(AuthMiddleware).route(web::post().to(activate)))
+            .service(web::resource("/totp").wrap(AuthMiddleware).route(web::delete().to(disable)))
+            .service(
+                web::resource("/recovery-codes")
+                    .wrap(AuthMiddleware)
+                    .route(web::post().to(regenerate_recovery_codes)),
+            ),
+    );
+}
+
+/// Answer to a login challenge
+#[derive(Debug, Deserialize)]
+pub struct ChallengeRequest {
+    pub mfa_token: String,
+    pub code: String,
+}
+
+/// A TOTP or recovery code confirming a change
+#[derive(Debug, Deserialize)]
+pub struct CodeRequest {
+    pub code: String,
+}
+
+/// Complete a password login with a second factor
+///
+/// POST /api/auth/mfa/challenge
+async fn answer_challenge(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    limiter: Option<web::Data<RateLimiter>>,
+    body: web::Json<ChallengeRequest>,
+) -> ApiResult<HttpResponse> {
+    let user_id = MfaService::attempt_challenge(pool.get_ref(), &body.mfa_token)
+        .await?
+        .ok_or_else(|| ApiError::unauthorized("MFA challenge is invalid or expired; please sign in again"))?;
+
+    let user: User = sqlx::query_as("SELECT * FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(pool.get_ref())
+        .await?;
+
+    // Wrong codes count towards the same lockout as wrong passwords
+    if let Some(limiter) = &limiter {
+        if let Some(remaining) = limiter.login_locked(&user.email).await {
+            return Err(ApiError::RateLimitExceeded(remaining.as_secs_f64().ceil() as u64));
+        }
+    }
+
+    let Some(method) = MfaService::verify_code(pool.get_ref(), user_id, &body.code).await? else {
+        if let Some(limiter) = &limiter {
+            limiter.login_failed(&user.email).await;
+        }
+        return Err(ApiError::unauthorized("Invalid authentication code"));
+    };
+    MfaService::complete_challenge(pool.get_ref(), &body.mfa_token).await?;
+
+    if method == MfaMethod::RecoveryCode {
+        log::info!("User {} signed in with a recovery code", user_id);
+    }
+    if let Some(limiter) = &limiter {
+        limiter.login_succeeded(&user.email).await;
+    }
+
+    let (access_token, refresh_token, expires_in) =
+        generate_tokens(pool.get_ref(), &user, &DeviceInfo::from_request(&req)).await?;
+
+    Ok(HttpResponse::Ok().json(AuthResponse {
+        access_token,
+        refresh_token,
+        expires_in,
+        token_type: "Bearer".to_string(),
+        user: user.into(),
+    }))
+}
+
+/// MFA status of the current user, and the teams that require it
+///
+/// GET /api/auth/mfa
+async fn status(req: HttpRequest, pool: web::Data<PgPool>) -> ApiResult<HttpResponse> {
+    let user_id = current_user_id(&req)?;
+
+    let status = MfaService::status(pool.get_ref(), user_id).await?;
+    let required_by = MfaService::requiring_teams(pool.get_ref(), user_id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "enabled": status.enabled,
+        "recovery_codes_remaining": status.recovery_codes_remaining,
+        "required_by_teams": required_by
+    })))
+}
+
+/// Start TOTP enrollment
+///
+/// POST /api/auth/mfa/totp/enroll
+/// Returns the secret and an otpauth URI for the authenticator app; MFA is
+/// not active until confirmed with a code
+async fn enroll(req: HttpRequest, pool: web::Data<PgPool>) -> ApiResult<HttpResponse> {
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+    let user_id = current_user_id(&req)?;
+
+    let secret = MfaService::start_enrollment(pool.get_ref(), user_id)
+        .await?
+        .ok_or_else(|| ApiError::bad_request("MFA is already enabled; disable it before enrolling a new authenticator"))?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "otpauth_uri": totp::otpauth_uri(&secret, &claims.email, TOTP_ISSUER),
+        "secret": secret
+    })))
+}
+
+/// Confirm enrollment with a first code
+///
+/// POST /api/auth/mfa/totp/activate
+/// Returns recovery codes; they are shown only once
+async fn activate(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<CodeRequest>,
+) -> ApiResult<HttpResponse> {
+    let user_id = current_user_id(&req)?;
+
+    let recovery_codes = MfaService::activate(pool.get_ref(), user_id, &body.code)
+        .await?
+        .ok_or_else(|| ApiError::bad_request("Invalid code, or no enrollment in progress"))?;
+
+    audit(pool.get_ref(), &req, user_id, AuditAction::UserMfaEnable, None).await;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "enabled": true,
+        "recovery_codes": recovery_codes
+    })))
+}
+
+/// Turn off MFA
+///
+/// DELETE /api/auth/mfa/totp
+/// Requires a current code, and is refused while a team requires MFA
+async fn disable(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    limiter: Option<web::Data<RateLimiter>>,
+    body: web::Json<CodeRequest>,
+) -> ApiResult<HttpResponse> {
+    let user_id = current_user_id(&req)?;
+
+    let required_by = MfaService::requiring_teams(pool.get_ref(), user_id).await?;
+    if !required_by.is_empty() {
+        let names: Vec<&str> = required_by.iter().map(|t| t.name.as_str()).collect();
+        return Err(ApiError::forbidden(format!(
+            "MFA is required by: {}",
+            names.join(", ")
+        )));
+    }
+
+    confirm_code(pool.get_ref(), limiter.as_ref().map(|l| l.get_ref()), user_id, &body.code).await?;
+    MfaService::disable(pool.get_ref(), user_id).await?;
+
+    audit(pool.get_ref(), &req, user_id, AuditAction::UserMfaDisable, None).await;
+
+    Ok(HttpResponse::Ok().json(json!({ "enabled": false })))
+}
+
+/// Replace recovery codes
+///
+/// POST /api/auth/mfa/recovery-codes
+async fn regenerate_recovery_codes(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    limiter: Option<web::Data<RateLimiter>>,
+    body: web::Json<CodeRequest>,
+) -> ApiResult<HttpResponse> {
+    let user_id = current_user_id(&req)?;
+
+    confirm_code(pool.get_ref(), limiter.as_ref().map(|l| l.get_ref()), user_id, &body.code).await?;
+    let recovery_codes = MfaService::regenerate_recovery_codes(pool.get_ref(), user_id).await?;
+
+    audit(
+        pool.get_ref(),
+        &req,
+        user_id,
+        AuditAction::UserMfaEnable,
+        Some(json!({ "recovery_codes_regenerated": true })),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(json!({ "recovery_codes": recovery_codes })))
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+fn current_user_id(req: &HttpRequest) -> ApiResult<Uuid> {
+    let claims = get_claims(req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+    claims.require_interactive()?;
+
+    Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))
+}
+
+/// Check a code confirming a change; wrong codes count towards the same
+/// lockout as wrong passwords, so a stolen session cannot guess them
+async fn confirm_code(
+    pool: &PgPool,
+    limiter: Option<&RateLimiter>,
+    user_id: Uuid,
+    code: &str,
+) -> ApiResult<()> {
+    let (email,): (String,) = sqlx::query_as("SELECT email FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+    if let Some(limiter) = limiter {
+        if let Some(remaining) = limiter.login_locked(&email).await {
+            return Err(ApiError::RateLimitExceeded(remaining.as_secs_f64().ceil() as u64));
+        }
+    }
+
+    if MfaService::verify_code(pool, user_id, code).await?.is_none() {
+        if let Some(limiter) = limiter {
+            limiter.login_failed(&email).await;
+        }
+        return Err(ApiError::unauthorized("Invalid authentication code"));
+    }
+
+    if let Some(limiter) = limiter {
+        limiter.login_succeeded(&email).await;
+    }
+    Ok(())
+}
+
+async fn audit(
+    pool: &PgPool,
+    req: &HttpRequest,
+    user_id: Uuid,
+    action: AuditAction,
+    details: Option<serde_json::Value>,
+) {
+    if let Err(e) = AuditService::log(pool, AuditEntry {
+        user_id: Some(user_id),
+        team_id: None,
+        action: action.clone(),
+        resource_type: Some(ResourceType::User),
+        resource_id: Some(user_id),
+        details,
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: DeviceInfo::from_request(req).user_agent,
+    })
+    .await
+    {
+        log::warn!("Failed to audit {} for {}: {}", action.as_str(), user_id, e);
+    }
+}
@@ -1,7 +1,20 @@
 //! API Routes module
 
+pub mod account;
+pub mod admin;
+pub mod api_keys;
 pub mod auth;
+pub mod classification;
 pub mod files;
 pub mod health;
+pub mod invitations;
+pub mod mfa;
+pub mod oidc;
+pub mod permissions;
+pub mod roles;
+pub mod row_policies;
+pub mod saml;
+pub mod sharing;
 pub mod teams;
+pub mod usage;
 
@@ -0,0 +1,149 @@
+//! OpenID Connect Login Routes
+//!
+//! SPA-driven authorization-code flow: the client requests an authorization
+//! URL, sends the user to the provider, then posts the returned `code` and
+//! `state` back to exchange them for the standard PilotBA token pair.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::models::AuthResponse;
+use crate::routes::auth::{find_or_provision_user, generate_tokens, ExternalIdentity};
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::auth::oidc::{OidcClient, PkceChallenge};
+use crate::services::auth::random_token;
+use crate::services::auth::sessions::DeviceInfo;
+
+/// How long a started login may take to complete
+const LOGIN_STATE_TTL_MINUTES: i64 = 10;
+
+/// Configure OIDC routes (mounted under `/auth`)
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/oidc")
+            .route("/authorize", web::post().to(authorize))
+            .route("/callback", web::post().to(callback)),
+    );
+}
+
+/// Authorization URL for a new login
+#[derive(Debug, Serialize)]
+pub struct AuthorizeResponse {
+    pub authorization_url: String,
+    pub state: String,
+}
+
+/// Parameters returned by the provider to the redirect URI
+#[derive(Debug, Deserialize)]
+pub struct CallbackRequest {
+    pub code: String,
+    pub state: String,
+}
+
+/// Start an OIDC login
+///
+/// POST /api/auth/oidc/authorize
+async fn authorize(
+    pool: web::Data<PgPool>,
+    oidc: Option<web::Data<OidcClient>>,
+) -> ApiResult<HttpResponse> {
+    let client = oidc.ok_or_else(|| ApiError::not_found("OIDC login is not configured"))?;
+
+    let state = random_token(32);
+    let nonce = random_token(32);
+    let pkce = PkceChallenge::new();
+    let authorization_url = client.authorization_url(&state, &nonce, &pkce).await?;
+
+    // Drop abandoned logins
+    sqlx::query("DELETE FROM oidc_login_states WHERE expires_at < NOW()")
+        .execute(pool.get_ref())
+        .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO oidc_login_states (state, nonce, code_verifier, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#
+    )
+    .bind(&state)
+    .bind(&nonce)
+    .bind(&pkce.verifier)
+    .bind(chrono::Utc::now() + chrono::Duration::minutes(LOGIN_STATE_TTL_MINUTES))
+    .execute(pool.get_ref())
+    .await?;
+
+    Ok(HttpResponse::Ok().json(AuthorizeResponse { authorization_url, state }))
+}
+
+/// Complete an OIDC login and issue tokens
+///
+/// POST /api/auth/oidc/callback
+async fn callback(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    oidc: Option<web::Data<OidcClient>>,
+    body: web::Json<CallbackRequest>,
+) -> ApiResult<HttpResponse> {
+    let client = oidc.ok_or_else(|| ApiError::not_found("OIDC login is not configured"))?;
+
+    // Each state can be redeemed once
+    let login: Option<(String, String)> = sqlx::query_as(
+        r#"
+        DELETE FROM oidc_login_states
+        WHERE state = $1 AND expires_at > NOW()
+        RETURNING nonce, code_verifier
+        "#
+    )
+    .bind(&body.state)
+    .fetch_optional(pool.get_ref())
+    .await?;
+
+    let (nonce, code_verifier) =
+        login.ok_or_else(|| ApiError::unauthorized("Invalid or expired login state"))?;
+
+    let id_token = client.exchange_code(&body.code, &code_verifier).await?;
+    let claims = client.validate_id_token(&id_token, &nonce).await?;
+
+    let identity = ExternalIdentity {
+        provider: client.config().provider.clone(),
+        subject: claims.sub.clone(),
+        email: claims.verified_email(),
+        link_by_email: true,
+        name: claims.name.clone(),
+        role: None,
+    };
+    let (user, created) =
+        find_or_provision_user(pool.get_ref(), &identity, client.config().allow_signup).await?;
+
+    let (access_token, refresh_token, expires_in) = generate_tokens(pool.get_ref(), &user, &DeviceInfo::from_request(&req)).await?;
+
+    if let Err(e) = AuditService::log(pool.get_ref(), AuditEntry {
+        user_id: Some(user.id),
+        team_id: None,
+        action: if created { AuditAction::UserRegister } else { AuditAction::UserLogin },
+        resource_type: Some(ResourceType::User),
+        resource_id: Some(user.id),
+        details: Some(json!({ "method": "oidc", "provider": identity.provider })),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: req
+            .headers()
+            .get("User-Agent")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.to_string()),
+    })
+    .await
+    {
+        log::warn!("Failed to audit OIDC login for {}: {}", user.id, e);
+    }
+
+    Ok(HttpResponse::Ok().json(AuthResponse {
+        access_token,
+        refresh_token,
+        expires_in,
+        token_type: "Bearer".to_string(),
+        user: user.into(),
+    }))
+}
@@ -0,0 +1,251 @@
+//! Effective permissions routes
+//!
+//! What the caller may do, for clients deciding which actions to offer.
+//! Answers come from the same checks the other routes make, narrowed by the
+//! API key in use, but they are hints: every route still checks for itself.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::auth::get_claims;
+use crate::middleware::guard::{check_permission, check_team_permission};
+use crate::services::acl::AclService;
+use crate::services::permissions::{Permission, PermissionInfo, PermissionService, ResourceRef};
+
+/// Maximum number of checks in one request
+pub const MAX_CHECKS: usize = 100;
+
+/// Configure permission check routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/permissions/check", web::post().to(check_permissions));
+}
+
+/// Permission to check, on a resource or a team, or at system level when
+/// `resource` is omitted
+#[derive(Debug, Deserialize)]
+pub struct PermissionCheck {
+    pub permission: String,
+    pub resource: Option<ResourceRef>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CheckPermissionsRequest {
+    pub checks: Vec<PermissionCheck>,
+}
+
+/// Get the current user's effective permissions, at system level and in
+/// each of their teams
+///
+/// GET /api/auth/permissions
+pub async fn my_permissions(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> ApiResult<HttpResponse> {
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
+
+    let mut summary = PermissionService::summarize(pool.get_ref(), user_id).await?;
+
+    // An API key may narrow what the user's roles grant
+    let allowed = |name: &String| Permission::parse(name).is_some_and(|p| claims.allows(p));
+    summary.system_permissions.retain(allowed);
+    summary.team_permissions.retain(|team| claims.require_team(team.team_id).is_ok());
+    for team in &mut summary.team_permissions {
+        team.permissions.retain(allowed);
+    }
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+/// Check a batch of permissions, each at system level, in a team or on a
+/// file, dashboard or saved query
+///
+/// POST /api/permissions/check
+pub async fn check_permissions(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<CheckPermissionsRequest>,
+) -> ApiResult<HttpResponse> {
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
+
+    let checks = parse_checks(&body.checks)?;
+
+    let mut results = Vec::with_capacity(checks.len());
+    for (permission, resource) in checks {
+        let granted = claims.allows(permission)
+            && match resource {
+                None => check_permission(&req, pool.get_ref(), user_id, permission).await?,
+                Some(ResourceRef::Team(team_id)) => {
+                    claims.require_team(team_id).is_ok()
+                        && check_team_permission(&req, pool.get_ref(), user_id, team_id, permission).await?
+                }
+                // Dashboards and saved queries are not stored yet, so nothing
+                // is allowed on them
+                Some(resource) => match resource.kind() {
+                    Some((kind, id)) => match AclService::owner(pool.get_ref(), kind, id).await? {
+                        Some((_, team_id)) => {
+                            claims.reaches(team_id)
+                                && PermissionService::can_access_resource(pool.get_ref(), user_id, kind, id, permission)
+                                    .await?
+                        }
+                        None => false,
+                    },
+                    None => false,
+                },
+            };
+
+        results.push(PermissionInfo {
+            resource,
+            permission: permission.as_str().to_string(),
+            granted,
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "results": results })))
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Validate a batch before checking any of it, so a typo fails the request
+/// instead of reading as a denial
+fn parse_checks(checks: &[PermissionCheck]) -> ApiResult<Vec<(Permission, Option<ResourceRef>)>> {
+    if checks.is_empty() {
+        return Err(ApiError::bad_request("No checks given"));
+    }
+    if checks.len() > MAX_CHECKS {
+        return Err(ApiError::bad_request(format!("At most {} checks may be made at once", MAX_CHECKS)));
+    }
+
+    checks
+        .iter()
+        .map(|check| {
+            Permission::parse(&check.permission)
+                .map(|permission| (permission, check.resource))
+                .ok_or_else(|| ApiError::bad_request(format!("Unknown permission: {}", check.permission)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::auth::Claims;
+    use crate::services::auth::api_keys::{ApiKeyPrincipal, ApiKeyScope};
+    use crate::test_db::TestDb;
+    use actix_web::{test as actix_test, HttpMessage};
+
+    fn check(permission: &str, resource: Option<ResourceRef>) -> PermissionCheck {
+        PermissionCheck {
+            permission: permission.to_string(),
+            resource,
+        }
+    }
+
+    #[test]
+    fn test_parse_checks() {
+        let team = ResourceRef::Team(Uuid::new_v4());
+        let parsed = parse_checks(&[check("team:manage_members", Some(team)), check("admin:manage_users", None)]).unwrap();
+        assert_eq!(parsed, vec![(Permission::TeamManageMembers, Some(team)), (Permission::AdminManageUsers, None)]);
+
+        assert!(parse_checks(&[]).is_err());
+        assert!(parse_checks(&[check("dashboard:read", None), check("dashboard:teleport", None)]).is_err());
+
+        let too_many: Vec<_> = (0..=MAX_CHECKS).map(|_| check("dashboard:read", None)).collect();
+        assert!(parse_checks(&too_many).is_err());
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_check_resources_within_team_key() {
+        let db = TestDb::create().await;
+        let pool = &db.pool;
+
+        let (user_id,): (Uuid,) =
+            sqlx::query_as("INSERT INTO users (email, password_hash, name) VALUES ('etl@example.com', 'x', 'ETL') RETURNING id")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        let mut teams = Vec::new();
+        for slug in ["sales", "finance"] {
+            let (team_id,): (Uuid,) =
+                sqlx::query_as("INSERT INTO teams (name, slug, owner_id) VALUES ($1, $1, $2) RETURNING id")
+                    .bind(slug)
+                    .bind(user_id)
+                    .fetch_one(pool)
+                    .await
+                    .unwrap();
+            sqlx::query("INSERT INTO team_members (team_id, user_id, role) VALUES ($1, $2, 'owner')")
+                .bind(team_id)
+                .bind(user_id)
+                .execute(pool)
+                .await
+                .unwrap();
+            teams.push(team_id);
+        }
+        let mut files = Vec::new();
+        for team_id in [Some(teams[0]), Some(teams[1]), None] {
+            let (file_id,): (Uuid,) = sqlx::query_as(
+                r#"
+                INSERT INTO files (user_id, team_id, name, original_name, mime_type, size_bytes, storage_path)
+                VALUES ($1, $2, 'sales.csv', 'sales.csv', 'text/csv', 10, '/tmp/sales.csv')
+                RETURNING id
+                "#
+            )
+            .bind(user_id)
+            .bind(team_id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+            files.push(file_id);
+        }
+
+        let claims = Claims::for_api_key(ApiKeyPrincipal {
+            user_id,
+            email: "etl@example.com".to_string(),
+            name: "ETL".to_string(),
+            scope: ApiKeyScope {
+                key_id: Uuid::new_v4(),
+                team_id: Some(teams[0]),
+                permissions: None,
+                expires_at: None,
+            },
+        });
+        let req = actix_test::TestRequest::default().to_http_request();
+        req.extensions_mut().insert(claims);
+
+        let mut checks: Vec<_> = files.iter().map(|&id| check("dataset:read", Some(ResourceRef::File(id)))).collect();
+        checks.push(check("dataset:read", Some(ResourceRef::File(Uuid::new_v4()))));
+        checks.push(check("dashboard:read", Some(ResourceRef::Dashboard(Uuid::new_v4()))));
+        checks.push(check("query:read", Some(ResourceRef::Query(Uuid::new_v4()))));
+
+        let resp = check_permissions(req, web::Data::new(pool.clone()), web::Json(CheckPermissionsRequest { checks }))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+        let body: serde_json::Value =
+            serde_json::from_slice(&actix_web::body::to_bytes(resp.into_body()).await.unwrap()).unwrap();
+        let granted: Vec<bool> = body["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["granted"].as_bool().unwrap())
+            .collect();
+        // Only the key's own team's file, though its creator owns them all
+        assert_eq!(granted, [true, false, false, false, false, false]);
+
+        db.drop().await;
+    }
+}
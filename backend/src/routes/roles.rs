@@ -0,0 +1,344 @@
+//! Team role routes
+//!
+//! Custom roles and overrides of built-in roles under
+//! `/api/teams/{id}/roles`. Any member may list the team's roles; changing
+//! them requires `team:manage_roles`, and a role may only grant permissions
+//! the member changing it holds.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::auth::get_claims;
+use crate::middleware::guard::RequireTeamMember;
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::auth::sessions::DeviceInfo;
+use crate::services::permissions::{Permission, PermissionService};
+use crate::services::roles::{Role, RoleService, MAX_CUSTOM_ROLES_PER_TEAM, OVERRIDABLE_TEAM_ROLES};
+
+/// New custom role
+#[derive(Debug, Deserialize)]
+pub struct CreateRoleRequest {
+    pub name: String,
+    pub description: Option<String>,
+    /// Permission strings, e.g. `query:execute`
+    pub permissions: Vec<String>,
+}
+
+/// Changes to a custom role
+#[derive(Debug, Deserialize)]
+pub struct UpdateRoleRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub permissions: Option<Vec<String>>,
+}
+
+/// Permissions of a built-in role within a team
+#[derive(Debug, Deserialize)]
+pub struct OverrideRoleRequest {
+    pub permissions: Vec<String>,
+}
+
+/// List the team's built-in and custom roles
+///
+/// GET /api/teams/{id}/roles
+pub async fn list_roles(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    let team_id = path.into_inner();
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
+
+    claims.require_team(team_id)?;
+    if PermissionService::get_team_permissions(pool.get_ref(), user_id, team_id).await?.is_empty() {
+        return Err(ApiError::forbidden("You are not a member of this team"));
+    }
+
+    let roles = RoleService::list_team(pool.get_ref(), team_id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "roles": roles,
+        "total": roles.len()
+    })))
+}
+
+/// Define a custom role
+///
+/// POST /api/teams/{id}/roles
+pub async fn create_role(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<CreateRoleRequest>,
+) -> ApiResult<HttpResponse> {
+    let team_id = path.into_inner();
+    let (user_id, held) = role_manager(&req, pool.get_ref()).await?;
+
+    let name = validate_name(&body.name)?;
+    let permissions = validate_permissions(&body.permissions, &held)?;
+
+    if RoleService::count_custom(pool.get_ref(), team_id).await? >= MAX_CUSTOM_ROLES_PER_TEAM {
+        return Err(ApiError::bad_request(format!(
+            "A team may define at most {} custom roles",
+            MAX_CUSTOM_ROLES_PER_TEAM
+        )));
+    }
+    ensure_name_available(pool.get_ref(), team_id, name, None).await?;
+
+    let role = RoleService::create_custom(
+        pool.get_ref(),
+        team_id,
+        name,
+        body.description.as_deref(),
+        &permissions,
+        user_id,
+    )
+    .await?;
+
+    audit(pool.get_ref(), &req, user_id, AuditAction::TeamRoleCreate, &role).await;
+
+    Ok(HttpResponse::Created().json(role))
+}
+
+/// Update a custom role
+///
+/// PUT /api/teams/{id}/roles/{role_id}
+pub async fn update_role(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+    body: web::Json<UpdateRoleRequest>,
+) -> ApiResult<HttpResponse> {
+    let (team_id, role_id) = path.into_inner();
+    let (user_id, held) = role_manager(&req, pool.get_ref()).await?;
+
+    let existing = RoleService::get_custom(pool.get_ref(), team_id, role_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Role not found"))?;
+
+    // Members holding a role the caller could not grant are out of reach
+    if !crate::services::roles::parse_permissions(&existing.permissions).is_subset(&held) {
+        return Err(ApiError::forbidden("You cannot change a role with permissions you do not have"));
+    }
+
+    let name = body.name.as_deref().map(validate_name).transpose()?;
+    if let Some(name) = name {
+        ensure_name_available(pool.get_ref(), team_id, name, Some(role_id)).await?;
+    }
+    let permissions = body
+        .permissions
+        .as_ref()
+        .map(|p| validate_permissions(p, &held))
+        .transpose()?;
+
+    let role = RoleService::update_custom(
+        pool.get_ref(),
+        team_id,
+        role_id,
+        name,
+        body.description.as_deref(),
+        permissions.as_deref(),
+    )
+    .await?
+    .ok_or_else(|| ApiError::not_found("Role not found"))?;
+
+    audit(pool.get_ref(), &req, user_id, AuditAction::TeamRoleUpdate, &role).await;
+
+    Ok(HttpResponse::Ok().json(role))
+}
+
+/// Delete a custom role no member is assigned
+///
+/// DELETE /api/teams/{id}/roles/{role_id}
+pub async fn delete_role(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> ApiResult<HttpResponse> {
+    let (team_id, role_id) = path.into_inner();
+    let (user_id, _) = role_manager(&req, pool.get_ref()).await?;
+
+    let role = RoleService::get_custom(pool.get_ref(), team_id, role_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Role not found"))?;
+
+    // Falling back to their built-in role could grant members more than the
+    // custom role did, so they must be reassigned first
+    let assigned = RoleService::count_assigned(pool.get_ref(), role_id).await?;
+    if assigned > 0 {
+        return Err(ApiError::bad_request(format!(
+            "{} member(s) have this role; assign them another role first",
+            assigned
+        )));
+    }
+
+    if !RoleService::delete_custom(pool.get_ref(), team_id, role_id).await? {
+        return Err(ApiError::not_found("Role not found"));
+    }
+
+    audit(pool.get_ref(), &req, user_id, AuditAction::TeamRoleDelete, &role).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Override a built-in role's permissions within the team
+///
+/// PUT /api/teams/{id}/roles/built-in/{builtin}
+pub async fn override_builtin_role(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, String)>,
+    body: web::Json<OverrideRoleRequest>,
+) -> ApiResult<HttpResponse> {
+    let (team_id, builtin) = path.into_inner();
+    let builtin = validate_builtin(&builtin)?;
+    let (user_id, held) = role_manager(&req, pool.get_ref()).await?;
+
+    let permissions = validate_permissions(&body.permissions, &held)?;
+    let role = RoleService::set_override(pool.get_ref(), team_id, builtin, &permissions, user_id).await?;
+
+    audit(pool.get_ref(), &req, user_id, AuditAction::TeamRoleUpdate, &role).await;
+
+    Ok(HttpResponse::Ok().json(role))
+}
+
+/// Restore a built-in role's default permissions within the team
+///
+/// DELETE /api/teams/{id}/roles/built-in/{builtin}
+pub async fn reset_builtin_role(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, String)>,
+) -> ApiResult<HttpResponse> {
+    let (team_id, builtin) = path.into_inner();
+    let builtin = validate_builtin(&builtin)?;
+    let (user_id, _) = role_manager(&req, pool.get_ref()).await?;
+
+    let overridden = RoleService::list_team(pool.get_ref(), team_id)
+        .await?
+        .into_iter()
+        .find(|role| role.team_id.is_some() && role.builtin.as_deref() == Some(builtin));
+    let role = overridden.ok_or_else(|| ApiError::not_found("Role is not overridden in this team"))?;
+
+    RoleService::clear_override(pool.get_ref(), team_id, builtin).await?;
+
+    audit(pool.get_ref(), &req, user_id, AuditAction::TeamRoleDelete, &role).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Member allowed to manage the team's roles, with the permissions they hold
+async fn role_manager(req: &HttpRequest, pool: &PgPool) -> ApiResult<(Uuid, HashSet<Permission>)> {
+    let manager = RequireTeamMember::authorize(req, Some(Permission::TeamManageRoles)).await?;
+    let held = PermissionService::get_team_permissions(pool, manager.user_id, manager.team_id).await?;
+    Ok((manager.user_id, held))
+}
+
+fn validate_name(name: &str) -> ApiResult<&str> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 100 {
+        return Err(ApiError::bad_request("Role name must be 1-100 characters"));
+    }
+    Ok(name)
+}
+
+fn validate_builtin(builtin: &str) -> ApiResult<&'static str> {
+    OVERRIDABLE_TEAM_ROLES
+        .into_iter()
+        .find(|b| *b == builtin)
+        .ok_or_else(|| ApiError::bad_request(format!(
+            "Only the {} roles can be overridden",
+            OVERRIDABLE_TEAM_ROLES.join(", ")
+        )))
+}
+
+/// Parse requested permissions, each of which the caller must hold
+fn validate_permissions(requested: &[String], held: &HashSet<Permission>) -> ApiResult<Vec<Permission>> {
+    let mut permissions = Vec::with_capacity(requested.len());
+    for s in requested {
+        let permission = Permission::parse(s.trim())
+            .ok_or_else(|| ApiError::bad_request(format!("Unknown permission: {}", s)))?;
+        if !held.contains(&permission) {
+            return Err(ApiError::forbidden(format!(
+                "You cannot grant {}, which you do not have",
+                permission.as_str()
+            )));
+        }
+        if !permissions.contains(&permission) {
+            permissions.push(permission);
+        }
+    }
+    Ok(permissions)
+}
+
+async fn ensure_name_available(pool: &PgPool, team_id: Uuid, name: &str, except: Option<Uuid>) -> ApiResult<()> {
+    let taken = RoleService::list_team(pool, team_id).await?.into_iter().any(|role| {
+        role.name.eq_ignore_ascii_case(name) && role.builtin.is_none() && Some(role.id) != except
+    });
+    if taken {
+        return Err(ApiError::bad_request("A role with this name already exists in this team"));
+    }
+    Ok(())
+}
+
+async fn audit(pool: &PgPool, req: &HttpRequest, user_id: Uuid, action: AuditAction, role: &Role) {
+    if let Err(e) = AuditService::log(pool, AuditEntry {
+        user_id: Some(user_id),
+        team_id: role.team_id,
+        action: action.clone(),
+        resource_type: Some(ResourceType::Role),
+        resource_id: Some(role.id),
+        details: Some(json!({
+            "name": role.name,
+            "builtin": role.builtin,
+            "permissions": role.permissions
+        })),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: DeviceInfo::from_request(req).user_agent,
+    })
+    .await
+    {
+        log::warn!("Failed to audit {} for {}: {}", action.as_str(), role.id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_permissions() {
+        let held: HashSet<Permission> = [Permission::QueryExecute, Permission::DatasetRead].into_iter().collect();
+
+        let permissions = validate_permissions(
+            &["query:execute".to_string(), "query:execute".to_string(), " dataset:read".to_string()],
+            &held,
+        )
+        .unwrap();
+        assert_eq!(permissions, vec![Permission::QueryExecute, Permission::DatasetRead]);
+
+        assert!(validate_permissions(&[], &held).unwrap().is_empty());
+        assert!(validate_permissions(&["dataset:upload".to_string()], &held).is_err());
+        assert!(validate_permissions(&["query:teleport".to_string()], &held).is_err());
+    }
+
+    #[test]
+    fn test_validate_builtin() {
+        assert_eq!(validate_builtin("member").unwrap(), "member");
+        assert!(validate_builtin("owner").is_err());
+        assert!(validate_builtin("superuser").is_err());
+    }
+}
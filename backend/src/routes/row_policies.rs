@@ -0,0 +1,339 @@
+//! Row-level security routes
+//!
+//! Policies on a file under `/api/files/{id}/row-policies`. Managing them is
+//! limited to the file's owner and, for team files, members holding
+//! `dataset:share` in the team; a share grant on the file is not enough, so
+//! a user cannot lift the policies that restrict them.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use super::files::{encode_json_rows, managed_file, DataView, FileRecord};
+use crate::connectors::{read_file, FileFormat};
+use crate::errors::{ApiError, ApiResult};
+use crate::services::acl::ResourceKind;
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::auth::sessions::DeviceInfo;
+use crate::services::masking::MaskingService;
+use crate::services::permissions::{Permission, PermissionService};
+use crate::services::query_engine::{QueryEngine, QuerySpec};
+use crate::services::row_security::{
+    validate_template, RowPolicy, RowPolicyInput, RowSecurityService, MAX_POLICIES_PER_FILE,
+};
+
+/// Default number of rows returned when testing policies
+const DEFAULT_TEST_ROWS: usize = 20;
+
+/// Maximum number of rows returned when testing policies
+const MAX_TEST_ROWS: usize = 100;
+
+/// User to view a file as; name exactly one of `user_id` or `email`
+#[derive(Debug, Deserialize)]
+pub struct TestPoliciesRequest {
+    pub user_id: Option<Uuid>,
+    pub email: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// List the row-level security policies on a file
+///
+/// GET /api/files/{id}/row-policies
+pub async fn list_policies(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    let (_, record) = managed_file(&req, pool.get_ref(), path.into_inner()).await?;
+
+    let policies = RowSecurityService::list(pool.get_ref(), record.id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "policies": policies,
+        "total": policies.len()
+    })))
+}
+
+/// Add a row-level security policy to a file
+///
+/// POST /api/files/{id}/row-policies
+pub async fn create_policy(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<RowPolicyInput>,
+) -> ApiResult<HttpResponse> {
+    let (user_id, record) = managed_file(&req, pool.get_ref(), path.into_inner()).await?;
+    let body = body.into_inner();
+
+    validate_policy(&body)?;
+    if RowSecurityService::count(pool.get_ref(), record.id).await? >= MAX_POLICIES_PER_FILE {
+        return Err(ApiError::bad_request(format!(
+            "A file may have at most {} row policies",
+            MAX_POLICIES_PER_FILE
+        )));
+    }
+    ensure_name_available(pool.get_ref(), record.id, &body.name, None).await?;
+
+    let policy = RowSecurityService::create(pool.get_ref(), record.id, &body, user_id).await?;
+
+    audit(pool.get_ref(), &req, user_id, &record, AuditAction::RowPolicyCreate, policy.id, policy_details(&policy)).await;
+
+    Ok(HttpResponse::Created().json(policy))
+}
+
+/// Replace a row-level security policy
+///
+/// PUT /api/files/{id}/row-policies/{policy_id}
+pub async fn update_policy(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+    body: web::Json<RowPolicyInput>,
+) -> ApiResult<HttpResponse> {
+    let (file_id, policy_id) = path.into_inner();
+    let (user_id, record) = managed_file(&req, pool.get_ref(), file_id).await?;
+    let body = body.into_inner();
+
+    validate_policy(&body)?;
+    ensure_name_available(pool.get_ref(), record.id, &body.name, Some(policy_id)).await?;
+
+    let previous = RowSecurityService::get(pool.get_ref(), record.id, policy_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Policy not found"))?;
+    let policy = RowSecurityService::update(pool.get_ref(), record.id, policy_id, &body)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Policy not found"))?;
+
+    audit(
+        pool.get_ref(),
+        &req,
+        user_id,
+        &record,
+        AuditAction::RowPolicyUpdate,
+        policy.id,
+        json!({ "before": policy_details(&previous), "after": policy_details(&policy) }),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(policy))
+}
+
+/// Delete a row-level security policy
+///
+/// DELETE /api/files/{id}/row-policies/{policy_id}
+pub async fn delete_policy(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> ApiResult<HttpResponse> {
+    let (file_id, policy_id) = path.into_inner();
+    let (user_id, record) = managed_file(&req, pool.get_ref(), file_id).await?;
+
+    let policy = RowSecurityService::get(pool.get_ref(), record.id, policy_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Policy not found"))?;
+    RowSecurityService::delete(pool.get_ref(), record.id, policy_id).await?;
+
+    audit(pool.get_ref(), &req, user_id, &record, AuditAction::RowPolicyDelete, policy.id, policy_details(&policy)).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// View a file as another user: the policies applying to them, with their
+/// values substituted, the columns masked for them and the first rows they
+/// would see
+///
+/// POST /api/files/{id}/row-policies/test
+pub async fn test_policies(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<TestPoliciesRequest>,
+) -> ApiResult<HttpResponse> {
+    let (user_id, record) = managed_file(&req, pool.get_ref(), path.into_inner()).await?;
+
+    let target: Option<(Uuid, String)> = match (body.user_id, body.email.as_deref()) {
+        (Some(id), None) => sqlx::query_as("SELECT id, email FROM users WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool.get_ref())
+            .await?,
+        (None, Some(email)) => sqlx::query_as("SELECT id, email FROM users WHERE email = $1")
+            .bind(email.trim().to_lowercase())
+            .fetch_optional(pool.get_ref())
+            .await?,
+        _ => return Err(ApiError::bad_request("Specify exactly one of user_id or email")),
+    };
+    let (target_id, target_email) = target.ok_or_else(|| ApiError::not_found("User not found"))?;
+
+    let has_access = target_id == record.user_id
+        || PermissionService::can_access_resource(
+            pool.get_ref(),
+            target_id,
+            ResourceKind::File,
+            record.id,
+            Permission::DatasetRead,
+        )
+        .await?;
+    let unmasked = MaskingService::can_unmask(pool.get_ref(), target_id, record.user_id, record.team_id).await?;
+    let view = DataView {
+        rows: RowSecurityService::access(pool.get_ref(), record.id, record.user_id, record.team_id, target_id).await?,
+        masks: if unmasked {
+            Vec::new()
+        } else {
+            MaskingService::masks(pool.get_ref(), record.id).await?
+        },
+        unclassified: !unmasked && !record.is_classified(),
+    };
+
+    let source_format = FileFormat::from_path(std::path::Path::new(&record.name))
+        .ok_or_else(|| ApiError::UnsupportedMediaType("File format cannot be previewed".to_string()))?;
+    let storage_path = PathBuf::from(&record.storage_path);
+    let read_options = record.read_options();
+    let spec = QuerySpec {
+        limit: Some(if has_access { body.limit.unwrap_or(DEFAULT_TEST_ROWS).clamp(1, MAX_TEST_ROWS) } else { 0 }),
+        ..Default::default()
+    };
+    let restricted = view.rows.filter.is_restricted();
+    let policies = view.rows.applied.clone();
+    let masks = view.masks.clone();
+
+    let dataset = web::block(move || -> ApiResult<_> {
+        let dataset = view.apply(&read_file(&storage_path, source_format, &read_options)?)?;
+        Ok(QueryEngine::execute(&dataset, &spec)?)
+    })
+    .await
+    .map_err(|e| ApiError::internal(format!("Preview task failed: {}", e)))??;
+    let rows = encode_json_rows(&dataset)
+        .map_err(|e| ApiError::internal(format!("Failed to encode preview: {}", e)))?;
+
+    audit(
+        pool.get_ref(),
+        &req,
+        user_id,
+        &record,
+        AuditAction::RowPolicyTest,
+        record.id,
+        json!({ "as_user_id": target_id }),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "user_id": target_id,
+        "email": target_email,
+        "has_access": has_access,
+        "restricted": restricted,
+        "policies": policies,
+        "masked_columns": masks,
+        "row_count": rows.len(),
+        "rows": rows
+    })))
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+fn validate_policy(policy: &RowPolicyInput) -> ApiResult<()> {
+    let name = policy.name.trim();
+    if name.is_empty() || name.chars().count() > 100 {
+        return Err(ApiError::bad_request("Policy name must be 1-100 characters"));
+    }
+    if policy.filters.is_empty() {
+        return Err(ApiError::bad_request("A policy needs at least one filter"));
+    }
+    if policy.applies_to_roles.as_ref().is_some_and(|roles| roles.is_empty()) {
+        return Err(ApiError::bad_request("applies_to_roles must name at least one role; omit it for everyone"));
+    }
+    validate_template(&policy.filters).map_err(|e| ApiError::bad_request(e.to_string()))
+}
+
+async fn ensure_name_available(pool: &PgPool, file_id: Uuid, name: &str, except: Option<Uuid>) -> ApiResult<()> {
+    let taken = RowSecurityService::list(pool, file_id)
+        .await?
+        .into_iter()
+        .any(|policy| policy.name.eq_ignore_ascii_case(name.trim()) && Some(policy.id) != except);
+    if taken {
+        return Err(ApiError::bad_request("A policy with this name already exists on this file"));
+    }
+    Ok(())
+}
+
+fn policy_details(policy: &RowPolicy) -> serde_json::Value {
+    json!({
+        "name": policy.name,
+        "filters": policy.filters,
+        "applies_to_roles": policy.applies_to_roles,
+        "enabled": policy.enabled
+    })
+}
+
+async fn audit(
+    pool: &PgPool,
+    req: &HttpRequest,
+    user_id: Uuid,
+    record: &FileRecord,
+    action: AuditAction,
+    resource_id: Uuid,
+    mut details: serde_json::Value,
+) {
+    if let Some(details) = details.as_object_mut() {
+        details.insert("file_id".to_string(), json!(record.id));
+    }
+    let resource_type = match action {
+        AuditAction::RowPolicyTest => ResourceType::File,
+        _ => ResourceType::RowPolicy,
+    };
+
+    if let Err(e) = AuditService::log(pool, AuditEntry {
+        user_id: Some(user_id),
+        team_id: record.team_id,
+        action: action.clone(),
+        resource_type: Some(resource_type),
+        resource_id: Some(resource_id),
+        details: Some(details),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: DeviceInfo::from_request(req).user_agent,
+    })
+    .await
+    {
+        log::warn!("Failed to audit {} on file {}: {}", action.as_str(), record.id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::query_engine::{Filter, FilterOperator};
+
+    fn input(name: &str, filters: Vec<Filter>, applies_to_roles: Option<Vec<String>>) -> RowPolicyInput {
+        RowPolicyInput {
+            name: name.to_string(),
+            description: None,
+            filters,
+            applies_to_roles,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_validate_policy() {
+        let filter = Filter {
+            column: "region".to_string(),
+            operator: FilterOperator::Eq,
+            value: json!("{{user.attributes.region}}"),
+            value2: None,
+        };
+
+        assert!(validate_policy(&input("By region", vec![filter.clone()], None)).is_ok());
+        assert!(validate_policy(&input("  ", vec![filter.clone()], None)).is_err());
+        assert!(validate_policy(&input("Empty", vec![], None)).is_err());
+        assert!(validate_policy(&input("No roles", vec![filter.clone()], Some(vec![]))).is_err());
+
+        let unknown = Filter { value: json!("{{user.password_hash}}"), ..filter };
+        assert!(validate_policy(&input("Unknown", vec![unknown], None)).is_err());
+    }
+}
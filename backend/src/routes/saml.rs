@@ -0,0 +1,654 @@
+//! SAML Login Routes
+//!
+//! SP-initiated SAML 2.0 single sign-on. Identity providers are configured
+//! per team and can additionally be routed by email domain. The client starts
+//! a login for a team or an email address and follows the returned redirect;
+//! the IdP posts its response to the Assertion Consumer Service, which
+//! redirects the browser to the frontend with a short-lived, single-use code.
+//! The frontend exchanges the code for the standard PilotBA token pair, so
+//! tokens never appear in the page answering the IdP's form post.
+//!
+//! New accounts are only provisioned for addresses in the email domains
+//! routed to the provider; those are verified by a system administrator, so a
+//! team's IdP cannot mint accounts for arbitrary addresses.
+//!
+//! Team owners manage their team's provider. Routing email domains, changing
+//! which IdP a routed provider trusts, and mapping IdP roles to the system
+//! `Admin` role additionally require system administrator rights, since each
+//! lets the IdP vouch for accounts outside the team.
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::auth::get_claims;
+use crate::models::{AuthResponse, TeamRole, User, UserRole};
+use crate::routes::auth::{ensure_active, find_or_provision_user, generate_tokens, ExternalIdentity};
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::auth::{random_token, sha256_hex};
+use crate::services::auth::saml::{AttributeMapping, IdentityProvider, ServiceProvider};
+use crate::services::auth::sessions::DeviceInfo;
+use crate::services::permissions::{Permission, PermissionService};
+
+/// How long a started login may take to complete
+const LOGIN_STATE_TTL_MINUTES: i64 = 10;
+
+/// How long the code handed to the frontend can be exchanged for tokens
+const LOGIN_CODE_TTL_SECONDS: i64 = 60;
+
+/// Configure SAML login routes (mounted under `/auth`)
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/saml")
+            .route("/metadata", web::get().to(metadata))
+            .route("/login", web::post().to(login))
+            .route("/acs", web::post().to(acs))
+            .route("/token", web::post().to(exchange_code)),
+    );
+}
+
+/// Stored identity provider configuration
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct SamlProviderRecord {
+    pub id: Uuid,
+    pub team_id: Uuid,
+    pub idp_entity_id: String,
+    pub sso_url: String,
+    pub certificate: String,
+    pub domains: Vec<String>,
+    pub attribute_mapping: sqlx::types::Json<AttributeMapping>,
+    pub allow_signup: bool,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SamlProviderRecord {
+    fn identity_provider(&self) -> ApiResult<IdentityProvider> {
+        Ok(IdentityProvider::new(&self.idp_entity_id, &self.sso_url, &self.certificate)?)
+    }
+
+    /// Provider name recorded on linked identities
+    fn provider_name(&self) -> String {
+        format!("saml:{}", self.id)
+    }
+}
+
+/// Request body for creating or replacing a team's identity provider
+#[derive(Debug, Deserialize)]
+pub struct SamlProviderRequest {
+    pub idp_entity_id: String,
+    pub sso_url: String,
+    /// PEM signing certificate of the IdP
+    pub certificate: String,
+    /// Email domains routed to this IdP (kept when omitted)
+    pub domains: Option<Vec<String>>,
+    #[serde(default)]
+    pub attribute_mapping: AttributeMapping,
+    #[serde(default = "default_true")]
+    pub allow_signup: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Request body for starting a login, by team or by email domain
+#[derive(Debug, Deserialize)]
+pub struct SamlLoginRequest {
+    pub team_id: Option<Uuid>,
+    pub email: Option<String>,
+}
+
+/// Redirect to the identity provider for a new login
+#[derive(Debug, Serialize)]
+pub struct SamlLoginResponse {
+    pub redirect_url: String,
+    pub relay_state: String,
+}
+
+/// HTTP-POST binding form posted by the IdP
+#[derive(Debug, Deserialize)]
+pub struct AcsForm {
+    #[serde(rename = "SAMLResponse")]
+    pub saml_response: String,
+    #[serde(rename = "RelayState")]
+    pub relay_state: String,
+}
+
+/// Request body for exchanging a login code for tokens
+#[derive(Debug, Deserialize)]
+pub struct SamlCodeRequest {
+    pub code: String,
+}
+
+/// Service provider metadata
+///
+/// GET /api/auth/saml/metadata
+async fn metadata(sp: Option<web::Data<ServiceProvider>>) -> ApiResult<HttpResponse> {
+    let sp = sp.ok_or_else(|| ApiError::not_found("SAML login is not configured"))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/samlmetadata+xml")
+        .body(sp.metadata_xml()))
+}
+
+/// Start a SAML login
+///
+/// POST /api/auth/saml/login
+async fn login(
+    pool: web::Data<PgPool>,
+    sp: Option<web::Data<ServiceProvider>>,
+    body: web::Json<SamlLoginRequest>,
+) -> ApiResult<HttpResponse> {
+    let sp = sp.ok_or_else(|| ApiError::not_found("SAML login is not configured"))?;
+
+    let provider: Option<SamlProviderRecord> = match (&body.team_id, &body.email) {
+        (Some(team_id), _) => {
+            sqlx::query_as("SELECT * FROM saml_providers WHERE team_id = $1 AND enabled")
+                .bind(team_id)
+                .fetch_optional(pool.get_ref())
+                .await?
+        }
+        (None, Some(email)) => {
+            let domain = email_domain(email)
+                .ok_or_else(|| ApiError::bad_request("Invalid email format"))?;
+            sqlx::query_as("SELECT * FROM saml_providers WHERE $1 = ANY(domains) AND enabled")
+                .bind(&domain)
+                .fetch_optional(pool.get_ref())
+                .await?
+        }
+        (None, None) => return Err(ApiError::bad_request("team_id or email is required")),
+    };
+    let provider =
+        provider.ok_or_else(|| ApiError::not_found("No SAML identity provider is configured"))?;
+
+    let relay_state = random_token(32);
+    let request = sp.authn_request(&provider.identity_provider()?, &relay_state)?;
+
+    // Drop abandoned logins
+    sqlx::query("DELETE FROM saml_login_states WHERE expires_at < NOW()")
+        .execute(pool.get_ref())
+        .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO saml_login_states (relay_state, request_id, provider_id, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#
+    )
+    .bind(&relay_state)
+    .bind(&request.id)
+    .bind(provider.id)
+    .bind(Utc::now() + chrono::Duration::minutes(LOGIN_STATE_TTL_MINUTES))
+    .execute(pool.get_ref())
+    .await?;
+
+    Ok(HttpResponse::Ok().json(SamlLoginResponse {
+        redirect_url: request.redirect_url,
+        relay_state,
+    }))
+}
+
+/// Assertion Consumer Service: validate the IdP response and redirect to the
+/// frontend with a one-time login code
+///
+/// POST /api/auth/saml/acs
+async fn acs(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    sp: Option<web::Data<ServiceProvider>>,
+    form: web::Form<AcsForm>,
+) -> ApiResult<HttpResponse> {
+    let sp = sp.ok_or_else(|| ApiError::not_found("SAML login is not configured"))?;
+
+    // Each login can be completed once
+    let login: Option<(String, Uuid)> = sqlx::query_as(
+        r#"
+        DELETE FROM saml_login_states
+        WHERE relay_state = $1 AND expires_at > NOW()
+        RETURNING request_id, provider_id
+        "#
+    )
+    .bind(&form.relay_state)
+    .fetch_optional(pool.get_ref())
+    .await?;
+
+    let (request_id, provider_id) =
+        login.ok_or_else(|| ApiError::unauthorized("Invalid or expired login state"))?;
+
+    let provider: SamlProviderRecord =
+        sqlx::query_as("SELECT * FROM saml_providers WHERE id = $1 AND enabled")
+            .bind(provider_id)
+            .fetch_optional(pool.get_ref())
+            .await?
+            .ok_or_else(|| ApiError::unauthorized("SAML identity provider is disabled"))?;
+
+    let assertion = sp.validate_response(
+        &provider.identity_provider()?,
+        &form.saml_response,
+        &request_id,
+        Utc::now(),
+    )?;
+
+    let mapping = &provider.attribute_mapping.0;
+    let email = mapping.email(&assertion);
+
+    // A provider may only assert addresses in the domains routed to it
+    let domain_verified = match email.as_deref().and_then(email_domain) {
+        Some(domain) if provider.domains.contains(&domain) => true,
+        Some(_) if !provider.domains.is_empty() => {
+            return Err(ApiError::forbidden(
+                "Email domain is not managed by this identity provider",
+            ))
+        }
+        _ => false,
+    };
+
+    let identity = ExternalIdentity {
+        provider: provider.provider_name(),
+        subject: assertion.name_id.clone(),
+        email,
+        link_by_email: domain_verified,
+        name: mapping.name(&assertion),
+        role: mapping.role(&assertion),
+    };
+    // Only addresses in verified domains may get new accounts
+    let allow_signup = provider.allow_signup && domain_verified;
+    let (user, created) = find_or_provision_user(pool.get_ref(), &identity, allow_signup).await?;
+
+    // Users signing in through a team's IdP belong to that team
+    sqlx::query(
+        r#"
+        INSERT INTO team_members (team_id, user_id, role)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (team_id, user_id) DO NOTHING
+        "#
+    )
+    .bind(provider.team_id)
+    .bind(user.id)
+    .bind(TeamRole::Member)
+    .execute(pool.get_ref())
+    .await?;
+
+    ensure_active(&user)?;
+
+    sqlx::query("DELETE FROM saml_login_codes WHERE expires_at < NOW()")
+        .execute(pool.get_ref())
+        .await?;
+
+    let code = random_token(32);
+    sqlx::query("INSERT INTO saml_login_codes (code_hash, user_id, expires_at) VALUES ($1, $2, $3)")
+        .bind(sha256_hex(&code))
+        .bind(user.id)
+        .bind(Utc::now() + chrono::Duration::seconds(LOGIN_CODE_TTL_SECONDS))
+        .execute(pool.get_ref())
+        .await?;
+
+    if let Err(e) = AuditService::log(pool.get_ref(), AuditEntry {
+        user_id: Some(user.id),
+        team_id: Some(provider.team_id),
+        action: if created { AuditAction::UserRegister } else { AuditAction::UserLogin },
+        resource_type: Some(ResourceType::User),
+        resource_id: Some(user.id),
+        details: Some(json!({ "method": "saml", "provider_id": provider.id })),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: req
+            .headers()
+            .get("User-Agent")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.to_string()),
+    })
+    .await
+    {
+        log::warn!("Failed to audit SAML login for {}: {}", user.id, e);
+    }
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, sp.login_redirect(&code)?))
+        .finish())
+}
+
+/// Exchange a login code from the Assertion Consumer Service for tokens
+///
+/// POST /api/auth/saml/token
+async fn exchange_code(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    body: web::Json<SamlCodeRequest>,
+) -> ApiResult<HttpResponse> {
+    // Each code can be exchanged once
+    let user: Option<User> = sqlx::query_as(
+        r#"
+        WITH code AS (
+            DELETE FROM saml_login_codes
+            WHERE code_hash = $1 AND expires_at > NOW()
+            RETURNING user_id
+        )
+        SELECT u.* FROM users u JOIN code ON code.user_id = u.id
+        "#
+    )
+    .bind(sha256_hex(body.code.trim()))
+    .fetch_optional(pool.get_ref())
+    .await?;
+    let user = user.ok_or_else(|| ApiError::unauthorized("Invalid or expired login code"))?;
+
+    let (access_token, refresh_token, expires_in) =
+        generate_tokens(pool.get_ref(), &user, &DeviceInfo::from_request(&req)).await?;
+
+    Ok(HttpResponse::Ok().json(AuthResponse {
+        access_token,
+        refresh_token,
+        expires_in,
+        token_type: "Bearer".to_string(),
+        user: user.into(),
+    }))
+}
+
+// ============================================================================
+// TEAM PROVIDER MANAGEMENT
+// ============================================================================
+
+/// Get a team's SAML identity provider
+///
+/// GET /api/teams/{id}/saml
+pub async fn get_team_provider(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    let team_id = path.into_inner();
+    require_team_settings(&req, pool.get_ref(), team_id).await?;
+
+    let provider: SamlProviderRecord =
+        sqlx::query_as("SELECT * FROM saml_providers WHERE team_id = $1")
+            .bind(team_id)
+            .fetch_optional(pool.get_ref())
+            .await?
+            .ok_or_else(|| ApiError::not_found("No SAML identity provider is configured"))?;
+
+    Ok(HttpResponse::Ok().json(provider))
+}
+
+/// Create or replace a team's SAML identity provider
+///
+/// PUT /api/teams/{id}/saml
+pub async fn put_team_provider(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<SamlProviderRequest>,
+) -> ApiResult<HttpResponse> {
+    let team_id = path.into_inner();
+    let user_id = require_team_settings(&req, pool.get_ref(), team_id).await?;
+
+    // Parses the certificate and SSO URL
+    IdentityProvider::new(&body.idp_entity_id, &body.sso_url, &body.certificate)?;
+    if body.idp_entity_id.trim().is_empty() {
+        return Err(ApiError::bad_request("idp_entity_id is required"));
+    }
+
+    let existing: Option<SamlProviderRecord> =
+        sqlx::query_as("SELECT * FROM saml_providers WHERE team_id = $1")
+            .bind(team_id)
+            .fetch_optional(pool.get_ref())
+            .await?;
+    // Routed domains link existing accounts, so whoever picks the IdP they
+    // trust can sign in as those users
+    let idp_changed = existing.as_ref().is_some_and(|p| {
+        p.idp_entity_id != body.idp_entity_id.trim() || p.sso_url != body.sso_url || p.certificate != body.certificate
+    });
+    let current_domains = existing.map(|p| p.domains).unwrap_or_default();
+
+    let domains = match &body.domains {
+        Some(domains) => normalize_domains(domains)?,
+        None => current_domains.clone(),
+    };
+
+    let grants_admin = body.attribute_mapping.roles.values().any(|r| *r == UserRole::Admin);
+    if (domains != current_domains || (idp_changed && !domains.is_empty()) || grants_admin)
+        && !PermissionService::has_permission(pool.get_ref(), user_id, Permission::AdminManageTeams).await?
+    {
+        return Err(ApiError::forbidden(
+            "Only system administrators can route email domains, change the identity provider they trust, or grant the admin role",
+        ));
+    }
+
+    let claimed: Option<(Uuid,)> = sqlx::query_as(
+        "SELECT team_id FROM saml_providers WHERE domains && $1 AND team_id <> $2"
+    )
+    .bind(&domains)
+    .bind(team_id)
+    .fetch_optional(pool.get_ref())
+    .await?;
+    if claimed.is_some() {
+        return Err(ApiError::bad_request("A domain is already routed to another identity provider"));
+    }
+
+    let provider: SamlProviderRecord = sqlx::query_as(
+        r#"
+        INSERT INTO saml_providers
+            (team_id, idp_entity_id, sso_url, certificate, domains, attribute_mapping, allow_signup, enabled)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (team_id) DO UPDATE SET
+            idp_entity_id = EXCLUDED.idp_entity_id,
+            sso_url = EXCLUDED.sso_url,
+            certificate = EXCLUDED.certificate,
+            domains = EXCLUDED.domains,
+            attribute_mapping = EXCLUDED.attribute_mapping,
+            allow_signup = EXCLUDED.allow_signup,
+            enabled = EXCLUDED.enabled
+        RETURNING *
+        "#
+    )
+    .bind(team_id)
+    .bind(body.idp_entity_id.trim())
+    .bind(&body.sso_url)
+    .bind(&body.certificate)
+    .bind(&domains)
+    .bind(sqlx::types::Json(&body.attribute_mapping))
+    .bind(body.allow_signup)
+    .bind(body.enabled)
+    .fetch_one(pool.get_ref())
+    .await?;
+
+    if let Err(e) = AuditService::log(pool.get_ref(), AuditEntry {
+        user_id: Some(user_id),
+        team_id: Some(team_id),
+        action: AuditAction::TeamUpdate,
+        resource_type: Some(ResourceType::Settings),
+        resource_id: Some(provider.id),
+        details: Some(json!({ "saml_provider": provider.idp_entity_id, "domains": provider.domains })),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: None,
+    })
+    .await
+    {
+        log::warn!("Failed to audit SAML provider change for team {}: {}", team_id, e);
+    }
+
+    Ok(HttpResponse::Ok().json(provider))
+}
+
+/// Remove a team's SAML identity provider
+///
+/// DELETE /api/teams/{id}/saml
+pub async fn delete_team_provider(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    let team_id = path.into_inner();
+    require_team_settings(&req, pool.get_ref(), team_id).await?;
+
+    let result = sqlx::query("DELETE FROM saml_providers WHERE team_id = $1")
+        .bind(team_id)
+        .execute(pool.get_ref())
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::not_found("No SAML identity provider is configured"));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+/// Authenticated user allowed to manage the team's settings
+async fn require_team_settings(req: &HttpRequest, pool: &PgPool, team_id: Uuid) -> ApiResult<Uuid> {
+    let claims = get_claims(req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
+    claims.require_interactive()?;
+
+    if !PermissionService::has_team_permission(pool, user_id, team_id, Permission::TeamManageSettings).await? {
+        return Err(ApiError::forbidden("Only team owners can configure single sign-on"));
+    }
+
+    Ok(user_id)
+}
+
+/// Lower-cased domain part of an email address
+fn email_domain(email: &str) -> Option<String> {
+    let (local, domain) = email.trim().rsplit_once('@')?;
+    (!local.is_empty() && domain.contains('.')).then(|| domain.to_lowercase())
+}
+
+/// Validate, lower-case and de-duplicate routed domains
+fn normalize_domains(domains: &[String]) -> ApiResult<Vec<String>> {
+    let mut normalized: Vec<String> = Vec::with_capacity(domains.len());
+    for domain in domains {
+        let domain = domain.trim().trim_start_matches('@').to_lowercase();
+        let valid = domain.contains('.')
+            && !domain.starts_with('.')
+            && !domain.ends_with('.')
+            && domain.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+        if !valid {
+            return Err(ApiError::bad_request(format!("Invalid domain: {}", domain)));
+        }
+        if !normalized.contains(&domain) {
+            normalized.push(domain);
+        }
+    }
+    normalized.sort();
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::AuthMiddleware;
+    use crate::routes::auth::generate_tokens;
+    use crate::test_db::TestDb;
+    use actix_web::{test as actix_test, App};
+
+    const IDP_CERT: &str = include_str!("../services/auth/testdata/idp_cert.pem");
+
+    #[test]
+    fn test_email_domain() {
+        assert_eq!(email_domain("Jane@Example.COM").as_deref(), Some("example.com"));
+        assert_eq!(email_domain("@example.com"), None);
+        assert_eq!(email_domain("jane@localhost"), None);
+    }
+
+    #[test]
+    fn test_normalize_domains() {
+        let domains = vec!["Example.com".to_string(), "@corp.example.com".to_string(), "example.com".to_string()];
+        assert_eq!(
+            normalize_domains(&domains).unwrap(),
+            vec!["corp.example.com".to_string(), "example.com".to_string()]
+        );
+        assert!(normalize_domains(&["example".to_string()]).is_err());
+        assert!(normalize_domains(&["exa mple.com".to_string()]).is_err());
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_team_owner_cannot_swap_the_idp_of_routed_domains() {
+        let db = TestDb::create().await;
+        let pool = &db.pool;
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .service(web::scope("/api").wrap(AuthMiddleware).configure(crate::routes::teams::config)),
+        )
+        .await;
+
+        let owner: User =
+            sqlx::query_as("INSERT INTO users (email, password_hash, name) VALUES ('owner@example.com', 'x', 'Owner') RETURNING *")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        let team_id: Uuid = sqlx::query_scalar("INSERT INTO teams (name, slug, owner_id) VALUES ('Sales', 'sales', $1) RETURNING id")
+            .bind(owner.id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO team_members (team_id, user_id, role) VALUES ($1, $2, 'owner')")
+            .bind(team_id)
+            .bind(owner.id)
+            .execute(pool)
+            .await
+            .unwrap();
+        // Domains an administrator has verified for the team's IdP
+        sqlx::query(
+            r#"
+            INSERT INTO saml_providers (team_id, idp_entity_id, sso_url, certificate, domains)
+            VALUES ($1, 'https://idp.example.com/metadata', 'https://idp.example.com/sso', $2, '{example.com}')
+            "#
+        )
+        .bind(team_id)
+        .bind(IDP_CERT)
+        .execute(pool)
+        .await
+        .unwrap();
+
+        let (access_token, _, _) = generate_tokens(pool, &owner, &DeviceInfo::default()).await.unwrap();
+        let put = |entity_id: &str, sso_url: &str, certificate: &str, allow_signup: bool| {
+            actix_test::TestRequest::put()
+                .uri(&format!("/api/teams/{}/saml", team_id))
+                .insert_header(("Authorization", format!("Bearer {}", access_token)))
+                .set_json(json!({
+                    "idp_entity_id": entity_id,
+                    "sso_url": sso_url,
+                    "certificate": certificate,
+                    "allow_signup": allow_signup
+                }))
+                .to_request()
+        };
+
+        // Other settings stay the owner's to change
+        let unchanged = put("https://idp.example.com/metadata", "https://idp.example.com/sso", IDP_CERT, false);
+        assert_eq!(actix_test::call_service(&app, unchanged).await.status(), 200);
+
+        let other_certificate = format!("\n{}", IDP_CERT);
+        for (entity_id, sso_url, certificate) in [
+            ("https://idp.evil.example/metadata", "https://idp.example.com/sso", IDP_CERT),
+            ("https://idp.example.com/metadata", "https://idp.evil.example/sso", IDP_CERT),
+            ("https://idp.example.com/metadata", "https://idp.example.com/sso", other_certificate.as_str()),
+        ] {
+            assert_eq!(actix_test::call_service(&app, put(entity_id, sso_url, certificate, false)).await.status(), 403);
+        }
+
+        let entity_id: String = sqlx::query_scalar("SELECT idp_entity_id FROM saml_providers WHERE team_id = $1")
+            .bind(team_id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(entity_id, "https://idp.example.com/metadata");
+
+        db.drop().await;
+    }
+}
@@ -0,0 +1,240 @@
+//! Resource sharing routes
+//!
+//! Grants on a single resource, under `/api/files/{id}/access`. Listing and
+//! changing who has access requires the share permission on the resource,
+//! i.e. owning it or holding a `manage` grant.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::auth::get_claims;
+use crate::models::UserStatus;
+use crate::services::acl::{AccessLevel, AclService, Grantee, ResourceKind};
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::auth::sessions::DeviceInfo;
+use crate::services::permissions::{Permission, PermissionService};
+
+/// New grant; name exactly one of `email` or `team_id`
+#[derive(Debug, Deserialize)]
+pub struct ShareRequest {
+    /// Email of the user to share with
+    pub email: Option<String>,
+    /// Team to share with; you must be a member
+    pub team_id: Option<Uuid>,
+    pub level: AccessLevel,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// List who has access to a file
+///
+/// GET /api/files/{id}/access
+pub async fn list_file_access(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    list_access(&req, pool.get_ref(), ResourceKind::File, path.into_inner()).await
+}
+
+/// Share a file with a user or team
+///
+/// POST /api/files/{id}/access
+pub async fn share_file(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<ShareRequest>,
+) -> ApiResult<HttpResponse> {
+    share(&req, pool.get_ref(), ResourceKind::File, path.into_inner(), body.into_inner()).await
+}
+
+/// Remove a grant on a file
+///
+/// DELETE /api/files/{id}/access/{grant_id}
+pub async fn unshare_file(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> ApiResult<HttpResponse> {
+    let (file_id, grant_id) = path.into_inner();
+    unshare(&req, pool.get_ref(), ResourceKind::File, file_id, grant_id).await
+}
+
+// ============================================================================
+// HELPER FUNCTIONS
+// ============================================================================
+
+async fn list_access(req: &HttpRequest, pool: &PgPool, kind: ResourceKind, resource_id: Uuid) -> ApiResult<HttpResponse> {
+    let (_, owner_id, team_id) = sharer(req, pool, kind, resource_id).await?;
+
+    let owner: (Uuid, String, String) = sqlx::query_as("SELECT id, email, name FROM users WHERE id = $1")
+        .bind(owner_id)
+        .fetch_one(pool)
+        .await?;
+    let team: Option<(Uuid, String)> = match team_id {
+        Some(team_id) => sqlx::query_as("SELECT id, name FROM teams WHERE id = $1")
+            .bind(team_id)
+            .fetch_optional(pool)
+            .await?,
+        None => None,
+    };
+    let grants = AclService::list(pool, kind, resource_id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "owner": { "user_id": owner.0, "email": owner.1, "name": owner.2 },
+        "team": team.map(|(id, name)| json!({ "team_id": id, "name": name })),
+        "grants": grants
+    })))
+}
+
+async fn share(
+    req: &HttpRequest,
+    pool: &PgPool,
+    kind: ResourceKind,
+    resource_id: Uuid,
+    body: ShareRequest,
+) -> ApiResult<HttpResponse> {
+    let (user_id, owner_id, _) = sharer(req, pool, kind, resource_id).await?;
+
+    if body.expires_at.is_some_and(|at| at <= Utc::now()) {
+        return Err(ApiError::bad_request("expires_at must be in the future"));
+    }
+
+    let grantee = match (body.email.as_deref(), body.team_id) {
+        (Some(email), None) => {
+            let target: Option<(Uuid, UserStatus)> = sqlx::query_as("SELECT id, status FROM users WHERE email = $1")
+                .bind(email.trim().to_lowercase())
+                .fetch_optional(pool)
+                .await?;
+            let target_id = match target {
+                Some((id, UserStatus::Active)) => id,
+                _ => return Err(ApiError::not_found("User not found with that email")),
+            };
+            if target_id == owner_id || target_id == user_id {
+                return Err(ApiError::bad_request("That user already has access"));
+            }
+            Grantee::User(target_id)
+        }
+        (None, Some(team_id)) => {
+            if PermissionService::get_team_permissions(pool, user_id, team_id).await?.is_empty() {
+                return Err(ApiError::forbidden("You can only share with teams you belong to"));
+            }
+            Grantee::Team(team_id)
+        }
+        _ => return Err(ApiError::bad_request("Specify exactly one of email or team_id")),
+    };
+
+    let grant = AclService::grant(pool, kind, resource_id, grantee, body.level, body.expires_at, user_id).await?;
+
+    audit(
+        pool,
+        req,
+        user_id,
+        AuditAction::FileShare,
+        kind,
+        resource_id,
+        json!({
+            "grant_id": grant.id,
+            "user_id": grant.user_id,
+            "team_id": grant.team_id,
+            "level": grant.level,
+            "expires_at": grant.expires_at
+        }),
+    )
+    .await;
+
+    Ok(HttpResponse::Created().json(grant))
+}
+
+async fn unshare(
+    req: &HttpRequest,
+    pool: &PgPool,
+    kind: ResourceKind,
+    resource_id: Uuid,
+    grant_id: Uuid,
+) -> ApiResult<HttpResponse> {
+    let (user_id, _, _) = sharer(req, pool, kind, resource_id).await?;
+
+    if !AclService::revoke(pool, kind, resource_id, grant_id).await? {
+        return Err(ApiError::not_found("Grant not found"));
+    }
+
+    audit(
+        pool,
+        req,
+        user_id,
+        AuditAction::FileUnshare,
+        kind,
+        resource_id,
+        json!({ "grant_id": grant_id }),
+    )
+    .await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// User allowed to share the resource, with its owner and team
+async fn sharer(
+    req: &HttpRequest,
+    pool: &PgPool,
+    kind: ResourceKind,
+    resource_id: Uuid,
+) -> ApiResult<(Uuid, Uuid, Option<Uuid>)> {
+    let claims = get_claims(req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID"))?;
+    let permission = share_permission(kind);
+    claims.require(permission)?;
+
+    let (owner_id, team_id) = AclService::owner(pool, kind, resource_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Resource not found"))?;
+
+    if !PermissionService::can_access_resource(pool, user_id, kind, resource_id, permission).await? {
+        return Err(ApiError::not_found("Resource not found"));
+    }
+
+    Ok((user_id, owner_id, team_id))
+}
+
+fn share_permission(kind: ResourceKind) -> Permission {
+    match kind {
+        ResourceKind::File => Permission::DatasetShare,
+    }
+}
+
+async fn audit(
+    pool: &PgPool,
+    req: &HttpRequest,
+    user_id: Uuid,
+    action: AuditAction,
+    kind: ResourceKind,
+    resource_id: Uuid,
+    details: serde_json::Value,
+) {
+    let resource_type = match kind {
+        ResourceKind::File => ResourceType::File,
+    };
+
+    if let Err(e) = AuditService::log(pool, AuditEntry {
+        user_id: Some(user_id),
+        team_id: None,
+        action: action.clone(),
+        resource_type: Some(resource_type),
+        resource_id: Some(resource_id),
+        details: Some(details),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: DeviceInfo::from_request(req).user_agent,
+    })
+    .await
+    {
+        log::warn!("Failed to audit {} of {}: {}", action.as_str(), resource_id, e);
+    }
+}
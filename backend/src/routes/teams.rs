@@ -10,10 +10,20 @@ use uuid::Uuid;
 
 use crate::errors::{ApiError, ApiResult};
 use crate::middleware::auth::get_claims;
+use crate::middleware::guard::{
+    check_permission, invalidate, perm, team_membership, RequireTeamMember, RequireTeamPermission,
+};
 use crate::models::{
-    CreateTeamRequest, InviteUserRequest, Team, TeamInfo, TeamMember, TeamMemberInfo,
-    TeamRole, UpdateMemberRoleRequest, UpdateTeamRequest, User,
+    CreateTeamRequest, Team, TeamInfo, TeamMemberInfo, TeamRole, TransferOwnershipRequest,
+    UpdateMemberRoleRequest, UpdateTeamRequest, UserStatus,
 };
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+use crate::services::auth::sessions::DeviceInfo;
+use crate::services::auth::mfa::MfaService;
+use crate::services::permissions::{Permission, PermissionService};
+use crate::services::roles::RoleService;
+use crate::services::team_settings::TeamSettingsService;
+use crate::services::usage::UsageLimits;
 
 /// Configure teams routes
 pub fn config(cfg: &mut web::ServiceConfig) {
@@ -24,11 +34,29 @@ pub fn config(cfg: &mut web::ServiceConfig) {
             .route("/{id}", web::get().to(get_team))
             .route("/{id}", web::put().to(update_team))
             .route("/{id}", web::delete().to(delete_team))
+            .route("/{id}/settings", web::get().to(get_settings))
+            .route("/{id}/settings", web::patch().to(update_settings))
             .route("/{id}/members", web::get().to(list_members))
-            .route("/{id}/members", web::post().to(add_member))
+            .route("/{id}/members", web::post().to(super::invitations::invite_member))
             .route("/{id}/members/{user_id}", web::put().to(update_member_role))
             .route("/{id}/members/{user_id}", web::delete().to(remove_member))
-            .route("/{id}/leave", web::post().to(leave_team)),
+            .route("/{id}/leave", web::post().to(leave_team))
+            .route("/{id}/transfer", web::post().to(transfer_ownership))
+            .route("/{id}/invitations", web::get().to(super::invitations::list_invitations))
+            .route("/{id}/invitations/{invitation_id}", web::delete().to(super::invitations::revoke_invitation))
+            .route("/{id}/invitations/{invitation_id}/resend", web::post().to(super::invitations::resend_invitation))
+            .route("/{id}/saml", web::get().to(super::saml::get_team_provider))
+            .route("/{id}/saml", web::put().to(super::saml::put_team_provider))
+            .route("/{id}/saml", web::delete().to(super::saml::delete_team_provider))
+            .route("/{id}/api-keys", web::get().to(super::api_keys::list_team_keys))
+            .route("/{id}/api-keys", web::post().to(super::api_keys::create_team_key))
+            .route("/{id}/api-keys/{key_id}", web::delete().to(super::api_keys::revoke_team_key))
+            .route("/{id}/roles", web::get().to(super::roles::list_roles))
+            .route("/{id}/roles", web::post().to(super::roles::create_role))
+            .route("/{id}/roles/built-in/{builtin}", web::put().to(super::roles::override_builtin_role))
+            .route("/{id}/roles/built-in/{builtin}", web::delete().to(super::roles::reset_builtin_role))
+            .route("/{id}/roles/{role_id}", web::put().to(super::roles::update_role))
+            .route("/{id}/roles/{role_id}", web::delete().to(super::roles::delete_role)),
     );
 }
 
@@ -151,170 +179,100 @@ async fn list_teams(
 ///
 /// GET /api/teams/{id}
 async fn get_team(
-    req: HttpRequest,
+    member: RequireTeamMember,
     pool: web::Data<PgPool>,
-    path: web::Path<Uuid>,
 ) -> ApiResult<HttpResponse> {
-    let claims = get_claims(&req)
-        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
-
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
-
-    let team_id = path.into_inner();
-
-    // Verify user is a member of the team
-    let membership: Option<TeamMember> = sqlx::query_as(
-        "SELECT * FROM team_members WHERE team_id = $1 AND user_id = $2"
-    )
-    .bind(&team_id)
-    .bind(&user_id)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    let membership = match membership {
-        Some(m) => m,
-        None => return Err(ApiError::forbidden("You are not a member of this team")),
-    };
-
-    // Get team details
-    let team: Option<Team> = sqlx::query_as(
-        "SELECT * FROM teams WHERE id = $1"
-    )
-    .bind(&team_id)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    let team = match team {
-        Some(t) => t,
-        None => return Err(ApiError::not_found("Team not found")),
-    };
-
-    // Get member count
-    let (member_count,): (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM team_members WHERE team_id = $1"
-    )
-    .bind(&team_id)
-    .fetch_one(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    Ok(HttpResponse::Ok().json(TeamInfo {
-        id: team.id,
-        name: team.name,
-        slug: team.slug,
-        description: team.description,
-        role: membership.role,
-        member_count,
-    }))
+    team_info(pool.get_ref(), member.team_id, member.membership.role).await
 }
 
-/// Update team details
+/// Update team details; `require_mfa` is saved as in
+/// [`update_settings`]
 ///
 /// PUT /api/teams/{id}
 async fn update_team(
-    req: HttpRequest,
+    guard: RequireTeamPermission<perm::TeamManageSettings>,
     pool: web::Data<PgPool>,
-    path: web::Path<Uuid>,
     body: web::Json<UpdateTeamRequest>,
 ) -> ApiResult<HttpResponse> {
-    let claims = get_claims(&req)
-        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
-
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
-
-    let team_id = path.into_inner();
-
-    // Verify user has admin or owner role
-    let membership: Option<TeamMember> = sqlx::query_as(
-        "SELECT * FROM team_members WHERE team_id = $1 AND user_id = $2"
-    )
-    .bind(&team_id)
-    .bind(&user_id)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    let membership = match membership {
-        Some(m) if m.role == TeamRole::Owner || m.role == TeamRole::Admin => m,
-        Some(_) => return Err(ApiError::forbidden("Only team owners and admins can update team settings")),
-        None => return Err(ApiError::forbidden("You are not a member of this team")),
-    };
+    let team_id = guard.team_id;
 
     // Build update query dynamically
     let mut updates = Vec::new();
-    let mut param_count = 1;
+    let mut param_count = 0;
 
     if body.name.is_some() {
-        updates.push(format!("name = ${}", param_count));
         param_count += 1;
+        updates.push(format!("name = ${}", param_count));
     }
     if body.description.is_some() {
+        param_count += 1;
         updates.push(format!("description = ${}", param_count));
     }
 
-    if updates.is_empty() {
+    if updates.is_empty() && body.require_mfa.is_none() {
         return Err(ApiError::bad_request("No fields to update"));
     }
 
-    // Update team
-    let query = format!(
-        "UPDATE teams SET {} WHERE id = ${}",
-        updates.join(", "),
-        param_count + 1
-    );
+    let mut tx = pool.begin().await?;
+
+    if let Some(require_mfa) = body.require_mfa {
+        let current = TeamSettingsService::lock(&mut tx, team_id)
+            .await?
+            .ok_or_else(|| ApiError::not_found("Team not found"))?;
+        let patch = serde_json::Map::from_iter([("require_mfa".to_string(), json!(require_mfa))]);
+        let settings = current
+            .patched(&patch)
+            .map_err(|e| ApiError::bad_request(e.to_string()))?;
+
+        // Only members who use MFA themselves may require it, so the change
+        // cannot lock out the person making it
+        if settings.require_mfa && !current.require_mfa && !MfaService::is_enabled(pool.get_ref(), guard.user_id).await? {
+            return Err(ApiError::bad_request("Enable multi-factor authentication on your account before requiring it for the team"));
+        }
 
-    let mut q = sqlx::query(&query);
-    if let Some(ref name) = body.name {
-        q = q.bind(name);
+        TeamSettingsService::save(&mut tx, team_id, &settings).await?;
     }
-    if let Some(ref description) = body.description {
-        q = q.bind(description);
+
+    if !updates.is_empty() {
+        let query = format!(
+            "UPDATE teams SET {} WHERE id = ${}",
+            updates.join(", "),
+            param_count + 1
+        );
+
+        let mut q = sqlx::query(&query);
+        if let Some(ref name) = body.name {
+            q = q.bind(name);
+        }
+        if let Some(ref description) = body.description {
+            q = q.bind(description);
+        }
+        q = q.bind(team_id);
+
+        q.execute(&mut *tx)
+            .await
+            .map_err(|e| ApiError::internal(format!("Failed to update team: {}", e)))?;
     }
-    q = q.bind(&team_id);
 
-    q.execute(pool.get_ref())
-        .await
-        .map_err(|e| ApiError::internal(format!("Failed to update team: {}", e)))?;
+    tx.commit().await?;
 
     // Return updated team info
-    get_team(req, pool, web::Path::from(team_id)).await
+    team_info(pool.get_ref(), team_id, guard.membership.role.clone()).await
 }
 
 /// Delete team
 ///
 /// DELETE /api/teams/{id}
 async fn delete_team(
-    req: HttpRequest,
+    guard: RequireTeamPermission<perm::TeamManageSettings>,
     pool: web::Data<PgPool>,
-    path: web::Path<Uuid>,
 ) -> ApiResult<HttpResponse> {
-    let claims = get_claims(&req)
-        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
-
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
+    let team_id = guard.team_id;
 
-    let team_id = path.into_inner();
-
-    // Verify user is the owner
-    let membership: Option<TeamMember> = sqlx::query_as(
-        "SELECT * FROM team_members WHERE team_id = $1 AND user_id = $2"
-    )
-    .bind(&team_id)
-    .bind(&user_id)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    match membership {
-        Some(m) if m.role == TeamRole::Owner => {},
-        Some(_) => return Err(ApiError::forbidden("Only team owners can delete teams")),
-        None => return Err(ApiError::forbidden("You are not a member of this team")),
-    };
+    // Deleting is not delegable: the team belongs to its owner
+    if guard.membership.role != TeamRole::Owner {
+        return Err(ApiError::forbidden("Only team owners can delete teams"));
+    }
 
     // Delete team (cascade will handle team_members)
     sqlx::query("DELETE FROM teams WHERE id = $1")
@@ -330,43 +288,101 @@ async fn delete_team(
 }
 
 // ============================================================================
-// TEAM MEMBERSHIP OPERATIONS
+// TEAM SETTINGS
 // ============================================================================
 
-/// List team members
+/// Get the team's settings
 ///
-/// GET /api/teams/{id}/members
-async fn list_members(
+/// GET /api/teams/{id}/settings
+async fn get_settings(
+    member: RequireTeamMember,
+    pool: web::Data<PgPool>,
+) -> ApiResult<HttpResponse> {
+    let settings = TeamSettingsService::get(pool.get_ref(), member.team_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Team not found"))?;
+
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+/// Change some of the team's settings; a setting given as `null` goes back
+/// to its default
+///
+/// PATCH /api/teams/{id}/settings
+async fn update_settings(
     req: HttpRequest,
+    guard: RequireTeamPermission<perm::TeamManageSettings>,
     pool: web::Data<PgPool>,
-    path: web::Path<Uuid>,
+    limits: web::Data<UsageLimits>,
+    body: web::Json<serde_json::Map<String, serde_json::Value>>,
 ) -> ApiResult<HttpResponse> {
-    let claims = get_claims(&req)
-        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+    let team_id = guard.team_id;
+    if body.is_empty() {
+        return Err(ApiError::bad_request("No settings to update"));
+    }
 
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
+    let mut tx = pool.begin().await?;
+    let current = TeamSettingsService::lock(&mut tx, team_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Team not found"))?;
+    let settings = current
+        .patched(&body)
+        .map_err(|e| ApiError::bad_request(e.to_string()))?;
+
+    // Only members who use MFA themselves may require it, so the change
+    // cannot lock out the person making it
+    if settings.require_mfa && !current.require_mfa && !MfaService::is_enabled(pool.get_ref(), guard.user_id).await? {
+        return Err(ApiError::bad_request("Enable multi-factor authentication on your account before requiring it for the team"));
+    }
 
-    let team_id = path.into_inner();
+    // Teams may lower their storage quota, but only system administrators
+    // may raise it above the server's
+    if settings.storage_quota_bytes > current.storage_quota_bytes
+        && limits.exceeds_team_quota(settings.storage_quota_bytes)
+        && !check_permission(&req, pool.get_ref(), guard.user_id, Permission::AdminManageTeams).await?
+    {
+        return Err(ApiError::forbidden("Only system administrators can raise a team's storage quota above the server's"));
+    }
 
-    // Verify user is a member
-    let is_member: Option<(Uuid,)> = sqlx::query_as(
-        "SELECT id FROM team_members WHERE team_id = $1 AND user_id = $2"
-    )
-    .bind(&team_id)
-    .bind(&user_id)
-    .fetch_optional(pool.get_ref())
+    TeamSettingsService::save(&mut tx, team_id, &settings).await?;
+    tx.commit().await?;
+    invalidate(&req);
+
+    if let Err(e) = AuditService::log(pool.get_ref(), AuditEntry {
+        user_id: Some(guard.user_id),
+        team_id: Some(team_id),
+        action: AuditAction::TeamUpdate,
+        resource_type: Some(ResourceType::Team),
+        resource_id: Some(team_id),
+        details: Some(json!({ "settings": body.into_inner() })),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: DeviceInfo::from_request(&req).user_agent,
+    })
     .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    if is_member.is_none() {
-        return Err(ApiError::forbidden("You are not a member of this team"));
+    {
+        log::warn!("Failed to audit settings change of team {}: {}", team_id, e);
     }
 
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+// ============================================================================
+// TEAM MEMBERSHIP OPERATIONS
+// ============================================================================
+
+/// List team members
+///
+/// GET /api/teams/{id}/members
+async fn list_members(
+    member: RequireTeamMember,
+    pool: web::Data<PgPool>,
+) -> ApiResult<HttpResponse> {
+    let team_id = member.team_id;
+
     // Get all team members with user details
-    let members: Vec<TeamMemberInfo> = sqlx::query_as::<_, (Uuid, Uuid, String, String, TeamRole, chrono::DateTime<chrono::Utc>)>(
+    let members: Vec<TeamMemberInfo> = sqlx::query_as::<_, (Uuid, Uuid, String, String, TeamRole, Option<Uuid>, chrono::DateTime<chrono::Utc>)>(
         r#"
-        SELECT tm.id, tm.user_id, u.email, u.name, tm.role, tm.joined_at
+        SELECT tm.id, tm.user_id, u.email, u.name, tm.role, tm.role_id, tm.joined_at
         FROM team_members tm
         JOIN users u ON tm.user_id = u.id
         WHERE tm.team_id = $1
@@ -378,139 +394,26 @@ async fn list_members(
     .await
     .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
     .into_iter()
-    .map(|(id, user_id, email, name, role, joined_at)| TeamMemberInfo {
-        id, user_id, email, name, role, joined_at,
+    .map(|(id, user_id, email, name, role, role_id, joined_at)| TeamMemberInfo {
+        id, user_id, email, name, role, role_id, joined_at,
     })
     .collect();
 
     Ok(HttpResponse::Ok().json(members))
 }
 
-/// Add member to team (invite)
-///
-/// POST /api/teams/{id}/members
-async fn add_member(
-    req: HttpRequest,
-    pool: web::Data<PgPool>,
-    path: web::Path<Uuid>,
-    body: web::Json<InviteUserRequest>,
-) -> ApiResult<HttpResponse> {
-    let claims = get_claims(&req)
-        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
-
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
-
-    let team_id = path.into_inner();
-
-    // Verify user has admin or owner role
-    let membership: Option<TeamMember> = sqlx::query_as(
-        "SELECT * FROM team_members WHERE team_id = $1 AND user_id = $2"
-    )
-    .bind(&team_id)
-    .bind(&user_id)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    match membership {
-        Some(m) if m.role == TeamRole::Owner || m.role == TeamRole::Admin => {},
-        Some(_) => return Err(ApiError::forbidden("Only team owners and admins can invite members")),
-        None => return Err(ApiError::forbidden("You are not a member of this team")),
-    };
-
-    // Find user by email
-    let target_user: Option<User> = sqlx::query_as(
-        "SELECT * FROM users WHERE email = $1"
-    )
-    .bind(&body.email.to_lowercase())
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    let target_user = match target_user {
-        Some(u) => u,
-        None => return Err(ApiError::not_found("User not found with that email")),
-    };
-
-    // Check if already a member
-    let existing: Option<(Uuid,)> = sqlx::query_as(
-        "SELECT id FROM team_members WHERE team_id = $1 AND user_id = $2"
-    )
-    .bind(&team_id)
-    .bind(&target_user.id)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    if existing.is_some() {
-        return Err(ApiError::bad_request("User is already a member of this team"));
-    }
-
-    // Cannot assign owner role through invite
-    let role = if body.role == TeamRole::Owner {
-        TeamRole::Admin
-    } else {
-        body.role.clone()
-    };
-
-    // Add member
-    let member: TeamMember = sqlx::query_as(
-        r#"
-        INSERT INTO team_members (team_id, user_id, role)
-        VALUES ($1, $2, $3)
-        RETURNING *
-        "#
-    )
-    .bind(&team_id)
-    .bind(&target_user.id)
-    .bind(&role)
-    .fetch_one(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Failed to add member: {}", e)))?;
-
-    Ok(HttpResponse::Created().json(TeamMemberInfo {
-        id: member.id,
-        user_id: target_user.id,
-        email: target_user.email,
-        name: target_user.name,
-        role: member.role,
-        joined_at: member.joined_at,
-    }))
-}
-
 /// Update member role
 ///
 /// PUT /api/teams/{id}/members/{user_id}
 async fn update_member_role(
     req: HttpRequest,
+    guard: RequireTeamPermission<perm::TeamManageRoles>,
     pool: web::Data<PgPool>,
     path: web::Path<(Uuid, Uuid)>,
     body: web::Json<UpdateMemberRoleRequest>,
 ) -> ApiResult<HttpResponse> {
-    let claims = get_claims(&req)
-        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
-
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
-
     let (team_id, target_user_id) = path.into_inner();
-
-    // Verify user has owner role (only owners can change roles)
-    let membership: Option<TeamMember> = sqlx::query_as(
-        "SELECT * FROM team_members WHERE team_id = $1 AND user_id = $2"
-    )
-    .bind(&team_id)
-    .bind(&user_id)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    match membership {
-        Some(m) if m.role == TeamRole::Owner => {},
-        Some(_) => return Err(ApiError::forbidden("Only team owners can change member roles")),
-        None => return Err(ApiError::forbidden("You are not a member of this team")),
-    };
+    guard.claims.require(Permission::TeamManageMembers)?;
 
     // Cannot change owner's role or assign owner role
     if body.role == TeamRole::Owner {
@@ -518,16 +421,7 @@ async fn update_member_role(
     }
 
     // Check target member exists and is not owner
-    let target_member: Option<TeamMember> = sqlx::query_as(
-        "SELECT * FROM team_members WHERE team_id = $1 AND user_id = $2"
-    )
-    .bind(&team_id)
-    .bind(&target_user_id)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    match target_member {
+    match team_membership(&req, pool.get_ref(), team_id, target_user_id).await? {
         Some(m) if m.role == TeamRole::Owner => {
             return Err(ApiError::bad_request("Cannot change owner's role"))
         },
@@ -535,13 +429,25 @@ async fn update_member_role(
         None => return Err(ApiError::not_found("Member not found")),
     };
 
+    // A custom role may grant only permissions its assigner holds
+    if let Some(role_id) = body.role_id {
+        let role = RoleService::get_custom(pool.get_ref(), team_id, role_id)
+            .await?
+            .ok_or_else(|| ApiError::not_found("Role not found"))?;
+        let held = PermissionService::get_team_permissions(pool.get_ref(), guard.user_id, team_id).await?;
+        if !crate::services::roles::parse_permissions(&role.permissions).is_subset(&held) {
+            return Err(ApiError::forbidden("You cannot assign a role with permissions you do not have"));
+        }
+    }
+
     // Update role
     sqlx::query(
-        "UPDATE team_members SET role = $1 WHERE team_id = $2 AND user_id = $3"
+        "UPDATE team_members SET role = $1, role_id = $4 WHERE team_id = $2 AND user_id = $3"
     )
     .bind(&body.role)
     .bind(&team_id)
     .bind(&target_user_id)
+    .bind(body.role_id)
     .execute(pool.get_ref())
     .await
     .map_err(|e| ApiError::internal(format!("Failed to update role: {}", e)))?;
@@ -557,44 +463,14 @@ async fn update_member_role(
 /// DELETE /api/teams/{id}/members/{user_id}
 async fn remove_member(
     req: HttpRequest,
+    _guard: RequireTeamPermission<perm::TeamManageMembers>,
     pool: web::Data<PgPool>,
     path: web::Path<(Uuid, Uuid)>,
 ) -> ApiResult<HttpResponse> {
-    let claims = get_claims(&req)
-        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
-
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
-
     let (team_id, target_user_id) = path.into_inner();
 
-    // Verify user has admin or owner role
-    let membership: Option<TeamMember> = sqlx::query_as(
-        "SELECT * FROM team_members WHERE team_id = $1 AND user_id = $2"
-    )
-    .bind(&team_id)
-    .bind(&user_id)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    match membership {
-        Some(m) if m.role == TeamRole::Owner || m.role == TeamRole::Admin => {},
-        Some(_) => return Err(ApiError::forbidden("Only team owners and admins can remove members")),
-        None => return Err(ApiError::forbidden("You are not a member of this team")),
-    };
-
     // Cannot remove owner
-    let target_member: Option<TeamMember> = sqlx::query_as(
-        "SELECT * FROM team_members WHERE team_id = $1 AND user_id = $2"
-    )
-    .bind(&team_id)
-    .bind(&target_user_id)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    match target_member {
+    match team_membership(&req, pool.get_ref(), team_id, target_user_id).await? {
         Some(m) if m.role == TeamRole::Owner => {
             return Err(ApiError::bad_request("Cannot remove team owner"))
         },
@@ -631,18 +507,11 @@ async fn leave_team(
         .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
 
     let team_id = path.into_inner();
+    claims.require_interactive()?;
 
-    // Check membership
-    let membership: Option<TeamMember> = sqlx::query_as(
-        "SELECT * FROM team_members WHERE team_id = $1 AND user_id = $2"
-    )
-    .bind(&team_id)
-    .bind(&user_id)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    match membership {
+    // Members may leave a team that requires MFA without enabling it, so
+    // this checks membership rather than using a guard
+    match team_membership(&req, pool.get_ref(), team_id, user_id).await? {
         Some(m) if m.role == TeamRole::Owner => {
             return Err(ApiError::bad_request("Team owners cannot leave. Transfer ownership or delete the team."))
         },
@@ -664,10 +533,164 @@ async fn leave_team(
     })))
 }
 
+/// Hand the team to another member: the team's owner, the new owner's role
+/// and the previous owner's role change together
+///
+/// POST /api/teams/{id}/transfer
+async fn transfer_ownership(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    body: web::Json<TransferOwnershipRequest>,
+) -> ApiResult<HttpResponse> {
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
+
+    let team_id = path.into_inner();
+    claims.require_interactive()?;
+
+    // The owner hands over their own team; system administrators may do it
+    // for an owner who is gone
+    let is_owner = match team_membership(&req, pool.get_ref(), team_id, user_id).await? {
+        Some(m) if m.role == TeamRole::Owner => {
+            RequireTeamMember::authorize(&req, None).await?;
+            true
+        }
+        _ => false,
+    };
+    if !is_owner && !check_permission(&req, pool.get_ref(), user_id, Permission::AdminManageTeams).await? {
+        return Err(ApiError::forbidden("Only the team owner can transfer ownership"));
+    }
+
+    let previous_owner_role = body.previous_owner_role.clone().unwrap_or(TeamRole::Admin);
+    if previous_owner_role == TeamRole::Owner {
+        return Err(ApiError::bad_request("A team has one owner; choose another role for the previous owner"));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let team: Option<Team> = sqlx::query_as("SELECT * FROM teams WHERE id = $1 FOR UPDATE")
+        .bind(team_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let team = team.ok_or_else(|| ApiError::not_found("Team not found"))?;
+
+    if body.confirm.trim() != team.slug {
+        return Err(ApiError::bad_request("Type the team's slug to confirm the transfer"));
+    }
+    if body.new_owner_id == team.owner_id {
+        return Err(ApiError::bad_request("This user already owns the team"));
+    }
+
+    let target: Option<(TeamRole, UserStatus)> = sqlx::query_as(
+        r#"
+        SELECT tm.role, u.status
+        FROM team_members tm
+        JOIN users u ON u.id = tm.user_id
+        WHERE tm.team_id = $1 AND tm.user_id = $2
+        FOR UPDATE OF tm
+        "#
+    )
+    .bind(team_id)
+    .bind(body.new_owner_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let new_owner_previous_role = match target {
+        Some((role, UserStatus::Active)) => role,
+        Some(_) => return Err(ApiError::bad_request("Ownership can only be transferred to an active user")),
+        None => return Err(ApiError::not_found("The new owner must be a member of the team")),
+    };
+
+    sqlx::query("UPDATE teams SET owner_id = $2 WHERE id = $1")
+        .bind(team_id)
+        .bind(body.new_owner_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE team_members SET role = 'owner', role_id = NULL WHERE team_id = $1 AND user_id = $2")
+        .bind(team_id)
+        .bind(body.new_owner_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE team_members SET role = $3, role_id = NULL WHERE team_id = $1 AND user_id = $2")
+        .bind(team_id)
+        .bind(team.owner_id)
+        .bind(&previous_owner_role)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    invalidate(&req);
+
+    if let Err(e) = AuditService::log(pool.get_ref(), AuditEntry {
+        user_id: Some(user_id),
+        team_id: Some(team_id),
+        action: AuditAction::TeamMemberRoleChange,
+        resource_type: Some(ResourceType::Team),
+        resource_id: Some(team_id),
+        details: Some(json!({
+            "ownership_transfer": true,
+            "from": team.owner_id,
+            "to": body.new_owner_id,
+            "previous_owner_role": previous_owner_role,
+            "new_owner_previous_role": new_owner_previous_role,
+            "admin_override": !is_owner
+        })),
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: DeviceInfo::from_request(&req).user_agent,
+    })
+    .await
+    {
+        log::warn!("Failed to audit ownership transfer of team {}: {}", team_id, e);
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "owner_id": body.new_owner_id,
+        "message": "Team ownership transferred"
+    })))
+}
+
 // ============================================================================
 // HELPER FUNCTIONS
 // ============================================================================
 
+/// Team details as seen by a member holding `role`
+async fn team_info(pool: &PgPool, team_id: Uuid, role: TeamRole) -> ApiResult<HttpResponse> {
+    let team: Option<Team> = sqlx::query_as(
+        "SELECT * FROM teams WHERE id = $1"
+    )
+    .bind(team_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    let team = match team {
+        Some(t) => t,
+        None => return Err(ApiError::not_found("Team not found")),
+    };
+
+    // Get member count
+    let (member_count,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM team_members WHERE team_id = $1"
+    )
+    .bind(team_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    Ok(HttpResponse::Ok().json(TeamInfo {
+        id: team.id,
+        name: team.name,
+        slug: team.slug,
+        description: team.description,
+        role,
+        member_count,
+    }))
+}
+
 /// Generate URL-friendly slug from name
 fn generate_slug(name: &str) -> String {
     let mut result = String::new();
@@ -699,6 +722,34 @@ fn generate_slug(name: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::middleware::AuthMiddleware;
+    use crate::models::User;
+    use crate::routes::auth::generate_tokens;
+    use crate::test_db::TestDb;
+    use actix_web::{test as actix_test, App};
+
+    async fn insert_user(pool: &PgPool, email: &str) -> User {
+        sqlx::query_as("INSERT INTO users (email, password_hash, name) VALUES ($1, 'x', $1) RETURNING *")
+            .bind(email)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    /// Access token for a new session of `user`
+    async fn sign_in(pool: &PgPool, user: &User) -> String {
+        let (access_token, _, _) = generate_tokens(pool, user, &DeviceInfo::default()).await.unwrap();
+        format!("Bearer {}", access_token)
+    }
+
+    async fn member_role(pool: &PgPool, team_id: Uuid, user_id: Uuid) -> Option<TeamRole> {
+        sqlx::query_scalar("SELECT role FROM team_members WHERE team_id = $1 AND user_id = $2")
+            .bind(team_id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+            .unwrap()
+    }
 
     #[test]
     fn test_generate_slug() {
@@ -707,5 +758,191 @@ mod tests {
         assert_eq!(generate_slug("Test  Team  123"), "test-team-123");
         assert_eq!(generate_slug("Special!@#Chars"), "specialchars");
     }
-}
 
+    #[actix_rt::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_transfer_ownership() {
+        let db = TestDb::create().await;
+        let pool = &db.pool;
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .service(web::scope("/api").wrap(AuthMiddleware).configure(config)),
+        )
+        .await;
+
+        let owner = insert_user(pool, "owner@example.com").await;
+        let member = insert_user(pool, "member@example.com").await;
+        let outsider = insert_user(pool, "outsider@example.com").await;
+        let team_id: Uuid = sqlx::query_scalar("INSERT INTO teams (name, slug, owner_id) VALUES ('Sales', 'sales', $1) RETURNING id")
+            .bind(owner.id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO team_members (team_id, user_id, role) VALUES ($1, $2, 'owner'), ($1, $3, 'member')")
+            .bind(team_id)
+            .bind(owner.id)
+            .bind(member.id)
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let token = sign_in(pool, &owner).await;
+        let transfer = |new_owner_id: Uuid, confirm: &str| {
+            actix_test::TestRequest::post()
+                .uri(&format!("/api/teams/{}/transfer", team_id))
+                .insert_header(("Authorization", token.clone()))
+                .set_json(json!({ "new_owner_id": new_owner_id, "confirm": confirm }))
+                .to_request()
+        };
+
+        // The slug must be typed to confirm
+        assert_eq!(actix_test::call_service(&app, transfer(member.id, "Sales")).await.status(), 400);
+        // Only a member can take over
+        assert_eq!(actix_test::call_service(&app, transfer(outsider.id, "sales")).await.status(), 404);
+        assert_eq!(member_role(pool, team_id, outsider.id).await, None);
+
+        let owner_id: Uuid = sqlx::query_scalar("SELECT owner_id FROM teams WHERE id = $1")
+            .bind(team_id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(owner_id, owner.id);
+        assert_eq!(member_role(pool, team_id, member.id).await, Some(TeamRole::Member));
+
+        assert_eq!(actix_test::call_service(&app, transfer(member.id, "sales")).await.status(), 200);
+
+        let owner_id: Uuid = sqlx::query_scalar("SELECT owner_id FROM teams WHERE id = $1")
+            .bind(team_id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(owner_id, member.id);
+        assert_eq!(member_role(pool, team_id, member.id).await, Some(TeamRole::Owner));
+        // The previous owner stays on as an admin
+        assert_eq!(member_role(pool, team_id, owner.id).await, Some(TeamRole::Admin));
+
+        // ...and can no longer hand the team on
+        assert_eq!(actix_test::call_service(&app, transfer(owner.id, "sales")).await.status(), 403);
+
+        db.drop().await;
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_update_team_saves_require_mfa_as_a_setting() {
+        let db = TestDb::create().await;
+        let pool = &db.pool;
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .service(web::scope("/api").wrap(AuthMiddleware).configure(config)),
+        )
+        .await;
+
+        let owner = insert_user(pool, "owner@example.com").await;
+        let team_id: Uuid = sqlx::query_scalar(
+            r#"INSERT INTO teams (name, slug, owner_id, settings) VALUES ('Sales', 'sales', $1, '{"data_retention_days": 30}') RETURNING id"#
+        )
+        .bind(owner.id)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO team_members (team_id, user_id, role) VALUES ($1, $2, 'owner')")
+            .bind(team_id)
+            .bind(owner.id)
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let token = sign_in(pool, &owner).await;
+        let update = |body: serde_json::Value| {
+            actix_test::TestRequest::put()
+                .uri(&format!("/api/teams/{}", team_id))
+                .insert_header(("Authorization", token.clone()))
+                .set_json(body)
+                .to_request()
+        };
+
+        // Requiring MFA without using it would lock the owner out
+        let resp = actix_test::call_service(&app, update(json!({ "name": "Revenue", "require_mfa": true }))).await;
+        assert_eq!(resp.status(), 400);
+
+        let resp = actix_test::call_service(&app, update(json!({ "name": "Revenue", "require_mfa": false }))).await;
+        assert_eq!(resp.status(), 200);
+
+        let (name, settings): (String, serde_json::Value) = sqlx::query_as("SELECT name, settings FROM teams WHERE id = $1")
+            .bind(team_id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(name, "Revenue");
+        assert_eq!(settings["require_mfa"], false);
+        assert_eq!(settings["data_retention_days"], 30);
+
+        db.drop().await;
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_only_admins_raise_storage_quota_above_the_servers() {
+        let db = TestDb::create().await;
+        let pool = &db.pool;
+        let limits = UsageLimits { team_storage_quota_bytes: Some(1000), ..Default::default() };
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(limits))
+                .service(web::scope("/api").wrap(AuthMiddleware).configure(config)),
+        )
+        .await;
+
+        let owner = insert_user(pool, "owner@example.com").await;
+        let team_id: Uuid = sqlx::query_scalar("INSERT INTO teams (name, slug, owner_id) VALUES ('Sales', 'sales', $1) RETURNING id")
+            .bind(owner.id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO team_members (team_id, user_id, role) VALUES ($1, $2, 'owner')")
+            .bind(team_id)
+            .bind(owner.id)
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let patch = |token: &str, quota: u64| {
+            actix_test::TestRequest::patch()
+                .uri(&format!("/api/teams/{}/settings", team_id))
+                .insert_header(("Authorization", token.to_string()))
+                .set_json(json!({ "storage_quota_bytes": quota }))
+                .to_request()
+        };
+
+        let token = sign_in(pool, &owner).await;
+        assert_eq!(actix_test::call_service(&app, patch(&token, 1001)).await.status(), 403);
+        assert_eq!(actix_test::call_service(&app, patch(&token, 1000)).await.status(), 200);
+
+        let admin: User = sqlx::query_as("UPDATE users SET role = 'admin' WHERE id = $1 RETURNING *")
+            .bind(owner.id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        let admin_token = sign_in(pool, &admin).await;
+        assert_eq!(actix_test::call_service(&app, patch(&admin_token, 5000)).await.status(), 200);
+
+        // The team may still lower a quota an administrator raised
+        sqlx::query("UPDATE users SET role = 'user' WHERE id = $1")
+            .bind(owner.id)
+            .execute(pool)
+            .await
+            .unwrap();
+        let token = sign_in(pool, &owner).await;
+        assert_eq!(actix_test::call_service(&app, patch(&token, 6000)).await.status(), 403);
+        assert_eq!(actix_test::call_service(&app, patch(&token, 2000)).await.status(), 200);
+
+        let settings = TeamSettingsService::get(pool, team_id).await.unwrap().unwrap();
+        assert_eq!(settings.storage_quota_bytes, Some(2000));
+
+        db.drop().await;
+    }
+}
@@ -0,0 +1,149 @@
+//! Usage routes
+//!
+//! Storage and query usage against quotas: the caller's own and their
+//! teams' under `/api/usage`, a team's with each member's share under
+//! `/api/usage/teams/{id}`, and the heaviest users and teams on the server
+//! for administrators under `/api/admin/usage`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::auth::get_claims;
+use crate::middleware::guard::{perm, RequirePermission, RequireTeamMember};
+use crate::services::usage::{period_start, UsageLimits, UsageService};
+
+/// Users and teams listed to administrators by default
+const DEFAULT_RANKING_LIMIT: i64 = 20;
+
+/// Most users and teams listed to administrators at once
+const MAX_RANKING_LIMIT: i64 = 100;
+
+/// Configure usage routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/usage")
+            .route("", web::get().to(my_usage))
+            .route("/teams/{id}", web::get().to(team_usage)),
+    )
+    .route("/admin/usage", web::get().to(admin_usage));
+}
+
+/// Query parameters for usage reports
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    /// Days of query usage to report, ending today (default 30)
+    pub days: Option<u32>,
+    /// Users and teams to list, for administrators
+    pub limit: Option<i64>,
+}
+
+/// Get the current user's usage of personal storage and queries, and that
+/// of each of their teams
+///
+/// GET /api/usage
+async fn my_usage(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    limits: web::Data<UsageLimits>,
+    query: web::Query<UsageQuery>,
+) -> ApiResult<HttpResponse> {
+    let claims = get_claims(&req)
+        .ok_or_else(|| ApiError::unauthorized("Not authenticated"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| ApiError::unauthorized("Invalid user ID in token"))?;
+
+    let since = period_start(query.days);
+    let usage = UsageService::user_usage(pool.get_ref(), &limits, user_id, since).await?;
+
+    let memberships: Vec<(Uuid, String)> = sqlx::query_as(
+        r#"
+        SELECT t.id, t.name
+        FROM teams t
+        JOIN team_members tm ON tm.team_id = t.id
+        WHERE tm.user_id = $1
+        ORDER BY t.name
+        "#
+    )
+    .bind(user_id)
+    .fetch_all(pool.get_ref())
+    .await?;
+
+    let mut teams = Vec::with_capacity(memberships.len());
+    for (team_id, team_name) in memberships {
+        // An API key scoped to other teams does not see these
+        if claims.require_team(team_id).is_err() {
+            continue;
+        }
+        if let Some(usage) = UsageService::team_usage(pool.get_ref(), &limits, team_id, since).await? {
+            teams.push(json!({
+                "team_id": team_id,
+                "team_name": team_name,
+                "usage": usage
+            }));
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "since": since,
+        "usage": usage,
+        "teams": teams
+    })))
+}
+
+/// Get a team's usage, with each member's share
+///
+/// GET /api/usage/teams/{id}
+async fn team_usage(
+    member: RequireTeamMember,
+    pool: web::Data<PgPool>,
+    limits: web::Data<UsageLimits>,
+    query: web::Query<UsageQuery>,
+) -> ApiResult<HttpResponse> {
+    let since = period_start(query.days);
+    let usage = UsageService::team_usage(pool.get_ref(), &limits, member.team_id, since)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Team not found"))?;
+    let members = UsageService::team_members(pool.get_ref(), member.team_id, since).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "since": since,
+        "team_id": member.team_id,
+        "usage": usage,
+        "members": members
+    })))
+}
+
+/// Get server-wide usage and the users and teams using the most storage
+///
+/// GET /api/admin/usage
+async fn admin_usage(
+    _admin: RequirePermission<perm::AdminManageUsers>,
+    pool: web::Data<PgPool>,
+    limits: web::Data<UsageLimits>,
+    query: web::Query<UsageQuery>,
+) -> ApiResult<HttpResponse> {
+    let since = period_start(query.days);
+    let limit = query.limit.unwrap_or(DEFAULT_RANKING_LIMIT).clamp(1, MAX_RANKING_LIMIT);
+
+    let (storage, queries) = UsageService::totals(pool.get_ref(), since).await?;
+    let users = UsageService::top_users(pool.get_ref(), since, limit).await?;
+    let teams = UsageService::top_teams(pool.get_ref(), since, limit).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "since": since,
+        "totals": {
+            "storage_bytes": storage.storage_bytes,
+            "file_count": storage.file_count,
+            "query_count": queries.query_count,
+            "compute_ms": queries.compute_ms
+        },
+        "default_quotas": limits.get_ref(),
+        "users": users,
+        "teams": teams
+    })))
+}
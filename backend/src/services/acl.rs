@@ -0,0 +1,285 @@
+//! Resource access grants
+//!
+//! A file may be shared with a specific user or team at an [`AccessLevel`],
+//! optionally until an expiry. Grants are evaluated by
+//! [`PermissionService::can_access_resource`] alongside ownership and team
+//! membership.
+//!
+//! [`PermissionService::can_access_resource`]: crate::services::permissions::PermissionService::can_access_resource
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::services::permissions::Permission;
+
+/// Kind of resource that can be shared
+///
+/// The database type also names dashboards and saved queries, which have no
+/// tables yet; they join here once they are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "grant_resource_type", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceKind {
+    File,
+}
+
+impl ResourceKind {
+    /// Table holding resources of this kind, each with `user_id` and `team_id`
+    fn table(&self) -> &'static str {
+        match self {
+            ResourceKind::File => "files",
+        }
+    }
+}
+
+/// Level of access granted on a resource; each includes the ones below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "access_level", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLevel {
+    /// Read, query and export
+    View,
+    /// Also modify
+    Edit,
+    /// Also delete and share
+    Manage,
+}
+
+impl AccessLevel {
+    /// Lowest level allowing `permission` on a resource; `None` for
+    /// permissions that are not about a single resource
+    pub fn required_for(permission: Permission) -> Option<AccessLevel> {
+        match permission {
+            Permission::DashboardRead
+            | Permission::DatasetRead
+            | Permission::QueryRead
+            | Permission::QueryExecute
+            | Permission::ChartRead
+            | Permission::ChartExport => Some(AccessLevel::View),
+
+            Permission::DashboardCreate
+            | Permission::DashboardUpdate
+            | Permission::DatasetUpload
+            | Permission::DatasetUpdate
+            | Permission::QueryCreate
+            | Permission::ChartCreate
+            | Permission::ChartUpdate => Some(AccessLevel::Edit),
+
+            Permission::DashboardDelete
+            | Permission::DashboardShare
+            | Permission::DatasetDelete
+            | Permission::DatasetShare
+            | Permission::QueryDelete
+            | Permission::ChartDelete => Some(AccessLevel::Manage),
+
+            _ => None,
+        }
+    }
+}
+
+/// Grant on a resource, with its grantee's details
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ResourceGrant {
+    pub id: Uuid,
+    pub resource_type: ResourceKind,
+    pub resource_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub user_email: Option<String>,
+    pub user_name: Option<String>,
+    pub team_id: Option<Uuid>,
+    pub team_name: Option<String>,
+    pub level: AccessLevel,
+    pub granted_by: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// User or team receiving a grant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grantee {
+    User(Uuid),
+    Team(Uuid),
+}
+
+const GRANT_COLUMNS: &str = r#"
+    g.id, g.resource_type, g.resource_id,
+    g.user_id, u.email AS user_email, u.name AS user_name,
+    g.team_id, t.name AS team_name,
+    g.level, g.granted_by, g.expires_at, g.created_at
+"#;
+
+/// Resource grant service
+pub struct AclService;
+
+impl AclService {
+    /// Owner and team of a resource, or `None` if it does not exist
+    pub async fn owner(
+        pool: &PgPool,
+        kind: ResourceKind,
+        resource_id: Uuid,
+    ) -> Result<Option<(Uuid, Option<Uuid>)>, sqlx::Error> {
+        sqlx::query_as(&format!("SELECT user_id, team_id FROM {} WHERE id = $1", kind.table()))
+            .bind(resource_id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Highest unexpired level granted to a user on a resource, directly or
+    /// through a team they belong to
+    pub async fn granted_level(
+        pool: &PgPool,
+        kind: ResourceKind,
+        resource_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<AccessLevel>, sqlx::Error> {
+        let (level,): (Option<AccessLevel>,) = sqlx::query_as(
+            r#"
+            SELECT MAX(level) FROM resource_grants
+            WHERE resource_type = $1 AND resource_id = $2
+              AND (expires_at IS NULL OR expires_at > NOW())
+              AND (user_id = $3 OR team_id IN (SELECT team_id FROM team_members WHERE user_id = $3))
+            "#
+        )
+        .bind(kind)
+        .bind(resource_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(level)
+    }
+
+    /// Unexpired grants on a resource
+    pub async fn list(pool: &PgPool, kind: ResourceKind, resource_id: Uuid) -> Result<Vec<ResourceGrant>, sqlx::Error> {
+        sqlx::query_as(&format!(
+            r#"
+            SELECT {}
+            FROM resource_grants g
+            LEFT JOIN users u ON u.id = g.user_id
+            LEFT JOIN teams t ON t.id = g.team_id
+            WHERE g.resource_type = $1 AND g.resource_id = $2
+              AND (g.expires_at IS NULL OR g.expires_at > NOW())
+            ORDER BY g.level DESC, g.created_at
+            "#,
+            GRANT_COLUMNS
+        ))
+        .bind(kind)
+        .bind(resource_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Ids of resources of a kind shared with a user, directly or through a team
+    pub async fn shared_with(pool: &PgPool, kind: ResourceKind, user_id: Uuid) -> Result<Vec<Uuid>, sqlx::Error> {
+        let rows: Vec<(Uuid,)> = sqlx::query_as(
+            r#"
+            SELECT DISTINCT resource_id FROM resource_grants
+            WHERE resource_type = $1
+              AND (expires_at IS NULL OR expires_at > NOW())
+              AND (user_id = $2 OR team_id IN (SELECT team_id FROM team_members WHERE user_id = $2))
+            "#
+        )
+        .bind(kind)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|(id,)| id).collect())
+    }
+
+    /// Grant access, replacing any existing grant to the same grantee
+    pub async fn grant(
+        pool: &PgPool,
+        kind: ResourceKind,
+        resource_id: Uuid,
+        grantee: Grantee,
+        level: AccessLevel,
+        expires_at: Option<DateTime<Utc>>,
+        granted_by: Uuid,
+    ) -> Result<ResourceGrant, sqlx::Error> {
+        let (user_id, team_id, conflict) = match grantee {
+            Grantee::User(id) => (Some(id), None, "(resource_type, resource_id, user_id) WHERE user_id IS NOT NULL"),
+            Grantee::Team(id) => (None, Some(id), "(resource_type, resource_id, team_id) WHERE team_id IS NOT NULL"),
+        };
+
+        let (grant_id,): (Uuid,) = sqlx::query_as(&format!(
+            r#"
+            INSERT INTO resource_grants (resource_type, resource_id, user_id, team_id, level, expires_at, granted_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT {}
+            DO UPDATE SET level = EXCLUDED.level, expires_at = EXCLUDED.expires_at, granted_by = EXCLUDED.granted_by
+            RETURNING id
+            "#,
+            conflict
+        ))
+        .bind(kind)
+        .bind(resource_id)
+        .bind(user_id)
+        .bind(team_id)
+        .bind(level)
+        .bind(expires_at)
+        .bind(granted_by)
+        .fetch_one(pool)
+        .await?;
+
+        sqlx::query_as(&format!(
+            r#"
+            SELECT {}
+            FROM resource_grants g
+            LEFT JOIN users u ON u.id = g.user_id
+            LEFT JOIN teams t ON t.id = g.team_id
+            WHERE g.id = $1
+            "#,
+            GRANT_COLUMNS
+        ))
+        .bind(grant_id)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Remove a grant on a resource; returns false if there is no such grant
+    pub async fn revoke(pool: &PgPool, kind: ResourceKind, resource_id: Uuid, grant_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM resource_grants WHERE id = $1 AND resource_type = $2 AND resource_id = $3")
+            .bind(grant_id)
+            .bind(kind)
+            .bind(resource_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Remove every grant on a resource, when it is deleted
+    pub async fn revoke_all(pool: &PgPool, kind: ResourceKind, resource_id: Uuid) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM resource_grants WHERE resource_type = $1 AND resource_id = $2")
+            .bind(kind)
+            .bind(resource_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_level() {
+        assert_eq!(AccessLevel::required_for(Permission::DatasetRead), Some(AccessLevel::View));
+        assert_eq!(AccessLevel::required_for(Permission::ChartExport), Some(AccessLevel::View));
+        assert_eq!(AccessLevel::required_for(Permission::DatasetUpdate), Some(AccessLevel::Edit));
+        assert_eq!(AccessLevel::required_for(Permission::DatasetShare), Some(AccessLevel::Manage));
+        assert_eq!(AccessLevel::required_for(Permission::TeamManageMembers), None);
+        assert_eq!(AccessLevel::required_for(Permission::AdminManageUsers), None);
+    }
+
+    #[test]
+    fn test_levels_are_ordered() {
+        assert!(AccessLevel::Manage > AccessLevel::Edit);
+        assert!(AccessLevel::Edit > AccessLevel::View);
+    }
+}
@@ -16,7 +16,12 @@ pub enum AuditAction {
     UserLogout,
     UserRegister,
     UserPasswordReset,
+    UserEmailVerify,
+    UserMfaEnable,
+    UserMfaDisable,
     TokenRefresh,
+    ApiKeyCreate,
+    ApiKeyRevoke,
     
     // Team management
     TeamCreate,
@@ -25,12 +30,28 @@ pub enum AuditAction {
     TeamMemberAdd,
     TeamMemberRemove,
     TeamMemberRoleChange,
+    TeamRoleCreate,
+    TeamRoleUpdate,
+    TeamRoleDelete,
+    TeamInvitationCreate,
+    TeamInvitationResend,
+    TeamInvitationRevoke,
+    TeamInvitationAccept,
+    TeamInvitationDecline,
     
     // File operations
     FileUpload,
     FileDownload,
     FileDelete,
+    FileRetentionPurge,
     FileShare,
+    FileUnshare,
+    FileExport,
+    FileColumnClassify,
+    RowPolicyCreate,
+    RowPolicyUpdate,
+    RowPolicyDelete,
+    RowPolicyTest,
     
     // Dashboard operations
     DashboardCreate,
@@ -55,7 +76,12 @@ impl AuditAction {
             AuditAction::UserLogout => "user.logout",
             AuditAction::UserRegister => "user.register",
             AuditAction::UserPasswordReset => "user.password_reset",
+            AuditAction::UserEmailVerify => "user.email_verify",
+            AuditAction::UserMfaEnable => "user.mfa_enable",
+            AuditAction::UserMfaDisable => "user.mfa_disable",
             AuditAction::TokenRefresh => "user.token_refresh",
+            AuditAction::ApiKeyCreate => "api_key.create",
+            AuditAction::ApiKeyRevoke => "api_key.revoke",
             
             AuditAction::TeamCreate => "team.create",
             AuditAction::TeamUpdate => "team.update",
@@ -63,11 +89,27 @@ impl AuditAction {
             AuditAction::TeamMemberAdd => "team.member_add",
             AuditAction::TeamMemberRemove => "team.member_remove",
             AuditAction::TeamMemberRoleChange => "team.member_role_change",
+            AuditAction::TeamRoleCreate => "team.role_create",
+            AuditAction::TeamRoleUpdate => "team.role_update",
+            AuditAction::TeamRoleDelete => "team.role_delete",
+            AuditAction::TeamInvitationCreate => "team.invitation_create",
+            AuditAction::TeamInvitationResend => "team.invitation_resend",
+            AuditAction::TeamInvitationRevoke => "team.invitation_revoke",
+            AuditAction::TeamInvitationAccept => "team.invitation_accept",
+            AuditAction::TeamInvitationDecline => "team.invitation_decline",
             
             AuditAction::FileUpload => "file.upload",
             AuditAction::FileDownload => "file.download",
             AuditAction::FileDelete => "file.delete",
+            AuditAction::FileRetentionPurge => "file.retention_purge",
             AuditAction::FileShare => "file.share",
+            AuditAction::FileUnshare => "file.unshare",
+            AuditAction::FileExport => "file.export",
+            AuditAction::FileColumnClassify => "file.column_classify",
+            AuditAction::RowPolicyCreate => "row_policy.create",
+            AuditAction::RowPolicyUpdate => "row_policy.update",
+            AuditAction::RowPolicyDelete => "row_policy.delete",
+            AuditAction::RowPolicyTest => "row_policy.test",
             
             AuditAction::DashboardCreate => "dashboard.create",
             AuditAction::DashboardUpdate => "dashboard.update",
@@ -93,6 +135,10 @@ pub enum ResourceType {
     Dashboard,
     Query,
     Settings,
+    ApiKey,
+    Role,
+    RowPolicy,
+    Invitation,
 }
 
 impl ResourceType {
@@ -104,6 +150,10 @@ impl ResourceType {
             ResourceType::Dashboard => "dashboard",
             ResourceType::Query => "query",
             ResourceType::Settings => "settings",
+            ResourceType::ApiKey => "api_key",
+            ResourceType::Role => "role",
+            ResourceType::RowPolicy => "row_policy",
+            ResourceType::Invitation => "invitation",
         }
     }
 }
@@ -130,7 +180,7 @@ impl AuditService {
         sqlx::query(
             r#"
             INSERT INTO audit_log (user_id, team_id, action, resource_type, resource_id, details, ip_address, user_agent)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            VALUES ($1, $2, $3, $4, $5, $6, $7::inet, $8)
             "#
         )
         .bind(entry.user_id)
@@ -196,7 +246,7 @@ impl AuditService {
     ) -> Result<Vec<AuditLogRecord>, sqlx::Error> {
         sqlx::query_as::<_, AuditLogRecord>(
             r#"
-            SELECT id, user_id, team_id, action, resource_type, resource_id, details, ip_address, user_agent, created_at
+            SELECT id, user_id, team_id, action, resource_type, resource_id, details, host(ip_address) AS ip_address, user_agent, created_at
             FROM audit_log
             WHERE user_id = $1
             ORDER BY created_at DESC
@@ -219,7 +269,7 @@ impl AuditService {
     ) -> Result<Vec<AuditLogRecord>, sqlx::Error> {
         sqlx::query_as::<_, AuditLogRecord>(
             r#"
-            SELECT id, user_id, team_id, action, resource_type, resource_id, details, ip_address, user_agent, created_at
+            SELECT id, user_id, team_id, action, resource_type, resource_id, details, host(ip_address) AS ip_address, user_agent, created_at
             FROM audit_log
             WHERE team_id = $1
             ORDER BY created_at DESC
@@ -252,6 +302,7 @@ pub struct AuditLogRecord {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_db::TestDb;
 
     #[test]
     fn test_action_strings() {
@@ -266,5 +317,36 @@ mod tests {
         assert_eq!(ResourceType::Team.as_str(), "team");
         assert_eq!(ResourceType::File.as_str(), "file");
     }
-}
 
+    #[actix_rt::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_log_round_trips_ip_address() {
+        let db = TestDb::create().await;
+        let pool = &db.pool;
+
+        let user_id: Uuid =
+            sqlx::query_scalar("INSERT INTO users (email, password_hash, name) VALUES ('ada@example.com', 'x', 'Ada') RETURNING id")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        AuditService::log(pool, AuditEntry {
+            user_id: Some(user_id),
+            team_id: None,
+            action: AuditAction::UserLogin,
+            resource_type: Some(ResourceType::User),
+            resource_id: Some(user_id),
+            details: None,
+            ip_address: Some("203.0.113.7".parse().unwrap()),
+            user_agent: Some("curl/8.0".to_string()),
+        })
+        .await
+        .unwrap();
+
+        let logs = AuditService::get_user_logs(pool, user_id, 10, 0).await.unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].action, "user.login");
+        assert_eq!(logs[0].ip_address.as_deref(), Some("203.0.113.7"));
+
+        db.drop().await;
+    }
+}
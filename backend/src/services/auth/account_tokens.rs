@@ -0,0 +1,100 @@
+//! Emailed account tokens
+//!
+//! Email verification and password reset links carry an opaque token that is
+//! stored only as a SHA-256 hash, expires, and can be redeemed once. Issuing a
+//! new token voids the user's earlier unused tokens for the same purpose, and
+//! a token only redeems while the account still has the address it was sent
+//! to.
+
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use super::{random_token, sha256_hex};
+
+/// What an account token authorizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenPurpose {
+    EmailVerification,
+    PasswordReset,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::EmailVerification => "email_verification",
+            TokenPurpose::PasswordReset => "password_reset",
+        }
+    }
+
+    /// How long an issued token stays valid
+    pub fn ttl(&self) -> Duration {
+        match self {
+            TokenPurpose::EmailVerification => Duration::hours(48),
+            TokenPurpose::PasswordReset => Duration::hours(1),
+        }
+    }
+}
+
+/// Account token service
+pub struct AccountTokenService;
+
+impl AccountTokenService {
+    /// Issue a token for `user_id` bound to `email`, voiding earlier unused
+    /// tokens with the same purpose
+    pub async fn issue(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        user_id: Uuid,
+        email: &str,
+        purpose: TokenPurpose,
+    ) -> Result<String, sqlx::Error> {
+        sqlx::query("DELETE FROM account_tokens WHERE user_id = $1 AND purpose = $2 AND used_at IS NULL")
+            .bind(user_id)
+            .bind(purpose.as_str())
+            .execute(&mut **tx)
+            .await?;
+
+        let token = random_token(32);
+        sqlx::query(
+            r#"
+            INSERT INTO account_tokens (token_hash, user_id, purpose, email, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#
+        )
+        .bind(sha256_hex(&token))
+        .bind(user_id)
+        .bind(purpose.as_str())
+        .bind(email)
+        .bind(Utc::now() + purpose.ttl())
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(token)
+    }
+
+    /// Redeem a token, returning its user if it was valid and unused
+    pub async fn redeem(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        token: &str,
+        purpose: TokenPurpose,
+    ) -> Result<Option<Uuid>, sqlx::Error> {
+        let redeemed: Option<(Uuid,)> = sqlx::query_as(
+            r#"
+            UPDATE account_tokens t SET used_at = NOW()
+            FROM users u
+            WHERE t.token_hash = $1
+              AND t.purpose = $2
+              AND t.used_at IS NULL
+              AND t.expires_at > NOW()
+              AND u.id = t.user_id
+              AND u.email = t.email
+            RETURNING t.user_id
+            "#
+        )
+        .bind(sha256_hex(token))
+        .bind(purpose.as_str())
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        Ok(redeemed.map(|(user_id,)| user_id))
+    }
+}
@@ -0,0 +1,271 @@
+//! API keys
+//!
+//! Long-lived credentials for scripts and CI, presented in place of a JWT.
+//! A key is `pba_` followed by 256 random bits; only its SHA-256 hash and a
+//! short display prefix are stored, so the secret is shown once, at creation.
+//!
+//! Personal keys act as their owner. Team keys are managed by the team's
+//! admins and act as the member who created them, bounded by that member's
+//! current role in the team; they stop working if the creator leaves.
+//! Either kind may be limited to a subset of permissions and may expire.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+use super::{random_token, sha256_hex};
+use crate::services::permissions::{Permission, PermissionService};
+
+/// Prefix identifying an API key, as opposed to a JWT
+pub const API_KEY_PREFIX: &str = "pba_";
+
+/// Characters of the key kept for display, including the prefix
+pub const DISPLAY_PREFIX_LEN: usize = 12;
+
+/// Keys a user or team may hold at once
+pub const MAX_KEYS_PER_OWNER: i64 = 50;
+
+/// Whether a bearer credential is an API key
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(API_KEY_PREFIX)
+}
+
+/// API key as listed to its owner
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub team_id: Option<Uuid>,
+    pub name: String,
+    pub key_prefix: String,
+    pub permissions: Option<Vec<String>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub last_used_ip: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Parameters of a new key
+#[derive(Debug, Clone)]
+pub struct NewApiKey {
+    pub name: String,
+    pub team_id: Option<Uuid>,
+    /// `None` for all of the owner's permissions
+    pub permissions: Option<Vec<Permission>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// What a request authenticated with an API key may do
+#[derive(Debug, Clone)]
+pub struct ApiKeyScope {
+    pub key_id: Uuid,
+    /// Team a team key is confined to
+    pub team_id: Option<Uuid>,
+    /// Permissions the key allows, `None` when unrestricted. Team keys are
+    /// always restricted to their creator's permissions in the team.
+    pub permissions: Option<HashSet<Permission>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKeyScope {
+    pub fn allows(&self, permission: Permission) -> bool {
+        self.permissions.as_ref().is_none_or(|p| p.contains(&permission))
+    }
+}
+
+/// Principal of an authenticated API key
+#[derive(Debug, Clone)]
+pub struct ApiKeyPrincipal {
+    pub user_id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub scope: ApiKeyScope,
+}
+
+/// Key row joined with its owner: (id, user_id, team_id, permissions, expires_at, email, name)
+type KeyRow = (Uuid, Uuid, Option<Uuid>, Option<Vec<String>>, Option<DateTime<Utc>>, String, String);
+
+/// API key management service
+pub struct ApiKeyService;
+
+impl ApiKeyService {
+    /// Create a key and return it with its secret, which is not stored
+    pub async fn create(pool: &PgPool, user_id: Uuid, key: &NewApiKey) -> Result<(ApiKey, String), sqlx::Error> {
+        let secret = format!("{}{}", API_KEY_PREFIX, random_token(32));
+        let permissions = key
+            .permissions
+            .as_ref()
+            .map(|p| p.iter().map(|p| p.as_str().to_string()).collect::<Vec<_>>());
+
+        let record: ApiKey = sqlx::query_as(
+            r#"
+            INSERT INTO api_keys (user_id, team_id, name, key_prefix, key_hash, permissions, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, user_id, team_id, name, key_prefix, permissions, expires_at, last_used_at, last_used_ip, created_at
+            "#
+        )
+        .bind(user_id)
+        .bind(key.team_id)
+        .bind(&key.name)
+        .bind(&secret[..DISPLAY_PREFIX_LEN])
+        .bind(sha256_hex(&secret))
+        .bind(permissions)
+        .bind(key.expires_at)
+        .fetch_one(pool)
+        .await?;
+
+        Ok((record, secret))
+    }
+
+    /// Active keys held by a user (`team_id` `None`) or a team
+    pub async fn list(pool: &PgPool, user_id: Uuid, team_id: Option<Uuid>) -> Result<Vec<ApiKey>, sqlx::Error> {
+        let owner = if team_id.is_some() { "team_id = $2" } else { "user_id = $1 AND team_id IS NULL" };
+        sqlx::query_as(&format!(
+            r#"
+            SELECT id, user_id, team_id, name, key_prefix, permissions, expires_at, last_used_at, last_used_ip, created_at
+            FROM api_keys
+            WHERE {} AND revoked_at IS NULL
+            ORDER BY created_at DESC
+            "#,
+            owner
+        ))
+        .bind(user_id)
+        .bind(team_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Number of active keys held by a user or team
+    pub async fn count(pool: &PgPool, user_id: Uuid, team_id: Option<Uuid>) -> Result<i64, sqlx::Error> {
+        let owner = if team_id.is_some() { "team_id = $2" } else { "user_id = $1 AND team_id IS NULL" };
+        let (count,): (i64,) = sqlx::query_as(&format!(
+            "SELECT COUNT(*) FROM api_keys WHERE {} AND revoked_at IS NULL",
+            owner
+        ))
+        .bind(user_id)
+        .bind(team_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Revoke a key held by a user or team; returns false if there is no such active key
+    pub async fn revoke(pool: &PgPool, key_id: Uuid, user_id: Uuid, team_id: Option<Uuid>) -> Result<bool, sqlx::Error> {
+        let owner = if team_id.is_some() { "team_id = $3" } else { "user_id = $2 AND team_id IS NULL" };
+        let result = sqlx::query(&format!(
+            "UPDATE api_keys SET revoked_at = NOW() WHERE id = $1 AND {} AND revoked_at IS NULL",
+            owner
+        ))
+        .bind(key_id)
+        .bind(user_id)
+        .bind(team_id)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Resolve a presented key, recording its use. Revoked and expired keys,
+    /// keys of disabled accounts, and team keys whose creator has left the
+    /// team yield `None`.
+    pub async fn authenticate(
+        pool: &PgPool,
+        secret: &str,
+        ip_address: Option<IpAddr>,
+    ) -> Result<Option<ApiKeyPrincipal>, sqlx::Error> {
+        if !is_api_key(secret) {
+            return Ok(None);
+        }
+
+        let row: Option<KeyRow> = sqlx::query_as(
+            r#"
+            SELECT k.id, k.user_id, k.team_id, k.permissions, k.expires_at, u.email, u.name
+            FROM api_keys k
+            JOIN users u ON u.id = k.user_id
+            WHERE k.key_hash = $1
+              AND k.revoked_at IS NULL
+              AND (k.expires_at IS NULL OR k.expires_at > NOW())
+              AND u.status = 'active'
+            "#
+        )
+        .bind(sha256_hex(secret))
+        .fetch_optional(pool)
+        .await?;
+
+        let Some((key_id, user_id, team_id, stored, expires_at, email, name)) = row else {
+            return Ok(None);
+        };
+
+        // Unknown strings (e.g. from a newer release) grant nothing
+        let mut permissions: Option<HashSet<Permission>> =
+            stored.map(|p| p.iter().filter_map(|s| Permission::parse(s)).collect());
+
+        if let Some(team_id) = team_id {
+            let team_permissions = PermissionService::get_team_permissions(pool, user_id, team_id).await?;
+            if team_permissions.is_empty() {
+                return Ok(None);
+            }
+            permissions = Some(match permissions {
+                Some(granted) => granted.intersection(&team_permissions).copied().collect(),
+                None => team_permissions,
+            });
+        }
+
+        // Record use, at most once a minute per key
+        sqlx::query(
+            r#"
+            UPDATE api_keys SET last_used_at = NOW(), last_used_ip = $2
+            WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')
+            "#
+        )
+        .bind(key_id)
+        .bind(ip_address.map(|ip| ip.to_string()))
+        .execute(pool)
+        .await?;
+
+        Ok(Some(ApiKeyPrincipal {
+            user_id,
+            email,
+            name,
+            scope: ApiKeyScope {
+                key_id,
+                team_id,
+                permissions,
+                expires_at,
+            },
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_allows() {
+        let unrestricted = ApiKeyScope {
+            key_id: Uuid::new_v4(),
+            team_id: None,
+            permissions: None,
+            expires_at: None,
+        };
+        assert!(unrestricted.allows(Permission::DatasetDelete));
+
+        let restricted = ApiKeyScope {
+            permissions: Some(HashSet::from([Permission::DatasetUpload, Permission::QueryExecute])),
+            ..unrestricted
+        };
+        assert!(restricted.allows(Permission::DatasetUpload));
+        assert!(!restricted.allows(Permission::DatasetDelete));
+    }
+
+    #[test]
+    fn test_is_api_key() {
+        assert!(is_api_key("pba_abc"));
+        assert!(!is_api_key("eyJhbGciOiJFZERTQSJ9.e30.sig"));
+    }
+}
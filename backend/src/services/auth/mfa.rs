@@ -0,0 +1,333 @@
+//! Multi-factor authentication
+//!
+//! TOTP enrollment, recovery codes and the login challenge. Enrollment is a
+//! two-step process: a secret is stored as pending, and only becomes active
+//! once the user proves their authenticator produces matching codes. Teams
+//! can require MFA (`settings.require_mfa`), in which case members without an
+//! active authenticator are denied access to the team.
+
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{random_token, sha256_hex, totp};
+
+/// How long a password login may take to answer its challenge
+pub const CHALLENGE_TTL_MINUTES: i64 = 5;
+
+/// Wrong codes accepted per challenge before it is void
+pub const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+
+/// Recovery codes issued on activation or regeneration
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Issuer shown in authenticator apps
+pub const TOTP_ISSUER: &str = "PilotBA";
+
+/// How a second factor was proven
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MfaMethod {
+    Totp,
+    RecoveryCode,
+}
+
+/// A user's MFA state
+#[derive(Debug, Clone, Serialize)]
+pub struct MfaStatus {
+    pub enabled: bool,
+    pub recovery_codes_remaining: i64,
+}
+
+/// Team that requires its members to use MFA
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct RequiringTeam {
+    pub id: Uuid,
+    pub name: String,
+}
+
+/// Authenticator row: (secret, active, last_used_step)
+type AuthenticatorRow = (String, bool, Option<i64>);
+
+/// MFA service
+pub struct MfaService;
+
+impl MfaService {
+    /// Whether the user has an active authenticator
+    pub async fn is_enabled(pool: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let (enabled,): (bool,) = sqlx::query_as(
+            "SELECT EXISTS (SELECT 1 FROM user_mfa WHERE user_id = $1 AND enabled_at IS NOT NULL)"
+        )
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(enabled)
+    }
+
+    pub async fn status(pool: &PgPool, user_id: Uuid) -> Result<MfaStatus, sqlx::Error> {
+        let (recovery_codes_remaining,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM mfa_recovery_codes WHERE user_id = $1 AND used_at IS NULL"
+        )
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(MfaStatus {
+            enabled: Self::is_enabled(pool, user_id).await?,
+            recovery_codes_remaining,
+        })
+    }
+
+    /// Store a new pending secret, replacing any earlier pending one; returns
+    /// None if an authenticator is already active
+    pub async fn start_enrollment(pool: &PgPool, user_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+        let secret = totp::generate_secret();
+
+        let stored: Option<(String,)> = sqlx::query_as(
+            r#"
+            INSERT INTO user_mfa (user_id, totp_secret) VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE
+                SET totp_secret = EXCLUDED.totp_secret, last_used_step = NULL, created_at = NOW()
+                WHERE user_mfa.enabled_at IS NULL
+            RETURNING totp_secret
+            "#
+        )
+        .bind(user_id)
+        .bind(&secret)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(stored.map(|(secret,)| secret))
+    }
+
+    /// Activate the pending authenticator if `code` matches it, returning
+    /// fresh recovery codes
+    pub async fn activate(pool: &PgPool, user_id: Uuid, code: &str) -> Result<Option<Vec<String>>, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        let pending: Option<(String,)> = sqlx::query_as(
+            "SELECT totp_secret FROM user_mfa WHERE user_id = $1 AND enabled_at IS NULL FOR UPDATE"
+        )
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let step = pending.and_then(|(secret,)| totp::verify(&secret, code, Utc::now().timestamp(), None));
+        let Some(step) = step else {
+            return Ok(None);
+        };
+
+        sqlx::query("UPDATE user_mfa SET enabled_at = NOW(), last_used_step = $2 WHERE user_id = $1")
+            .bind(user_id)
+            .bind(step)
+            .execute(&mut *tx)
+            .await?;
+
+        let codes = Self::replace_recovery_codes(&mut tx, user_id).await?;
+        tx.commit().await?;
+
+        Ok(Some(codes))
+    }
+
+    /// Check a TOTP or recovery code against the active authenticator,
+    /// consuming it so it cannot be used again
+    pub async fn verify_code(pool: &PgPool, user_id: Uuid, code: &str) -> Result<Option<MfaMethod>, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        let authenticator: Option<AuthenticatorRow> = sqlx::query_as(
+            "SELECT totp_secret, enabled_at IS NOT NULL, last_used_step FROM user_mfa WHERE user_id = $1 FOR UPDATE"
+        )
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some((secret, true, last_used_step)) = authenticator else {
+            return Ok(None);
+        };
+
+        if let Some(step) = totp::verify(&secret, code, Utc::now().timestamp(), last_used_step) {
+            sqlx::query("UPDATE user_mfa SET last_used_step = $2 WHERE user_id = $1")
+                .bind(user_id)
+                .bind(step)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            return Ok(Some(MfaMethod::Totp));
+        }
+
+        let used = sqlx::query(
+            r#"
+            UPDATE mfa_recovery_codes SET used_at = NOW()
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+            "#
+        )
+        .bind(user_id)
+        .bind(sha256_hex(&normalize_recovery_code(code)))
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok((used.rows_affected() > 0).then_some(MfaMethod::RecoveryCode))
+    }
+
+    /// Replace the user's recovery codes
+    pub async fn regenerate_recovery_codes(pool: &PgPool, user_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        let codes = Self::replace_recovery_codes(&mut tx, user_id).await?;
+        tx.commit().await?;
+        Ok(codes)
+    }
+
+    /// Remove the authenticator and recovery codes
+    pub async fn disable(pool: &PgPool, user_id: Uuid) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM user_mfa WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM mfa_recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Issue a challenge token for a user who passed the password check
+    pub async fn create_challenge(pool: &PgPool, user_id: Uuid) -> Result<String, sqlx::Error> {
+        sqlx::query("DELETE FROM mfa_challenges WHERE expires_at < NOW()")
+            .execute(pool)
+            .await?;
+
+        let token = random_token(32);
+        sqlx::query("INSERT INTO mfa_challenges (token_hash, user_id, expires_at) VALUES ($1, $2, $3)")
+            .bind(sha256_hex(&token))
+            .bind(user_id)
+            .bind(Utc::now() + Duration::minutes(CHALLENGE_TTL_MINUTES))
+            .execute(pool)
+            .await?;
+
+        Ok(token)
+    }
+
+    /// Count an attempt at a challenge, returning its user while it is
+    /// unexpired and under the attempt limit
+    pub async fn attempt_challenge(pool: &PgPool, token: &str) -> Result<Option<Uuid>, sqlx::Error> {
+        let user: Option<(Uuid,)> = sqlx::query_as(
+            r#"
+            UPDATE mfa_challenges SET attempts = attempts + 1
+            WHERE token_hash = $1 AND expires_at > NOW() AND attempts < $2
+            RETURNING user_id
+            "#
+        )
+        .bind(sha256_hex(token))
+        .bind(MAX_CHALLENGE_ATTEMPTS)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(user.map(|(user_id,)| user_id))
+    }
+
+    /// Remove an answered challenge
+    pub async fn complete_challenge(pool: &PgPool, token: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM mfa_challenges WHERE token_hash = $1")
+            .bind(sha256_hex(token))
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Teams the user belongs to that require MFA
+    pub async fn requiring_teams(pool: &PgPool, user_id: Uuid) -> Result<Vec<RequiringTeam>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT t.id, t.name
+            FROM teams t
+            JOIN team_members tm ON tm.team_id = t.id
+            WHERE tm.user_id = $1 AND COALESCE((t.settings->>'require_mfa')::boolean, false)
+            ORDER BY t.name
+            "#
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Whether the user satisfies the team's MFA requirement (trivially true
+    /// when the team does not require it)
+    pub async fn meets_team_requirement(pool: &PgPool, user_id: Uuid, team_id: Uuid) -> Result<bool, sqlx::Error> {
+        let (met,): (bool,) = sqlx::query_as(
+            r#"
+            SELECT NOT EXISTS (
+                    SELECT 1 FROM teams
+                    WHERE id = $2 AND COALESCE((settings->>'require_mfa')::boolean, false)
+                )
+                OR EXISTS (SELECT 1 FROM user_mfa WHERE user_id = $1 AND enabled_at IS NOT NULL)
+            "#
+        )
+        .bind(user_id)
+        .bind(team_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(met)
+    }
+
+    async fn replace_recovery_codes(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        user_id: Uuid,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query("DELETE FROM mfa_recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await?;
+
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| generate_recovery_code()).collect();
+        for code in &codes {
+            sqlx::query("INSERT INTO mfa_recovery_codes (code_hash, user_id) VALUES ($1, $2)")
+                .bind(sha256_hex(&normalize_recovery_code(code)))
+                .bind(user_id)
+                .execute(&mut **tx)
+                .await?;
+        }
+
+        Ok(codes)
+    }
+}
+
+/// Random recovery code such as `k3f9q-x7m2p` (50 bits)
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 7];
+    rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut bytes);
+    let encoded = totp::base32_encode(&bytes).to_lowercase();
+    format!("{}-{}", &encoded[..5], &encoded[5..10])
+}
+
+/// Lower-case with separators and spaces removed, as users may retype it
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recovery_codes() {
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), 11);
+        assert_eq!(&code[5..6], "-");
+        assert_ne!(code, generate_recovery_code());
+
+        assert_eq!(normalize_recovery_code(" K3F9Q-x7m2p "), "k3f9qx7m2p");
+        assert_eq!(
+            sha256_hex(&normalize_recovery_code(&code.to_uppercase())),
+            sha256_hex(&normalize_recovery_code(&code))
+        );
+    }
+}
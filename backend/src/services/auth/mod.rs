@@ -1,3 +1,49 @@
-// Authentication service - will be implemented in detail
+//! Authentication services
+//!
+//! External identity providers, login sessions and helpers shared by the
+//! auth routes.
 
+pub mod account_tokens;
+pub mod api_keys;
+pub mod keys;
+pub mod mfa;
+pub mod oidc;
+pub mod saml;
+pub mod sessions;
+pub mod totp;
+pub mod xmldsig;
 
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Random URL-safe token with `bytes` bytes of entropy
+pub fn random_token(bytes: usize) -> String {
+    let mut buffer = vec![0u8; bytes];
+    rand::thread_rng().fill_bytes(&mut buffer);
+    URL_SAFE_NO_PAD.encode(buffer)
+}
+
+/// Lower-case hex SHA-256 digest, used to store tokens without keeping them
+pub fn sha256_hex(input: &str) -> String {
+    Sha256::digest(input.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_ne!(random_token(32), random_token(32));
+        assert_eq!(random_token(32).len(), 43);
+    }
+}
@@ -0,0 +1,481 @@
+//! OpenID Connect relying party
+//!
+//! Generic authorization-code flow with PKCE: provider discovery,
+//! authorization URL construction, code exchange and ID-token validation
+//! against the provider's JWKS. Provider metadata and keys are fetched lazily
+//! and cached; the key set is refetched when a token names an unknown key ID,
+//! at most once per [`JWKS_REFRESH_INTERVAL`], so provider key rotation needs
+//! no restart and forged key IDs cannot hammer the provider.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use url::Url;
+
+use super::random_token;
+
+/// Minimum time between JWKS fetches triggered by unknown key IDs
+pub const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Errors raised during an OIDC login
+#[derive(Error, Debug)]
+pub enum OidcError {
+    #[error("Provider discovery failed: {0}")]
+    Discovery(String),
+
+    #[error("Token exchange failed: {0}")]
+    TokenExchange(String),
+
+    #[error("Invalid ID token: {0}")]
+    InvalidIdToken(String),
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+}
+
+pub type OidcResult<T> = Result<T, OidcError>;
+
+/// Relying-party configuration
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    /// Provider name recorded on linked identities
+    pub provider: String,
+    /// Issuer URL; discovery is served from `{issuer}/.well-known/openid-configuration`
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    /// Callback URL registered with the provider
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    /// Create accounts for unknown users (otherwise only existing users can link)
+    pub allow_signup: bool,
+}
+
+impl OidcConfig {
+    /// Load configuration from `OIDC_*` environment variables; `None` when
+    /// OIDC is not configured
+    pub fn from_env() -> Option<Self> {
+        let issuer = std::env::var("OIDC_ISSUER_URL").ok()?;
+        let client_id = std::env::var("OIDC_CLIENT_ID").ok()?;
+        let redirect_uri = std::env::var("OIDC_REDIRECT_URI").ok()?;
+
+        Some(OidcConfig {
+            provider: std::env::var("OIDC_PROVIDER_NAME").unwrap_or_else(|_| "oidc".to_string()),
+            issuer,
+            client_id,
+            client_secret: std::env::var("OIDC_CLIENT_SECRET").ok(),
+            redirect_uri,
+            scopes: std::env::var("OIDC_SCOPES")
+                .map(|s| s.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_else(|_| vec!["openid".into(), "email".into(), "profile".into()]),
+            allow_signup: std::env::var("OIDC_ALLOW_SIGNUP")
+                .map(|v| v != "false")
+                .unwrap_or(true),
+        })
+    }
+}
+
+/// Subset of the provider discovery document used by the flow
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// PKCE verifier and its S256 challenge
+#[derive(Debug, Clone)]
+pub struct PkceChallenge {
+    pub verifier: String,
+    pub challenge: String,
+}
+
+impl PkceChallenge {
+    /// Generate a fresh random verifier
+    pub fn new() -> Self {
+        Self::from_verifier(random_token(32))
+    }
+
+    pub fn from_verifier(verifier: String) -> Self {
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        PkceChallenge { verifier, challenge }
+    }
+}
+
+impl Default for PkceChallenge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Validated ID-token claims
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: Option<bool>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub nonce: Option<String>,
+}
+
+impl IdTokenClaims {
+    /// Email address, only if the provider asserts it is verified
+    pub fn verified_email(&self) -> Option<String> {
+        match (&self.email, self.email_verified) {
+            (Some(email), Some(true)) => Some(email.to_lowercase()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    #[serde(default)]
+    id_token: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+/// OIDC client for a single provider
+pub struct OidcClient {
+    config: OidcConfig,
+    http: reqwest::Client,
+    metadata: RwLock<Option<ProviderMetadata>>,
+    jwks: RwLock<Option<CachedJwks>>,
+}
+
+/// Provider key set and when it was fetched
+struct CachedJwks {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Self {
+        OidcClient {
+            config,
+            http: reqwest::Client::new(),
+            metadata: RwLock::new(None),
+            jwks: RwLock::new(None),
+        }
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    /// Provider metadata, discovered on first use
+    pub async fn metadata(&self) -> OidcResult<ProviderMetadata> {
+        if let Some(metadata) = self.metadata.read().await.as_ref() {
+            return Ok(metadata.clone());
+        }
+
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer.trim_end_matches('/')
+        );
+        let response = self.http.get(&url).send().await?;
+        if !response.status().is_success() {
+            return Err(OidcError::Discovery(format!("{} returned {}", url, response.status())));
+        }
+        let metadata: ProviderMetadata = response.json().await?;
+
+        if metadata.issuer.trim_end_matches('/') != self.config.issuer.trim_end_matches('/') {
+            return Err(OidcError::Discovery(format!(
+                "issuer mismatch: expected {}, got {}",
+                self.config.issuer, metadata.issuer
+            )));
+        }
+
+        *self.metadata.write().await = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    /// Build the provider authorization URL for a login attempt
+    pub async fn authorization_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        pkce: &PkceChallenge,
+    ) -> OidcResult<String> {
+        let metadata = self.metadata().await?;
+        let mut url = Url::parse(&metadata.authorization_endpoint)
+            .map_err(|e| OidcError::Discovery(format!("invalid authorization endpoint: {}", e)))?;
+
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_uri)
+            .append_pair("scope", &self.config.scopes.join(" "))
+            .append_pair("state", state)
+            .append_pair("nonce", nonce)
+            .append_pair("code_challenge", &pkce.challenge)
+            .append_pair("code_challenge_method", "S256");
+
+        Ok(url.into())
+    }
+
+    /// Exchange an authorization code for the raw ID token
+    pub async fn exchange_code(&self, code: &str, code_verifier: &str) -> OidcResult<String> {
+        let metadata = self.metadata().await?;
+
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            params.push(("client_secret", secret.as_str()));
+        }
+
+        let response = self.http.post(&metadata.token_endpoint).form(&params).send().await?;
+        let status = response.status();
+        let body: TokenResponse = response.json().await?;
+
+        if !status.is_success() || body.error.is_some() {
+            return Err(OidcError::TokenExchange(
+                body.error_description
+                    .or(body.error)
+                    .unwrap_or_else(|| format!("token endpoint returned {}", status)),
+            ));
+        }
+
+        body.id_token
+            .ok_or_else(|| OidcError::TokenExchange("response contained no id_token".to_string()))
+    }
+
+    /// Validate an ID token's signature, issuer, audience, expiry and nonce
+    pub async fn validate_id_token(&self, id_token: &str, nonce: &str) -> OidcResult<IdTokenClaims> {
+        let metadata = self.metadata().await?;
+        let header = decode_header(id_token)
+            .map_err(|e| OidcError::InvalidIdToken(e.to_string()))?;
+
+        if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+            return Err(OidcError::InvalidIdToken(format!(
+                "unsupported algorithm {:?}",
+                header.alg
+            )));
+        }
+
+        let key = match self.decoding_key(&metadata, header.kid.as_deref(), false).await? {
+            Some(key) => key,
+            // Unknown key ID: the provider may have rotated its keys
+            None => self
+                .decoding_key(&metadata, header.kid.as_deref(), true)
+                .await?
+                .ok_or_else(|| OidcError::InvalidIdToken("signing key not found".to_string()))?,
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_issuer(&[&metadata.issuer]);
+
+        let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(|e| OidcError::InvalidIdToken(e.to_string()))?
+            .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(OidcError::InvalidIdToken("nonce mismatch".to_string()));
+        }
+
+        Ok(claims)
+    }
+
+    async fn decoding_key(
+        &self,
+        metadata: &ProviderMetadata,
+        kid: Option<&str>,
+        refresh: bool,
+    ) -> OidcResult<Option<DecodingKey>> {
+        {
+            // Re-checked under the write lock so concurrent misses fetch once
+            let mut cached = self.jwks.write().await;
+            let stale = match cached.as_ref() {
+                None => true,
+                Some(cached) => refresh && cached.fetched_at.elapsed() >= JWKS_REFRESH_INTERVAL,
+            };
+            if stale {
+                let keys: JwkSet = self.http.get(&metadata.jwks_uri).send().await?.json().await?;
+                *cached = Some(CachedJwks {
+                    keys,
+                    fetched_at: Instant::now(),
+                });
+            }
+        }
+
+        let guard = self.jwks.read().await;
+        let jwks = &guard.as_ref().expect("JWKS loaded above").keys;
+        let jwk = match kid {
+            Some(kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        };
+
+        jwk.map(|jwk| {
+            DecodingKey::from_jwk(jwk).map_err(|e| OidcError::InvalidIdToken(e.to_string()))
+        })
+        .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const IDP_KEY: &str = include_str!("testdata/idp_rsa.pem");
+    const IDP_MODULUS: &str = "tyeO1TKTZzs8WCxK9Si6kbHn_GeLL97Txl5yPXR9dRaGKZW8TkY393TW53YerO7UaUfUkz6_1dXh7Xfvan8iIr9a2k1r6dkDuneU_-tMnoWIYEx5Jv7aJZFiQitigldypvKsOqyBvN39NHQQ_IUBkLl9tkMe_JP-4tbhm2rSdeEK-Rk-EbjZT5WTj5O73InIJoqpJpkzhCvbcuSismdNaeawRr7MMtJEtXLwg2XfH2Tk0DBFx7pE94Ewepg2HEAlkJrRLF-TwVsIxWUAhLZ7V2O1aqOTfryHglhBbgoz1xnfDg2vNDdZyxHcifGoMF3Vh_tSn--X6ISiGwxqHV2G1w";
+
+    fn id_token(issuer: &str, nonce: &str) -> String {
+        id_token_with_kid(issuer, nonce, "test-key")
+    }
+
+    fn id_token_with_kid(issuer: &str, nonce: &str, kid: &str) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.to_string());
+        let claims = json!({
+            "iss": issuer,
+            "aud": "pilotba",
+            "sub": "idp-user-1",
+            "email": "Jane@Example.com",
+            "email_verified": true,
+            "name": "Jane",
+            "nonce": nonce,
+            "iat": chrono::Utc::now().timestamp(),
+            "exp": chrono::Utc::now().timestamp() + 300,
+        });
+        encode(&header, &claims, &EncodingKey::from_rsa_pem(IDP_KEY.as_bytes()).unwrap()).unwrap()
+    }
+
+    /// Mock IdP serving discovery, JWKS and a token endpoint
+    async fn mock_idp(nonce: &str) -> (MockServer, OidcClient) {
+        let server = MockServer::start().await;
+        let issuer = server.uri();
+
+        Mock::given(method("GET"))
+            .and(path("/.well-known/openid-configuration"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "issuer": issuer,
+                "authorization_endpoint": format!("{}/authorize", issuer),
+                "token_endpoint": format!("{}/token", issuer),
+                "jwks_uri": format!("{}/jwks", issuer),
+            })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/jwks"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "keys": [{"kty": "RSA", "kid": "test-key", "alg": "RS256", "use": "sig",
+                          "n": IDP_MODULUS, "e": "AQAB"}]
+            })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(body_string_contains("code_verifier=verifier-123"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "idp-access",
+                "token_type": "Bearer",
+                "id_token": id_token(&issuer, nonce),
+            })))
+            .mount(&server)
+            .await;
+
+        let client = OidcClient::new(OidcConfig {
+            provider: "mock".to_string(),
+            issuer: issuer.clone(),
+            client_id: "pilotba".to_string(),
+            client_secret: Some("secret".to_string()),
+            redirect_uri: "http://localhost:3000/auth/callback".to_string(),
+            scopes: vec!["openid".to_string(), "email".to_string()],
+            allow_signup: true,
+        });
+
+        (server, client)
+    }
+
+    #[test]
+    fn test_pkce_s256_challenge() {
+        // BASE64URL(SHA256(verifier)) without padding
+        let pkce = PkceChallenge::from_verifier("dBjftJeZ4CVP-mJ0kFLnMyiMU8Hx1tl4BHYVCdIQyRA".to_string());
+        assert_eq!(pkce.challenge, "4mDcwedEhvu61JXZsHQWT-QXh4CMY58YwO5Zvw-IBkk");
+        assert_ne!(PkceChallenge::new().verifier, PkceChallenge::new().verifier);
+    }
+
+    #[tokio::test]
+    async fn test_authorization_code_flow_against_mock_idp() {
+        let (_server, client) = mock_idp("nonce-abc").await;
+
+        let pkce = PkceChallenge::from_verifier("verifier-123".to_string());
+        let url = client.authorization_url("state-xyz", "nonce-abc", &pkce).await.unwrap();
+        assert!(url.contains("code_challenge_method=S256"));
+        assert!(url.contains(&format!("code_challenge={}", pkce.challenge)));
+        assert!(url.contains("state=state-xyz"));
+
+        let token = client.exchange_code("auth-code", &pkce.verifier).await.unwrap();
+        let claims = client.validate_id_token(&token, "nonce-abc").await.unwrap();
+        assert_eq!(claims.sub, "idp-user-1");
+        assert_eq!(claims.verified_email().as_deref(), Some("jane@example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_wrong_nonce_and_audience() {
+        let (server, client) = mock_idp("nonce-abc").await;
+        let token = id_token(&server.uri(), "nonce-abc");
+
+        assert!(matches!(
+            client.validate_id_token(&token, "other-nonce").await,
+            Err(OidcError::InvalidIdToken(_))
+        ));
+
+        let other_client = OidcClient::new(OidcConfig {
+            client_id: "someone-else".to_string(),
+            ..client.config().clone()
+        });
+        assert!(other_client.validate_id_token(&token, "nonce-abc").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_key_ids_refetch_jwks_at_most_once_per_interval() {
+        let (server, client) = mock_idp("nonce-abc").await;
+        let token = id_token(&server.uri(), "nonce-abc");
+        client.validate_id_token(&token, "nonce-abc").await.unwrap();
+
+        let forged = id_token_with_kid(&server.uri(), "nonce-abc", "unknown-key");
+        for _ in 0..3 {
+            assert!(matches!(
+                client.validate_id_token(&forged, "nonce-abc").await,
+                Err(OidcError::InvalidIdToken(_))
+            ));
+        }
+
+        let jwks_fetches = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.url.path() == "/jwks")
+            .count();
+        assert_eq!(jwks_fetches, 1);
+    }
+}
@@ -0,0 +1,778 @@
+//! SAML 2.0 service provider
+//!
+//! SP-initiated Web Browser SSO: AuthnRequests are sent with the
+//! HTTP-Redirect binding and responses arrive at the Assertion Consumer
+//! Service through HTTP-POST. A response is only trusted when the Response or
+//! its Assertion carries a valid enveloped signature from the identity
+//! provider's pinned certificate; certificates embedded in `KeyInfo` are
+//! ignored. Encrypted assertions are not supported.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use roxmltree::{Document, Node};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::RsaPublicKey;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use thiserror::Error;
+use url::Url;
+use x509_cert::der::{Decode, Encode};
+use x509_cert::Certificate;
+
+use super::xmldsig::{self, child, NS_DSIG};
+use crate::models::UserRole;
+
+pub const NS_PROTOCOL: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+pub const NS_ASSERTION: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+const NS_METADATA: &str = "urn:oasis:names:tc:SAML:2.0:metadata";
+
+const STATUS_SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+const BINDING_POST: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST";
+const CONFIRMATION_BEARER: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+const NAMEID_EMAIL: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress";
+const NAMEID_UNSPECIFIED: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:unspecified";
+
+/// Attribute names tried for the email address when no mapping is configured
+const DEFAULT_EMAIL_ATTRIBUTES: &[&str] = &[
+    "email",
+    "mail",
+    "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/emailaddress",
+    "urn:oid:0.9.2342.19200300.100.1.3",
+];
+
+/// Attribute names tried for the display name when no mapping is configured
+const DEFAULT_NAME_ATTRIBUTES: &[&str] = &[
+    "displayName",
+    "name",
+    "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/name",
+    "urn:oid:2.16.840.1.113730.3.1.241",
+];
+
+/// Errors raised while handling SAML messages
+#[derive(Error, Debug)]
+pub enum SamlError {
+    #[error("Invalid SAML configuration: {0}")]
+    Config(String),
+
+    #[error("Invalid SAML response: {0}")]
+    InvalidResponse(String),
+
+    #[error("Invalid SAML signature: {0}")]
+    InvalidSignature(String),
+
+    #[error("Malformed XML: {0}")]
+    Xml(#[from] roxmltree::Error),
+}
+
+pub type SamlResult<T> = Result<T, SamlError>;
+
+/// This application's service-provider settings
+#[derive(Debug, Clone)]
+pub struct ServiceProvider {
+    /// SP entity ID registered with identity providers
+    pub entity_id: String,
+    /// Assertion Consumer Service URL (HTTP-POST binding)
+    pub acs_url: String,
+    /// Frontend page the ACS redirects to with a one-time login code
+    pub login_redirect_url: String,
+    /// Tolerated clock difference when checking assertion validity windows
+    pub clock_skew: Duration,
+}
+
+impl ServiceProvider {
+    /// Load settings from `SAML_*` environment variables; `None` when SAML is
+    /// not configured
+    pub fn from_env() -> Option<Self> {
+        let entity_id = std::env::var("SAML_SP_ENTITY_ID").ok()?;
+        let acs_url = std::env::var("SAML_ACS_URL").ok()?;
+        let login_redirect_url = std::env::var("SAML_LOGIN_REDIRECT_URL").unwrap_or_else(|_| {
+            let app_url = std::env::var("APP_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+            format!("{}/auth/saml/callback", app_url.trim_end_matches('/'))
+        });
+
+        Some(ServiceProvider {
+            entity_id,
+            acs_url,
+            login_redirect_url,
+            clock_skew: Duration::seconds(
+                std::env::var("SAML_CLOCK_SKEW_SECONDS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(120),
+            ),
+        })
+    }
+
+    /// SP metadata document for registering the application with an IdP
+    pub fn metadata_xml(&self) -> String {
+        format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                r#"<md:EntityDescriptor xmlns:md="{md}" entityID="{entity_id}">"#,
+                r#"<md:SPSSODescriptor AuthnRequestsSigned="false" WantAssertionsSigned="true" "#,
+                r#"protocolSupportEnumeration="{protocol}">"#,
+                r#"<md:NameIDFormat>{email}</md:NameIDFormat>"#,
+                r#"<md:NameIDFormat>{unspecified}</md:NameIDFormat>"#,
+                r#"<md:AssertionConsumerService Binding="{binding}" Location="{acs}" index="0" isDefault="true"/>"#,
+                r#"</md:SPSSODescriptor>"#,
+                r#"</md:EntityDescriptor>"#,
+            ),
+            md = NS_METADATA,
+            entity_id = escape_xml(&self.entity_id),
+            protocol = NS_PROTOCOL,
+            email = NAMEID_EMAIL,
+            unspecified = NAMEID_UNSPECIFIED,
+            binding = BINDING_POST,
+            acs = escape_xml(&self.acs_url),
+        )
+    }
+
+    /// Frontend URL completing a login with the one-time `code`
+    pub fn login_redirect(&self, code: &str) -> SamlResult<String> {
+        let mut url = Url::parse(&self.login_redirect_url)
+            .map_err(|e| SamlError::Config(format!("invalid login redirect URL: {}", e)))?;
+        url.query_pairs_mut().append_pair("code", code);
+        Ok(url.into())
+    }
+
+    /// Build an AuthnRequest for `idp` and the HTTP-Redirect URL carrying it
+    pub fn authn_request(&self, idp: &IdentityProvider, relay_state: &str) -> SamlResult<AuthnRequest> {
+        let id = format!("_{}", uuid::Uuid::new_v4().simple());
+        let xml = format!(
+            concat!(
+                r#"<samlp:AuthnRequest xmlns:samlp="{protocol}" xmlns:saml="{assertion}" "#,
+                r#"ID="{id}" Version="2.0" IssueInstant="{instant}" Destination="{destination}" "#,
+                r#"AssertionConsumerServiceURL="{acs}" ProtocolBinding="{binding}">"#,
+                r#"<saml:Issuer>{issuer}</saml:Issuer>"#,
+                r#"<samlp:NameIDPolicy Format="{unspecified}" AllowCreate="true"/>"#,
+                r#"</samlp:AuthnRequest>"#,
+            ),
+            protocol = NS_PROTOCOL,
+            assertion = NS_ASSERTION,
+            id = id,
+            instant = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            destination = escape_xml(&idp.sso_url),
+            acs = escape_xml(&self.acs_url),
+            binding = BINDING_POST,
+            issuer = escape_xml(&self.entity_id),
+            unspecified = NAMEID_UNSPECIFIED,
+        );
+
+        // HTTP-Redirect binding: raw DEFLATE, then base64
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(xml.as_bytes())
+            .and_then(|_| encoder.try_finish())
+            .map_err(|e| SamlError::Config(format!("failed to encode AuthnRequest: {}", e)))?;
+        let encoded = STANDARD.encode(encoder.get_ref());
+
+        let mut url = Url::parse(&idp.sso_url)
+            .map_err(|e| SamlError::Config(format!("invalid IdP SSO URL: {}", e)))?;
+        url.query_pairs_mut()
+            .append_pair("SAMLRequest", &encoded)
+            .append_pair("RelayState", relay_state);
+
+        Ok(AuthnRequest { id, redirect_url: url.into() })
+    }
+
+    /// Validate a base64-encoded `SAMLResponse` posted to the ACS.
+    ///
+    /// The response must answer `request_id`, be signed by `idp`, be addressed
+    /// to this SP and be within its validity window at `now`.
+    pub fn validate_response(
+        &self,
+        idp: &IdentityProvider,
+        saml_response: &str,
+        request_id: &str,
+        now: DateTime<Utc>,
+    ) -> SamlResult<Assertion> {
+        let encoded: String = saml_response.chars().filter(|c| !c.is_whitespace()).collect();
+        let xml = STANDARD
+            .decode(encoded)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(|| invalid("SAMLResponse is not base64-encoded XML"))?;
+
+        // DTDs are rejected by the parser, which rules out entity expansion
+        let doc = Document::parse(&xml)?;
+        let response = doc.root_element();
+        if !is_element(response, NS_PROTOCOL, "Response") {
+            return Err(invalid("root element is not a samlp:Response"));
+        }
+
+        // Duplicate IDs would let a signature cover a different element than
+        // the one read (signature wrapping)
+        let mut ids = HashSet::new();
+        for id in doc.descendants().filter_map(|n| n.attribute("ID")) {
+            if !ids.insert(id) {
+                return Err(invalid("duplicate ID attribute"));
+            }
+        }
+
+        let status = child(response, NS_PROTOCOL, "Status")
+            .and_then(|s| child(s, NS_PROTOCOL, "StatusCode"))
+            .and_then(|c| c.attribute("Value"));
+        if status != Some(STATUS_SUCCESS) {
+            return Err(invalid(&format!(
+                "identity provider returned status {}",
+                status.unwrap_or("none")
+            )));
+        }
+
+        if let Some(destination) = response.attribute("Destination") {
+            if destination != self.acs_url {
+                return Err(invalid("response destination does not match the ACS URL"));
+            }
+        }
+        if response.attribute("InResponseTo") != Some(request_id) {
+            return Err(invalid("response does not answer the pending request"));
+        }
+
+        if child(response, NS_ASSERTION, "EncryptedAssertion").is_some() {
+            return Err(invalid("encrypted assertions are not supported"));
+        }
+        let mut assertions = response
+            .children()
+            .filter(|n| is_element(*n, NS_ASSERTION, "Assertion"));
+        let assertion = assertions.next().ok_or_else(|| invalid("response contains no assertion"))?;
+        if assertions.next().is_some() {
+            return Err(invalid("response must contain exactly one assertion"));
+        }
+
+        // Either signature is sufficient; any signature present must verify
+        let response_signed = child(response, NS_DSIG, "Signature").is_some();
+        let assertion_signed = child(assertion, NS_DSIG, "Signature").is_some();
+        if !response_signed && !assertion_signed {
+            return Err(SamlError::InvalidSignature("neither response nor assertion is signed".into()));
+        }
+        for (signed, element) in [(response_signed, response), (assertion_signed, assertion)] {
+            if signed {
+                xmldsig::verify_enveloped(element, &idp.key).map_err(SamlError::InvalidSignature)?;
+            }
+        }
+
+        let issuer = child(assertion, NS_ASSERTION, "Issuer").map(plain_text).transpose()?;
+        if issuer != Some(idp.entity_id.as_str()) {
+            return Err(invalid("assertion issuer does not match the identity provider"));
+        }
+
+        self.check_conditions(assertion, now)?;
+        let subject = child(assertion, NS_ASSERTION, "Subject")
+            .ok_or_else(|| invalid("assertion has no subject"))?;
+        self.check_subject_confirmation(subject, request_id, now)?;
+
+        let name_id = child(subject, NS_ASSERTION, "NameID")
+            .ok_or_else(|| invalid("subject has no NameID"))?;
+        let name_id_value = plain_text(name_id)?;
+        if name_id_value.is_empty() {
+            return Err(invalid("subject NameID is empty"));
+        }
+
+        let mut attributes: HashMap<String, Vec<String>> = HashMap::new();
+        for statement in assertion
+            .children()
+            .filter(|n| is_element(*n, NS_ASSERTION, "AttributeStatement"))
+        {
+            for attribute in statement
+                .children()
+                .filter(|n| is_element(*n, NS_ASSERTION, "Attribute"))
+            {
+                let Some(name) = attribute.attribute("Name") else { continue };
+                let values = attributes.entry(name.to_string()).or_default();
+                for value in attribute
+                    .children()
+                    .filter(|n| is_element(*n, NS_ASSERTION, "AttributeValue"))
+                {
+                    let value = plain_text(value)?;
+                    if !value.is_empty() {
+                        values.push(value.to_string());
+                    }
+                }
+            }
+        }
+
+        Ok(Assertion {
+            name_id: name_id_value.to_string(),
+            name_id_format: name_id.attribute("Format").map(str::to_string),
+            attributes,
+            session_index: child(assertion, NS_ASSERTION, "AuthnStatement")
+                .and_then(|n| n.attribute("SessionIndex"))
+                .map(str::to_string),
+        })
+    }
+
+    fn check_conditions(&self, assertion: Node, now: DateTime<Utc>) -> SamlResult<()> {
+        let Some(conditions) = child(assertion, NS_ASSERTION, "Conditions") else {
+            return Ok(());
+        };
+
+        if let Some(not_before) = parse_instant(conditions.attribute("NotBefore"))? {
+            if now + self.clock_skew < not_before {
+                return Err(invalid("assertion is not yet valid"));
+            }
+        }
+        if let Some(not_on_or_after) = parse_instant(conditions.attribute("NotOnOrAfter"))? {
+            if now - self.clock_skew >= not_on_or_after {
+                return Err(invalid("assertion has expired"));
+            }
+        }
+
+        // Every audience restriction must include this SP
+        for restriction in conditions
+            .children()
+            .filter(|n| is_element(*n, NS_ASSERTION, "AudienceRestriction"))
+        {
+            let audiences = restriction
+                .children()
+                .filter(|n| is_element(*n, NS_ASSERTION, "Audience"))
+                .map(plain_text)
+                .collect::<SamlResult<Vec<_>>>()?;
+            let allowed = audiences.contains(&self.entity_id.as_str());
+            if !allowed {
+                return Err(invalid("assertion is not intended for this service provider"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_subject_confirmation(&self, subject: Node, request_id: &str, now: DateTime<Utc>) -> SamlResult<()> {
+        for confirmation in subject
+            .children()
+            .filter(|n| is_element(*n, NS_ASSERTION, "SubjectConfirmation"))
+            .filter(|n| n.attribute("Method") == Some(CONFIRMATION_BEARER))
+        {
+            let Some(data) = child(confirmation, NS_ASSERTION, "SubjectConfirmationData") else {
+                continue;
+            };
+            let Some(expires) = parse_instant(data.attribute("NotOnOrAfter"))? else {
+                continue;
+            };
+
+            if data.attribute("Recipient") == Some(self.acs_url.as_str())
+                && data.attribute("InResponseTo") == Some(request_id)
+                && now - self.clock_skew < expires
+            {
+                return Ok(());
+            }
+        }
+
+        Err(invalid("no valid bearer subject confirmation"))
+    }
+}
+
+/// An AuthnRequest ready to be sent with the HTTP-Redirect binding
+#[derive(Debug, Clone)]
+pub struct AuthnRequest {
+    /// Request ID the response must answer (`InResponseTo`)
+    pub id: String,
+    pub redirect_url: String,
+}
+
+/// A trusted identity provider
+#[derive(Debug, Clone)]
+pub struct IdentityProvider {
+    pub entity_id: String,
+    /// Single sign-on endpoint (HTTP-Redirect binding)
+    pub sso_url: String,
+    /// Public key of the IdP's signing certificate
+    pub key: RsaPublicKey,
+}
+
+impl IdentityProvider {
+    pub fn new(entity_id: &str, sso_url: &str, certificate: &str) -> SamlResult<Self> {
+        Url::parse(sso_url).map_err(|e| SamlError::Config(format!("invalid SSO URL: {}", e)))?;
+        Ok(IdentityProvider {
+            entity_id: entity_id.to_string(),
+            sso_url: sso_url.to_string(),
+            key: parse_certificate(certificate)?,
+        })
+    }
+}
+
+/// Extract the RSA public key from a PEM (or bare base64) X.509 certificate
+pub fn parse_certificate(certificate: &str) -> SamlResult<RsaPublicKey> {
+    let encoded: String = certificate
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .flat_map(|line| line.chars())
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let der = STANDARD
+        .decode(encoded)
+        .map_err(|_| SamlError::Config("certificate is not valid base64".into()))?;
+
+    let certificate = Certificate::from_der(&der)
+        .map_err(|e| SamlError::Config(format!("invalid X.509 certificate: {}", e)))?;
+    let spki = certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .map_err(|e| SamlError::Config(format!("invalid certificate key: {}", e)))?;
+
+    RsaPublicKey::from_public_key_der(&spki)
+        .map_err(|_| SamlError::Config("certificate does not contain an RSA key".into()))
+}
+
+/// Validated assertion contents
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    pub name_id: String,
+    pub name_id_format: Option<String>,
+    pub attributes: HashMap<String, Vec<String>>,
+    pub session_index: Option<String>,
+}
+
+impl Assertion {
+    /// First value of an attribute
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .get(name)
+            .and_then(|values| values.first())
+            .map(String::as_str)
+    }
+}
+
+/// How assertion attributes map onto user fields
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AttributeMapping {
+    /// Attribute holding the email address (well-known names when unset)
+    #[serde(default)]
+    pub email: Option<String>,
+    /// Attribute holding the display name (well-known names when unset)
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Attribute holding role or group values
+    #[serde(default)]
+    pub role: Option<String>,
+    /// Role attribute value to user role; the most privileged match wins
+    #[serde(default)]
+    pub roles: BTreeMap<String, UserRole>,
+}
+
+impl AttributeMapping {
+    /// Email address from the mapped attribute, a well-known attribute, or an
+    /// email-formatted NameID
+    pub fn email(&self, assertion: &Assertion) -> Option<String> {
+        let from_attribute = match &self.email {
+            Some(name) => assertion.attribute(name),
+            None => DEFAULT_EMAIL_ATTRIBUTES.iter().find_map(|name| assertion.attribute(name)),
+        };
+
+        from_attribute
+            .map(str::to_string)
+            .or_else(|| {
+                (assertion.name_id_format.as_deref() == Some(NAMEID_EMAIL))
+                    .then(|| assertion.name_id.clone())
+            })
+            .filter(|email| email.contains('@'))
+            .map(|email| email.to_lowercase())
+    }
+
+    pub fn name(&self, assertion: &Assertion) -> Option<String> {
+        match &self.name {
+            Some(name) => assertion.attribute(name),
+            None => DEFAULT_NAME_ATTRIBUTES.iter().find_map(|name| assertion.attribute(name)),
+        }
+        .map(str::to_string)
+    }
+
+    /// Role for the asserted role values, `None` when nothing maps
+    pub fn role(&self, assertion: &Assertion) -> Option<UserRole> {
+        let values = assertion.attributes.get(self.role.as_deref()?)?;
+        values
+            .iter()
+            .filter_map(|value| self.roles.get(value))
+            .max_by_key(|role| match role {
+                UserRole::ReadOnly => 0,
+                UserRole::User => 1,
+                UserRole::Admin => 2,
+            })
+            .cloned()
+    }
+}
+
+fn is_element(node: Node, namespace: &str, name: &str) -> bool {
+    node.is_element() && node.tag_name().namespace() == Some(namespace) && node.tag_name().name() == name
+}
+
+/// Trimmed text of an element that holds nothing else. Comments are not
+/// signed, so `jane@example.com<!---->.evil` verifies as if it read
+/// `jane@example.com.evil` while its first text node is `jane@example.com`;
+/// values split like that are rejected rather than read in part.
+fn plain_text<'a>(node: Node<'a, '_>) -> SamlResult<&'a str> {
+    let mut children = node.children();
+    match (children.next(), children.next()) {
+        (None, _) => Ok(""),
+        (Some(text), None) if text.is_text() => Ok(text.text().unwrap_or_default().trim()),
+        _ => Err(invalid(&format!("{} must contain only text", node.tag_name().name()))),
+    }
+}
+
+fn parse_instant(value: Option<&str>) -> SamlResult<Option<DateTime<Utc>>> {
+    value
+        .map(|v| {
+            DateTime::parse_from_rfc3339(v)
+                .map(|t| t.with_timezone(&Utc))
+                .map_err(|_| invalid(&format!("invalid timestamp: {}", v)))
+        })
+        .transpose()
+}
+
+fn invalid(message: &str) -> SamlError {
+    SamlError::InvalidResponse(message.to_string())
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::DeflateDecoder;
+    use rsa::pkcs8::DecodePrivateKey;
+    use rsa::{Pkcs1v15Sign, RsaPrivateKey};
+    use sha2::{Digest, Sha256};
+    use std::io::Read;
+
+    const IDP_KEY: &str = include_str!("testdata/idp_rsa.pem");
+    const IDP_CERT: &str = include_str!("testdata/idp_cert.pem");
+    const IDP_ENTITY: &str = "https://idp.example.com/metadata";
+    const REQUEST_ID: &str = "_request-1";
+
+    fn sp() -> ServiceProvider {
+        ServiceProvider {
+            entity_id: "https://bi.example.com/saml".to_string(),
+            acs_url: "https://bi.example.com/api/auth/saml/acs".to_string(),
+            login_redirect_url: "https://bi.example.com/auth/saml/callback".to_string(),
+            clock_skew: Duration::seconds(60),
+        }
+    }
+
+    fn idp() -> IdentityProvider {
+        IdentityProvider::new(IDP_ENTITY, "https://idp.example.com/sso?tenant=1", IDP_CERT).unwrap()
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z").unwrap().with_timezone(&Utc)
+    }
+
+    fn assertion_xml(audience: &str, extra_attribute: &str) -> String {
+        format!(
+            r#"<saml:Assertion xmlns:saml="{ns}" ID="_assertion-1" Version="2.0" IssueInstant="2024-05-01T11:59:58Z">
+  <saml:Issuer>{idp}</saml:Issuer>
+  {{SIGNATURE}}
+  <saml:Subject>
+    <saml:NameID Format="{email_format}">jane@example.com</saml:NameID>
+    <saml:SubjectConfirmation Method="{bearer}">
+      <saml:SubjectConfirmationData InResponseTo="{request}" NotOnOrAfter="2024-05-01T12:05:00Z" Recipient="https://bi.example.com/api/auth/saml/acs"/>
+    </saml:SubjectConfirmation>
+  </saml:Subject>
+  <saml:Conditions NotBefore="2024-05-01T11:59:00Z" NotOnOrAfter="2024-05-01T12:05:00Z">
+    <saml:AudienceRestriction><saml:Audience>{audience}</saml:Audience></saml:AudienceRestriction>
+  </saml:Conditions>
+  <saml:AuthnStatement AuthnInstant="2024-05-01T11:59:58Z" SessionIndex="_session-1"/>
+  <saml:AttributeStatement>
+    <saml:Attribute Name="displayName"><saml:AttributeValue>Jane Doe</saml:AttributeValue></saml:Attribute>
+    <saml:Attribute Name="groups">
+      <saml:AttributeValue>bi-viewers</saml:AttributeValue>
+      <saml:AttributeValue>bi-admins</saml:AttributeValue>
+    </saml:Attribute>{extra}
+  </saml:AttributeStatement>
+</saml:Assertion>"#,
+            ns = NS_ASSERTION,
+            idp = IDP_ENTITY,
+            email_format = NAMEID_EMAIL,
+            bearer = CONFIRMATION_BEARER,
+            request = REQUEST_ID,
+            audience = audience,
+            extra = extra_attribute,
+        )
+    }
+
+    fn response_xml(assertion: &str) -> String {
+        format!(
+            r#"<samlp:Response xmlns:samlp="{protocol}" ID="_response-1" Version="2.0" IssueInstant="2024-05-01T11:59:58Z" Destination="https://bi.example.com/api/auth/saml/acs" InResponseTo="{request}"><samlp:Status><samlp:StatusCode Value="{success}"/></samlp:Status>{assertion}</samlp:Response>"#,
+            protocol = NS_PROTOCOL,
+            request = REQUEST_ID,
+            success = STATUS_SUCCESS,
+            assertion = assertion,
+        )
+    }
+
+    /// Sign the assertion in `response` as an IdP would, filling the
+    /// `{SIGNATURE}` placeholder
+    fn sign(response: &str) -> String {
+        let template = format!(
+            concat!(
+                r#"<ds:Signature xmlns:ds="{ds}"><ds:SignedInfo>"#,
+                r#"<ds:CanonicalizationMethod Algorithm="http://www.w3.org/2001/10/xml-exc-c14n#"/>"#,
+                r#"<ds:SignatureMethod Algorithm="http://www.w3.org/2001/04/xmldsig-more#rsa-sha256"/>"#,
+                r##"<ds:Reference URI="#_assertion-1"><ds:Transforms>"##,
+                r#"<ds:Transform Algorithm="http://www.w3.org/2000/09/xmldsig#enveloped-signature"/>"#,
+                r#"<ds:Transform Algorithm="http://www.w3.org/2001/10/xml-exc-c14n#"/></ds:Transforms>"#,
+                r#"<ds:DigestMethod Algorithm="http://www.w3.org/2001/04/xmlenc#sha256"/>"#,
+                r#"<ds:DigestValue>DIGEST</ds:DigestValue></ds:Reference></ds:SignedInfo>"#,
+                r#"<ds:SignatureValue>SIGNATURE</ds:SignatureValue></ds:Signature>"#,
+            ),
+            ds = NS_DSIG,
+        );
+        let unsigned = response.replace("{SIGNATURE}", &template);
+
+        let find = |doc: &Document<'_>, name: &str| {
+            doc.descendants().find(|n| n.tag_name().name() == name).unwrap().id()
+        };
+
+        let doc = Document::parse(&unsigned).unwrap();
+        let assertion = doc.get_node(find(&doc, "Assertion")).unwrap();
+        let canonical = xmldsig::canonicalize(assertion, Some(find(&doc, "Signature")), &[]);
+        let digested = unsigned.replace("DIGEST", &STANDARD.encode(Sha256::digest(canonical.as_bytes())));
+
+        let doc = Document::parse(&digested).unwrap();
+        let signed_info = xmldsig::canonicalize(doc.get_node(find(&doc, "SignedInfo")).unwrap(), None, &[]);
+        let key = RsaPrivateKey::from_pkcs8_pem(IDP_KEY).unwrap();
+        let signature = key
+            .sign(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(signed_info.as_bytes()))
+            .unwrap();
+        digested.replace("SIGNATURE", &STANDARD.encode(signature))
+    }
+
+    fn encode(xml: &str) -> String {
+        STANDARD.encode(xml)
+    }
+
+    #[test]
+    fn test_valid_signed_assertion_and_attribute_mapping() {
+        let xml = sign(&response_xml(&assertion_xml("https://bi.example.com/saml", "")));
+        let assertion = sp().validate_response(&idp(), &encode(&xml), REQUEST_ID, now()).unwrap();
+
+        assert_eq!(assertion.name_id, "jane@example.com");
+        assert_eq!(assertion.session_index.as_deref(), Some("_session-1"));
+
+        let mapping = AttributeMapping {
+            role: Some("groups".to_string()),
+            roles: [
+                ("bi-viewers".to_string(), UserRole::ReadOnly),
+                ("bi-admins".to_string(), UserRole::Admin),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        assert_eq!(mapping.email(&assertion).as_deref(), Some("jane@example.com"));
+        assert_eq!(mapping.name(&assertion).as_deref(), Some("Jane Doe"));
+        assert_eq!(mapping.role(&assertion), Some(UserRole::Admin));
+        assert_eq!(AttributeMapping::default().role(&assertion), None);
+    }
+
+    #[test]
+    fn test_rejects_tampering_and_unsigned_responses() {
+        let xml = sign(&response_xml(&assertion_xml("https://bi.example.com/saml", "")));
+        let tampered = xml.replace("Jane Doe", "Mallory");
+        assert!(matches!(
+            sp().validate_response(&idp(), &encode(&tampered), REQUEST_ID, now()),
+            Err(SamlError::InvalidSignature(_))
+        ));
+
+        let unsigned = response_xml(&assertion_xml("https://bi.example.com/saml", "")).replace("{SIGNATURE}", "");
+        assert!(matches!(
+            sp().validate_response(&idp(), &encode(&unsigned), REQUEST_ID, now()),
+            Err(SamlError::InvalidSignature(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_comments_splitting_values() {
+        // Comments are left out of the signed bytes, so these verify; read up
+        // to the comment they would name someone else
+        for (original, split) in [
+            (">jane@example.com</saml:NameID>", ">jane@example.com<!---->.evil.example</saml:NameID>"),
+            (">Jane Doe<", ">Jane<!-- -->Doe<"),
+            (&format!(">{}</saml:Issuer>", IDP_ENTITY), &format!(">{}<!---->.evil</saml:Issuer>", IDP_ENTITY)),
+        ] {
+            let assertion = assertion_xml("https://bi.example.com/saml", "").replace(original, split);
+            let xml = sign(&response_xml(&assertion));
+            assert!(matches!(
+                sp().validate_response(&idp(), &encode(&xml), REQUEST_ID, now()),
+                Err(SamlError::InvalidResponse(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_rejects_signature_wrapping() {
+        // The signed assertion is hidden in an extension while an unsigned
+        // forgery takes its place
+        let signed = sign(&response_xml(&assertion_xml("https://bi.example.com/saml", "")));
+        let start = signed.find("<saml:Assertion").unwrap();
+        let end = signed.find("</samlp:Response>").unwrap();
+        let original = &signed[start..end];
+        let forged = assertion_xml("https://bi.example.com/saml", "")
+            .replace("{SIGNATURE}", "")
+            .replace("_assertion-1", "_forged")
+            .replace("jane@example.com", "admin@example.com");
+        let wrapped = signed.replace(
+            original,
+            &format!("<samlp:Extensions>{}</samlp:Extensions>{}", original, forged),
+        );
+
+        assert!(sp().validate_response(&idp(), &encode(&wrapped), REQUEST_ID, now()).is_err());
+    }
+
+    #[test]
+    fn test_rejects_wrong_audience_request_and_expiry() {
+        let xml = sign(&response_xml(&assertion_xml("https://other.example.com", "")));
+        assert!(sp().validate_response(&idp(), &encode(&xml), REQUEST_ID, now()).is_err());
+
+        let xml = sign(&response_xml(&assertion_xml("https://bi.example.com/saml", "")));
+        assert!(sp().validate_response(&idp(), &encode(&xml), "_other-request", now()).is_err());
+
+        let later = now() + Duration::minutes(10);
+        assert!(matches!(
+            sp().validate_response(&idp(), &encode(&xml), REQUEST_ID, later),
+            Err(SamlError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_authn_request_redirect_binding() {
+        let request = sp().authn_request(&idp(), "relay-1").unwrap();
+        let url = Url::parse(&request.redirect_url).unwrap();
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+        assert_eq!(params.get("tenant").map(String::as_str), Some("1"));
+        assert_eq!(params.get("RelayState").map(String::as_str), Some("relay-1"));
+
+        let deflated = STANDARD.decode(&params["SAMLRequest"]).unwrap();
+        let mut xml = String::new();
+        DeflateDecoder::new(deflated.as_slice()).read_to_string(&mut xml).unwrap();
+        let doc = Document::parse(&xml).unwrap();
+        assert_eq!(doc.root_element().attribute("ID"), Some(request.id.as_str()));
+        assert!(xml.contains("<saml:Issuer>https://bi.example.com/saml</saml:Issuer>"));
+
+        assert!(sp().metadata_xml().contains(r#"Location="https://bi.example.com/api/auth/saml/acs""#));
+    }
+
+    #[test]
+    fn test_login_redirect_carries_code() {
+        assert_eq!(
+            sp().login_redirect("abc-123").unwrap(),
+            "https://bi.example.com/auth/saml/callback?code=abc-123"
+        );
+
+        let misconfigured = ServiceProvider {
+            login_redirect_url: "/relative".to_string(),
+            ..sp()
+        };
+        assert!(matches!(misconfigured.login_redirect("abc"), Err(SamlError::Config(_))));
+    }
+}
@@ -0,0 +1,449 @@
+//! Login sessions and refresh token rotation
+//!
+//! Each login starts a session for the signing-in device. The session owns a
+//! family of opaque, single-use refresh tokens stored only as SHA-256 hashes.
+//! Refreshing consumes the presented token and issues its successor; if a
+//! consumed token is ever presented again, one of the two holders is an
+//! attacker, so the whole family is revoked and both must sign in again.
+//!
+//! Access tokens name their session (`sid`), so ending a session also rejects
+//! its outstanding access tokens. Session state is cached in-process for
+//! [`SESSION_CACHE_TTL`]; revocations on this instance take effect at once.
+//! With `SESSION_STORE=redis`, revocations are also published to Redis and
+//! checked before the cache, so they take effect on every instance at once;
+//! otherwise other instances notice once their cached entry expires. Redis
+//! errors fall back to the cache and database.
+
+use ::redis::aio::ConnectionManager;
+use actix_web::HttpRequest;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::net::IpAddr;
+use std::sync::{LazyLock, OnceLock};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::{random_token, sha256_hex};
+use crate::services::cache::TtlCache;
+
+/// Lifetime of a refresh token; each rotation extends the session by this much
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 7;
+
+/// How long a session's active/revoked state is cached per instance
+pub const SESSION_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How long a revocation is kept in Redis; outlives any access token
+const REVOCATION_TTL: std::time::Duration = std::time::Duration::from_secs(24 * 3600);
+
+/// Whether each recently checked session is active
+static ACTIVE_SESSIONS: LazyLock<TtlCache<Uuid, bool>> =
+    LazyLock::new(|| TtlCache::new(SESSION_CACHE_TTL, 100_000));
+
+/// Revocation list shared by all instances, when configured
+static SHARED_REVOCATIONS: OnceLock<ConnectionManager> = OnceLock::new();
+
+/// Errors configuring the shared session store
+#[derive(Error, Debug)]
+pub enum SessionStoreError {
+    #[error("Invalid session store configuration: {0}")]
+    Config(String),
+
+    #[error("Redis error: {0}")]
+    Redis(#[from] ::redis::RedisError),
+}
+
+/// Device a session was started from
+#[derive(Debug, Clone, Default)]
+pub struct DeviceInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<IpAddr>,
+}
+
+impl DeviceInfo {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        DeviceInfo {
+            user_agent: req
+                .headers()
+                .get("User-Agent")
+                .and_then(|h| h.to_str().ok())
+                .map(|s| s.to_string()),
+            ip_address: req.peer_addr().map(|addr| addr.ip()),
+        }
+    }
+}
+
+/// Why a session ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevocationReason {
+    Logout,
+    ReuseDetected,
+    Revoked,
+    PasswordReset,
+    AccountDisabled,
+}
+
+impl RevocationReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RevocationReason::Logout => "logout",
+            RevocationReason::ReuseDetected => "reuse_detected",
+            RevocationReason::Revoked => "revoked",
+            RevocationReason::PasswordReset => "password_reset",
+            RevocationReason::AccountDisabled => "account_disabled",
+        }
+    }
+}
+
+/// Active login session, as listed to its user
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Result of presenting a refresh token
+#[derive(Debug)]
+pub enum RefreshOutcome {
+    /// The token was consumed and replaced by `refresh_token`
+    Rotated {
+        session_id: Uuid,
+        user_id: Uuid,
+        refresh_token: String,
+    },
+    /// An already-consumed token was replayed; the session is now revoked
+    ReuseDetected { session_id: Uuid, user_id: Uuid },
+    /// Unknown, expired, or belonging to an ended session
+    Invalid,
+}
+
+/// Token row joined with its session: (session_id, user_id, expires_at, used_at, revoked_at)
+type TokenRow = (Uuid, Uuid, DateTime<Utc>, Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// Session management service
+pub struct SessionService;
+
+impl SessionService {
+    /// Share revocations through Redis when `SESSION_STORE` is `redis` (uses
+    /// `REDIS_URL`); the default `memory` keeps them per instance
+    pub async fn configure_from_env() -> Result<(), SessionStoreError> {
+        match std::env::var("SESSION_STORE").unwrap_or_else(|_| "memory".to_string()).as_str() {
+            "memory" => Ok(()),
+            "redis" => {
+                let url = std::env::var("REDIS_URL").map_err(|_| {
+                    SessionStoreError::Config("REDIS_URL is required for SESSION_STORE=redis".into())
+                })?;
+                let client = ::redis::Client::open(url)?;
+                // Only the first configuration counts
+                let _ = SHARED_REVOCATIONS.set(ConnectionManager::new(client).await?);
+                Ok(())
+            }
+            other => Err(SessionStoreError::Config(format!("unknown SESSION_STORE {}", other))),
+        }
+    }
+
+    /// Start a session for `user_id` and return it with its first refresh token
+    pub async fn start(
+        pool: &PgPool,
+        user_id: Uuid,
+        device: &DeviceInfo,
+    ) -> Result<(Uuid, String), sqlx::Error> {
+        let expires_at = Utc::now() + Duration::days(REFRESH_TOKEN_TTL_DAYS);
+        let mut tx = pool.begin().await?;
+
+        let (session_id,): (Uuid,) = sqlx::query_as(
+            r#"
+            INSERT INTO auth_sessions (user_id, user_agent, ip_address, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#
+        )
+        .bind(user_id)
+        .bind(&device.user_agent)
+        .bind(device.ip_address.map(|ip| ip.to_string()))
+        .bind(expires_at)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let refresh_token = Self::issue(&mut tx, session_id, expires_at).await?;
+        tx.commit().await?;
+
+        Ok((session_id, refresh_token))
+    }
+
+    /// Consume a refresh token and issue its successor, revoking the session
+    /// when a consumed token is replayed
+    pub async fn rotate(pool: &PgPool, refresh_token: &str) -> Result<RefreshOutcome, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        let row: Option<TokenRow> = sqlx::query_as(
+            r#"
+            SELECT t.session_id, s.user_id, t.expires_at, t.used_at, s.revoked_at
+            FROM refresh_tokens t
+            JOIN auth_sessions s ON s.id = t.session_id
+            WHERE t.token_hash = $1
+            FOR UPDATE OF t, s
+            "#
+        )
+        .bind(sha256_hex(refresh_token))
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some((session_id, user_id, expires_at, used_at, revoked_at)) = row else {
+            return Ok(RefreshOutcome::Invalid);
+        };
+
+        if revoked_at.is_some() || expires_at <= Utc::now() {
+            return Ok(RefreshOutcome::Invalid);
+        }
+
+        if used_at.is_some() {
+            Self::revoke_in(&mut tx, session_id, RevocationReason::ReuseDetected).await?;
+            tx.commit().await?;
+            Self::mark_revoked(&[session_id]).await;
+            return Ok(RefreshOutcome::ReuseDetected { session_id, user_id });
+        }
+
+        sqlx::query("UPDATE refresh_tokens SET used_at = NOW() WHERE token_hash = $1")
+            .bind(sha256_hex(refresh_token))
+            .execute(&mut *tx)
+            .await?;
+
+        let expires_at = Utc::now() + Duration::days(REFRESH_TOKEN_TTL_DAYS);
+        sqlx::query("UPDATE auth_sessions SET last_used_at = NOW(), expires_at = $2 WHERE id = $1")
+            .bind(session_id)
+            .bind(expires_at)
+            .execute(&mut *tx)
+            .await?;
+
+        let refresh_token = Self::issue(&mut tx, session_id, expires_at).await?;
+        tx.commit().await?;
+
+        Ok(RefreshOutcome::Rotated { session_id, user_id, refresh_token })
+    }
+
+    /// End the session a refresh token belongs to; returns the session ID
+    pub async fn revoke_by_token(
+        pool: &PgPool,
+        refresh_token: &str,
+        reason: RevocationReason,
+    ) -> Result<Option<Uuid>, sqlx::Error> {
+        let session: Option<(Uuid,)> = sqlx::query_as(
+            "SELECT session_id FROM refresh_tokens WHERE token_hash = $1"
+        )
+        .bind(sha256_hex(refresh_token))
+        .fetch_optional(pool)
+        .await?;
+
+        match session {
+            Some((session_id,)) => {
+                let mut tx = pool.begin().await?;
+                let revoked = Self::revoke_in(&mut tx, session_id, reason).await?;
+                tx.commit().await?;
+                if revoked {
+                    Self::mark_revoked(&[session_id]).await;
+                }
+                Ok(Some(session_id))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Whether a session is still active (exists, not revoked, not expired,
+    /// and its account is not disabled)
+    pub async fn is_active(pool: &PgPool, session_id: Uuid) -> Result<bool, sqlx::Error> {
+        let cached = ACTIVE_SESSIONS.get(&session_id);
+        if cached == Some(false) {
+            return Ok(false);
+        }
+
+        // Another instance may have ended a session cached here as active
+        if Self::shared_revoked(session_id).await {
+            ACTIVE_SESSIONS.insert(session_id, false);
+            return Ok(false);
+        }
+
+        if let Some(active) = cached {
+            return Ok(active);
+        }
+
+        let (active,): (bool,) = sqlx::query_as(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM auth_sessions s
+                JOIN users u ON u.id = s.user_id
+                WHERE s.id = $1 AND s.revoked_at IS NULL AND s.expires_at > NOW()
+                  AND u.status = 'active'
+            )
+            "#
+        )
+        .bind(session_id)
+        .fetch_one(pool)
+        .await?;
+
+        ACTIVE_SESSIONS.insert(session_id, active);
+        Ok(active)
+    }
+
+    /// Active sessions of a user, most recently used first
+    pub async fn list_active(pool: &PgPool, user_id: Uuid) -> Result<Vec<Session>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT id, user_agent, ip_address, created_at, last_used_at, expires_at
+            FROM auth_sessions
+            WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+            ORDER BY last_used_at DESC
+            "#
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Revoke one of a user's sessions; returns false if it is not theirs or
+    /// already ended
+    pub async fn revoke(
+        pool: &PgPool,
+        user_id: Uuid,
+        session_id: Uuid,
+        reason: RevocationReason,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE auth_sessions SET revoked_at = NOW(), revoked_reason = $3
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .bind(reason.as_str())
+        .execute(pool)
+        .await?;
+
+        // Sessions of other users are left alone, cached state included
+        let revoked = result.rows_affected() > 0;
+        if revoked {
+            Self::mark_revoked(&[session_id]).await;
+        }
+        Ok(revoked)
+    }
+
+    /// Revoke every active session of a user ("sign out everywhere");
+    /// returns the number of sessions ended
+    pub async fn revoke_all(
+        pool: &PgPool,
+        user_id: Uuid,
+        reason: RevocationReason,
+    ) -> Result<u64, sqlx::Error> {
+        let revoked: Vec<(Uuid,)> = sqlx::query_as(
+            r#"
+            UPDATE auth_sessions SET revoked_at = NOW(), revoked_reason = $2
+            WHERE user_id = $1 AND revoked_at IS NULL
+            RETURNING id
+            "#
+        )
+        .bind(user_id)
+        .bind(reason.as_str())
+        .fetch_all(pool)
+        .await?;
+
+        let revoked: Vec<Uuid> = revoked.into_iter().map(|(session_id,)| session_id).collect();
+        Self::mark_revoked(&revoked).await;
+        Ok(revoked.len() as u64)
+    }
+
+    async fn issue(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        session_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<String, sqlx::Error> {
+        let refresh_token = random_token(32);
+
+        sqlx::query(
+            "INSERT INTO refresh_tokens (token_hash, session_id, expires_at) VALUES ($1, $2, $3)"
+        )
+        .bind(sha256_hex(&refresh_token))
+        .bind(session_id)
+        .bind(expires_at)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(refresh_token)
+    }
+
+    /// Revoke a session; returns false if it had already ended
+    async fn revoke_in(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        session_id: Uuid,
+        reason: RevocationReason,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE auth_sessions SET revoked_at = NOW(), revoked_reason = $2
+            WHERE id = $1 AND revoked_at IS NULL
+            "#
+        )
+        .bind(session_id)
+        .bind(reason.as_str())
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record ended sessions in this instance's cache and the shared list
+    async fn mark_revoked(session_ids: &[Uuid]) {
+        for session_id in session_ids {
+            ACTIVE_SESSIONS.insert(*session_id, false);
+        }
+
+        let Some(connection) = SHARED_REVOCATIONS.get() else {
+            return;
+        };
+        if session_ids.is_empty() {
+            return;
+        }
+
+        let mut pipeline = ::redis::pipe();
+        for session_id in session_ids {
+            pipeline
+                .cmd("SET")
+                .arg(revocation_key(*session_id))
+                .arg(1)
+                .arg("PX")
+                .arg(REVOCATION_TTL.as_millis() as u64)
+                .ignore();
+        }
+        if let Err(e) = pipeline.query_async::<_, ()>(&mut connection.clone()).await {
+            log::warn!("Failed to share session revocations: {}", e);
+        }
+    }
+
+    /// Whether the shared list names the session; false without Redis or on
+    /// errors, leaving the decision to the cache and database
+    async fn shared_revoked(session_id: Uuid) -> bool {
+        let Some(connection) = SHARED_REVOCATIONS.get() else {
+            return false;
+        };
+
+        match ::redis::cmd("EXISTS")
+            .arg(revocation_key(session_id))
+            .query_async::<_, bool>(&mut connection.clone())
+            .await
+        {
+            Ok(revoked) => revoked,
+            Err(e) => {
+                log::warn!("Failed to check shared session revocations: {}", e);
+                false
+            }
+        }
+    }
+}
+
+fn revocation_key(session_id: Uuid) -> String {
+    format!("session:revoked:{}", session_id)
+}
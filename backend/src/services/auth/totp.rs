@@ -0,0 +1,171 @@
+//! Time-based one-time passwords (RFC 6238)
+//!
+//! Six-digit HMAC-SHA1 codes over 30-second steps, the parameters every
+//! authenticator app supports. Secrets are exchanged as unpadded base32 in an
+//! `otpauth://` URI.
+
+use ring::hmac;
+
+/// Seconds per time step
+pub const STEP_SECONDS: i64 = 30;
+
+/// Digits per code
+pub const DIGITS: u32 = 6;
+
+/// Steps either side of the current one that are accepted, for clock drift
+pub const ALLOWED_DRIFT_STEPS: i64 = 1;
+
+/// Length of generated secrets in bytes (160 bits, as RFC 4226 recommends)
+pub const SECRET_BYTES: usize = 20;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Random secret, base32-encoded
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_BYTES];
+    rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut secret);
+    base32_encode(&secret)
+}
+
+/// Code for a time step
+pub fn code_at(secret: &[u8], step: i64) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let digest = hmac::sign(&key, &(step as u64).to_be_bytes());
+    let digest = digest.as_ref();
+
+    // Dynamic truncation
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([digest[offset], digest[offset + 1], digest[offset + 2], digest[offset + 3]])
+        & 0x7fff_ffff;
+
+    format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize)
+}
+
+/// Time step containing a Unix timestamp
+pub fn step_at(unix_seconds: i64) -> i64 {
+    unix_seconds.div_euclid(STEP_SECONDS)
+}
+
+/// Check `code` against the steps around `unix_seconds`, returning the step
+/// it matched. Steps at or before `last_used_step` are rejected so a code
+/// cannot be replayed.
+pub fn verify(secret_base32: &str, code: &str, unix_seconds: i64, last_used_step: Option<i64>) -> Option<i64> {
+    let secret = base32_decode(secret_base32)?;
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let current = step_at(unix_seconds);
+    (current - ALLOWED_DRIFT_STEPS..=current + ALLOWED_DRIFT_STEPS)
+        .filter(|step| last_used_step.is_none_or(|last| *step > last))
+        .find(|step| constant_time_eq(code_at(&secret, *step).as_bytes(), code.as_bytes()))
+}
+
+/// Provisioning URI understood by authenticator apps (and QR code encoders)
+pub fn otpauth_uri(secret_base32: &str, account: &str, issuer: &str) -> String {
+    let encode = |s: &str| url::form_urlencoded::byte_serialize(s.as_bytes()).collect::<String>().replace('+', "%20");
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        encode(issuer),
+        encode(account),
+        secret_base32,
+        encode(issuer),
+        DIGITS,
+        STEP_SECONDS
+    )
+}
+
+/// RFC 4648 base32 without padding
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            output.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        output.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    output
+}
+
+/// Decode base32, ignoring case, spaces and padding
+pub fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in input.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(output)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        // RFC 6238 appendix B (SHA-1), truncated to six digits
+        let secret = b"12345678901234567890";
+        assert_eq!(code_at(secret, step_at(59)), "287082");
+        assert_eq!(code_at(secret, step_at(1_111_111_109)), "081804");
+        assert_eq!(code_at(secret, step_at(1_234_567_890)), "005924");
+        assert_eq!(code_at(secret, step_at(2_000_000_000)), "279037");
+    }
+
+    #[test]
+    fn test_verify_window_and_replay() {
+        let secret = base32_encode(b"12345678901234567890");
+        let now = 1_111_111_109;
+        let step = step_at(now);
+
+        assert_eq!(verify(&secret, "081804", now, None), Some(step));
+        assert_eq!(verify(&secret, "081 804", now + STEP_SECONDS, None), Some(step));
+        assert_eq!(verify(&secret, "081804", now + 3 * STEP_SECONDS, None), None);
+        assert_eq!(verify(&secret, "081804", now, Some(step)), None);
+        assert_eq!(verify(&secret, "000000", now, None), None);
+        assert_eq!(verify(&secret, "81804", now, None), None);
+    }
+
+    #[test]
+    fn test_base32_roundtrip() {
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_decode("mzxw 6ytb oi==").unwrap(), b"foobar");
+        assert!(base32_decode("MZXW1").is_none());
+
+        let secret = generate_secret();
+        assert_eq!(secret.len(), 32);
+        assert_eq!(base32_decode(&secret).unwrap().len(), SECRET_BYTES);
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        assert_eq!(
+            otpauth_uri("MZXW6YTBOI", "jane@example.com", "PilotBA"),
+            "otpauth://totp/PilotBA:jane%40example.com?secret=MZXW6YTBOI&issuer=PilotBA&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
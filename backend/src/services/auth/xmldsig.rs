@@ -0,0 +1,347 @@
+//! XML Signature verification
+//!
+//! Verifies enveloped XML-DSig signatures as produced by SAML identity
+//! providers: a `ds:Signature` that is a direct child of the signed element
+//! and references it by ID. Only the algorithms SAML deployments use today
+//! are accepted — exclusive canonicalization (without comments), SHA-256
+//! digests and RSA-SHA256 signatures — so weaker or exotic transforms fail
+//! closed instead of being silently skipped.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use roxmltree::{Node, NodeId};
+use rsa::{Pkcs1v15Sign, RsaPublicKey};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+pub const NS_DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+const NS_XML: &str = "http://www.w3.org/XML/1998/namespace";
+const NS_EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+
+const ALG_EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+const ALG_ENVELOPED: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+const ALG_SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+const ALG_RSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256";
+
+/// Verify the enveloped signature of `element` with the signer's public key.
+///
+/// Fails when the element is unsigned, when the reference does not point at
+/// the element itself, or when the digest or signature do not match.
+pub fn verify_enveloped(element: Node, key: &RsaPublicKey) -> Result<(), String> {
+    let id = element
+        .attribute("ID")
+        .ok_or_else(|| "signed element has no ID".to_string())?;
+
+    let signature = child(element, NS_DSIG, "Signature")
+        .ok_or_else(|| "element is not signed".to_string())?;
+    let signed_info = require_child(signature, "SignedInfo")?;
+
+    expect_algorithm(require_child(signed_info, "CanonicalizationMethod")?, ALG_EXC_C14N)?;
+    expect_algorithm(require_child(signed_info, "SignatureMethod")?, ALG_RSA_SHA256)?;
+
+    let mut references = children(signed_info, NS_DSIG, "Reference");
+    let reference = references
+        .next()
+        .ok_or_else(|| "signature has no reference".to_string())?;
+    if references.next().is_some() {
+        return Err("signature must have exactly one reference".to_string());
+    }
+
+    if reference.attribute("URI") != Some(&format!("#{}", id)) {
+        return Err("signature does not reference the signed element".to_string());
+    }
+
+    // Enveloped signature followed by exclusive canonicalization
+    let transforms: Vec<Node> = child(reference, NS_DSIG, "Transforms")
+        .map(|t| children(t, NS_DSIG, "Transform").collect())
+        .unwrap_or_default();
+    let mut enveloped = false;
+    let mut inclusive_prefixes = Vec::new();
+    for transform in &transforms {
+        match transform.attribute("Algorithm") {
+            Some(ALG_ENVELOPED) => enveloped = true,
+            Some(ALG_EXC_C14N) => inclusive_prefixes = prefix_list(*transform),
+            other => {
+                return Err(format!("unsupported transform: {}", other.unwrap_or("none")));
+            }
+        }
+    }
+    if !enveloped {
+        return Err("signature is not enveloped".to_string());
+    }
+
+    expect_algorithm(require_child(reference, "DigestMethod")?, ALG_SHA256)?;
+    let expected_digest = decode_base64(require_child(reference, "DigestValue")?)?;
+
+    let canonical = canonicalize(element, Some(signature.id()), &inclusive_prefixes);
+    if Sha256::digest(canonical.as_bytes()).as_slice() != expected_digest.as_slice() {
+        return Err("digest mismatch".to_string());
+    }
+
+    let signed_info_prefixes = child(signed_info, NS_DSIG, "CanonicalizationMethod")
+        .map(prefix_list)
+        .unwrap_or_default();
+    let canonical_signed_info = canonicalize(signed_info, None, &signed_info_prefixes);
+    let signature_value = decode_base64(require_child(signature, "SignatureValue")?)?;
+
+    key.verify(
+        Pkcs1v15Sign::new::<Sha256>(),
+        &Sha256::digest(canonical_signed_info.as_bytes()),
+        &signature_value,
+    )
+    .map_err(|_| "signature mismatch".to_string())
+}
+
+/// Exclusive XML canonicalization (without comments) of the subtree rooted
+/// at `apex`, leaving out the `exclude` subtree (the enveloped signature).
+///
+/// `inclusive_prefixes` is the transform's `InclusiveNamespaces PrefixList`;
+/// `#default` names the default namespace.
+pub fn canonicalize(apex: Node, exclude: Option<NodeId>, inclusive_prefixes: &[String]) -> String {
+    let mut output = String::new();
+    write_node(apex, exclude, inclusive_prefixes, &BTreeMap::new(), &mut output);
+    output
+}
+
+/// Namespace declarations already rendered by output ancestors
+type Rendered<'a> = BTreeMap<&'a str, &'a str>;
+
+fn write_node<'a>(
+    node: Node<'a, 'a>,
+    exclude: Option<NodeId>,
+    inclusive_prefixes: &[String],
+    rendered: &Rendered<'a>,
+    output: &mut String,
+) {
+    if Some(node.id()) == exclude {
+        return;
+    }
+
+    if node.is_text() {
+        escape_text(node.text().unwrap_or_default(), output);
+        return;
+    }
+
+    if let Some(pi) = node.pi() {
+        output.push_str("<?");
+        output.push_str(pi.target);
+        if let Some(value) = pi.value {
+            output.push(' ');
+            output.push_str(value);
+        }
+        output.push_str("?>");
+        return;
+    }
+
+    if !node.is_element() {
+        return;
+    }
+
+    let input = node.document().input_text();
+    let qname = element_qname(node, input);
+    let prefix = qname.split_once(':').map(|(prefix, _)| prefix).unwrap_or("");
+
+    // Namespaces visibly utilized by the element and its attributes, plus the
+    // inclusive prefixes that are in scope
+    let mut utilized: Vec<&str> = vec![prefix];
+    for attribute in node.attributes() {
+        if let Some((attr_prefix, _)) = input[attribute.range_qname()].split_once(':') {
+            if attr_prefix != "xml" {
+                utilized.push(attr_prefix);
+            }
+        }
+    }
+    for inclusive in inclusive_prefixes {
+        let inclusive = if inclusive == "#default" { "" } else { inclusive.as_str() };
+        if in_scope(node, inclusive).is_some() {
+            utilized.push(inclusive);
+        }
+    }
+
+    let mut scope = rendered.clone();
+    let mut declarations: BTreeMap<&str, &str> = BTreeMap::new();
+    for utilized_prefix in utilized {
+        let uri = in_scope(node, utilized_prefix).unwrap_or("");
+        let current = rendered.get(utilized_prefix).copied().unwrap_or("");
+        if uri != current || (!uri.is_empty() && !rendered.contains_key(utilized_prefix)) {
+            declarations.insert(utilized_prefix, uri);
+            scope.insert(utilized_prefix, uri);
+        }
+    }
+
+    output.push('<');
+    output.push_str(qname);
+    for (declared, uri) in &declarations {
+        if declared.is_empty() {
+            output.push_str(" xmlns=\"");
+        } else {
+            output.push_str(" xmlns:");
+            output.push_str(declared);
+            output.push_str("=\"");
+        }
+        escape_attribute(uri, output);
+        output.push('"');
+    }
+
+    let mut attributes: Vec<(&str, &str, &str, &str)> = node
+        .attributes()
+        .map(|a| (a.namespace().unwrap_or(""), a.name(), &input[a.range_qname()], a.value()))
+        .collect();
+    attributes.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+    for (_, _, name, value) in attributes {
+        output.push(' ');
+        output.push_str(name);
+        output.push_str("=\"");
+        escape_attribute(value, output);
+        output.push('"');
+    }
+    output.push('>');
+
+    for child in node.children() {
+        write_node(child, exclude, inclusive_prefixes, &scope, output);
+    }
+
+    output.push_str("</");
+    output.push_str(qname);
+    output.push('>');
+}
+
+/// The element's qualified name as written in the source document
+fn element_qname<'a>(node: Node, input: &'a str) -> &'a str {
+    let start = node.range().start + 1;
+    let rest = &input[start..];
+    let end = rest
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .unwrap_or(rest.len());
+    &rest[..end]
+}
+
+/// Namespace URI bound to `prefix` (empty for the default namespace) at `node`
+fn in_scope<'a>(node: Node<'a, 'a>, prefix: &str) -> Option<&'a str> {
+    if prefix == "xml" {
+        return Some(NS_XML);
+    }
+    let name = if prefix.is_empty() { None } else { Some(prefix) };
+    node.namespaces()
+        .find(|ns| ns.name() == name)
+        .map(|ns| ns.uri())
+        .filter(|uri| !uri.is_empty())
+}
+
+fn escape_text(text: &str, output: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '\r' => output.push_str("&#xD;"),
+            c => output.push(c),
+        }
+    }
+}
+
+fn escape_attribute(value: &str, output: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '"' => output.push_str("&quot;"),
+            '\t' => output.push_str("&#x9;"),
+            '\n' => output.push_str("&#xA;"),
+            '\r' => output.push_str("&#xD;"),
+            c => output.push(c),
+        }
+    }
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    namespace: &'a str,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |c| c.is_element() && c.tag_name().namespace() == Some(namespace) && c.tag_name().name() == name)
+}
+
+/// First child element with the given namespace and local name
+pub fn child<'a, 'input>(node: Node<'a, 'input>, namespace: &str, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|c| c.is_element() && c.tag_name().namespace() == Some(namespace) && c.tag_name().name() == name)
+}
+
+fn require_child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Result<Node<'a, 'input>, String> {
+    child(node, NS_DSIG, name).ok_or_else(|| format!("signature is missing {}", name))
+}
+
+fn expect_algorithm(node: Node, expected: &str) -> Result<(), String> {
+    match node.attribute("Algorithm") {
+        Some(algorithm) if algorithm == expected => Ok(()),
+        other => Err(format!(
+            "unsupported {} algorithm: {}",
+            node.tag_name().name(),
+            other.unwrap_or("none")
+        )),
+    }
+}
+
+/// `InclusiveNamespaces PrefixList` of an exclusive canonicalization element
+fn prefix_list(node: Node) -> Vec<String> {
+    child(node, NS_EXC_C14N, "InclusiveNamespaces")
+        .and_then(|n| n.attribute("PrefixList"))
+        .map(|list| list.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn decode_base64(node: Node) -> Result<Vec<u8>, String> {
+    let text: String = node
+        .text()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    STANDARD
+        .decode(text)
+        .map_err(|_| format!("invalid base64 in {}", node.tag_name().name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use roxmltree::Document;
+
+    fn c14n(xml: &str, path: &[&str]) -> String {
+        let doc = Document::parse(xml).unwrap();
+        let mut node = doc.root_element();
+        for name in path {
+            node = node.children().find(|c| c.tag_name().name() == *name).unwrap();
+        }
+        canonicalize(node, None, &[])
+    }
+
+    #[test]
+    fn test_exclusive_c14n_renders_only_utilized_namespaces() {
+        let xml = "<r:Root xmlns:r=\"urn:r\" xmlns:a=\"urn:a\" xmlns=\"urn:d\">\r\n\
+                   <a:Item z=\"1\"   b='x&amp;y\"' r:id=\"7\"/><Plain>a &lt; b</Plain></r:Root>";
+
+        assert_eq!(
+            c14n(xml, &["Item"]),
+            "<a:Item xmlns:a=\"urn:a\" xmlns:r=\"urn:r\" b=\"x&amp;y&quot;\" z=\"1\" r:id=\"7\"></a:Item>"
+        );
+        assert_eq!(c14n(xml, &["Plain"]), "<Plain xmlns=\"urn:d\">a &lt; b</Plain>");
+        assert_eq!(
+            c14n(xml, &[]),
+            "<r:Root xmlns:r=\"urn:r\">\n<a:Item xmlns:a=\"urn:a\" b=\"x&amp;y&quot;\" z=\"1\" r:id=\"7\"></a:Item>\
+             <Plain xmlns=\"urn:d\">a &lt; b</Plain></r:Root>"
+        );
+    }
+
+    #[test]
+    fn test_exclusive_c14n_inclusive_prefixes_and_default_reset() {
+        let xml = "<Root xmlns=\"urn:d\" xmlns:xs=\"urn:xs\"><Inner xmlns=\"\"><x/></Inner></Root>";
+        let doc = Document::parse(xml).unwrap();
+        assert_eq!(
+            canonicalize(doc.root_element(), None, &["xs".to_string()]),
+            "<Root xmlns=\"urn:d\" xmlns:xs=\"urn:xs\"><Inner xmlns=\"\"><x></x></Inner></Root>"
+        );
+    }
+}
@@ -1,3 +1,76 @@
-// Cache service - will be implemented in detail
+//! In-process caching
+//!
+//! A small time-bounded cache for lookups on the request path. Entries expire
+//! after a fixed TTL so that changes made by other instances are picked up
+//! within that window; the local instance invalidates entries directly.
 
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
 
+/// Thread-safe map whose entries expire after `ttl`
+pub struct TtlCache<K, V> {
+    entries: RwLock<HashMap<K, (V, Instant)>>,
+    ttl: Duration,
+    capacity: usize,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        TtlCache {
+            entries: RwLock::new(HashMap::new()),
+            ttl,
+            capacity,
+        }
+    }
+
+    /// Cached value, if present and not expired
+    pub fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.read().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(key)
+            .filter(|(_, inserted)| inserted.elapsed() < self.ttl)
+            .map(|(value, _)| value.clone())
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.write().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let ttl = self.ttl;
+            entries.retain(|_, (_, inserted)| inserted.elapsed() < ttl);
+            if entries.len() >= self.capacity {
+                entries.clear();
+            }
+        }
+        entries.insert(key, (value, Instant::now()));
+    }
+
+    pub fn remove(&self, key: &K) {
+        self.entries.write().unwrap_or_else(|e| e.into_inner()).remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_expire_and_capacity_is_bounded() {
+        let cache = TtlCache::new(Duration::from_millis(20), 2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        cache.insert("c", 3);
+        assert!(cache.entries.read().unwrap().len() <= 2);
+        assert_eq!(cache.get(&"c"), Some(3));
+
+        cache.remove(&"c");
+        assert_eq!(cache.get(&"c"), None);
+
+        cache.insert("d", 4);
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get(&"d"), None);
+    }
+}
@@ -0,0 +1,311 @@
+//! Data Export Service
+//!
+//! Encodes Arrow datasets as CSV, Parquet, newline-delimited JSON or XLSX.
+//! Encoders write to any `std::io::Write`, so routes can stream the output
+//! through a [`ChannelWriter`] instead of buffering whole files in memory.
+
+use actix_web::web::Bytes;
+use arrow::array::{Array, ArrayRef, BooleanArray, Float64Array, StringArray};
+use arrow::compute::kernels::cast::cast;
+use arrow::datatypes::DataType;
+use arrow::error::ArrowError;
+use parquet::arrow::ArrowWriter;
+use rust_xlsxwriter::{Format, Workbook, XlsxError};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+use crate::connectors::Dataset;
+
+/// Maximum number of data rows in an XLSX worksheet (excluding the header)
+pub const XLSX_MAX_ROWS: usize = 1_048_575;
+
+/// Size of the chunks handed to the response stream
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Errors raised while encoding an export
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Too many rows for {format}: {rows} (maximum {max})")]
+    TooManyRows {
+        format: &'static str,
+        rows: usize,
+        max: usize,
+    },
+
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+
+    #[error("Parquet error: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+
+    #[error("Excel error: {0}")]
+    Xlsx(#[from] XlsxError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+}
+
+pub type ExportResult<T> = Result<T, ExportError>;
+
+/// Supported export formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+    /// Newline-delimited JSON (one object per row)
+    #[serde(alias = "ndjson")]
+    Json,
+    Xlsx,
+}
+
+impl ExportFormat {
+    /// File extension for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+            ExportFormat::Json => "ndjson",
+            ExportFormat::Xlsx => "xlsx",
+        }
+    }
+
+    /// MIME type for this format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+            ExportFormat::Json => "application/x-ndjson",
+            ExportFormat::Xlsx => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+        }
+    }
+}
+
+/// Export encoding service
+pub struct ExportService;
+
+impl ExportService {
+    /// Check that a dataset can be encoded in the given format
+    pub fn validate(dataset: &Dataset, format: ExportFormat) -> ExportResult<()> {
+        let rows = dataset.num_rows();
+        if format == ExportFormat::Xlsx && rows > XLSX_MAX_ROWS {
+            return Err(ExportError::TooManyRows {
+                format: "xlsx",
+                rows,
+                max: XLSX_MAX_ROWS,
+            });
+        }
+        Ok(())
+    }
+
+    /// Encode a dataset into `writer`
+    pub fn write<W: Write + Send>(
+        dataset: &Dataset,
+        format: ExportFormat,
+        mut writer: W,
+    ) -> ExportResult<()> {
+        Self::validate(dataset, format)?;
+
+        match format {
+            ExportFormat::Csv => {
+                let mut csv = arrow::csv::Writer::new(&mut writer);
+                for batch in &dataset.batches {
+                    csv.write(batch)?;
+                }
+            }
+            ExportFormat::Json => {
+                let mut json = arrow::json::LineDelimitedWriter::new(&mut writer);
+                for batch in &dataset.batches {
+                    json.write(batch)?;
+                }
+                json.finish()?;
+            }
+            ExportFormat::Parquet => {
+                let mut parquet = ArrowWriter::try_new(&mut writer, dataset.schema.clone(), None)?;
+                for batch in &dataset.batches {
+                    parquet.write(batch)?;
+                }
+                parquet.close()?;
+            }
+            ExportFormat::Xlsx => {
+                let buffer = Self::encode_xlsx(dataset)?;
+                writer.write_all(&buffer)?;
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Build an XLSX workbook with a bold header row and typed cells
+    fn encode_xlsx(dataset: &Dataset) -> ExportResult<Vec<u8>> {
+        let mut workbook = Workbook::new();
+        let header_format = Format::new().set_bold();
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name("Export")?;
+
+        for (col, field) in dataset.schema.fields().iter().enumerate() {
+            worksheet.write_string_with_format(0, col as u16, field.name(), &header_format)?;
+        }
+
+        let mut row_offset: u32 = 1;
+        for batch in &dataset.batches {
+            for (col, column) in batch.columns().iter().enumerate() {
+                let col = col as u16;
+                match column.data_type() {
+                    DataType::Boolean => {
+                        let values = column.as_any().downcast_ref::<BooleanArray>().unwrap();
+                        for row in 0..values.len() {
+                            if values.is_valid(row) {
+                                worksheet.write_boolean(row_offset + row as u32, col, values.value(row))?;
+                            }
+                        }
+                    }
+                    data_type if data_type.is_numeric() => {
+                        let values = cast(column, &DataType::Float64)?;
+                        let values = values.as_any().downcast_ref::<Float64Array>().unwrap();
+                        for row in 0..values.len() {
+                            if values.is_valid(row) {
+                                worksheet.write_number(row_offset + row as u32, col, values.value(row))?;
+                            }
+                        }
+                    }
+                    _ => {
+                        let values: ArrayRef = cast(column, &DataType::Utf8)?;
+                        let values = values.as_any().downcast_ref::<StringArray>().unwrap();
+                        for row in 0..values.len() {
+                            if values.is_valid(row) {
+                                worksheet.write_string(row_offset + row as u32, col, values.value(row))?;
+                            }
+                        }
+                    }
+                }
+            }
+            row_offset += batch.num_rows() as u32;
+        }
+
+        Ok(workbook.save_to_buffer()?)
+    }
+}
+
+/// `Write` adapter that forwards output to an async response stream.
+///
+/// Intended for use on a blocking thread; each full chunk is sent with
+/// `blocking_send`. Writes fail with `BrokenPipe` once the receiver (the HTTP
+/// response) has been dropped, which stops the encoder early when a client
+/// disconnects.
+pub struct ChannelWriter {
+    sender: mpsc::Sender<Result<Bytes, io::Error>>,
+    buffer: Vec<u8>,
+}
+
+impl ChannelWriter {
+    pub fn new(sender: mpsc::Sender<Result<Bytes, io::Error>>) -> Self {
+        ChannelWriter {
+            sender,
+            buffer: Vec::with_capacity(STREAM_CHUNK_SIZE),
+        }
+    }
+
+    fn send_buffer(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(STREAM_CHUNK_SIZE));
+        self.sender
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "export stream closed"))
+    }
+
+    /// Report a failure to the client by terminating the stream with an error
+    pub fn abort(self, error: io::Error) {
+        let _ = self.sender.blocking_send(Err(error));
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= STREAM_CHUNK_SIZE {
+            self.send_buffer()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_buffer()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int64Array;
+    use arrow::datatypes::{Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use std::sync::Arc;
+
+    fn sample() -> Dataset {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, true),
+            Field::new("amount", DataType::Int64, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![Some("Alice"), None])),
+                Arc::new(Int64Array::from(vec![Some(10), Some(20)])),
+            ],
+        )
+        .unwrap();
+        Dataset { schema, batches: vec![batch] }
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let mut output = Vec::new();
+        ExportService::write(&sample(), ExportFormat::Csv, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "name,amount\nAlice,10\n,20\n");
+    }
+
+    #[test]
+    fn test_export_ndjson() {
+        let mut output = Vec::new();
+        ExportService::write(&sample(), ExportFormat::Json, &mut output).unwrap();
+        let rows: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                serde_json::json!({"name": "Alice", "amount": 10}),
+                serde_json::json!({"amount": 20}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export_parquet_and_xlsx_produce_archives() {
+        let mut parquet = Vec::new();
+        ExportService::write(&sample(), ExportFormat::Parquet, &mut parquet).unwrap();
+        assert_eq!(&parquet[..4], b"PAR1");
+
+        let mut xlsx = Vec::new();
+        ExportService::write(&sample(), ExportFormat::Xlsx, &mut xlsx).unwrap();
+        assert_eq!(&xlsx[..2], b"PK");
+    }
+
+    #[test]
+    fn test_format_metadata() {
+        assert_eq!(ExportFormat::Xlsx.extension(), "xlsx");
+        assert_eq!(ExportFormat::Json.content_type(), "application/x-ndjson");
+        let format: ExportFormat = serde_json::from_str("\"ndjson\"").unwrap();
+        assert_eq!(format, ExportFormat::Json);
+    }
+}
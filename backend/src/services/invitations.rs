@@ -0,0 +1,309 @@
+//! Team invitations
+//!
+//! Members join a team by answering an emailed invitation rather than being
+//! added outright. The link carries an opaque token stored only as a SHA-256
+//! hash; it expires after [`INVITATION_TTL`], and resending issues a new token
+//! that voids the old one. Only an account with the invited address may
+//! accept, and an address with no account yet joins the teams that invited it
+//! once it registers and proves the address.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::TeamRole;
+use crate::services::auth::{random_token, sha256_hex};
+use crate::services::team_settings::TeamSettings;
+
+/// How long an invitation can be answered
+pub const INVITATION_TTL: Duration = Duration::days(7);
+
+type Tx<'a> = sqlx::Transaction<'a, sqlx::Postgres>;
+
+/// Where an invitation stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "invitation_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum InvitationStatus {
+    Pending,
+    Accepted,
+    Declined,
+    Revoked,
+}
+
+/// Invitation as stored, with its team's and inviter's names
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Invitation {
+    pub id: Uuid,
+    pub team_id: Uuid,
+    pub team_name: String,
+    pub email: String,
+    pub role: TeamRole,
+    pub invited_by: Option<Uuid>,
+    pub invited_by_name: Option<String>,
+    pub status: InvitationStatus,
+    pub expires_at: DateTime<Utc>,
+    pub responded_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Invitation {
+    /// Whether the invitation can still be answered
+    pub fn is_open(&self) -> bool {
+        self.status == InvitationStatus::Pending && self.expires_at > Utc::now()
+    }
+}
+
+const INVITATION_SELECT: &str = r#"
+    SELECT i.id, i.team_id, t.name AS team_name, i.email, i.role,
+           i.invited_by, u.name AS invited_by_name,
+           i.status, i.expires_at, i.responded_at, i.created_at
+    FROM team_invitations i
+    JOIN teams t ON t.id = i.team_id
+    LEFT JOIN users u ON u.id = i.invited_by
+"#;
+
+/// Team invitation service
+pub struct InvitationService;
+
+impl InvitationService {
+    /// Invite `email` to a team, returning the invitation and the token to
+    /// email; `None` if the address already has a pending invitation
+    pub async fn create(
+        tx: &mut Tx<'_>,
+        team_id: Uuid,
+        email: &str,
+        role: &TeamRole,
+        invited_by: Uuid,
+    ) -> Result<Option<(Invitation, String)>, sqlx::Error> {
+        let token = random_token(32);
+        let inserted: Option<(Uuid,)> = sqlx::query_as(
+            r#"
+            INSERT INTO team_invitations (team_id, email, role, token_hash, invited_by, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (team_id, email) WHERE status = 'pending' DO NOTHING
+            RETURNING id
+            "#
+        )
+        .bind(team_id)
+        .bind(email)
+        .bind(role)
+        .bind(sha256_hex(&token))
+        .bind(invited_by)
+        .bind(Utc::now() + INVITATION_TTL)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        match inserted {
+            Some((id,)) => Ok(Some((Self::fetch(tx, id).await?, token))),
+            None => Ok(None),
+        }
+    }
+
+    /// A team's pending invitations, newest first, including expired ones
+    /// that may be resent
+    pub async fn list_pending(pool: &sqlx::PgPool, team_id: Uuid) -> Result<Vec<Invitation>, sqlx::Error> {
+        sqlx::query_as(&format!(
+            "{} WHERE i.team_id = $1 AND i.status = 'pending' ORDER BY i.created_at DESC",
+            INVITATION_SELECT
+        ))
+        .bind(team_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Open invitations sent to an address
+    pub async fn open_for_email(pool: &sqlx::PgPool, email: &str) -> Result<Vec<Invitation>, sqlx::Error> {
+        sqlx::query_as(&format!(
+            "{} WHERE i.email = $1 AND i.status = 'pending' AND i.expires_at > NOW() ORDER BY i.created_at DESC",
+            INVITATION_SELECT
+        ))
+        .bind(email)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Invitation by ID
+    pub async fn get(pool: &sqlx::PgPool, id: Uuid) -> Result<Option<Invitation>, sqlx::Error> {
+        sqlx::query_as(&format!("{} WHERE i.id = $1", INVITATION_SELECT))
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Open invitation carrying `token`
+    pub async fn find_by_token(pool: &sqlx::PgPool, token: &str) -> Result<Option<Invitation>, sqlx::Error> {
+        let invitation: Option<Invitation> = sqlx::query_as(&format!("{} WHERE i.token_hash = $1", INVITATION_SELECT))
+            .bind(sha256_hex(token))
+            .fetch_optional(pool)
+            .await?;
+        Ok(invitation.filter(Invitation::is_open))
+    }
+
+    /// Issue a new token for a pending invitation and restart its expiry,
+    /// voiding the previous link
+    pub async fn reissue(
+        tx: &mut Tx<'_>,
+        team_id: Uuid,
+        id: Uuid,
+    ) -> Result<Option<(Invitation, String)>, sqlx::Error> {
+        let token = random_token(32);
+        let updated = sqlx::query(
+            r#"
+            UPDATE team_invitations SET token_hash = $3, expires_at = $4
+            WHERE id = $1 AND team_id = $2 AND status = 'pending'
+            "#
+        )
+        .bind(id)
+        .bind(team_id)
+        .bind(sha256_hex(&token))
+        .bind(Utc::now() + INVITATION_TTL)
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+
+        if updated == 0 {
+            return Ok(None);
+        }
+        Ok(Some((Self::fetch(tx, id).await?, token)))
+    }
+
+    /// Withdraw a pending invitation
+    pub async fn revoke(pool: &sqlx::PgPool, team_id: Uuid, id: Uuid) -> Result<Option<Invitation>, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        let revoked = Self::respond(&mut tx, id, Some(team_id), InvitationStatus::Revoked).await?;
+        tx.commit().await?;
+        Ok(revoked)
+    }
+
+    /// Decline an open invitation
+    pub async fn decline(pool: &sqlx::PgPool, id: Uuid) -> Result<Option<Invitation>, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        let declined = Self::respond(&mut tx, id, None, InvitationStatus::Declined).await?;
+        tx.commit().await?;
+        Ok(declined)
+    }
+
+    /// Accept an open invitation for `user_id`, adding them to the team with
+    /// the invited role unless they are already a member
+    pub async fn accept(tx: &mut Tx<'_>, id: Uuid, user_id: Uuid) -> Result<Option<Invitation>, sqlx::Error> {
+        let accepted = match Self::respond(tx, id, None, InvitationStatus::Accepted).await? {
+            Some(invitation) => invitation,
+            None => return Ok(None),
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO team_members (team_id, user_id, role)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (team_id, user_id) DO NOTHING
+            "#
+        )
+        .bind(accepted.team_id)
+        .bind(user_id)
+        .bind(&accepted.role)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(Some(accepted))
+    }
+
+    /// Accept the open invitations to `email` sent up to `invited_before`,
+    /// for a new user who has just proved they own the address; invitations
+    /// sent once the account exists are left for the user to answer, as are
+    /// those from teams that no longer admit the address's domain
+    pub async fn accept_all(
+        tx: &mut Tx<'_>,
+        user_id: Uuid,
+        email: &str,
+        invited_before: DateTime<Utc>,
+    ) -> Result<Vec<Invitation>, sqlx::Error> {
+        let open: Vec<(Uuid, serde_json::Value)> = sqlx::query_as(
+            r#"
+            SELECT i.id, t.settings
+            FROM team_invitations i
+            JOIN teams t ON t.id = i.team_id
+            WHERE i.email = $1 AND i.status = 'pending' AND i.expires_at > NOW() AND i.created_at <= $2
+            "#
+        )
+        .bind(email)
+        .bind(invited_before)
+        .fetch_all(&mut **tx)
+        .await?;
+
+        let mut accepted = Vec::with_capacity(open.len());
+        for (id, settings) in open {
+            if TeamSettings::from_value(&settings).allows_email(email) {
+                accepted.extend(Self::accept(tx, id, user_id).await?);
+            }
+        }
+        Ok(accepted)
+    }
+
+    /// Move an open invitation to `status`; revoking also applies to
+    /// invitations that have expired
+    async fn respond(
+        tx: &mut Tx<'_>,
+        id: Uuid,
+        team_id: Option<Uuid>,
+        status: InvitationStatus,
+    ) -> Result<Option<Invitation>, sqlx::Error> {
+        let updated = sqlx::query(
+            r#"
+            UPDATE team_invitations SET status = $3, responded_at = NOW()
+            WHERE id = $1
+              AND ($2::uuid IS NULL OR team_id = $2)
+              AND status = 'pending'
+              AND ($3 = 'revoked' OR expires_at > NOW())
+            "#
+        )
+        .bind(id)
+        .bind(team_id)
+        .bind(status)
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+
+        if updated == 0 {
+            return Ok(None);
+        }
+        Ok(Some(Self::fetch(tx, id).await?))
+    }
+
+    async fn fetch(conn: &mut sqlx::PgConnection, id: Uuid) -> Result<Invitation, sqlx::Error> {
+        sqlx::query_as(&format!("{} WHERE i.id = $1", INVITATION_SELECT))
+            .bind(id)
+            .fetch_one(conn)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invitation_is_open() {
+        let mut invitation = Invitation {
+            id: Uuid::new_v4(),
+            team_id: Uuid::new_v4(),
+            team_name: "Analytics".to_string(),
+            email: "new@example.com".to_string(),
+            role: TeamRole::Member,
+            invited_by: None,
+            invited_by_name: None,
+            status: InvitationStatus::Pending,
+            expires_at: Utc::now() + INVITATION_TTL,
+            responded_at: None,
+            created_at: Utc::now(),
+        };
+        assert!(invitation.is_open());
+
+        invitation.expires_at = Utc::now() - Duration::minutes(1);
+        assert!(!invitation.is_open());
+
+        invitation.expires_at = Utc::now() + INVITATION_TTL;
+        invitation.status = InvitationStatus::Revoked;
+        assert!(!invitation.is_open());
+    }
+}
@@ -0,0 +1,545 @@
+//! Column masking and PII classification
+//!
+//! Columns of a file can be classified as PII or sensitive, by hand or by
+//! detection when the file is profiled. Classified columns carry a
+//! [`MaskRule`] applied to previews, queries and exports for everyone except
+//! the file's owner and users holding `dataset:unmask`.
+//!
+//! Hashed values are keyed with `MASKING_HASH_KEY`, so equal values still
+//! group and join but cannot be recovered by hashing guesses. Without it a
+//! random key is used and hashes change when the server restarts.
+
+use arrow::array::{new_null_array, Array, ArrayRef, StringArray};
+use arrow::compute::kernels::cast::cast;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::{Arc, LazyLock};
+use uuid::Uuid;
+
+use crate::connectors::Dataset;
+use crate::services::auth::mfa::MfaService;
+use crate::services::auth::random_token;
+use crate::services::permissions::{Permission, PermissionService};
+
+/// Non-null values sampled per column for detection
+pub const DETECTION_SAMPLE: usize = 200;
+
+/// Share of sampled values that must match a pattern to classify a column
+const DETECTION_THRESHOLD: f64 = 0.8;
+
+/// Hex characters kept from each hashed value
+const HASH_LENGTH: usize = 16;
+
+/// Trailing characters left visible by a partial mask
+const PARTIAL_VISIBLE: usize = 4;
+
+static HASH_KEY: LazyLock<String> = LazyLock::new(|| {
+    std::env::var("MASKING_HASH_KEY").unwrap_or_else(|_| {
+        log::warn!("MASKING_HASH_KEY is not set; masked hashes will change on restart");
+        random_token(32)
+    })
+});
+
+/// Column name tokens suggesting personal data
+const PII_NAMES: &[&str] = &[
+    "email", "mail", "phone", "mobile", "tel", "ssn", "passport", "address", "street", "postcode", "zipcode",
+    "zip", "birthday", "birthdate", "dob", "firstname", "lastname", "fullname", "surname", "iban", "ip",
+];
+
+/// Column name tokens suggesting other sensitive data
+const SENSITIVE_NAMES: &[&str] = &[
+    "salary", "salaries", "wage", "wages", "compensation", "income", "bonus", "password", "secret", "diagnosis",
+    "medical", "religion", "ethnicity",
+];
+
+/// Classification of a column's contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "data_classification", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Classification {
+    /// Personally identifiable information
+    Pii,
+    /// Confidential but not identifying, e.g. salaries
+    Sensitive,
+}
+
+impl Classification {
+    /// Rule applied when a column is classified without choosing one
+    pub fn default_mask(&self) -> MaskRule {
+        match self {
+            Classification::Pii => MaskRule::Partial,
+            Classification::Sensitive => MaskRule::Null,
+        }
+    }
+}
+
+/// How a classified column is shown to users who may not unmask it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "mask_rule", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum MaskRule {
+    /// Remove the column
+    Hide,
+    /// Replace values with a keyed hash
+    Hash,
+    /// Keep the last characters (or an email's domain) and star the rest
+    Partial,
+    /// Replace values with nulls
+    Null,
+}
+
+/// Mask applied to one column
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ColumnMask {
+    pub column: String,
+    pub rule: MaskRule,
+}
+
+/// Classification of a file column
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ColumnClassification {
+    pub name: String,
+    pub classification: Option<Classification>,
+    pub mask: Option<MaskRule>,
+    /// Whether the classification was detected rather than set by a user
+    pub classification_detected: bool,
+}
+
+/// Column masking service
+pub struct MaskingService;
+
+impl MaskingService {
+    /// Masks on a file's classified columns
+    pub async fn masks(pool: &PgPool, file_id: Uuid) -> Result<Vec<ColumnMask>, sqlx::Error> {
+        let rows: Vec<(String, MaskRule)> = sqlx::query_as(
+            r#"
+            SELECT name, mask FROM file_columns
+            WHERE file_id = $1 AND classification IS NOT NULL AND mask IS NOT NULL
+            ORDER BY position
+            "#
+        )
+        .bind(file_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|(column, rule)| ColumnMask { column, rule }).collect())
+    }
+
+    /// Classification of every column of a file, in column order
+    pub async fn list(pool: &PgPool, file_id: Uuid) -> Result<Vec<ColumnClassification>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT name, classification, mask, classification_detected
+            FROM file_columns
+            WHERE file_id = $1
+            ORDER BY position
+            "#
+        )
+        .bind(file_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Whether a user sees a file's classified columns unmasked: as its owner,
+    /// or holding `dataset:unmask` system-wide or through their role in the
+    /// file's team. Managing teams does not count; only members see a team
+    /// file's data in the clear.
+    pub async fn can_unmask(
+        pool: &PgPool,
+        user_id: Uuid,
+        owner_id: Uuid,
+        team_id: Option<Uuid>,
+    ) -> Result<bool, sqlx::Error> {
+        if user_id == owner_id || PermissionService::has_permission(pool, user_id, Permission::DatasetUnmask).await? {
+            return Ok(true);
+        }
+        match team_id {
+            Some(team_id) => Ok(MfaService::meets_team_requirement(pool, user_id, team_id).await?
+                && PermissionService::get_team_permissions(pool, user_id, team_id)
+                    .await?
+                    .contains(&Permission::DatasetUnmask)),
+            None => Ok(false),
+        }
+    }
+
+    /// Classify a column, or clear its classification with `None`; returns
+    /// `None` if the file has no such column
+    pub async fn classify(
+        pool: &PgPool,
+        file_id: Uuid,
+        column: &str,
+        classification: Option<Classification>,
+        mask: Option<MaskRule>,
+    ) -> Result<Option<ColumnClassification>, sqlx::Error> {
+        let mask = classification.map(|c| mask.unwrap_or_else(|| c.default_mask()));
+
+        sqlx::query_as(
+            r#"
+            UPDATE file_columns
+            SET classification = $3, mask = $4, classification_detected = FALSE
+            WHERE file_id = $1 AND name = $2
+            RETURNING name, classification, mask, classification_detected
+            "#
+        )
+        .bind(file_id)
+        .bind(column)
+        .bind(classification)
+        .bind(mask)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Apply masks to a dataset; columns without a mask are unchanged
+    pub fn apply(dataset: &Dataset, masks: &[ColumnMask]) -> Result<Dataset, ArrowError> {
+        if masks.is_empty() {
+            return Ok(Dataset {
+                schema: dataset.schema.clone(),
+                batches: dataset.batches.clone(),
+            });
+        }
+
+        let rule_for = |name: &str| masks.iter().find(|m| m.column == name).map(|m| m.rule);
+
+        let fields: Vec<Field> = dataset
+            .schema
+            .fields()
+            .iter()
+            .filter_map(|field| match rule_for(field.name()) {
+                None => Some(field.as_ref().clone()),
+                Some(MaskRule::Hide) => None,
+                Some(MaskRule::Null) => Some(field.as_ref().clone().with_nullable(true)),
+                Some(MaskRule::Hash | MaskRule::Partial) => Some(Field::new(field.name(), DataType::Utf8, true)),
+            })
+            .collect();
+        let schema = Arc::new(Schema::new(fields));
+
+        let mut batches = Vec::with_capacity(dataset.batches.len());
+        for batch in &dataset.batches {
+            let mut columns: Vec<ArrayRef> = Vec::with_capacity(schema.fields().len());
+            for (field, column) in dataset.schema.fields().iter().zip(batch.columns()) {
+                match rule_for(field.name()) {
+                    None => columns.push(column.clone()),
+                    Some(MaskRule::Hide) => {}
+                    Some(MaskRule::Null) => columns.push(new_null_array(field.data_type(), column.len())),
+                    Some(rule) => columns.push(Arc::new(mask_text(column, rule)?)),
+                }
+            }
+            batches.push(RecordBatch::try_new(schema.clone(), columns)?);
+        }
+
+        Ok(Dataset { schema, batches })
+    }
+
+    /// Detect the classification of each column of a dataset, in schema
+    /// order, from its name and a sample of its text values
+    pub fn detect(dataset: &Dataset) -> Result<Vec<Option<Classification>>, ArrowError> {
+        let mut detected = Vec::with_capacity(dataset.schema.fields().len());
+        for (index, field) in dataset.schema.fields().iter().enumerate() {
+            let mut samples = Vec::new();
+            if matches!(field.data_type(), DataType::Utf8 | DataType::LargeUtf8) {
+                for batch in &dataset.batches {
+                    let text = cast(batch.column(index), &DataType::Utf8)?;
+                    let text = text.as_any().downcast_ref::<StringArray>().unwrap();
+                    samples.extend(
+                        text.iter()
+                            .flatten()
+                            .map(str::trim)
+                            .filter(|v| !v.is_empty())
+                            .take(DETECTION_SAMPLE - samples.len())
+                            .map(str::to_string),
+                    );
+                    if samples.len() >= DETECTION_SAMPLE {
+                        break;
+                    }
+                }
+            }
+            detected.push(classify_column(field.name(), &samples));
+        }
+        Ok(detected)
+    }
+}
+
+/// Classification suggested by a column's name or values
+pub fn classify_column(name: &str, samples: &[String]) -> Option<Classification> {
+    let tokens = name_tokens(name);
+    // Adjacent tokens joined, so `first_name` matches `firstname`
+    let pairs: Vec<String> = tokens.windows(2).map(|w| format!("{}{}", w[0], w[1])).collect();
+    let named = |names: &[&str]| tokens.iter().chain(&pairs).any(|t| names.contains(&t.as_str()));
+
+    if named(SENSITIVE_NAMES) {
+        return Some(Classification::Sensitive);
+    }
+    if named(PII_NAMES) {
+        return Some(Classification::Pii);
+    }
+
+    if !samples.is_empty() {
+        let matching = samples
+            .iter()
+            .filter(|v| is_email(v) || is_phone(v) || is_ssn(v) || is_card_number(v))
+            .count();
+        if matching as f64 / samples.len() as f64 >= DETECTION_THRESHOLD {
+            return Some(Classification::Pii);
+        }
+    }
+
+    None
+}
+
+/// Lower-case words of a column name, split on punctuation and camel case
+fn name_tokens(name: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if (!c.is_alphanumeric() || (c.is_uppercase() && previous_lower)) && !current.is_empty() {
+            tokens.push(std::mem::take(&mut current));
+        }
+        if c.is_alphanumeric() {
+            current.extend(c.to_lowercase());
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn is_email(value: &str) -> bool {
+    match value.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !value.contains(char::is_whitespace)
+                && !domain.contains('@')
+                && domain.split('.').count() >= 2
+                && domain.split('.').all(|part| !part.is_empty())
+        }
+        None => false,
+    }
+}
+
+fn is_phone(value: &str) -> bool {
+    let digits = value.chars().filter(char::is_ascii_digit).count();
+    (7..=15).contains(&digits)
+        && value.chars().all(|c| c.is_ascii_digit() || " +-().".contains(c))
+        // Separated or international, so plain numbers are not mistaken for phones
+        && (value.starts_with('+') || value.contains(['-', ' ', '(']))
+        && !is_date(value)
+}
+
+/// `YYYY-MM-DD`, which would otherwise pass for a phone number
+fn is_date(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    parts.len() == 3 && parts.iter().map(|p| p.len()).eq([4, 2, 2])
+}
+
+fn is_ssn(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    parts.len() == 3
+        && [3, 2, 4].iter().zip(&parts).all(|(len, part)| part.len() == *len && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// 13-19 digits, optionally grouped, passing the Luhn check
+fn is_card_number(value: &str) -> bool {
+    if !value.chars().all(|c| c.is_ascii_digit() || c == ' ' || c == '-') {
+        return false;
+    }
+    let digits: Vec<u32> = value.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 1 { if d * 2 > 9 { d * 2 - 9 } else { d * 2 } } else { *d })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+fn mask_text(column: &ArrayRef, rule: MaskRule) -> Result<StringArray, ArrowError> {
+    let text = cast(column, &DataType::Utf8)?;
+    let text = text.as_any().downcast_ref::<StringArray>().unwrap();
+    Ok(text
+        .iter()
+        .map(|value| {
+            value.map(|v| match rule {
+                MaskRule::Hash => hash_value(v),
+                _ => partial_mask(v),
+            })
+        })
+        .collect())
+}
+
+fn hash_value(value: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(HASH_KEY.as_bytes())
+        .chain_update([0])
+        .chain_update(value.as_bytes())
+        .finalize();
+    digest.iter().map(|b| format!("{:02x}", b)).collect::<String>()[..HASH_LENGTH].to_string()
+}
+
+/// Star all but the last few characters; emails keep their first character
+/// and domain
+pub fn partial_mask(value: &str) -> String {
+    if is_email(value) {
+        let (local, domain) = value.split_once('@').unwrap();
+        let first = local.chars().next().unwrap();
+        return format!("{}***@{}", first, domain);
+    }
+
+    let length = value.chars().count();
+    let visible = if length > PARTIAL_VISIBLE * 2 { PARTIAL_VISIBLE } else { 0 };
+    value
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i < length - visible && !c.is_whitespace() { '*' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::UserRole;
+    use crate::test_db::TestDb;
+    use arrow::array::Float64Array;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_classify_by_name() {
+        assert_eq!(classify_column("Email", &[]), Some(Classification::Pii));
+        assert_eq!(classify_column("first_name", &[]), Some(Classification::Pii));
+        assert_eq!(classify_column("customerPhoneNumber", &[]), Some(Classification::Pii));
+        assert_eq!(classify_column("base_salary", &[]), Some(Classification::Sensitive));
+        assert_eq!(classify_column("region", &[]), None);
+        // Substrings of other words do not count
+        assert_eq!(classify_column("shipping_method", &[]), None);
+        assert_eq!(classify_column("zipped", &[]), None);
+    }
+
+    #[test]
+    fn test_classify_by_values() {
+        let emails = strings(&["ada@example.com", "grace@navy.mil", "n/a", "alan@example.org", "x@y.io"]);
+        assert_eq!(classify_column("contact", &emails), Some(Classification::Pii));
+
+        let phones = strings(&["+44 20 7946 0958", "(555) 123-4567", "555-987-6543"]);
+        assert_eq!(classify_column("contact", &phones), Some(Classification::Pii));
+
+        assert_eq!(classify_column("card", &strings(&["4111 1111 1111 1111"])), Some(Classification::Pii));
+        assert_eq!(classify_column("id", &strings(&["123-45-6789"])), Some(Classification::Pii));
+        assert_eq!(classify_column("order_id", &strings(&["1234567", "7654321"])), None);
+        assert_eq!(classify_column("hired", &strings(&["2021-03-01", "2019-11-15"])), None);
+        assert_eq!(classify_column("note", &strings(&["ada@example.com", "hello", "world"])), None);
+    }
+
+    #[test]
+    fn test_partial_mask() {
+        assert_eq!(partial_mask("ada@example.com"), "a***@example.com");
+        assert_eq!(partial_mask("4111 1111 1111 1234"), "**** **** **** 1234");
+        assert_eq!(partial_mask("secret"), "******");
+    }
+
+    #[test]
+    fn test_apply_masks() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("email", DataType::Utf8, false),
+            Field::new("salary", DataType::Float64, false),
+            Field::new("ssn", DataType::Utf8, true),
+            Field::new("region", DataType::Utf8, false),
+            Field::new("employee", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["ada@example.com", "bob@example.com"])),
+                Arc::new(Float64Array::from(vec![100.0, 200.0])),
+                Arc::new(StringArray::from(vec![Some("123-45-6789"), None])),
+                Arc::new(StringArray::from(vec!["north", "south"])),
+                Arc::new(StringArray::from(vec!["ada", "ada"])),
+            ],
+        )
+        .unwrap();
+        let dataset = Dataset { schema, batches: vec![batch] };
+        let masks = vec![
+            ColumnMask { column: "email".to_string(), rule: MaskRule::Partial },
+            ColumnMask { column: "salary".to_string(), rule: MaskRule::Null },
+            ColumnMask { column: "ssn".to_string(), rule: MaskRule::Hide },
+            ColumnMask { column: "employee".to_string(), rule: MaskRule::Hash },
+        ];
+
+        let masked = MaskingService::apply(&dataset, &masks).unwrap();
+        let names: Vec<&str> = masked.schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, ["email", "salary", "region", "employee"]);
+
+        let batch = &masked.batches[0];
+        let email = batch.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(email.value(0), "a***@example.com");
+        assert_eq!(batch.column(1).null_count(), 2);
+        let region = batch.column(2).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(region.value(1), "south");
+
+        let employee = batch.column(3).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(employee.value(0).len(), HASH_LENGTH);
+        assert_eq!(employee.value(0), employee.value(1));
+        assert_ne!(employee.value(0), "ada");
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_team_files_unmask_by_team_role_not_team_management() {
+        let db = TestDb::create().await;
+        let pool = &db.pool;
+
+        let mut ids = Vec::new();
+        for (email, role) in [
+            ("owner@example.com", UserRole::User),
+            ("member@example.com", UserRole::User),
+            ("analyst@example.com", UserRole::User),
+            ("admin@example.com", UserRole::Admin),
+        ] {
+            let (id,): (Uuid,) =
+                sqlx::query_as("INSERT INTO users (email, password_hash, name, role) VALUES ($1, 'x', $1, $2) RETURNING id")
+                    .bind(email)
+                    .bind(role)
+                    .fetch_one(pool)
+                    .await
+                    .unwrap();
+            ids.push(id);
+        }
+        let (owner, member, analyst, admin) = (ids[0], ids[1], ids[2], ids[3]);
+
+        let (team_id,): (Uuid,) =
+            sqlx::query_as("INSERT INTO teams (name, slug, owner_id) VALUES ('Sales', 'sales', $1) RETURNING id")
+                .bind(owner)
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        sqlx::query("INSERT INTO team_members (team_id, user_id, role) VALUES ($1, $2, 'owner'), ($1, $3, 'member'), ($1, $4, 'member')")
+            .bind(team_id)
+            .bind(owner)
+            .bind(member)
+            .bind(analyst)
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let uploader = member;
+        assert!(MaskingService::can_unmask(pool, owner, uploader, Some(team_id)).await.unwrap());
+        assert!(MaskingService::can_unmask(pool, member, uploader, Some(team_id)).await.unwrap());
+        assert!(!MaskingService::can_unmask(pool, analyst, uploader, Some(team_id)).await.unwrap());
+        assert!(!MaskingService::can_unmask(pool, owner, uploader, None).await.unwrap());
+        // Managing every team does not reveal a team's data
+        assert!(!MaskingService::can_unmask(pool, admin, uploader, Some(team_id)).await.unwrap());
+
+        db.drop().await;
+    }
+}
@@ -1,9 +1,20 @@
 pub mod query_engine;
 pub mod dashboard;
+pub mod acl;
 pub mod auth;
 pub mod cache;
+pub mod mail;
+pub mod masking;
 pub mod realtime;
 pub mod audit;
+pub mod export;
+pub mod invitations;
 pub mod permissions;
+pub mod profiler;
+pub mod rate_limit;
+pub mod roles;
+pub mod row_security;
+pub mod team_settings;
+pub mod usage;
 
 
@@ -1,13 +1,19 @@
 //! RBAC Permission System
 //!
 //! Provides fine-grained role-based access control for PilotBA.
-//! Permissions can be assigned at system level or team level.
+//! Permissions can be assigned at system level or team level. Role
+//! permission sets are stored in the database (see [`crate::services::roles`]);
+//! the mappings below are the built-in defaults they are seeded from.
 
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use uuid::Uuid;
 use std::collections::HashSet;
 
+use crate::services::acl::{AccessLevel, AclService, ResourceKind};
+use crate::services::auth::mfa::MfaService;
+use crate::services::roles::{RoleRef, RoleService};
+
 // ============================================================================
 // PERMISSIONS
 // ============================================================================
@@ -29,6 +35,8 @@ pub enum Permission {
     DatasetUpdate,
     DatasetDelete,
     DatasetShare,
+    /// See classified columns unmasked
+    DatasetUnmask,
     
     // Query permissions
     QueryCreate,
@@ -71,6 +79,7 @@ impl Permission {
             Permission::DatasetUpdate => "dataset:update",
             Permission::DatasetDelete => "dataset:delete",
             Permission::DatasetShare => "dataset:share",
+            Permission::DatasetUnmask => "dataset:unmask",
             
             Permission::QueryCreate => "query:create",
             Permission::QueryRead => "query:read",
@@ -95,6 +104,11 @@ impl Permission {
         }
     }
 
+    /// Parse the string representation, e.g. `dataset:upload`
+    pub fn parse(s: &str) -> Option<Permission> {
+        Self::all().into_iter().find(|p| p.as_str() == s)
+    }
+
     /// Get all permissions
     pub fn all() -> Vec<Permission> {
         vec![
@@ -108,6 +122,7 @@ impl Permission {
             Permission::DatasetUpdate,
             Permission::DatasetDelete,
             Permission::DatasetShare,
+            Permission::DatasetUnmask,
             Permission::QueryCreate,
             Permission::QueryRead,
             Permission::QueryExecute,
@@ -147,6 +162,16 @@ pub enum SystemRole {
 }
 
 impl SystemRole {
+    /// Map a `users.role` value to its role
+    pub fn from_db(role: &str) -> SystemRole {
+        match role {
+            "admin" => SystemRole::Admin,
+            "user" => SystemRole::User,
+            "readonly" => SystemRole::ReadOnly,
+            _ => SystemRole::User,
+        }
+    }
+
     /// Get permissions for this role
     pub fn permissions(&self) -> HashSet<Permission> {
         match self {
@@ -224,6 +249,17 @@ pub enum TeamRoleType {
 }
 
 impl TeamRoleType {
+    /// Map a `team_members.role` value to its role
+    pub fn from_db(role: &str) -> TeamRoleType {
+        match role {
+            "owner" => TeamRoleType::Owner,
+            "admin" => TeamRoleType::Admin,
+            "member" => TeamRoleType::Member,
+            "viewer" => TeamRoleType::Viewer,
+            _ => TeamRoleType::Viewer,
+        }
+    }
+
     /// Get team permissions for this role
     pub fn team_permissions(&self) -> HashSet<Permission> {
         match self {
@@ -238,6 +274,7 @@ impl TeamRoleType {
                 Permission::DatasetUpdate,
                 Permission::DatasetDelete,
                 Permission::DatasetShare,
+                Permission::DatasetUnmask,
                 Permission::QueryCreate,
                 Permission::QueryRead,
                 Permission::QueryExecute,
@@ -270,6 +307,7 @@ impl TeamRoleType {
                 Permission::ChartUpdate,
                 Permission::ChartExport,
                 Permission::TeamManageMembers,
+                Permission::TeamManageSettings,
             ].into_iter().collect(),
             
             TeamRoleType::Member => vec![
@@ -312,28 +350,7 @@ impl PermissionService {
         user_id: Uuid,
         permission: Permission,
     ) -> Result<bool, sqlx::Error> {
-        // Get user's system role
-        let user: Option<(String,)> = sqlx::query_as(
-            "SELECT role::text FROM users WHERE id = $1"
-        )
-        .bind(user_id)
-        .fetch_optional(pool)
-        .await?;
-
-        let user_role = match user {
-            Some((role,)) => role,
-            None => return Ok(false),
-        };
-
-        // Map database role to SystemRole
-        let system_role = match user_role.as_str() {
-            "admin" => SystemRole::Admin,
-            "user" => SystemRole::User,
-            "readonly" => SystemRole::ReadOnly,
-            _ => SystemRole::User,
-        };
-
-        Ok(system_role.permissions().contains(&permission))
+        Ok(Self::get_user_permissions(pool, user_id).await?.contains(&permission))
     }
 
     /// Check if user has a specific permission within a team
@@ -343,35 +360,22 @@ impl PermissionService {
         team_id: Uuid,
         permission: Permission,
     ) -> Result<bool, sqlx::Error> {
-        // First check system-level admin permissions
+        // A team's MFA requirement binds system administrators too
+        if !MfaService::meets_team_requirement(pool, user_id, team_id).await? {
+            return Ok(false);
+        }
+
+        // Then check system-level admin permissions
         if Self::has_permission(pool, user_id, Permission::AdminManageTeams).await? {
             return Ok(true);
         }
 
-        // Get user's team role
-        let team_member: Option<(String,)> = sqlx::query_as(
-            "SELECT role::text FROM team_members WHERE team_id = $1 AND user_id = $2"
-        )
-        .bind(team_id)
-        .bind(user_id)
-        .fetch_optional(pool)
-        .await?;
-
-        let team_role = match team_member {
-            Some((role,)) => role,
+        let role = match Self::team_role(pool, user_id, team_id).await? {
+            Some(role) => role,
             None => return Ok(false), // Not a team member
         };
 
-        // Map database role to TeamRoleType
-        let role_type = match team_role.as_str() {
-            "owner" => TeamRoleType::Owner,
-            "admin" => TeamRoleType::Admin,
-            "member" => TeamRoleType::Member,
-            "viewer" => TeamRoleType::Viewer,
-            _ => TeamRoleType::Viewer,
-        };
-
-        Ok(role_type.team_permissions().contains(&permission))
+        Ok(RoleService::permissions(pool, &role).await?.contains(&permission))
     }
 
     /// Get all permissions for a user at system level
@@ -386,19 +390,10 @@ impl PermissionService {
         .fetch_optional(pool)
         .await?;
 
-        let user_role = match user {
-            Some((role,)) => role,
-            None => return Ok(HashSet::new()),
-        };
-
-        let system_role = match user_role.as_str() {
-            "admin" => SystemRole::Admin,
-            "user" => SystemRole::User,
-            "readonly" => SystemRole::ReadOnly,
-            _ => SystemRole::User,
-        };
-
-        Ok(system_role.permissions())
+        match user {
+            Some((role,)) => RoleService::permissions(pool, &RoleRef::System(role)).await,
+            None => Ok(HashSet::new()),
+        }
     }
 
     /// Get all permissions for a user within a team
@@ -407,94 +402,115 @@ impl PermissionService {
         user_id: Uuid,
         team_id: Uuid,
     ) -> Result<HashSet<Permission>, sqlx::Error> {
-        let team_member: Option<(String,)> = sqlx::query_as(
-            "SELECT role::text FROM team_members WHERE team_id = $1 AND user_id = $2"
+        match Self::team_role(pool, user_id, team_id).await? {
+            Some(role) => RoleService::permissions(pool, &role).await,
+            None => Ok(HashSet::new()),
+        }
+    }
+
+    /// Effective permissions of a user at system level and in each of their
+    /// teams, as decided by [`Self::has_permission`] and
+    /// [`Self::has_team_permission`]
+    pub async fn summarize(pool: &PgPool, user_id: Uuid) -> Result<UserPermissionsSummary, sqlx::Error> {
+        let system = Self::get_user_permissions(pool, user_id).await?;
+
+        let memberships: Vec<(Uuid, String, String, Option<String>)> = sqlx::query_as(
+            r#"
+            SELECT t.id, t.name, tm.role::text, r.name
+            FROM team_members tm
+            JOIN teams t ON t.id = tm.team_id
+            LEFT JOIN roles r ON r.id = tm.role_id AND tm.role <> 'owner'
+            WHERE tm.user_id = $1
+            ORDER BY t.name
+            "#
         )
-        .bind(team_id)
         .bind(user_id)
-        .fetch_optional(pool)
+        .fetch_all(pool)
         .await?;
 
-        let team_role = match team_member {
-            Some((role,)) => role,
-            None => return Ok(HashSet::new()),
-        };
+        let mut team_permissions = Vec::with_capacity(memberships.len());
+        for (team_id, team_name, role, custom_role) in memberships {
+            let permissions = if !MfaService::meets_team_requirement(pool, user_id, team_id).await? {
+                HashSet::new()
+            } else if system.contains(&Permission::AdminManageTeams) {
+                Permission::all().into_iter().collect()
+            } else {
+                Self::get_team_permissions(pool, user_id, team_id).await?
+            };
+
+            team_permissions.push(TeamPermissionInfo {
+                team_id,
+                team_name,
+                role,
+                custom_role,
+                permissions: permission_names(&permissions),
+            });
+        }
 
-        let role_type = match team_role.as_str() {
-            "owner" => TeamRoleType::Owner,
-            "admin" => TeamRoleType::Admin,
-            "member" => TeamRoleType::Member,
-            "viewer" => TeamRoleType::Viewer,
-            _ => TeamRoleType::Viewer,
-        };
+        Ok(UserPermissionsSummary {
+            user_id,
+            system_permissions: permission_names(&system),
+            team_permissions,
+        })
+    }
+
+    /// Role granting a member's permissions in a team: their custom role if
+    /// assigned one, else their built-in role. Owners always hold the
+    /// built-in owner role.
+    async fn team_role(
+        pool: &PgPool,
+        user_id: Uuid,
+        team_id: Uuid,
+    ) -> Result<Option<RoleRef>, sqlx::Error> {
+        let team_member: Option<(String, Option<Uuid>)> = sqlx::query_as(
+            "SELECT role::text, role_id FROM team_members WHERE team_id = $1 AND user_id = $2"
+        )
+        .bind(team_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
 
-        Ok(role_type.team_permissions())
+        Ok(team_member.map(|(builtin, role_id)| match role_id {
+            Some(role_id) if builtin != "owner" => RoleRef::Custom(role_id),
+            _ => RoleRef::Team { team_id, builtin },
+        }))
     }
 
-    /// Check if user can access a specific resource
+    /// Check if user can access a specific resource: as its owner, through
+    /// an unexpired grant to them or one of their teams (bounded by what
+    /// their system role allows), or through the team the resource belongs to
     pub async fn can_access_resource(
         pool: &PgPool,
         user_id: Uuid,
-        resource_type: &str,
+        resource: ResourceKind,
         resource_id: Uuid,
         required_permission: Permission,
     ) -> Result<bool, sqlx::Error> {
-        // Check if user owns the resource directly
-        let owns = match resource_type {
-            "file" => {
-                let result: Option<(Uuid,)> = sqlx::query_as(
-                    "SELECT id FROM files WHERE id = $1 AND user_id = $2"
-                )
-                .bind(resource_id)
-                .bind(user_id)
-                .fetch_optional(pool)
-                .await?;
-                result.is_some()
-            },
-            "dashboard" => {
-                let result: Option<(Uuid,)> = sqlx::query_as(
-                    "SELECT id FROM dashboards WHERE id = $1 AND user_id = $2"
-                )
-                .bind(resource_id)
-                .bind(user_id)
-                .fetch_optional(pool)
-                .await?;
-                result.is_some()
-            },
-            _ => false,
+        let (owner_id, team_id) = match AclService::owner(pool, resource, resource_id).await? {
+            Some(owner) => owner,
+            None => return Ok(false),
         };
 
-        if owns {
+        // Check if user owns the resource directly
+        if owner_id == user_id {
             return Ok(true);
         }
 
-        // Check if resource belongs to a team user has access to
-        let team_id: Option<(Uuid,)> = match resource_type {
-            "file" => {
-                sqlx::query_as(
-                    "SELECT team_id FROM files WHERE id = $1 AND team_id IS NOT NULL"
-                )
-                .bind(resource_id)
-                .fetch_optional(pool)
-                .await?
-            },
-            "dashboard" => {
-                sqlx::query_as(
-                    "SELECT team_id FROM dashboards WHERE id = $1 AND team_id IS NOT NULL"
-                )
-                .bind(resource_id)
-                .fetch_optional(pool)
-                .await?
-            },
-            _ => None,
-        };
-
-        if let Some((team_id,)) = team_id {
-            return Self::has_team_permission(pool, user_id, team_id, required_permission).await;
+        // Check grants on the resource
+        if let Some(required) = AccessLevel::required_for(required_permission) {
+            let granted = AclService::granted_level(pool, resource, resource_id, user_id).await?;
+            if granted.is_some_and(|level| level >= required)
+                && Self::has_permission(pool, user_id, required_permission).await?
+            {
+                return Ok(true);
+            }
         }
 
-        // Check system-level permissions
-        Self::has_permission(pool, user_id, required_permission).await
+        // Check if resource belongs to a team user has access to
+        match team_id {
+            Some(team_id) => Self::has_team_permission(pool, user_id, team_id, required_permission).await,
+            None => Ok(false),
+        }
     }
 }
 
@@ -502,9 +518,33 @@ impl PermissionService {
 // PERMISSION RESPONSE TYPES
 // ============================================================================
 
+/// Resource a permission is checked against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "id", rename_all = "lowercase")]
+pub enum ResourceRef {
+    Team(Uuid),
+    File(Uuid),
+    Dashboard(Uuid),
+    Query(Uuid),
+}
+
+impl ResourceRef {
+    /// Kind of a shareable resource; `None` for teams, and for dashboards
+    /// and saved queries, which are not stored yet
+    pub fn kind(&self) -> Option<(ResourceKind, Uuid)> {
+        match *self {
+            ResourceRef::File(id) => Some((ResourceKind::File, id)),
+            ResourceRef::Team(_) | ResourceRef::Dashboard(_) | ResourceRef::Query(_) => None,
+        }
+    }
+}
+
 /// Permission info for API responses
 #[derive(Debug, Clone, Serialize)]
 pub struct PermissionInfo {
+    /// Resource checked; absent for system permissions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource: Option<ResourceRef>,
     pub permission: String,
     pub granted: bool,
 }
@@ -522,10 +562,22 @@ pub struct UserPermissionsSummary {
 pub struct TeamPermissionInfo {
     pub team_id: Uuid,
     pub team_name: String,
+    /// Built-in role
     pub role: String,
+    /// Custom role granting the permissions instead of the built-in one
+    pub custom_role: Option<String>,
     pub permissions: Vec<String>,
 }
 
+/// Names of `permissions` in declaration order
+pub fn permission_names(permissions: &HashSet<Permission>) -> Vec<String> {
+    Permission::all()
+        .into_iter()
+        .filter(|p| permissions.contains(p))
+        .map(|p| p.as_str().to_string())
+        .collect()
+}
+
 // ============================================================================
 // TESTS
 // ============================================================================
@@ -538,6 +590,8 @@ mod tests {
     fn test_permission_strings() {
         assert_eq!(Permission::DashboardCreate.as_str(), "dashboard:create");
         assert_eq!(Permission::AdminManageUsers.as_str(), "admin:manage_users");
+        assert_eq!(Permission::parse("dataset:upload"), Some(Permission::DatasetUpload));
+        assert_eq!(Permission::parse("dataset_upload"), None);
     }
 
     #[test]
@@ -563,6 +617,32 @@ mod tests {
         assert!(!viewer_perms.contains(&Permission::DashboardCreate));
     }
 
+    #[test]
+    fn test_roles_from_db() {
+        assert_eq!(SystemRole::from_db("readonly"), SystemRole::ReadOnly);
+        assert_eq!(SystemRole::from_db("unknown"), SystemRole::User);
+        assert_eq!(TeamRoleType::from_db("owner"), TeamRoleType::Owner);
+        assert_eq!(TeamRoleType::from_db("unknown"), TeamRoleType::Viewer);
+    }
+
+    #[test]
+    fn test_permission_names_are_ordered() {
+        let perms = HashSet::from([Permission::TeamManageMembers, Permission::DashboardRead]);
+        assert_eq!(permission_names(&perms), vec!["dashboard:read", "team:manage_members"]);
+    }
+
+    #[test]
+    fn test_resource_ref_serde() {
+        let id = Uuid::new_v4();
+        let json = serde_json::json!({ "type": "dashboard", "id": id });
+        let resource: ResourceRef = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(resource, ResourceRef::Dashboard(id));
+        assert_eq!(resource.kind(), None);
+        assert_eq!(serde_json::to_value(resource).unwrap(), json);
+        assert_eq!(ResourceRef::File(id).kind(), Some((ResourceKind::File, id)));
+        assert_eq!(ResourceRef::Team(id).kind(), None);
+    }
+
     #[test]
     fn test_super_admin_has_all_permissions() {
         let perms = SystemRole::SuperAdmin.permissions();
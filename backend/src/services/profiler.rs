@@ -0,0 +1,536 @@
+//! Column Profiling Service
+//!
+//! Computes per-column statistics for uploaded files: null counts, a
+//! HyperLogLog distinct estimate, min/max, mean/stddev, the most frequent
+//! values and a histogram for numeric columns. Profiling runs in the
+//! background after upload and the results are stored in `file_columns`.
+
+use arrow::array::{Array, Float64Array, StringArray};
+use arrow::compute::kernels::cast::cast;
+use arrow::datatypes::DataType;
+use arrow::error::ArrowError;
+use chrono::{DateTime, Utc};
+use hyperloglogplus::{HyperLogLog, HyperLogLogPlus};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::connectors::{read_file, Dataset, FileFormat, ReadOptions};
+use crate::services::masking::{Classification, MaskRule, MaskingService};
+use crate::services::query_engine::reduce::{bin_edges, bin_index};
+
+/// Number of most frequent values reported per column
+pub const TOP_VALUES: usize = 10;
+
+/// Number of equal-width histogram bins for numeric columns
+pub const HISTOGRAM_BINS: usize = 20;
+
+/// Distinct values tracked for top-value counting; values first seen after
+/// this limit are ignored, so top values are approximate for high-cardinality
+/// columns
+const MAX_TRACKED_VALUES: usize = 100_000;
+
+/// HyperLogLog precision (2^14 registers, ~0.8% standard error)
+const HLL_PRECISION: u8 = 14;
+
+/// Profiling state of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+impl ProfileStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProfileStatus::Pending => "pending",
+            ProfileStatus::Ready => "ready",
+            ProfileStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "pending" => Some(ProfileStatus::Pending),
+            "ready" => Some(ProfileStatus::Ready),
+            "failed" => Some(ProfileStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// A value and its number of occurrences
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: u64,
+}
+
+/// Equal-width histogram; `counts[i]` covers `[edges[i], edges[i + 1])`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    pub edges: Vec<f64>,
+    pub counts: Vec<u64>,
+}
+
+/// Statistics for a single column
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnStats {
+    /// Number of non-null values
+    pub count: usize,
+    pub null_count: usize,
+    /// Approximate number of distinct non-null values
+    pub distinct_estimate: u64,
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+    /// Mean of numeric columns
+    pub mean: Option<f64>,
+    /// Sample standard deviation of numeric columns
+    pub stddev: Option<f64>,
+    pub top_values: Vec<ValueCount>,
+    /// Histogram of numeric columns
+    pub histogram: Option<Histogram>,
+}
+
+/// Profile of a single column
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnProfile {
+    pub name: String,
+    pub data_type: String,
+    pub nullable: bool,
+    pub position: i32,
+    pub stats: Option<ColumnStats>,
+    #[serde(default)]
+    pub classification: Option<Classification>,
+    #[serde(default)]
+    pub mask: Option<MaskRule>,
+}
+
+/// Profile of a whole file
+#[derive(Debug, Clone, Serialize)]
+pub struct FileProfile {
+    pub file_id: Uuid,
+    pub status: ProfileStatus,
+    pub error: Option<String>,
+    pub profiled_at: Option<DateTime<Utc>>,
+    pub row_count: Option<i32>,
+    pub columns: Vec<ColumnProfile>,
+}
+
+/// `files` profiling columns: status, error, profiled_at, row_count
+type ProfileStateRow = (String, Option<String>, Option<DateTime<Utc>>, Option<i32>);
+
+/// `file_columns` row: name, data_type, nullable, position, stats, classification, mask
+type ColumnRow = (
+    String,
+    String,
+    bool,
+    i32,
+    Option<sqlx::types::Json<ColumnStats>>,
+    Option<Classification>,
+    Option<MaskRule>,
+);
+
+/// Running statistics for one column
+struct ColumnAccumulator {
+    numeric: bool,
+    integer: bool,
+    count: usize,
+    null_count: usize,
+    distinct: HyperLogLogPlus<str, RandomState>,
+    /// Finite numeric values seen, for the running mean and variance
+    samples: u64,
+    min: f64,
+    max: f64,
+    mean: f64,
+    m2: f64,
+    min_text: Option<String>,
+    max_text: Option<String>,
+    frequencies: HashMap<String, u64>,
+}
+
+impl ColumnAccumulator {
+    fn new(data_type: &DataType) -> Self {
+        ColumnAccumulator {
+            numeric: data_type.is_numeric(),
+            integer: data_type.is_integer(),
+            count: 0,
+            null_count: 0,
+            distinct: HyperLogLogPlus::new(HLL_PRECISION, RandomState::new())
+                .expect("valid HyperLogLog precision"),
+            samples: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            m2: 0.0,
+            min_text: None,
+            max_text: None,
+            frequencies: HashMap::new(),
+        }
+    }
+
+    fn update(&mut self, column: &dyn Array) -> Result<(), ArrowError> {
+        self.null_count += column.null_count();
+
+        let text = cast(column, &DataType::Utf8)?;
+        let text = text.as_any().downcast_ref::<StringArray>().unwrap();
+        for value in text.iter().flatten() {
+            self.count += 1;
+            self.distinct.insert(value);
+
+            if let Some(count) = self.frequencies.get_mut(value) {
+                *count += 1;
+            } else if self.frequencies.len() < MAX_TRACKED_VALUES {
+                self.frequencies.insert(value.to_string(), 1);
+            }
+
+            if !self.numeric {
+                if self.min_text.as_deref().is_none_or(|min| value < min) {
+                    self.min_text = Some(value.to_string());
+                }
+                if self.max_text.as_deref().is_none_or(|max| value > max) {
+                    self.max_text = Some(value.to_string());
+                }
+            }
+        }
+
+        if self.numeric {
+            let values = cast(column, &DataType::Float64)?;
+            let values = values.as_any().downcast_ref::<Float64Array>().unwrap();
+            // Welford's online algorithm over the finite values
+            for value in values.iter().flatten().filter(|v| v.is_finite()) {
+                self.samples += 1;
+                let delta = value - self.mean;
+                self.mean += delta / self.samples as f64;
+                self.m2 += delta * (value - self.mean);
+                self.min = self.min.min(value);
+                self.max = self.max.max(value);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn numeric_value(&self, value: f64) -> Value {
+        if self.integer {
+            Value::from(value as i64)
+        } else {
+            Value::from(value)
+        }
+    }
+
+    fn finish(mut self, histogram: Option<Histogram>) -> ColumnStats {
+        let has_range = self.numeric && self.min <= self.max;
+
+        let mut top_values: Vec<ValueCount> = std::mem::take(&mut self.frequencies)
+            .into_iter()
+            .map(|(value, count)| ValueCount { value, count })
+            .collect();
+        top_values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        top_values.truncate(TOP_VALUES);
+
+        ColumnStats {
+            count: self.count,
+            null_count: self.null_count,
+            distinct_estimate: self.distinct.count().round() as u64,
+            min: if has_range {
+                Some(self.numeric_value(self.min))
+            } else {
+                self.min_text.take().map(Value::String)
+            },
+            max: if has_range {
+                Some(self.numeric_value(self.max))
+            } else {
+                self.max_text.take().map(Value::String)
+            },
+            mean: has_range.then_some(self.mean),
+            stddev: (has_range && self.samples > 1).then(|| (self.m2 / (self.samples - 1) as f64).sqrt()),
+            top_values,
+            histogram,
+        }
+    }
+}
+
+/// Column profiling service
+pub struct ProfilerService;
+
+impl ProfilerService {
+    /// Compute statistics for every column of a dataset, in schema order
+    pub fn profile(dataset: &Dataset) -> Result<Vec<ColumnProfile>, ArrowError> {
+        let fields = dataset.schema.fields();
+        let mut accumulators: Vec<ColumnAccumulator> = fields
+            .iter()
+            .map(|field| ColumnAccumulator::new(field.data_type()))
+            .collect();
+
+        for batch in &dataset.batches {
+            for (accumulator, column) in accumulators.iter_mut().zip(batch.columns()) {
+                accumulator.update(column.as_ref())?;
+            }
+        }
+
+        let mut profiles = Vec::with_capacity(fields.len());
+        for (position, (field, accumulator)) in fields.iter().zip(accumulators).enumerate() {
+            let histogram = if accumulator.numeric && accumulator.min <= accumulator.max {
+                Some(Self::histogram(dataset, position, accumulator.min, accumulator.max)?)
+            } else {
+                None
+            };
+
+            profiles.push(ColumnProfile {
+                name: field.name().clone(),
+                data_type: field.data_type().to_string(),
+                nullable: field.is_nullable(),
+                position: position as i32,
+                stats: Some(accumulator.finish(histogram)),
+                classification: None,
+                mask: None,
+            });
+        }
+
+        Ok(profiles)
+    }
+
+    fn histogram(dataset: &Dataset, index: usize, min: f64, max: f64) -> Result<Histogram, ArrowError> {
+        let edges = bin_edges(min, max, HISTOGRAM_BINS);
+        let mut counts = vec![0u64; HISTOGRAM_BINS];
+
+        for batch in &dataset.batches {
+            let values = cast(batch.column(index), &DataType::Float64)?;
+            let values = values.as_any().downcast_ref::<Float64Array>().unwrap();
+            for value in values.iter().flatten() {
+                if let Some(bin) = bin_index(value, &edges) {
+                    counts[bin] += 1;
+                }
+            }
+        }
+
+        Ok(Histogram { edges, counts })
+    }
+
+    /// Profile a stored file and save the results, classifying columns that
+    /// look like PII if `detect_pii` is set; failures are recorded on the
+    /// file rather than returned, as this runs as a background task
+    pub async fn profile_file(
+        pool: PgPool,
+        file_id: Uuid,
+        path: PathBuf,
+        format: FileFormat,
+        options: ReadOptions,
+        detect_pii: bool,
+    ) {
+        let result = tokio::task::spawn_blocking(move || -> Result<_, String> {
+            let dataset = read_file(&path, format, &options).map_err(|e| e.to_string())?;
+            let mut columns = Self::profile(&dataset).map_err(|e| e.to_string())?;
+            if detect_pii {
+                let detected = MaskingService::detect(&dataset).map_err(|e| e.to_string())?;
+                for (column, classification) in columns.iter_mut().zip(detected) {
+                    column.classification = classification;
+                    column.mask = classification.map(|c| c.default_mask());
+                }
+            }
+            Ok((dataset.num_rows(), columns))
+        })
+        .await
+        .map_err(|e| format!("Profiling task failed: {}", e))
+        .and_then(|result| result);
+
+        let saved = match result {
+            Ok((row_count, columns)) => Self::save(&pool, file_id, row_count, &columns).await,
+            Err(error) => {
+                log::warn!("Profiling file {} failed: {}", file_id, error);
+                Self::mark_failed(&pool, file_id, &error).await
+            }
+        };
+
+        if let Err(e) = saved {
+            log::error!("Failed to store profile for file {}: {}", file_id, e);
+        }
+    }
+
+    async fn save(
+        pool: &PgPool,
+        file_id: Uuid,
+        row_count: usize,
+        columns: &[ColumnProfile],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query("DELETE FROM file_columns WHERE file_id = $1")
+            .bind(file_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for column in columns {
+            sqlx::query(
+                r#"
+                INSERT INTO file_columns
+                    (file_id, name, data_type, nullable, position, stats, classification, mask, classification_detected)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                "#
+            )
+            .bind(file_id)
+            .bind(&column.name)
+            .bind(&column.data_type)
+            .bind(column.nullable)
+            .bind(column.position)
+            .bind(sqlx::types::Json(&column.stats))
+            .bind(column.classification)
+            .bind(column.mask)
+            .bind(column.classification.is_some())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE files
+            SET row_count = $2, column_count = $3, profile_status = $4,
+                profile_error = NULL, profiled_at = NOW()
+            WHERE id = $1
+            "#
+        )
+        .bind(file_id)
+        .bind(row_count as i32)
+        .bind(columns.len() as i32)
+        .bind(ProfileStatus::Ready.as_str())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    async fn mark_failed(pool: &PgPool, file_id: Uuid, error: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE files SET profile_status = $2, profile_error = $3 WHERE id = $1")
+            .bind(file_id)
+            .bind(ProfileStatus::Failed.as_str())
+            .bind(error)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Load the stored profile of a file
+    pub async fn get_profile(pool: &PgPool, file_id: Uuid) -> Result<Option<FileProfile>, sqlx::Error> {
+        let file: Option<ProfileStateRow> = sqlx::query_as(
+            "SELECT profile_status, profile_error, profiled_at, row_count FROM files WHERE id = $1"
+        )
+        .bind(file_id)
+        .fetch_optional(pool)
+        .await?;
+
+        let Some((status, error, profiled_at, row_count)) = file else {
+            return Ok(None);
+        };
+
+        let rows: Vec<ColumnRow> = sqlx::query_as(
+            r#"
+            SELECT name, data_type, nullable, position, stats, classification, mask
+            FROM file_columns
+            WHERE file_id = $1
+            ORDER BY position
+            "#
+        )
+        .bind(file_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Some(FileProfile {
+            file_id,
+            status: ProfileStatus::parse(&status).unwrap_or(ProfileStatus::Pending),
+            error,
+            profiled_at,
+            row_count,
+            columns: rows
+                .into_iter()
+                .map(|(name, data_type, nullable, position, stats, classification, mask)| ColumnProfile {
+                    name,
+                    data_type,
+                    nullable,
+                    position,
+                    stats: stats.map(|s| s.0),
+                    classification,
+                    mask,
+                })
+                .collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Float64Array, Int64Array};
+    use arrow::datatypes::{Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use std::sync::Arc;
+
+    fn sample() -> Dataset {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("units", DataType::Int64, true),
+            Field::new("price", DataType::Float64, true),
+            Field::new("region", DataType::Utf8, true),
+        ]));
+        let first = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![Some(2), Some(4), None])),
+                Arc::new(Float64Array::from(vec![1.0, 2.0, 3.0])),
+                Arc::new(StringArray::from(vec![Some("north"), Some("south"), Some("north")])),
+            ],
+        )
+        .unwrap();
+        let second = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![Some(6), Some(8)])),
+                Arc::new(Float64Array::from(vec![4.0, 5.0])),
+                Arc::new(StringArray::from(vec![None, Some("east")])),
+            ],
+        )
+        .unwrap();
+        Dataset { schema, batches: vec![first, second] }
+    }
+
+    #[test]
+    fn test_numeric_stats_across_batches() {
+        let profiles = ProfilerService::profile(&sample()).unwrap();
+        let units = profiles[0].stats.as_ref().unwrap();
+
+        assert_eq!(units.count, 4);
+        assert_eq!(units.null_count, 1);
+        assert_eq!(units.distinct_estimate, 4);
+        assert_eq!(units.min, Some(Value::from(2)));
+        assert_eq!(units.max, Some(Value::from(8)));
+        assert_eq!(units.mean, Some(5.0));
+        assert!((units.stddev.unwrap() - 2.581_988_897).abs() < 1e-6);
+
+        let histogram = units.histogram.as_ref().unwrap();
+        assert_eq!(histogram.counts.len(), HISTOGRAM_BINS);
+        assert_eq!(histogram.counts.iter().sum::<u64>(), 4);
+    }
+
+    #[test]
+    fn test_text_stats() {
+        let profiles = ProfilerService::profile(&sample()).unwrap();
+        let region = profiles[2].stats.as_ref().unwrap();
+
+        assert_eq!(region.count, 4);
+        assert_eq!(region.null_count, 1);
+        assert_eq!(region.distinct_estimate, 3);
+        assert_eq!(region.min, Some(Value::from("east")));
+        assert_eq!(region.max, Some(Value::from("south")));
+        assert_eq!(region.mean, None);
+        assert!(region.histogram.is_none());
+        assert_eq!(
+            region.top_values[0],
+            ValueCount { value: "north".to_string(), count: 2 }
+        );
+    }
+}
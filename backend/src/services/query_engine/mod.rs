@@ -1,3 +1,357 @@
-// Query engine service - will be implemented in detail
+//! Query Engine Service
+//!
+//! Executes structured queries (projection, filters, sorting and paging)
+//! over Arrow record batches loaded by the connectors. The operators mirror
+//! the frontend data pipeline so a view built in the browser can be replayed
+//! on the server against the full dataset. Results can be reduced to
+//! render-ready chart series (see [`reduce`]).
 
+pub mod reduce;
 
+use arrow::array::{Array, ArrayRef, BooleanArray, Scalar, StringArray};
+use arrow::compute::kernels::boolean::{and_kleene, or_kleene};
+use arrow::compute::kernels::cast::cast;
+use arrow::compute::kernels::cmp;
+use arrow::compute::kernels::comparison::contains;
+use arrow::compute::kernels::concat::concat_batches;
+use arrow::compute::kernels::filter::filter_record_batch;
+use arrow::compute::kernels::sort::{lexsort_to_indices, SortColumn, SortOptions};
+use arrow::compute::kernels::take::take;
+use arrow::datatypes::{DataType, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::connectors::Dataset;
+
+pub use reduce::{ChartSeries, Reduction};
+
+/// Errors raised while planning or executing a query
+#[derive(Error, Debug)]
+pub enum QueryError {
+    #[error("Unknown column: {0}")]
+    UnknownColumn(String),
+
+    #[error("Invalid filter on column '{column}': {reason}")]
+    InvalidFilter { column: String, reason: String },
+
+    #[error("Invalid reduction: {0}")]
+    InvalidReduction(String),
+
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+}
+
+pub type QueryResult<T> = Result<T, QueryError>;
+
+/// Filter comparison operators (same names as the frontend `FilterParams`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterOperator {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Gte,
+    Lte,
+    In,
+    Between,
+    Contains,
+}
+
+/// A single filter condition; all filters in a query are ANDed together
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Filter {
+    pub column: String,
+    pub operator: FilterOperator,
+    pub value: Value,
+    /// Upper bound for `between`
+    #[serde(default)]
+    pub value2: Option<Value>,
+}
+
+/// Sort direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Sort key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SortKey {
+    pub column: String,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+/// Structured query over a single dataset
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuerySpec {
+    /// Output columns, in order (all columns when omitted)
+    #[serde(default)]
+    pub columns: Option<Vec<String>>,
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    #[serde(default)]
+    pub sort: Vec<SortKey>,
+    #[serde(default)]
+    pub offset: Option<usize>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Query engine over in-memory Arrow data
+pub struct QueryEngine;
+
+impl QueryEngine {
+    /// Execute a query against a dataset, returning a single-batch result
+    pub fn execute(dataset: &Dataset, spec: &QuerySpec) -> QueryResult<Dataset> {
+        let mut batch = concat_batches(&dataset.schema, &dataset.batches)?;
+
+        if !spec.filters.is_empty() {
+            let mask = Self::filter_mask(&batch, &spec.filters)?;
+            batch = filter_record_batch(&batch, &mask)?;
+        }
+
+        if !spec.sort.is_empty() {
+            batch = Self::sort(&batch, &spec.sort)?;
+        }
+
+        let offset = spec.offset.unwrap_or(0).min(batch.num_rows());
+        let length = spec
+            .limit
+            .unwrap_or(usize::MAX)
+            .min(batch.num_rows() - offset);
+        batch = batch.slice(offset, length);
+
+        if let Some(columns) = &spec.columns {
+            let indices = columns
+                .iter()
+                .map(|name| Self::column_index(&batch.schema(), name))
+                .collect::<QueryResult<Vec<usize>>>()?;
+            batch = batch.project(&indices)?;
+        }
+
+        Ok(Dataset {
+            schema: batch.schema(),
+            batches: vec![batch],
+        })
+    }
+
+    /// Evaluate all filters into a single boolean mask
+    pub fn filter_mask(batch: &RecordBatch, filters: &[Filter]) -> QueryResult<BooleanArray> {
+        let mut mask: Option<BooleanArray> = None;
+        for filter in filters {
+            let result = Self::evaluate_filter(batch, filter)?;
+            mask = Some(match mask {
+                Some(existing) => and_kleene(&existing, &result)?,
+                None => result,
+            });
+        }
+        Ok(mask.unwrap_or_else(|| BooleanArray::from(vec![true; batch.num_rows()])))
+    }
+
+    fn evaluate_filter(batch: &RecordBatch, filter: &Filter) -> QueryResult<BooleanArray> {
+        let index = Self::column_index(&batch.schema(), &filter.column)?;
+        let column = batch.column(index);
+
+        let result = match filter.operator {
+            FilterOperator::Eq => cmp::eq(column, &Self::scalar(column, filter, &filter.value)?)?,
+            FilterOperator::Ne => cmp::neq(column, &Self::scalar(column, filter, &filter.value)?)?,
+            FilterOperator::Gt => cmp::gt(column, &Self::scalar(column, filter, &filter.value)?)?,
+            FilterOperator::Lt => cmp::lt(column, &Self::scalar(column, filter, &filter.value)?)?,
+            FilterOperator::Gte => cmp::gt_eq(column, &Self::scalar(column, filter, &filter.value)?)?,
+            FilterOperator::Lte => cmp::lt_eq(column, &Self::scalar(column, filter, &filter.value)?)?,
+            FilterOperator::Between => {
+                let upper = filter.value2.as_ref().ok_or_else(|| QueryError::InvalidFilter {
+                    column: filter.column.clone(),
+                    reason: "'between' requires value2".to_string(),
+                })?;
+                let lower = cmp::gt_eq(column, &Self::scalar(column, filter, &filter.value)?)?;
+                let upper = cmp::lt_eq(column, &Self::scalar(column, filter, upper)?)?;
+                and_kleene(&lower, &upper)?
+            }
+            FilterOperator::In => {
+                let values = filter.value.as_array().ok_or_else(|| QueryError::InvalidFilter {
+                    column: filter.column.clone(),
+                    reason: "'in' requires an array value".to_string(),
+                })?;
+                let mut result = BooleanArray::from(vec![false; batch.num_rows()]);
+                for value in values {
+                    let matches = cmp::eq(column, &Self::scalar(column, filter, value)?)?;
+                    result = or_kleene(&result, &matches)?;
+                }
+                result
+            }
+            FilterOperator::Contains => {
+                let haystack = cast(column, &DataType::Utf8)?;
+                let needle = Scalar::new(StringArray::from(vec![json_to_string(&filter.value)]));
+                contains(&haystack, &needle)?
+            }
+        };
+
+        Ok(result)
+    }
+
+    /// Build a length-1 scalar of the column's type from a JSON filter value
+    fn scalar(column: &ArrayRef, filter: &Filter, value: &Value) -> QueryResult<Scalar<ArrayRef>> {
+        let text: ArrayRef = Arc::new(StringArray::from(vec![json_to_string(value)]));
+        let typed = cast(&text, column.data_type())?;
+        if typed.is_null(0) && !value.is_null() {
+            return Err(QueryError::InvalidFilter {
+                column: filter.column.clone(),
+                reason: format!("cannot compare {} with {}", column.data_type(), value),
+            });
+        }
+        Ok(Scalar::new(typed))
+    }
+
+    fn sort(batch: &RecordBatch, keys: &[SortKey]) -> QueryResult<RecordBatch> {
+        let sort_columns = keys
+            .iter()
+            .map(|key| {
+                let index = Self::column_index(&batch.schema(), &key.column)?;
+                Ok(SortColumn {
+                    values: batch.column(index).clone(),
+                    options: Some(SortOptions {
+                        descending: key.order == SortOrder::Desc,
+                        nulls_first: false,
+                    }),
+                })
+            })
+            .collect::<QueryResult<Vec<SortColumn>>>()?;
+
+        let indices = lexsort_to_indices(&sort_columns, None)?;
+        let columns = batch
+            .columns()
+            .iter()
+            .map(|column| take(column.as_ref(), &indices, None))
+            .collect::<Result<Vec<ArrayRef>, ArrowError>>()?;
+
+        Ok(RecordBatch::try_new(batch.schema(), columns)?)
+    }
+
+    fn column_index(schema: &SchemaRef, name: &str) -> QueryResult<usize> {
+        schema
+            .index_of(name)
+            .map_err(|_| QueryError::UnknownColumn(name.to_string()))
+    }
+}
+
+/// Render a JSON filter value as the text Arrow's cast kernel expects
+fn json_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Float64Array, Int64Array};
+    use arrow::datatypes::{Field, Schema};
+    use serde_json::json;
+
+    fn sample() -> Dataset {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("region", DataType::Utf8, true),
+            Field::new("units", DataType::Int64, true),
+            Field::new("price", DataType::Float64, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["north", "south", "north", "east"])),
+                Arc::new(Int64Array::from(vec![10, 20, 30, 40])),
+                Arc::new(Float64Array::from(vec![1.5, 2.5, 3.5, 4.5])),
+            ],
+        )
+        .unwrap();
+        Dataset { schema, batches: vec![batch] }
+    }
+
+    fn units(dataset: &Dataset) -> Vec<i64> {
+        let batch = &dataset.batches[0];
+        let index = batch.schema().index_of("units").unwrap();
+        batch
+            .column(index)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap()
+            .values()
+            .to_vec()
+    }
+
+    #[test]
+    fn test_filter_and_sort() {
+        let spec: QuerySpec = serde_json::from_value(json!({
+            "filters": [{"column": "region", "operator": "eq", "value": "north"}],
+            "sort": [{"column": "units", "order": "desc"}]
+        }))
+        .unwrap();
+
+        let result = QueryEngine::execute(&sample(), &spec).unwrap();
+        assert_eq!(units(&result), vec![30, 10]);
+    }
+
+    #[test]
+    fn test_numeric_between_and_in() {
+        let spec: QuerySpec = serde_json::from_value(json!({
+            "filters": [
+                {"column": "units", "operator": "between", "value": 15, "value2": 40},
+                {"column": "region", "operator": "in", "value": ["south", "east"]}
+            ]
+        }))
+        .unwrap();
+
+        let result = QueryEngine::execute(&sample(), &spec).unwrap();
+        assert_eq!(units(&result), vec![20, 40]);
+    }
+
+    #[test]
+    fn test_projection_and_limit() {
+        let spec = QuerySpec {
+            columns: Some(vec!["units".to_string()]),
+            offset: Some(1),
+            limit: Some(2),
+            ..Default::default()
+        };
+
+        let result = QueryEngine::execute(&sample(), &spec).unwrap();
+        assert_eq!(result.schema.fields().len(), 1);
+        assert_eq!(units(&result), vec![20, 30]);
+    }
+
+    #[test]
+    fn test_invalid_filter_value() {
+        let spec: QuerySpec = serde_json::from_value(json!({
+            "filters": [{"column": "units", "operator": "gt", "value": "lots"}]
+        }))
+        .unwrap();
+
+        assert!(matches!(
+            QueryEngine::execute(&sample(), &spec),
+            Err(QueryError::InvalidFilter { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unknown_column() {
+        let spec = QuerySpec {
+            columns: Some(vec!["missing".to_string()]),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            QueryEngine::execute(&sample(), &spec),
+            Err(QueryError::UnknownColumn(_))
+        ));
+    }
+}
@@ -0,0 +1,524 @@
+//! Chart-oriented reductions
+//!
+//! Reduces a query result to a small, render-ready series so the browser
+//! never has to download every row: LTTB and min/max downsampling for line
+//! and scatter charts, 1D histograms, 2D heatmap binning and top-K categories
+//! with an "other" bucket.
+
+use arrow::array::{Array, ArrayRef, Float64Array, Int64Array, StringArray};
+use arrow::compute::kernels::cast::cast;
+use arrow::datatypes::{DataType, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{QueryError, QueryResult};
+
+/// Upper bound on the number of output points or bins a client may request
+pub const MAX_REDUCTION_SIZE: usize = 100_000;
+
+fn default_other_label() -> String {
+    "Other".to_string()
+}
+
+/// Reduction applied to a query result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reduction {
+    /// Largest-Triangle-Three-Buckets downsampling to `points` points
+    Lttb { x: String, y: String, points: usize },
+    /// Keep the minimum and maximum `y` of each bucket (`points` in total)
+    MinMax { x: String, y: String, points: usize },
+    /// Equal-width histogram of a numeric column
+    Histogram {
+        column: String,
+        bins: usize,
+        #[serde(default)]
+        min: Option<f64>,
+        #[serde(default)]
+        max: Option<f64>,
+    },
+    /// Equal-width 2D binning of two numeric columns
+    Heatmap {
+        x: String,
+        y: String,
+        x_bins: usize,
+        y_bins: usize,
+    },
+    /// The `k` largest categories, by row count or by the sum of `value`
+    TopK {
+        column: String,
+        k: usize,
+        #[serde(default)]
+        value: Option<String>,
+        #[serde(default = "default_other_label")]
+        other_label: String,
+    },
+}
+
+/// Render-ready output of a reduction
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChartSeries {
+    /// Point series; temporal `x` values are epoch milliseconds
+    Points {
+        x: Vec<f64>,
+        y: Vec<f64>,
+        source_rows: usize,
+    },
+    /// `counts[i]` covers `[edges[i], edges[i + 1])`; the last bin is closed
+    Histogram {
+        edges: Vec<f64>,
+        counts: Vec<u64>,
+        null_count: usize,
+    },
+    /// `counts[row][col]` covers `y_edges[row]..` by `x_edges[col]..`
+    Heatmap {
+        x_edges: Vec<f64>,
+        y_edges: Vec<f64>,
+        counts: Vec<Vec<u64>>,
+    },
+    /// Categories sorted by descending value, "other" bucket last
+    Categories { labels: Vec<String>, values: Vec<f64> },
+}
+
+impl Reduction {
+    /// Apply this reduction to a single record batch
+    pub fn apply(&self, batch: &RecordBatch) -> QueryResult<ChartSeries> {
+        self.validate()?;
+
+        match self {
+            Reduction::Lttb { x, y, points } => {
+                let (xs, ys) = point_pairs(batch, x, y)?;
+                let source_rows = xs.len();
+                let (x, y) = lttb(&xs, &ys, *points);
+                Ok(ChartSeries::Points { x, y, source_rows })
+            }
+            Reduction::MinMax { x, y, points } => {
+                let (xs, ys) = point_pairs(batch, x, y)?;
+                let source_rows = xs.len();
+                let (x, y) = min_max(&xs, &ys, *points);
+                Ok(ChartSeries::Points { x, y, source_rows })
+            }
+            Reduction::Histogram { column, bins, min, max } => {
+                let values = numeric_column(batch, column)?;
+                let null_count = values.iter().filter(|v| v.is_none()).count();
+                let values: Vec<f64> = values.into_iter().flatten().filter(|v| v.is_finite()).collect();
+                let (lo, hi) = value_range(&values);
+                let edges = bin_edges(min.unwrap_or(lo), max.unwrap_or(hi), *bins);
+                let mut counts = vec![0u64; *bins];
+                for value in values {
+                    if let Some(bin) = bin_index(value, &edges) {
+                        counts[bin] += 1;
+                    }
+                }
+                Ok(ChartSeries::Histogram { edges, counts, null_count })
+            }
+            Reduction::Heatmap { x, y, x_bins, y_bins } => {
+                let (xs, ys) = point_pairs(batch, x, y)?;
+                let (x_lo, x_hi) = value_range(&xs);
+                let (y_lo, y_hi) = value_range(&ys);
+                let x_edges = bin_edges(x_lo, x_hi, *x_bins);
+                let y_edges = bin_edges(y_lo, y_hi, *y_bins);
+                let mut counts = vec![vec![0u64; *x_bins]; *y_bins];
+                for (xv, yv) in xs.iter().zip(ys.iter()) {
+                    if let (Some(col), Some(row)) = (bin_index(*xv, &x_edges), bin_index(*yv, &y_edges)) {
+                        counts[row][col] += 1;
+                    }
+                }
+                Ok(ChartSeries::Heatmap { x_edges, y_edges, counts })
+            }
+            Reduction::TopK { column, k, value, other_label } => {
+                let labels = string_column(batch, column)?;
+                let weights = match value {
+                    Some(value) => Some(numeric_column(batch, value)?),
+                    None => None,
+                };
+
+                let mut totals: HashMap<String, f64> = HashMap::new();
+                for (row, label) in labels.into_iter().enumerate() {
+                    let weight = match &weights {
+                        Some(weights) => weights[row].unwrap_or(0.0),
+                        None => 1.0,
+                    };
+                    *totals.entry(label.unwrap_or_else(|| "(null)".to_string())).or_insert(0.0) += weight;
+                }
+
+                let mut ranked: Vec<(String, f64)> = totals.into_iter().collect();
+                ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+                let other: f64 = ranked.iter().skip(*k).map(|(_, v)| v).sum();
+                let has_other = ranked.len() > *k;
+                ranked.truncate(*k);
+
+                let (mut labels, mut values): (Vec<String>, Vec<f64>) = ranked.into_iter().unzip();
+                if has_other {
+                    labels.push(other_label.clone());
+                    values.push(other);
+                }
+                Ok(ChartSeries::Categories { labels, values })
+            }
+        }
+    }
+
+    fn validate(&self) -> QueryResult<()> {
+        let sizes: Vec<(&str, usize, usize)> = match self {
+            Reduction::Lttb { points, .. } => vec![("points", *points, 3)],
+            Reduction::MinMax { points, .. } => vec![("points", *points, 2)],
+            Reduction::Histogram { bins, .. } => vec![("bins", *bins, 1)],
+            Reduction::Heatmap { x_bins, y_bins, .. } => {
+                vec![("x_bins", *x_bins, 1), ("y_bins", *y_bins, 1)]
+            }
+            Reduction::TopK { k, .. } => vec![("k", *k, 1)],
+        };
+
+        for (name, size, min) in sizes {
+            if size < min || size > MAX_REDUCTION_SIZE {
+                return Err(QueryError::InvalidReduction(format!(
+                    "{} must be between {} and {}",
+                    name, min, MAX_REDUCTION_SIZE
+                )));
+            }
+        }
+
+        // A 2D grid is bounded by its total cell count, not each axis
+        if let Reduction::Heatmap { x_bins, y_bins, .. } = self {
+            if x_bins.saturating_mul(*y_bins) > MAX_REDUCTION_SIZE {
+                return Err(QueryError::InvalidReduction(format!(
+                    "x_bins * y_bins must not exceed {}",
+                    MAX_REDUCTION_SIZE
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// ============================================================================
+// COLUMN EXTRACTION
+// ============================================================================
+
+fn column<'a>(batch: &'a RecordBatch, name: &str) -> QueryResult<&'a ArrayRef> {
+    let index = batch
+        .schema()
+        .index_of(name)
+        .map_err(|_| QueryError::UnknownColumn(name.to_string()))?;
+    Ok(batch.column(index))
+}
+
+/// Read a column as `f64`, converting temporal types to epoch milliseconds
+fn numeric_column(batch: &RecordBatch, name: &str) -> QueryResult<Vec<Option<f64>>> {
+    let array = column(batch, name)?;
+
+    let millis_per_unit = match array.data_type() {
+        DataType::Timestamp(TimeUnit::Second, _) => Some(1_000.0),
+        DataType::Timestamp(TimeUnit::Millisecond, _) | DataType::Date64 => Some(1.0),
+        DataType::Timestamp(TimeUnit::Microsecond, _) => Some(1e-3),
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => Some(1e-6),
+        DataType::Date32 => Some(86_400_000.0),
+        _ => None,
+    };
+
+    if let Some(scale) = millis_per_unit {
+        let raw = cast(array, &DataType::Int64)?;
+        let raw = raw.as_any().downcast_ref::<Int64Array>().unwrap();
+        return Ok(raw.iter().map(|v| v.map(|v| v as f64 * scale)).collect());
+    }
+
+    if !array.data_type().is_numeric() {
+        return Err(QueryError::InvalidReduction(format!(
+            "column '{}' is not numeric or temporal ({})",
+            name,
+            array.data_type()
+        )));
+    }
+
+    let values = cast(array, &DataType::Float64)?;
+    let values = values.as_any().downcast_ref::<Float64Array>().unwrap();
+    Ok(values.iter().collect())
+}
+
+fn string_column(batch: &RecordBatch, name: &str) -> QueryResult<Vec<Option<String>>> {
+    let values = cast(column(batch, name)?, &DataType::Utf8)?;
+    let values = values.as_any().downcast_ref::<StringArray>().unwrap();
+    Ok(values.iter().map(|v| v.map(|s| s.to_string())).collect())
+}
+
+/// Non-null `(x, y)` pairs sorted by `x`
+fn point_pairs(batch: &RecordBatch, x: &str, y: &str) -> QueryResult<(Vec<f64>, Vec<f64>)> {
+    let xs = numeric_column(batch, x)?;
+    let ys = numeric_column(batch, y)?;
+
+    let mut pairs: Vec<(f64, f64)> = xs
+        .into_iter()
+        .zip(ys)
+        .filter_map(|(x, y)| match (x, y) {
+            (Some(x), Some(y)) if x.is_finite() && y.is_finite() => Some((x, y)),
+            _ => None,
+        })
+        .collect();
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    Ok(pairs.into_iter().unzip())
+}
+
+// ============================================================================
+// ALGORITHMS
+// ============================================================================
+
+/// Largest-Triangle-Three-Buckets downsampling (Steinarsson, 2013)
+pub fn lttb(xs: &[f64], ys: &[f64], threshold: usize) -> (Vec<f64>, Vec<f64>) {
+    let len = xs.len();
+    if threshold >= len || threshold < 3 {
+        return (xs.to_vec(), ys.to_vec());
+    }
+
+    let mut out_x = Vec::with_capacity(threshold);
+    let mut out_y = Vec::with_capacity(threshold);
+    let bucket_size = (len - 2) as f64 / (threshold - 2) as f64;
+
+    let mut selected = 0;
+    out_x.push(xs[0]);
+    out_y.push(ys[0]);
+
+    for bucket in 0..threshold - 2 {
+        // Average of the next bucket is the third triangle vertex
+        let next_start = ((bucket + 1) as f64 * bucket_size) as usize + 1;
+        let next_end = (((bucket + 2) as f64 * bucket_size) as usize + 1).min(len);
+        let next_len = (next_end - next_start).max(1) as f64;
+        let avg_x = xs[next_start..next_end].iter().sum::<f64>() / next_len;
+        let avg_y = ys[next_start..next_end].iter().sum::<f64>() / next_len;
+
+        let start = (bucket as f64 * bucket_size) as usize + 1;
+        let end = next_start;
+
+        let (ax, ay) = (xs[selected], ys[selected]);
+        let mut best_area = -1.0;
+        let mut best = start;
+        for i in start..end {
+            let area = ((ax - avg_x) * (ys[i] - ay) - (ax - xs[i]) * (avg_y - ay)).abs();
+            if area > best_area {
+                best_area = area;
+                best = i;
+            }
+        }
+
+        out_x.push(xs[best]);
+        out_y.push(ys[best]);
+        selected = best;
+    }
+
+    out_x.push(xs[len - 1]);
+    out_y.push(ys[len - 1]);
+    (out_x, out_y)
+}
+
+/// Keep the min and max point of each of `threshold / 2` equal-count buckets
+pub fn min_max(xs: &[f64], ys: &[f64], threshold: usize) -> (Vec<f64>, Vec<f64>) {
+    let len = xs.len();
+    if threshold >= len || threshold < 2 {
+        return (xs.to_vec(), ys.to_vec());
+    }
+
+    let buckets = threshold / 2;
+    let mut out_x = Vec::with_capacity(buckets * 2);
+    let mut out_y = Vec::with_capacity(buckets * 2);
+
+    for bucket in 0..buckets {
+        let start = bucket * len / buckets;
+        let end = (bucket + 1) * len / buckets;
+        if start == end {
+            continue;
+        }
+
+        let mut min = start;
+        let mut max = start;
+        for i in start..end {
+            if ys[i] < ys[min] {
+                min = i;
+            }
+            if ys[i] > ys[max] {
+                max = i;
+            }
+        }
+
+        // Emit in x order so the line is drawn left to right
+        let (first, second) = if min <= max { (min, max) } else { (max, min) };
+        out_x.push(xs[first]);
+        out_y.push(ys[first]);
+        if second != first {
+            out_x.push(xs[second]);
+            out_y.push(ys[second]);
+        }
+    }
+
+    (out_x, out_y)
+}
+
+fn value_range(values: &[f64]) -> (f64, f64) {
+    values.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+        (lo.min(*v), hi.max(*v))
+    })
+}
+
+/// `bins + 1` equal-width edges; an empty or degenerate range becomes a unit span
+pub(crate) fn bin_edges(lo: f64, hi: f64, bins: usize) -> Vec<f64> {
+    let (lo, hi) = if !lo.is_finite() || !hi.is_finite() {
+        (0.0, 1.0)
+    } else if hi <= lo {
+        (lo - 0.5, lo + 0.5)
+    } else {
+        (lo, hi)
+    };
+
+    let width = (hi - lo) / bins as f64;
+    (0..=bins).map(|i| lo + width * i as f64).collect()
+}
+
+/// Bin for `value`, or `None` when outside the edges (upper edge inclusive)
+pub(crate) fn bin_index(value: f64, edges: &[f64]) -> Option<usize> {
+    let bins = edges.len() - 1;
+    let (lo, hi) = (edges[0], edges[bins]);
+    if value < lo || value > hi {
+        return None;
+    }
+    let index = ((value - lo) / (hi - lo) * bins as f64) as usize;
+    Some(index.min(bins - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::{Field, Schema};
+    use std::sync::Arc;
+
+    fn batch() -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("t", DataType::Int64, false),
+            Field::new("v", DataType::Float64, true),
+            Field::new("region", DataType::Utf8, true),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from((0..100).collect::<Vec<i64>>())),
+                Arc::new(Float64Array::from(
+                    (0..100).map(|i| Some((i % 10) as f64)).collect::<Vec<_>>(),
+                )),
+                Arc::new(StringArray::from(
+                    (0..100)
+                        .map(|i| match i % 10 {
+                            0..=4 => "north",
+                            5..=7 => "south",
+                            8 => "east",
+                            _ => "west",
+                        })
+                        .collect::<Vec<_>>(),
+                )),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_lttb_keeps_endpoints() {
+        let xs: Vec<f64> = (0..1000).map(|i| i as f64).collect();
+        let ys: Vec<f64> = xs.iter().map(|x| (x / 10.0).sin()).collect();
+        let (x, y) = lttb(&xs, &ys, 50);
+
+        assert_eq!(x.len(), 50);
+        assert_eq!(y.len(), 50);
+        assert_eq!(x[0], 0.0);
+        assert_eq!(x[49], 999.0);
+        assert!(x.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_min_max_preserves_extremes() {
+        let xs: Vec<f64> = (0..100).map(|i| i as f64).collect();
+        let mut ys = vec![0.0; 100];
+        ys[42] = 100.0;
+        ys[77] = -50.0;
+        let (_, y) = min_max(&xs, &ys, 10);
+
+        assert!(y.len() <= 10);
+        assert!(y.contains(&100.0));
+        assert!(y.contains(&-50.0));
+    }
+
+    #[test]
+    fn test_histogram() {
+        let reduction = Reduction::Histogram { column: "v".to_string(), bins: 5, min: None, max: None };
+        match reduction.apply(&batch()).unwrap() {
+            ChartSeries::Histogram { edges, counts, null_count } => {
+                assert_eq!(edges.len(), 6);
+                assert_eq!(counts.iter().sum::<u64>(), 100);
+                assert_eq!(counts, vec![20, 20, 20, 20, 20]);
+                assert_eq!(null_count, 0);
+            }
+            other => panic!("unexpected series {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_heatmap_counts_every_point() {
+        let reduction = Reduction::Heatmap {
+            x: "t".to_string(),
+            y: "v".to_string(),
+            x_bins: 4,
+            y_bins: 3,
+        };
+        match reduction.apply(&batch()).unwrap() {
+            ChartSeries::Heatmap { x_edges, y_edges, counts } => {
+                assert_eq!(x_edges.len(), 5);
+                assert_eq!(y_edges.len(), 4);
+                assert_eq!(counts.len(), 3);
+                assert_eq!(counts.iter().flatten().sum::<u64>(), 100);
+            }
+            other => panic!("unexpected series {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_top_k_with_other_bucket() {
+        let reduction = Reduction::TopK {
+            column: "region".to_string(),
+            k: 2,
+            value: None,
+            other_label: default_other_label(),
+        };
+        assert_eq!(
+            reduction.apply(&batch()).unwrap(),
+            ChartSeries::Categories {
+                labels: vec!["north".to_string(), "south".to_string(), "Other".to_string()],
+                values: vec![50.0, 30.0, 20.0],
+            }
+        );
+    }
+
+    #[test]
+    fn test_top_k_sums_value_column() {
+        let reduction: Reduction = serde_json::from_value(serde_json::json!({
+            "type": "top_k", "column": "region", "k": 10, "value": "v"
+        }))
+        .unwrap();
+        match reduction.apply(&batch()).unwrap() {
+            ChartSeries::Categories { labels, values } => {
+                assert_eq!(labels[0], "south");
+                assert_eq!(values[0], 180.0);
+                assert!(!labels.contains(&"Other".to_string()));
+            }
+            other => panic!("unexpected series {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rejects_invalid_sizes() {
+        let reduction = Reduction::Lttb { x: "t".to_string(), y: "v".to_string(), points: 1 };
+        assert!(matches!(reduction.apply(&batch()), Err(QueryError::InvalidReduction(_))));
+
+        let reduction = Reduction::Histogram { column: "region".to_string(), bins: 10, min: None, max: None };
+        assert!(matches!(reduction.apply(&batch()), Err(QueryError::InvalidReduction(_))));
+    }
+}
@@ -0,0 +1,167 @@
+//! In-process rate limit store for single-instance deployments
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::{LockoutPolicy, RateLimit, RateLimitError, RateLimitStore};
+
+/// Entries kept before idle ones are swept
+const MAX_ENTRIES: usize = 100_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// When the bucket will be full again, after which it can be dropped
+    full_at: Instant,
+}
+
+struct Failures {
+    count: u32,
+    last_failure: Instant,
+    window: Duration,
+    locked_until: Option<Instant>,
+}
+
+impl Failures {
+    fn is_stale(&self, now: Instant) -> bool {
+        now.duration_since(self.last_failure) >= self.window && self.locked_until.is_none_or(|until| until <= now)
+    }
+}
+
+/// Buckets and failure counts in process memory
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore {
+            buckets: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn take(&self, key: &str, limit: RateLimit) -> Result<Option<Duration>, RateLimitError> {
+        let now = Instant::now();
+        let rate = limit.refill_rate();
+        let capacity = limit.capacity as f64;
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_ENTRIES && !buckets.contains_key(key) {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+            full_at: now,
+        });
+
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate).min(capacity);
+        bucket.updated = now;
+
+        let wait = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        };
+        bucket.full_at = now + Duration::from_secs_f64((capacity - bucket.tokens) / rate);
+
+        Ok(wait)
+    }
+
+    async fn record_failure(&self, key: &str, policy: LockoutPolicy) -> Result<Option<Duration>, RateLimitError> {
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        if failures.len() >= MAX_ENTRIES && !failures.contains_key(key) {
+            failures.retain(|_, entry| !entry.is_stale(now));
+        }
+
+        let entry = failures.entry(key.to_string()).or_insert(Failures {
+            count: 0,
+            last_failure: now,
+            window: policy.window,
+            locked_until: None,
+        });
+        if entry.is_stale(now) {
+            entry.count = 0;
+        }
+
+        entry.count = entry.count.saturating_add(1);
+        entry.last_failure = now;
+        entry.window = policy.window;
+
+        let lockout = policy.lockout_for(entry.count);
+        if let Some(duration) = lockout {
+            entry.locked_until = Some(now + duration);
+        }
+        Ok(lockout)
+    }
+
+    async fn locked_for(&self, key: &str) -> Result<Option<Duration>, RateLimitError> {
+        let now = Instant::now();
+        let failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(failures
+            .get(key)
+            .and_then(|entry| entry.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| until - now))
+    }
+
+    async fn clear_failures(&self, key: &str) -> Result<(), RateLimitError> {
+        self.failures.lock().unwrap_or_else(|e| e.into_inner()).remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_bucket_refills() {
+        let store = MemoryStore::new();
+        let limit = RateLimit::new(2, Duration::from_millis(100));
+
+        assert_eq!(store.take("k", limit).await.unwrap(), None);
+        assert_eq!(store.take("k", limit).await.unwrap(), None);
+        let wait = store.take("k", limit).await.unwrap().unwrap();
+        assert!(wait <= Duration::from_millis(50));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(store.take("k", limit).await.unwrap(), None);
+    }
+
+    #[actix_rt::test]
+    async fn test_failures_expire_after_window() {
+        let store = MemoryStore::new();
+        let policy = LockoutPolicy {
+            threshold: 2,
+            window: Duration::from_millis(50),
+            base: Duration::from_millis(20),
+            max: Duration::from_secs(1),
+        };
+
+        assert_eq!(store.record_failure("a", policy).await.unwrap(), None);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(store.record_failure("a", policy).await.unwrap(), None);
+        assert_eq!(store.record_failure("a", policy).await.unwrap(), Some(Duration::from_millis(20)));
+        assert_eq!(store.record_failure("a", policy).await.unwrap(), Some(Duration::from_millis(40)));
+
+        tokio::time::sleep(Duration::from_millis(45)).await;
+        assert_eq!(store.locked_for("a").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,320 @@
+//! Rate limiting and login lockout
+//!
+//! Requests are metered with token buckets, one per route group and client
+//! key (IP address, and user when authenticated). A bucket holds up to
+//! `capacity` requests and refills continuously at `capacity / period`, so
+//! bursts are allowed but the sustained rate is bounded.
+//!
+//! Failed logins are counted per account. Once an account reaches the
+//! threshold it is locked for a period that doubles with every further
+//! failure, up to a maximum; a successful login clears the count.
+//!
+//! State lives in a [`RateLimitStore`]: in memory for a single instance, or
+//! in Redis when several instances must share limits.
+
+pub mod memory;
+pub mod redis;
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+pub use self::memory::MemoryStore;
+pub use self::redis::RedisStore;
+
+/// Errors raised by a rate limit store or its configuration
+#[derive(Error, Debug)]
+pub enum RateLimitError {
+    #[error("Redis error: {0}")]
+    Redis(#[from] ::redis::RedisError),
+
+    #[error("Rate limit configuration error: {0}")]
+    Config(String),
+}
+
+/// Token bucket parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// Largest burst
+    pub capacity: u32,
+    /// Time to refill an empty bucket
+    pub period: Duration,
+}
+
+impl RateLimit {
+    pub fn new(capacity: u32, period: Duration) -> Self {
+        RateLimit { capacity, period }
+    }
+
+    /// Parse `<requests>/<period>`, e.g. `10/min`, `300/5m` or `5/s`
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (count, period) = spec.trim().split_once('/')?;
+        let capacity: u32 = count.trim().parse().ok().filter(|c| *c > 0)?;
+
+        let period = period.trim();
+        let split = period.find(|c: char| !c.is_ascii_digit()).unwrap_or(period.len());
+        let (amount, unit) = period.split_at(split);
+        let amount: u64 = if amount.is_empty() { 1 } else { amount.parse().ok()? };
+        let seconds = match unit {
+            "s" | "sec" | "second" => 1,
+            "m" | "min" | "minute" => 60,
+            "h" | "hour" => 3600,
+            _ => return None,
+        };
+
+        (amount > 0).then(|| RateLimit::new(capacity, Duration::from_secs(amount * seconds)))
+    }
+
+    /// Tokens added per second
+    pub fn refill_rate(&self) -> f64 {
+        self.capacity as f64 / self.period.as_secs_f64()
+    }
+}
+
+/// Progressive lockout parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LockoutPolicy {
+    /// Failures before the first lockout
+    pub threshold: u32,
+    /// Failures are forgotten after this long without another one
+    pub window: Duration,
+    /// First lockout; doubles with each failure past the threshold
+    pub base: Duration,
+    /// Longest lockout
+    pub max: Duration,
+}
+
+impl LockoutPolicy {
+    /// Lockout after `failures` consecutive failures, if any
+    pub fn lockout_for(&self, failures: u32) -> Option<Duration> {
+        if failures < self.threshold {
+            return None;
+        }
+        let doublings = (failures - self.threshold).min(20);
+        Some(self.base.saturating_mul(1 << doublings).min(self.max))
+    }
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        LockoutPolicy {
+            threshold: 5,
+            window: Duration::from_secs(15 * 60),
+            base: Duration::from_secs(60),
+            max: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// Shared limiter state
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Take a token from bucket `key`; returns how long to wait if it is empty
+    async fn take(&self, key: &str, limit: RateLimit) -> Result<Option<Duration>, RateLimitError>;
+
+    /// Count a failure for `key`; returns the lockout it triggered, if any
+    async fn record_failure(&self, key: &str, policy: LockoutPolicy) -> Result<Option<Duration>, RateLimitError>;
+
+    /// Remaining lockout for `key`
+    async fn locked_for(&self, key: &str) -> Result<Option<Duration>, RateLimitError>;
+
+    /// Forget failures and lockout for `key`
+    async fn clear_failures(&self, key: &str) -> Result<(), RateLimitError>;
+}
+
+/// Route-group limits and account lockout over a store
+///
+/// Store errors are logged and the request allowed: an unavailable Redis
+/// should degrade protection, not take the API down.
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    limits: HashMap<String, RateLimit>,
+    lockout: LockoutPolicy,
+    /// Use `X-Forwarded-For` / `Forwarded` for the client IP
+    pub trust_proxy: bool,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>, limits: HashMap<String, RateLimit>, lockout: LockoutPolicy) -> Self {
+        RateLimiter {
+            store,
+            limits,
+            lockout,
+            trust_proxy: false,
+        }
+    }
+
+    /// Default limits per route group
+    pub fn default_limits() -> HashMap<String, RateLimit> {
+        HashMap::from([
+            ("auth".to_string(), RateLimit::new(60, Duration::from_secs(60))),
+            ("api".to_string(), RateLimit::new(600, Duration::from_secs(60))),
+        ])
+    }
+
+    /// Build from the environment:
+    /// - `RATE_LIMIT_STORE`: `memory` (default) or `redis` (uses `REDIS_URL`)
+    /// - `RATE_LIMIT_<GROUP>`: limit for a route group, e.g. `RATE_LIMIT_AUTH=60/min`
+    /// - `RATE_LIMIT_TRUST_PROXY`: take the client IP from forwarding headers
+    /// - `LOGIN_LOCKOUT_THRESHOLD`, `LOGIN_LOCKOUT_WINDOW_SECONDS`,
+    ///   `LOGIN_LOCKOUT_BASE_SECONDS`, `LOGIN_LOCKOUT_MAX_SECONDS`
+    pub async fn from_env() -> Result<Self, RateLimitError> {
+        let store: Arc<dyn RateLimitStore> =
+            match std::env::var("RATE_LIMIT_STORE").unwrap_or_else(|_| "memory".to_string()).as_str() {
+                "memory" => Arc::new(MemoryStore::new()),
+                "redis" => {
+                    let url = std::env::var("REDIS_URL")
+                        .map_err(|_| RateLimitError::Config("REDIS_URL is required for RATE_LIMIT_STORE=redis".into()))?;
+                    Arc::new(RedisStore::connect(&url).await?)
+                }
+                other => return Err(RateLimitError::Config(format!("unknown RATE_LIMIT_STORE {}", other))),
+            };
+
+        let mut limits = Self::default_limits();
+        for (name, value) in std::env::vars() {
+            if let Some(group) = name.strip_prefix("RATE_LIMIT_") {
+                if matches!(group, "STORE" | "TRUST_PROXY") {
+                    continue;
+                }
+                let limit = RateLimit::parse(&value)
+                    .ok_or_else(|| RateLimitError::Config(format!("invalid {}: {}", name, value)))?;
+                limits.insert(group.to_lowercase(), limit);
+            }
+        }
+
+        let env_seconds = |name: &str, default: Duration| -> Result<Duration, RateLimitError> {
+            match std::env::var(name) {
+                Ok(value) => value
+                    .parse()
+                    .map(Duration::from_secs)
+                    .map_err(|_| RateLimitError::Config(format!("invalid {}: {}", name, value))),
+                Err(_) => Ok(default),
+            }
+        };
+        let defaults = LockoutPolicy::default();
+        let lockout = LockoutPolicy {
+            threshold: match std::env::var("LOGIN_LOCKOUT_THRESHOLD") {
+                Ok(value) => value
+                    .parse()
+                    .map_err(|_| RateLimitError::Config(format!("invalid LOGIN_LOCKOUT_THRESHOLD: {}", value)))?,
+                Err(_) => defaults.threshold,
+            },
+            window: env_seconds("LOGIN_LOCKOUT_WINDOW_SECONDS", defaults.window)?,
+            base: env_seconds("LOGIN_LOCKOUT_BASE_SECONDS", defaults.base)?,
+            max: env_seconds("LOGIN_LOCKOUT_MAX_SECONDS", defaults.max)?,
+        };
+
+        let mut limiter = Self::new(store, limits, lockout);
+        limiter.trust_proxy = std::env::var("RATE_LIMIT_TRUST_PROXY")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        Ok(limiter)
+    }
+
+    /// Take a token from every `keys` bucket of `group`; returns the longest
+    /// wait if any is empty. Groups without a configured limit are unlimited.
+    pub async fn check(&self, group: &str, keys: &[String]) -> Option<Duration> {
+        let limit = *self.limits.get(group)?;
+
+        let mut wait: Option<Duration> = None;
+        for key in keys {
+            match self.store.take(&format!("rl:{}:{}", group, key), limit).await {
+                Ok(Some(retry)) => wait = Some(wait.map_or(retry, |w| w.max(retry))),
+                Ok(None) => {}
+                Err(e) => log::warn!("Rate limit store error, allowing request: {}", e),
+            }
+        }
+        wait
+    }
+
+    /// Remaining lockout for an account
+    pub async fn login_locked(&self, account: &str) -> Option<Duration> {
+        self.store
+            .locked_for(&lockout_key(account))
+            .await
+            .unwrap_or_else(|e| {
+                log::warn!("Rate limit store error, skipping lockout check: {}", e);
+                None
+            })
+    }
+
+    /// Count a failed login; returns the lockout it triggered, if any
+    pub async fn login_failed(&self, account: &str) -> Option<Duration> {
+        match self.store.record_failure(&lockout_key(account), self.lockout).await {
+            Ok(lockout) => {
+                if let Some(duration) = lockout {
+                    log::warn!("Locking out {} for {}s after repeated failed logins", account, duration.as_secs());
+                }
+                lockout
+            }
+            Err(e) => {
+                log::warn!("Rate limit store error, failed login not counted: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Clear the failure count after a successful login
+    pub async fn login_succeeded(&self, account: &str) {
+        if let Err(e) = self.store.clear_failures(&lockout_key(account)).await {
+            log::warn!("Rate limit store error, failures not cleared: {}", e);
+        }
+    }
+}
+
+fn lockout_key(account: &str) -> String {
+    format!("login:{}", account.trim().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_limits() {
+        assert_eq!(RateLimit::parse("10/min"), Some(RateLimit::new(10, Duration::from_secs(60))));
+        assert_eq!(RateLimit::parse("300/5m"), Some(RateLimit::new(300, Duration::from_secs(300))));
+        assert_eq!(RateLimit::parse(" 5 / s "), Some(RateLimit::new(5, Duration::from_secs(1))));
+        assert_eq!(RateLimit::parse("1000/hour"), Some(RateLimit::new(1000, Duration::from_secs(3600))));
+        assert_eq!(RateLimit::parse("0/min"), None);
+        assert_eq!(RateLimit::parse("10/0m"), None);
+        assert_eq!(RateLimit::parse("10/fortnight"), None);
+        assert_eq!(RateLimit::parse("ten"), None);
+    }
+
+    #[test]
+    fn test_progressive_lockout() {
+        let policy = LockoutPolicy::default();
+        assert_eq!(policy.lockout_for(4), None);
+        assert_eq!(policy.lockout_for(5), Some(Duration::from_secs(60)));
+        assert_eq!(policy.lockout_for(6), Some(Duration::from_secs(120)));
+        assert_eq!(policy.lockout_for(8), Some(Duration::from_secs(480)));
+        assert_eq!(policy.lockout_for(12), Some(Duration::from_secs(3600)));
+        assert_eq!(policy.lockout_for(u32::MAX), Some(Duration::from_secs(3600)));
+    }
+
+    #[actix_rt::test]
+    async fn test_limiter_groups_and_lockout() {
+        let limits = HashMap::from([("auth".to_string(), RateLimit::new(2, Duration::from_secs(60)))]);
+        let limiter = RateLimiter::new(Arc::new(MemoryStore::new()), limits, LockoutPolicy::default());
+        let keys = vec!["ip:10.0.0.1".to_string()];
+
+        assert_eq!(limiter.check("auth", &keys).await, None);
+        assert_eq!(limiter.check("auth", &keys).await, None);
+        assert!(limiter.check("auth", &keys).await.is_some());
+        assert_eq!(limiter.check("auth", &["ip:10.0.0.2".to_string()]).await, None);
+        assert_eq!(limiter.check("unlimited", &keys).await, None);
+
+        for _ in 0..4 {
+            assert_eq!(limiter.login_failed("Jane@Example.com").await, None);
+        }
+        assert_eq!(limiter.login_failed("jane@example.com").await, Some(Duration::from_secs(60)));
+        assert!(limiter.login_locked("jane@example.com").await.is_some());
+        assert_eq!(limiter.login_locked("john@example.com").await, None);
+
+        limiter.login_succeeded("jane@example.com").await;
+        assert_eq!(limiter.login_locked("jane@example.com").await, None);
+    }
+}
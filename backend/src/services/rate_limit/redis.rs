@@ -0,0 +1,120 @@
+//! Redis rate limit store, shared by all instances
+//!
+//! Each operation is a Lua script so the read-modify-write is atomic, and
+//! timestamps come from the Redis server clock so instances need not agree.
+
+use ::redis::aio::ConnectionManager;
+use ::redis::Script;
+use async_trait::async_trait;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use super::{LockoutPolicy, RateLimit, RateLimitError, RateLimitStore};
+
+/// Take a token; returns milliseconds to wait, 0 if allowed.
+/// KEYS[1] bucket; ARGV capacity, refill tokens per millisecond
+static TAKE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        local capacity = tonumber(ARGV[1])
+        local rate = tonumber(ARGV[2])
+        local time = redis.call('TIME')
+        local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+        local state = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+        local tokens = tonumber(state[1]) or capacity
+        local updated = tonumber(state[2]) or now
+        tokens = math.min(capacity, tokens + math.max(0, now - updated) * rate)
+
+        local wait = 0
+        if tokens >= 1 then
+            tokens = tokens - 1
+        else
+            wait = math.ceil((1 - tokens) / rate)
+        end
+
+        redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', now)
+        redis.call('PEXPIRE', KEYS[1], math.ceil((capacity - tokens) / rate) + 1000)
+        return wait
+        "#,
+    )
+});
+
+/// Count a failure and restart its window; returns the failure count.
+/// KEYS[1] counter; ARGV window ms
+static RECORD_FAILURE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        local count = redis.call('INCR', KEYS[1])
+        redis.call('PEXPIRE', KEYS[1], ARGV[1])
+        return count
+        "#,
+    )
+});
+
+/// Redis-backed store
+pub struct RedisStore {
+    connection: ConnectionManager,
+}
+
+impl RedisStore {
+    pub async fn connect(url: &str) -> Result<Self, RateLimitError> {
+        let client = ::redis::Client::open(url)?;
+        Ok(RedisStore {
+            connection: ConnectionManager::new(client).await?,
+        })
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for RedisStore {
+    async fn take(&self, key: &str, limit: RateLimit) -> Result<Option<Duration>, RateLimitError> {
+        let wait_ms: u64 = TAKE
+            .key(key)
+            .arg(limit.capacity)
+            .arg(limit.refill_rate() / 1000.0)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+
+        Ok((wait_ms > 0).then(|| Duration::from_millis(wait_ms)))
+    }
+
+    async fn record_failure(&self, key: &str, policy: LockoutPolicy) -> Result<Option<Duration>, RateLimitError> {
+        let mut connection = self.connection.clone();
+        let count: u32 = RECORD_FAILURE
+            .key(format!("{}:failures", key))
+            .arg(policy.window.as_millis() as u64)
+            .invoke_async(&mut connection)
+            .await?;
+
+        let lockout = policy.lockout_for(count);
+        if let Some(duration) = lockout {
+            ::redis::cmd("SET")
+                .arg(format!("{}:locked", key))
+                .arg(1)
+                .arg("PX")
+                .arg(duration.as_millis() as u64)
+                .query_async::<_, ()>(&mut connection)
+                .await?;
+        }
+        Ok(lockout)
+    }
+
+    async fn locked_for(&self, key: &str) -> Result<Option<Duration>, RateLimitError> {
+        let ttl_ms: i64 = ::redis::cmd("PTTL")
+            .arg(format!("{}:locked", key))
+            .query_async(&mut self.connection.clone())
+            .await?;
+
+        Ok((ttl_ms > 0).then(|| Duration::from_millis(ttl_ms as u64)))
+    }
+
+    async fn clear_failures(&self, key: &str) -> Result<(), RateLimitError> {
+        ::redis::cmd("DEL")
+            .arg(format!("{}:failures", key))
+            .arg(format!("{}:locked", key))
+            .query_async::<_, ()>(&mut self.connection.clone())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,355 @@
+//! Database-backed roles
+//!
+//! Each built-in system and team role has a default row (`team_id` NULL)
+//! seeded from [`SystemRole`] and [`TeamRoleType`]. Teams may override the
+//! permissions of their built-in roles, other than owner, and define custom
+//! roles from any subset of permissions.
+//!
+//! Resolved permission sets are cached in-process for [`ROLE_CACHE_TTL`];
+//! changes made through this service take effect on this instance at once,
+//! and on other instances once the cached entry expires.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+use uuid::Uuid;
+
+use crate::services::cache::TtlCache;
+use crate::services::permissions::{Permission, SystemRole, TeamRoleType};
+
+/// How long a role's resolved permissions are cached per instance
+pub const ROLE_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Custom roles a team may define
+pub const MAX_CUSTOM_ROLES_PER_TEAM: i64 = 50;
+
+/// Built-in team roles a team may override
+pub const OVERRIDABLE_TEAM_ROLES: [&str; 3] = ["admin", "member", "viewer"];
+
+static ROLE_PERMISSIONS: LazyLock<TtlCache<RoleRef, HashSet<Permission>>> =
+    LazyLock::new(|| TtlCache::new(ROLE_CACHE_TTL, 10_000));
+
+/// Whether a role applies system-wide or within a team
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "role_scope", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum RoleScope {
+    System,
+    Team,
+}
+
+/// Role as stored
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Role {
+    pub id: Uuid,
+    pub scope: RoleScope,
+    pub team_id: Option<Uuid>,
+    /// Built-in role this row defines or overrides; `None` for custom roles
+    pub builtin: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Role whose permissions are being resolved
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RoleRef {
+    /// Built-in system role, by `users.role` value
+    System(String),
+    /// Built-in team role, by `team_members.role` value, as seen by a team
+    Team { team_id: Uuid, builtin: String },
+    /// Custom team role
+    Custom(Uuid),
+}
+
+/// Role management service
+pub struct RoleService;
+
+impl RoleService {
+    /// Permissions granted by a role. Built-in roles fall back to their
+    /// hardcoded defaults if their row is missing; custom roles that no
+    /// longer exist grant nothing.
+    pub async fn permissions(pool: &PgPool, role: &RoleRef) -> Result<HashSet<Permission>, sqlx::Error> {
+        if let Some(permissions) = ROLE_PERMISSIONS.get(role) {
+            return Ok(permissions);
+        }
+
+        let stored: Option<(Vec<String>,)> = match role {
+            RoleRef::System(builtin) => {
+                sqlx::query_as(
+                    "SELECT permissions FROM roles WHERE scope = 'system' AND team_id IS NULL AND builtin = $1"
+                )
+                .bind(builtin)
+                .fetch_optional(pool)
+                .await?
+            }
+            RoleRef::Team { team_id, builtin } => {
+                // The team's override, else the default; owners are never overridden
+                sqlx::query_as(
+                    r#"
+                    SELECT permissions FROM roles
+                    WHERE scope = 'team' AND builtin = $2
+                      AND (team_id IS NULL OR (team_id = $1 AND builtin <> 'owner'))
+                    ORDER BY team_id NULLS LAST
+                    LIMIT 1
+                    "#
+                )
+                .bind(team_id)
+                .bind(builtin)
+                .fetch_optional(pool)
+                .await?
+            }
+            RoleRef::Custom(role_id) => {
+                sqlx::query_as("SELECT permissions FROM roles WHERE id = $1 AND builtin IS NULL")
+                    .bind(role_id)
+                    .fetch_optional(pool)
+                    .await?
+            }
+        };
+
+        let permissions = match (stored, role) {
+            (Some((stored,)), _) => parse_permissions(&stored),
+            (None, RoleRef::System(builtin)) => SystemRole::from_db(builtin).permissions(),
+            (None, RoleRef::Team { builtin, .. }) => TeamRoleType::from_db(builtin).team_permissions(),
+            (None, RoleRef::Custom(_)) => HashSet::new(),
+        };
+
+        ROLE_PERMISSIONS.insert(role.clone(), permissions.clone());
+        Ok(permissions)
+    }
+
+    /// Built-in team roles as seen by a team, followed by its custom roles
+    pub async fn list_team(pool: &PgPool, team_id: Uuid) -> Result<Vec<Role>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT DISTINCT ON (COALESCE(builtin, id::text)) *
+            FROM roles
+            WHERE scope = 'team'
+              AND (team_id = $1 OR team_id IS NULL)
+              AND (team_id IS NULL OR builtin IS DISTINCT FROM 'owner')
+            ORDER BY COALESCE(builtin, id::text), team_id NULLS LAST
+            "#
+        )
+        .bind(team_id)
+        .fetch_all(pool)
+        .await
+        .map(|mut roles: Vec<Role>| {
+            roles.sort_by_key(|role| (role.builtin.is_none(), builtin_rank(role.builtin.as_deref()), role.name.to_lowercase()));
+            roles
+        })
+    }
+
+    /// A team's custom role
+    pub async fn get_custom(pool: &PgPool, team_id: Uuid, role_id: Uuid) -> Result<Option<Role>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM roles WHERE id = $1 AND team_id = $2 AND builtin IS NULL")
+            .bind(role_id)
+            .bind(team_id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Number of custom roles a team has defined
+    pub async fn count_custom(pool: &PgPool, team_id: Uuid) -> Result<i64, sqlx::Error> {
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM roles WHERE team_id = $1 AND builtin IS NULL")
+            .bind(team_id)
+            .fetch_one(pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Members assigned a custom role
+    pub async fn count_assigned(pool: &PgPool, role_id: Uuid) -> Result<i64, sqlx::Error> {
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM team_members WHERE role_id = $1")
+            .bind(role_id)
+            .fetch_one(pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Define a custom role for a team
+    pub async fn create_custom(
+        pool: &PgPool,
+        team_id: Uuid,
+        name: &str,
+        description: Option<&str>,
+        permissions: &[Permission],
+        created_by: Uuid,
+    ) -> Result<Role, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            INSERT INTO roles (scope, team_id, name, description, permissions, created_by)
+            VALUES ('team', $1, $2, $3, $4, $5)
+            RETURNING *
+            "#
+        )
+        .bind(team_id)
+        .bind(name)
+        .bind(description)
+        .bind(permission_strings(permissions))
+        .bind(created_by)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Update a team's custom role; fields left `None` are unchanged
+    pub async fn update_custom(
+        pool: &PgPool,
+        team_id: Uuid,
+        role_id: Uuid,
+        name: Option<&str>,
+        description: Option<&str>,
+        permissions: Option<&[Permission]>,
+    ) -> Result<Option<Role>, sqlx::Error> {
+        let role: Option<Role> = sqlx::query_as(
+            r#"
+            UPDATE roles SET
+                name = COALESCE($3, name),
+                description = COALESCE($4, description),
+                permissions = COALESCE($5, permissions)
+            WHERE id = $1 AND team_id = $2 AND builtin IS NULL
+            RETURNING *
+            "#
+        )
+        .bind(role_id)
+        .bind(team_id)
+        .bind(name)
+        .bind(description)
+        .bind(permissions.map(permission_strings))
+        .fetch_optional(pool)
+        .await?;
+
+        ROLE_PERMISSIONS.remove(&RoleRef::Custom(role_id));
+        Ok(role)
+    }
+
+    /// Delete a team's custom role; returns false if there is no such role
+    pub async fn delete_custom(pool: &PgPool, team_id: Uuid, role_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM roles WHERE id = $1 AND team_id = $2 AND builtin IS NULL")
+            .bind(role_id)
+            .bind(team_id)
+            .execute(pool)
+            .await?;
+
+        ROLE_PERMISSIONS.remove(&RoleRef::Custom(role_id));
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Override the permissions of a built-in role within a team
+    pub async fn set_override(
+        pool: &PgPool,
+        team_id: Uuid,
+        builtin: &str,
+        permissions: &[Permission],
+        created_by: Uuid,
+    ) -> Result<Role, sqlx::Error> {
+        let role = sqlx::query_as(
+            r#"
+            INSERT INTO roles (scope, team_id, builtin, name, description, permissions, created_by)
+            SELECT 'team', $1, builtin, name, description, $3, $4
+            FROM roles WHERE scope = 'team' AND team_id IS NULL AND builtin = $2
+            ON CONFLICT (team_id, builtin) WHERE team_id IS NOT NULL AND builtin IS NOT NULL
+            DO UPDATE SET permissions = EXCLUDED.permissions
+            RETURNING *
+            "#
+        )
+        .bind(team_id)
+        .bind(builtin)
+        .bind(permission_strings(permissions))
+        .bind(created_by)
+        .fetch_one(pool)
+        .await?;
+
+        ROLE_PERMISSIONS.remove(&RoleRef::Team { team_id, builtin: builtin.to_string() });
+        Ok(role)
+    }
+
+    /// Restore a built-in role's default permissions within a team; returns
+    /// false if it was not overridden
+    pub async fn clear_override(pool: &PgPool, team_id: Uuid, builtin: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM roles WHERE team_id = $1 AND builtin = $2")
+            .bind(team_id)
+            .bind(builtin)
+            .execute(pool)
+            .await?;
+
+        ROLE_PERMISSIONS.remove(&RoleRef::Team { team_id, builtin: builtin.to_string() });
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Permissions named by stored strings; unknown strings (e.g. from a newer
+/// release) grant nothing
+pub fn parse_permissions(stored: &[String]) -> HashSet<Permission> {
+    stored.iter().filter_map(|s| Permission::parse(s)).collect()
+}
+
+fn permission_strings(permissions: &[Permission]) -> Vec<String> {
+    permissions.iter().map(|p| p.as_str().to_string()).collect()
+}
+
+/// Order of built-in team roles, most privileged first
+fn builtin_rank(builtin: Option<&str>) -> usize {
+    ["owner", "admin", "member", "viewer"]
+        .iter()
+        .position(|b| Some(*b) == builtin)
+        .unwrap_or(usize::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_permissions_ignores_unknown() {
+        let stored = vec!["query:execute".to_string(), "query:teleport".to_string()];
+        assert_eq!(parse_permissions(&stored), HashSet::from([Permission::QueryExecute]));
+    }
+
+    /// Permissions the migrations give a built-in role: the roles migration's
+    /// seed plus later `array_append` grants
+    fn seeded(scope: &str, builtin: &str) -> HashSet<Permission> {
+        let sql = include_str!("../../migrations/20231223_015_create_roles.sql");
+        let row = &sql[sql.find(&format!("('{}', '{}'", scope, builtin)).unwrap()..];
+        let array = &row[row.find("ARRAY[").unwrap() + 6..row.find(']').unwrap()];
+        let mut permissions: HashSet<Permission> = array
+            .split(',')
+            .map(|s| Permission::parse(s.trim().trim_matches('\'')).unwrap())
+            .collect();
+
+        let grants = [
+            include_str!("../../migrations/20231223_018_add_column_classification.sql"),
+            include_str!("../../migrations/20231223_019_grant_admin_team_settings.sql"),
+        ];
+        let target = format!("scope = '{}' AND builtin = '{}'", scope, builtin);
+        for sql in grants {
+            for statement in sql.split(';').filter(|s| s.contains(&target)) {
+                let start = statement.find("array_append(permissions, '").unwrap() + 27;
+                let end = start + statement[start..].find('\'').unwrap();
+                permissions.insert(Permission::parse(&statement[start..end]).unwrap());
+            }
+        }
+        permissions
+    }
+
+    #[test]
+    fn test_seeded_roles_match_defaults() {
+        for builtin in ["admin", "user", "readonly"] {
+            assert_eq!(seeded("system", builtin), SystemRole::from_db(builtin).permissions(), "{}", builtin);
+        }
+        for builtin in ["owner", "admin", "member", "viewer"] {
+            assert_eq!(seeded("team", builtin), TeamRoleType::from_db(builtin).team_permissions(), "{}", builtin);
+        }
+    }
+
+    #[test]
+    fn test_builtin_rank() {
+        assert!(builtin_rank(Some("owner")) < builtin_rank(Some("viewer")));
+        assert_eq!(builtin_rank(None), usize::MAX);
+    }
+}
@@ -0,0 +1,547 @@
+//! Row-level security
+//!
+//! Policies on a dataset restrict which rows users other than its owner can
+//! see. A policy is a list of query filters whose values may reference the
+//! querying user through placeholders:
+//!
+//! - `{{user.id}}`, `{{user.email}}`, `{{user.name}}`, `{{user.role}}`
+//! - `{{team.role}}`, the user's role in the file's team
+//! - `{{user.attributes.<key>}}`, a custom attribute set by an administrator
+//!
+//! A value that is exactly one placeholder takes the referenced JSON value,
+//! so an array attribute can feed an `in` filter; otherwise placeholders are
+//! substituted as text. Every applicable policy must match for a row to be
+//! visible. A policy referencing an attribute the user does not have hides
+//! every row rather than failing open, and users without a role in the
+//! file's team are held to every role's policies.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::Json;
+use sqlx::PgPool;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::services::query_engine::{Filter, QuerySpec};
+
+/// Policies a file may have
+pub const MAX_POLICIES_PER_FILE: i64 = 50;
+
+/// Errors resolving a policy template
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RowSecurityError {
+    #[error("Unknown placeholder: {{{{{0}}}}}")]
+    UnknownPlaceholder(String),
+
+    #[error("Unterminated placeholder in '{0}'")]
+    Unterminated(String),
+
+    #[error("User has no value for {{{{{0}}}}}")]
+    MissingValue(String),
+}
+
+/// Row-level security policy on a file
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct RowPolicy {
+    pub id: Uuid,
+    pub file_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub filters: Json<Vec<Filter>>,
+    /// Team roles the policy applies to, and to users outside the team who
+    /// reach the file through a share; `None` for everyone but the owner
+    pub applies_to_roles: Option<Vec<String>>,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Parameters of a new or replaced policy
+#[derive(Debug, Clone, Deserialize)]
+pub struct RowPolicyInput {
+    pub name: String,
+    pub description: Option<String>,
+    pub filters: Vec<Filter>,
+    pub applies_to_roles: Option<Vec<String>>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// The user a policy is evaluated for
+#[derive(Debug, Clone, Serialize)]
+pub struct UserContext {
+    pub user_id: Uuid,
+    pub email: String,
+    pub name: String,
+    /// System role
+    pub role: String,
+    /// Role in the file's team, if a member
+    pub team_role: Option<String>,
+    pub attributes: serde_json::Map<String, Value>,
+}
+
+impl UserContext {
+    /// Load a user's context for a file belonging to `team_id`
+    pub async fn load(pool: &PgPool, user_id: Uuid, team_id: Option<Uuid>) -> Result<Option<Self>, sqlx::Error> {
+        let user: Option<(String, String, String, Json<Value>)> = sqlx::query_as(
+            "SELECT email, name, role::text, attributes FROM users WHERE id = $1"
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+        let Some((email, name, role, Json(attributes))) = user else {
+            return Ok(None);
+        };
+
+        let team_role: Option<(String,)> = match team_id {
+            Some(team_id) => {
+                sqlx::query_as("SELECT role::text FROM team_members WHERE team_id = $1 AND user_id = $2")
+                    .bind(team_id)
+                    .bind(user_id)
+                    .fetch_optional(pool)
+                    .await?
+            }
+            None => None,
+        };
+
+        Ok(Some(UserContext {
+            user_id,
+            email,
+            name,
+            role,
+            team_role: team_role.map(|(role,)| role),
+            attributes: match attributes {
+                Value::Object(map) => map,
+                _ => serde_json::Map::new(),
+            },
+        }))
+    }
+
+    /// Value of a placeholder, or `None` if the user has none
+    fn lookup(&self, placeholder: &str) -> Result<Option<Value>, RowSecurityError> {
+        let value = match placeholder {
+            "user.id" => Some(Value::String(self.user_id.to_string())),
+            "user.email" => Some(Value::String(self.email.clone())),
+            "user.name" => Some(Value::String(self.name.clone())),
+            "user.role" => Some(Value::String(self.role.clone())),
+            "team.role" => self.team_role.clone().map(Value::String),
+            other => match other.strip_prefix("user.attributes.") {
+                Some(key) if !key.is_empty() => self.attributes.get(key).filter(|v| !v.is_null()).cloned(),
+                _ => return Err(RowSecurityError::UnknownPlaceholder(other.to_string())),
+            },
+        };
+        Ok(value)
+    }
+}
+
+/// Rows a user may see in a file
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowFilter {
+    /// Every row
+    Unrestricted,
+    /// Rows matching all of these filters
+    Restricted(Vec<Filter>),
+    /// No rows, because a policy could not be resolved for the user
+    DenyAll,
+}
+
+impl RowFilter {
+    pub fn is_restricted(&self) -> bool {
+        *self != RowFilter::Unrestricted
+    }
+
+    /// Narrow a query to the rows this filter allows
+    pub fn restrict(&self, spec: &mut QuerySpec) {
+        match self {
+            RowFilter::Unrestricted => {}
+            RowFilter::Restricted(filters) => spec.filters.extend(filters.iter().cloned()),
+            RowFilter::DenyAll => spec.limit = Some(0),
+        }
+    }
+}
+
+/// How one policy applied to a user, as shown by "view as user"
+#[derive(Debug, Clone, Serialize)]
+pub struct AppliedPolicy {
+    pub id: Uuid,
+    pub name: String,
+    /// Filters with the user's values substituted
+    pub filters: Option<Vec<Filter>>,
+    /// Why the policy hides every row from this user
+    pub error: Option<String>,
+}
+
+/// Row filter for a user, with the policies that produced it
+#[derive(Debug, Clone)]
+pub struct RowAccess {
+    pub filter: RowFilter,
+    pub applied: Vec<AppliedPolicy>,
+}
+
+impl RowAccess {
+    fn unrestricted() -> Self {
+        RowAccess {
+            filter: RowFilter::Unrestricted,
+            applied: Vec::new(),
+        }
+    }
+}
+
+/// Row-level security service
+pub struct RowSecurityService;
+
+impl RowSecurityService {
+    /// Policies on a file
+    pub async fn list(pool: &PgPool, file_id: Uuid) -> Result<Vec<RowPolicy>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM row_policies WHERE file_id = $1 ORDER BY name")
+            .bind(file_id)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// A policy on a file
+    pub async fn get(pool: &PgPool, file_id: Uuid, policy_id: Uuid) -> Result<Option<RowPolicy>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM row_policies WHERE id = $1 AND file_id = $2")
+            .bind(policy_id)
+            .bind(file_id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn count(pool: &PgPool, file_id: Uuid) -> Result<i64, sqlx::Error> {
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM row_policies WHERE file_id = $1")
+            .bind(file_id)
+            .fetch_one(pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        file_id: Uuid,
+        policy: &RowPolicyInput,
+        created_by: Uuid,
+    ) -> Result<RowPolicy, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            INSERT INTO row_policies (file_id, name, description, filters, applies_to_roles, enabled, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *
+            "#
+        )
+        .bind(file_id)
+        .bind(policy.name.trim())
+        .bind(&policy.description)
+        .bind(Json(&policy.filters))
+        .bind(&policy.applies_to_roles)
+        .bind(policy.enabled)
+        .bind(created_by)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Replace a policy; returns `None` if there is no such policy
+    pub async fn update(
+        pool: &PgPool,
+        file_id: Uuid,
+        policy_id: Uuid,
+        policy: &RowPolicyInput,
+    ) -> Result<Option<RowPolicy>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            UPDATE row_policies
+            SET name = $3, description = $4, filters = $5, applies_to_roles = $6, enabled = $7
+            WHERE id = $1 AND file_id = $2
+            RETURNING *
+            "#
+        )
+        .bind(policy_id)
+        .bind(file_id)
+        .bind(policy.name.trim())
+        .bind(&policy.description)
+        .bind(Json(&policy.filters))
+        .bind(&policy.applies_to_roles)
+        .bind(policy.enabled)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Delete a policy; returns false if there is no such policy
+    pub async fn delete(pool: &PgPool, file_id: Uuid, policy_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM row_policies WHERE id = $1 AND file_id = $2")
+            .bind(policy_id)
+            .bind(file_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Rows of a file a user may see. The owner always sees every row.
+    pub async fn access(
+        pool: &PgPool,
+        file_id: Uuid,
+        owner_id: Uuid,
+        team_id: Option<Uuid>,
+        user_id: Uuid,
+    ) -> Result<RowAccess, sqlx::Error> {
+        if user_id == owner_id {
+            return Ok(RowAccess::unrestricted());
+        }
+
+        let policies: Vec<RowPolicy> =
+            sqlx::query_as("SELECT * FROM row_policies WHERE file_id = $1 AND enabled ORDER BY name")
+                .bind(file_id)
+                .fetch_all(pool)
+                .await?;
+        if policies.is_empty() {
+            return Ok(RowAccess::unrestricted());
+        }
+
+        match UserContext::load(pool, user_id, team_id).await? {
+            Some(context) => Ok(evaluate(&policies, &context)),
+            None => Ok(RowAccess {
+                filter: RowFilter::DenyAll,
+                applied: Vec::new(),
+            }),
+        }
+    }
+}
+
+/// Combine the policies applying to a user into a row filter
+pub fn evaluate(policies: &[RowPolicy], context: &UserContext) -> RowAccess {
+    let mut filters = Vec::new();
+    let mut applied = Vec::new();
+    let mut deny = false;
+
+    for policy in policies.iter().filter(|p| p.enabled && applies_to(p, context)) {
+        match render(&policy.filters, context) {
+            Ok(rendered) => {
+                filters.extend(rendered.iter().cloned());
+                applied.push(AppliedPolicy {
+                    id: policy.id,
+                    name: policy.name.clone(),
+                    filters: Some(rendered),
+                    error: None,
+                });
+            }
+            Err(e) => {
+                deny = true;
+                applied.push(AppliedPolicy {
+                    id: policy.id,
+                    name: policy.name.clone(),
+                    filters: None,
+                    error: Some(e.to_string()),
+                });
+            }
+        }
+    }
+
+    let filter = if deny {
+        RowFilter::DenyAll
+    } else if applied.is_empty() {
+        RowFilter::Unrestricted
+    } else {
+        RowFilter::Restricted(filters)
+    };
+
+    RowAccess { filter, applied }
+}
+
+fn applies_to(policy: &RowPolicy, context: &UserContext) -> bool {
+    match &policy.applies_to_roles {
+        None => true,
+        // Someone the file was shared with has no team role to exempt them
+        Some(roles) => context.team_role.as_ref().is_none_or(|role| roles.contains(role)),
+    }
+}
+
+/// Check that a template only uses known placeholders
+pub fn validate_template(filters: &[Filter]) -> Result<(), RowSecurityError> {
+    let context = UserContext {
+        user_id: Uuid::nil(),
+        email: String::new(),
+        name: String::new(),
+        role: String::new(),
+        team_role: None,
+        attributes: serde_json::Map::new(),
+    };
+    for filter in filters {
+        for value in std::iter::once(&filter.value).chain(filter.value2.as_ref()) {
+            match render_value(value, &context) {
+                Ok(_) | Err(RowSecurityError::MissingValue(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Substitute a user's values into a policy's filters
+pub fn render(filters: &[Filter], context: &UserContext) -> Result<Vec<Filter>, RowSecurityError> {
+    filters
+        .iter()
+        .map(|filter| {
+            Ok(Filter {
+                column: filter.column.clone(),
+                operator: filter.operator,
+                value: render_value(&filter.value, context)?,
+                value2: filter.value2.as_ref().map(|v| render_value(v, context)).transpose()?,
+            })
+        })
+        .collect()
+}
+
+fn render_value(value: &Value, context: &UserContext) -> Result<Value, RowSecurityError> {
+    match value {
+        Value::String(text) => render_text(text, context),
+        Value::Array(items) => items.iter().map(|item| render_value(item, context)).collect::<Result<_, _>>().map(Value::Array),
+        other => Ok(other.clone()),
+    }
+}
+
+fn render_text(text: &str, context: &UserContext) -> Result<Value, RowSecurityError> {
+    let resolve = |placeholder: &str| {
+        context
+            .lookup(placeholder)?
+            .ok_or_else(|| RowSecurityError::MissingValue(placeholder.to_string()))
+    };
+
+    // A lone placeholder keeps the value's JSON type
+    let trimmed = text.trim();
+    if let Some(inner) = trimmed.strip_prefix("{{").and_then(|rest| rest.strip_suffix("}}")) {
+        if !inner.contains("{{") && !inner.contains("}}") {
+            return resolve(inner.trim());
+        }
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| RowSecurityError::Unterminated(text.to_string()))?;
+        match resolve(rest[start + 2..start + end].trim())? {
+            Value::String(s) => output.push_str(&s),
+            other => output.push_str(&other.to_string()),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    output.push_str(rest);
+
+    Ok(Value::String(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::query_engine::FilterOperator;
+    use serde_json::json;
+
+    fn context() -> UserContext {
+        UserContext {
+            user_id: Uuid::nil(),
+            email: "rep@example.com".to_string(),
+            name: "Rep".to_string(),
+            role: "user".to_string(),
+            team_role: Some("member".to_string()),
+            attributes: json!({ "region": "EMEA", "accounts": ["a1", "a2"] })
+                .as_object()
+                .cloned()
+                .unwrap(),
+        }
+    }
+
+    fn filter(column: &str, operator: FilterOperator, value: Value) -> Filter {
+        Filter {
+            column: column.to_string(),
+            operator,
+            value,
+            value2: None,
+        }
+    }
+
+    fn policy(filters: Vec<Filter>, applies_to_roles: Option<Vec<&str>>) -> RowPolicy {
+        RowPolicy {
+            id: Uuid::new_v4(),
+            file_id: Uuid::nil(),
+            name: "policy".to_string(),
+            description: None,
+            filters: Json(filters),
+            applies_to_roles: applies_to_roles.map(|r| r.into_iter().map(String::from).collect()),
+            enabled: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_render_substitutes_user_values() {
+        let filters = vec![
+            filter("region", FilterOperator::Eq, json!("{{user.attributes.region}}")),
+            filter("account", FilterOperator::In, json!("{{ user.attributes.accounts }}")),
+            filter("owner", FilterOperator::Eq, json!("{{user.email}}")),
+            filter("label", FilterOperator::Contains, json!("{{team.role}}-{{user.role}}")),
+        ];
+
+        let rendered = render(&filters, &context()).unwrap();
+        assert_eq!(rendered[0].value, json!("EMEA"));
+        assert_eq!(rendered[1].value, json!(["a1", "a2"]));
+        assert_eq!(rendered[2].value, json!("rep@example.com"));
+        assert_eq!(rendered[3].value, json!("member-user"));
+    }
+
+    #[test]
+    fn test_missing_attribute_denies_all() {
+        let policies = vec![policy(vec![filter("region", FilterOperator::Eq, json!("{{user.attributes.country}}"))], None)];
+
+        let access = evaluate(&policies, &context());
+        assert_eq!(access.filter, RowFilter::DenyAll);
+        assert!(access.applied[0].error.is_some());
+
+        let mut spec = QuerySpec::default();
+        access.filter.restrict(&mut spec);
+        assert_eq!(spec.limit, Some(0));
+    }
+
+    #[test]
+    fn test_policies_apply_by_team_role() {
+        let policies = vec![
+            policy(vec![filter("region", FilterOperator::Eq, json!("{{user.attributes.region}}"))], Some(vec!["member"])),
+            policy(vec![filter("region", FilterOperator::Eq, json!("none"))], Some(vec!["viewer"])),
+        ];
+
+        let access = evaluate(&policies, &context());
+        assert_eq!(access.applied.len(), 1);
+        assert_eq!(
+            access.filter,
+            RowFilter::Restricted(vec![filter("region", FilterOperator::Eq, json!("EMEA"))])
+        );
+
+        // Reaching the file through a share, without a team role, does not
+        // escape the policies set for members
+        let outsider = UserContext { team_role: None, ..context() };
+        assert_eq!(
+            evaluate(&policies, &outsider).filter,
+            RowFilter::Restricted(vec![
+                filter("region", FilterOperator::Eq, json!("EMEA")),
+                filter("region", FilterOperator::Eq, json!("none")),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert!(validate_template(&[filter("a", FilterOperator::Eq, json!("{{user.attributes.anything}}"))]).is_ok());
+        assert!(validate_template(&[filter("a", FilterOperator::Eq, json!("{{user.password}}"))]).is_err());
+        assert!(validate_template(&[filter("a", FilterOperator::Eq, json!("{{user.email"))]).is_err());
+    }
+}
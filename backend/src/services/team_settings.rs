@@ -0,0 +1,456 @@
+//! Team settings
+//!
+//! Typed view of the `teams.settings` JSONB column. Every field has a
+//! default, so a team created before a setting existed reads as if it had
+//! never changed it, and keys this version does not know are left alone
+//! when settings are saved.
+//!
+//! Upload limits narrow the system-wide [`MAX_FILE_SIZE`] and
+//! [`SUPPORTED_FILE_TYPES`]; a team cannot raise them. Files past a team's
+//! retention period are purged by a periodic job
+//! ([`TeamSettingsService::purge_expired_files`]).
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::collections::BTreeSet;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::models::TeamRole;
+use crate::services::acl::{AclService, ResourceKind};
+use crate::services::audit::{AuditAction, AuditEntry, AuditService, ResourceType};
+
+/// Largest file anyone may upload (100MB)
+pub const MAX_FILE_SIZE: u64 = 100 * 1024 * 1024;
+
+/// File extensions the server can read
+pub const SUPPORTED_FILE_TYPES: &[&str] = &["csv", "json", "ndjson", "jsonl", "parquet", "arrow"];
+
+/// Longest retention period a team may set (ten years)
+pub const MAX_RETENTION_DAYS: u32 = 3650;
+
+/// Most email domains a team may restrict membership to
+pub const MAX_EMAIL_DOMAINS: usize = 50;
+
+/// Invalid team settings
+#[derive(Debug, Error, PartialEq)]
+pub enum TeamSettingsError {
+    #[error("Unknown setting: {0}")]
+    UnknownSetting(String),
+
+    #[error("Invalid value for {0}: {1}")]
+    InvalidValue(String, String),
+
+    #[error("Maximum file size must be between 1 and {MAX_FILE_SIZE} bytes")]
+    FileSize,
+
+    #[error("Storage quota must be at least 1 byte")]
+    StorageQuota,
+
+    #[error("File type '{0}' is not supported. Supported types: {SUPPORTED_FILE_TYPES:?}")]
+    FileType(String),
+
+    #[error("At least one file type must be allowed")]
+    NoFileTypes,
+
+    #[error("New members cannot join as owner")]
+    DefaultRole,
+
+    #[error("Data retention must be between 1 and {MAX_RETENTION_DAYS} days")]
+    Retention,
+
+    #[error("Invalid email domain: {0}")]
+    EmailDomain(String),
+
+    #[error("At most {MAX_EMAIL_DOMAINS} email domains may be allowed")]
+    TooManyDomains,
+}
+
+/// A team's settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TeamSettings {
+    /// Total bytes the team's files may take up; `None` for the server's
+    /// team quota
+    pub storage_quota_bytes: Option<u64>,
+    /// Largest file that may be uploaded to the team
+    pub max_file_size_bytes: u64,
+    /// File extensions that may be uploaded to the team
+    pub allowed_file_types: Vec<String>,
+    /// Role given to invitees when the invitation does not name one
+    pub default_member_role: TeamRole,
+    /// Deny team access to members without multi-factor authentication
+    pub require_mfa: bool,
+    /// Days after upload that the team's files are deleted; `None` to keep
+    /// them until someone deletes them
+    pub data_retention_days: Option<u32>,
+    /// Domains whose addresses may be invited and join; empty for any
+    pub allowed_email_domains: Vec<String>,
+}
+
+impl Default for TeamSettings {
+    fn default() -> Self {
+        TeamSettings {
+            storage_quota_bytes: None,
+            max_file_size_bytes: MAX_FILE_SIZE,
+            allowed_file_types: SUPPORTED_FILE_TYPES.iter().map(|t| t.to_string()).collect(),
+            default_member_role: TeamRole::Member,
+            require_mfa: false,
+            data_retention_days: None,
+            allowed_email_domains: Vec::new(),
+        }
+    }
+}
+
+impl TeamSettings {
+    /// Read stored settings; a value that does not parse reads as its
+    /// default rather than failing every request to the team
+    pub fn from_value(value: &Value) -> Self {
+        let mut settings = TeamSettings::default().to_object();
+        if let Value::Object(stored) = value {
+            for (key, value) in stored {
+                if !settings.contains_key(key) {
+                    continue;
+                }
+                match Self::with_value(&settings, key, value) {
+                    Ok(_) => {
+                        settings.insert(key.clone(), value.clone());
+                    }
+                    Err(e) => log::warn!("Ignoring stored team setting {}: {}", key, e),
+                }
+            }
+        }
+        serde_json::from_value(Value::Object(settings)).unwrap_or_default()
+    }
+
+    /// Apply a partial update; `null` resets a setting to its default
+    pub fn patched(&self, patch: &serde_json::Map<String, Value>) -> Result<Self, TeamSettingsError> {
+        let defaults = TeamSettings::default().to_object();
+        let mut merged = self.to_object();
+
+        for (key, value) in patch {
+            let default = defaults
+                .get(key)
+                .ok_or_else(|| TeamSettingsError::UnknownSetting(key.clone()))?;
+            let value = if value.is_null() { default } else { value };
+            Self::with_value(&merged, key, value)
+                .map_err(|e| TeamSettingsError::InvalidValue(key.clone(), e.to_string()))?;
+            merged.insert(key.clone(), value.clone());
+        }
+
+        serde_json::from_value::<TeamSettings>(Value::Object(merged))
+            .map_err(|e| TeamSettingsError::InvalidValue("settings".to_string(), e.to_string()))?
+            .validated()
+    }
+
+    /// Normalize and check the settings
+    pub fn validated(mut self) -> Result<Self, TeamSettingsError> {
+        if self.max_file_size_bytes == 0 || self.max_file_size_bytes > MAX_FILE_SIZE {
+            return Err(TeamSettingsError::FileSize);
+        }
+        if self.storage_quota_bytes == Some(0) {
+            return Err(TeamSettingsError::StorageQuota);
+        }
+
+        let mut file_types = BTreeSet::new();
+        for file_type in &self.allowed_file_types {
+            let file_type = file_type.trim().trim_start_matches('.').to_lowercase();
+            if !SUPPORTED_FILE_TYPES.contains(&file_type.as_str()) {
+                return Err(TeamSettingsError::FileType(file_type));
+            }
+            file_types.insert(file_type);
+        }
+        if file_types.is_empty() {
+            return Err(TeamSettingsError::NoFileTypes);
+        }
+        self.allowed_file_types = file_types.into_iter().collect();
+
+        if self.default_member_role == TeamRole::Owner {
+            return Err(TeamSettingsError::DefaultRole);
+        }
+        if matches!(self.data_retention_days, Some(days) if days == 0 || days > MAX_RETENTION_DAYS) {
+            return Err(TeamSettingsError::Retention);
+        }
+
+        let mut domains = BTreeSet::new();
+        for domain in &self.allowed_email_domains {
+            let domain = domain.trim().trim_start_matches('@').to_lowercase();
+            if !is_domain(&domain) {
+                return Err(TeamSettingsError::EmailDomain(domain));
+            }
+            domains.insert(domain);
+        }
+        if domains.len() > MAX_EMAIL_DOMAINS {
+            return Err(TeamSettingsError::TooManyDomains);
+        }
+        self.allowed_email_domains = domains.into_iter().collect();
+
+        Ok(self)
+    }
+
+    /// Whether files with this extension may be uploaded to the team
+    pub fn allows_file_type(&self, extension: &str) -> bool {
+        self.allowed_file_types.iter().any(|t| t.eq_ignore_ascii_case(extension))
+    }
+
+    /// Whether this address may be invited to and join the team
+    pub fn allows_email(&self, email: &str) -> bool {
+        if self.allowed_email_domains.is_empty() {
+            return true;
+        }
+        let domain = email.rsplit_once('@').map(|(_, domain)| domain).unwrap_or_default();
+        self.allowed_email_domains.iter().any(|d| d.eq_ignore_ascii_case(domain))
+    }
+
+    /// Settings `object` with one key replaced, if the value has the right type
+    fn with_value(object: &serde_json::Map<String, Value>, key: &str, value: &Value) -> serde_json::Result<Self> {
+        let mut object = object.clone();
+        object.insert(key.to_string(), value.clone());
+        serde_json::from_value(Value::Object(object))
+    }
+
+    fn to_object(&self) -> serde_json::Map<String, Value> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(object)) => object,
+            _ => unreachable!("team settings serialize to an object"),
+        }
+    }
+}
+
+/// A file past its team's retention period
+#[derive(Debug, sqlx::FromRow)]
+struct ExpiredFile {
+    id: Uuid,
+    user_id: Uuid,
+    team_id: Uuid,
+    name: String,
+    storage_path: String,
+    retention_days: i32,
+}
+
+/// Team settings service
+pub struct TeamSettingsService;
+
+impl TeamSettingsService {
+    /// A team's settings, or `None` if there is no such team
+    pub async fn get(pool: &PgPool, team_id: Uuid) -> Result<Option<TeamSettings>, sqlx::Error> {
+        let row: Option<(Value,)> = sqlx::query_as("SELECT settings FROM teams WHERE id = $1")
+            .bind(team_id)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(row.map(|(value,)| TeamSettings::from_value(&value)))
+    }
+
+    /// A team's settings, locked until the transaction ends so they can be
+    /// updated without losing a concurrent change
+    pub async fn lock(conn: &mut sqlx::PgConnection, team_id: Uuid) -> Result<Option<TeamSettings>, sqlx::Error> {
+        let row: Option<(Value,)> = sqlx::query_as("SELECT settings FROM teams WHERE id = $1 FOR UPDATE")
+            .bind(team_id)
+            .fetch_optional(conn)
+            .await?;
+
+        Ok(row.map(|(value,)| TeamSettings::from_value(&value)))
+    }
+
+    /// Save a team's settings, keeping any stored keys they do not cover
+    pub async fn save(conn: &mut sqlx::PgConnection, team_id: Uuid, settings: &TeamSettings) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE teams SET settings = settings || $2 WHERE id = $1")
+            .bind(team_id)
+            .bind(sqlx::types::Json(settings))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Delete team files older than their team's retention period, with
+    /// their grants and stored data, auditing each; returns how many were
+    /// deleted
+    pub async fn purge_expired_files(pool: &PgPool) -> Result<usize, sqlx::Error> {
+        let expired: Vec<ExpiredFile> = sqlx::query_as(
+            r#"
+            DELETE FROM files f
+            USING teams t
+            WHERE f.team_id = t.id
+              AND jsonb_typeof(t.settings->'data_retention_days') = 'number'
+              AND f.created_at < NOW() - make_interval(days => (t.settings->>'data_retention_days')::int)
+            RETURNING f.id, f.user_id, f.team_id, f.name, f.storage_path,
+                (t.settings->>'data_retention_days')::int AS retention_days
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        for file in &expired {
+            if let Err(e) = AclService::revoke_all(pool, ResourceKind::File, file.id).await {
+                log::warn!("Failed to remove grants on expired file {}: {}", file.id, e);
+            }
+            let _ = tokio::fs::remove_file(&file.storage_path).await;
+
+            if let Err(e) = AuditService::log(pool, AuditEntry {
+                user_id: None,
+                team_id: Some(file.team_id),
+                action: AuditAction::FileRetentionPurge,
+                resource_type: Some(ResourceType::File),
+                resource_id: Some(file.id),
+                details: Some(json!({
+                    "name": file.name,
+                    "owner_id": file.user_id,
+                    "retention_days": file.retention_days
+                })),
+                ip_address: None,
+                user_agent: None,
+            })
+            .await
+            {
+                log::warn!("Failed to audit retention purge of file {}: {}", file.id, e);
+            }
+        }
+
+        Ok(expired.len())
+    }
+}
+
+/// Lowercase DNS name with at least two labels
+fn is_domain(domain: &str) -> bool {
+    domain.len() <= 253
+        && domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db::TestDb;
+
+    fn patch(value: Value) -> serde_json::Map<String, Value> {
+        match value {
+            Value::Object(object) => object,
+            _ => panic!("patch must be an object"),
+        }
+    }
+
+    #[test]
+    fn test_from_value_defaults_and_keeps_known_keys() {
+        assert_eq!(TeamSettings::from_value(&json!({})), TeamSettings::default());
+        assert_eq!(TeamSettings::from_value(&Value::Null), TeamSettings::default());
+
+        let settings = TeamSettings::from_value(&json!({
+            "require_mfa": true,
+            "max_file_size_bytes": "huge",
+            "theme": "dark"
+        }));
+        assert!(settings.require_mfa);
+        assert_eq!(settings.max_file_size_bytes, MAX_FILE_SIZE);
+    }
+
+    #[test]
+    fn test_patched_validates_and_normalizes() {
+        let settings = TeamSettings::default()
+            .patched(&patch(json!({
+                "allowed_file_types": ["CSV", ".parquet", "csv"],
+                "allowed_email_domains": ["@Example.com"],
+                "data_retention_days": 90,
+                "default_member_role": "Viewer"
+            })))
+            .unwrap();
+        assert_eq!(settings.allowed_file_types, vec!["csv", "parquet"]);
+        assert_eq!(settings.allowed_email_domains, vec!["example.com"]);
+        assert_eq!(settings.data_retention_days, Some(90));
+        assert_eq!(settings.default_member_role, TeamRole::Viewer);
+
+        let reset = settings.patched(&patch(json!({ "data_retention_days": null }))).unwrap();
+        assert_eq!(reset.data_retention_days, None);
+
+        let defaults = TeamSettings::default();
+        let invalid = [
+            json!({ "colour": "blue" }),
+            json!({ "max_file_size_bytes": MAX_FILE_SIZE + 1 }),
+            json!({ "max_file_size_bytes": -1 }),
+            json!({ "storage_quota_bytes": 0 }),
+            json!({ "allowed_file_types": ["exe"] }),
+            json!({ "allowed_file_types": [] }),
+            json!({ "default_member_role": "Owner" }),
+            json!({ "data_retention_days": 0 }),
+            json!({ "allowed_email_domains": ["not a domain"] }),
+        ];
+        for value in invalid {
+            assert!(defaults.patched(&patch(value.clone())).is_err(), "accepted {}", value);
+        }
+    }
+
+    #[test]
+    fn test_allows() {
+        let settings = TeamSettings {
+            allowed_file_types: vec!["csv".to_string()],
+            allowed_email_domains: vec!["example.com".to_string()],
+            ..Default::default()
+        };
+        assert!(settings.allows_file_type("CSV"));
+        assert!(!settings.allows_file_type("json"));
+        assert!(settings.allows_email("ana@Example.com"));
+        assert!(!settings.allows_email("ana@example.com.evil.io"));
+        assert!(!settings.allows_email("ana@sub.example.com"));
+        assert!(TeamSettings::default().allows_email("anyone@anywhere.org"));
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_purge_expired_files_audits_each_file() {
+        let db = TestDb::create().await;
+        let pool = &db.pool;
+
+        let user_id: Uuid =
+            sqlx::query_scalar("INSERT INTO users (email, password_hash, name) VALUES ('ada@example.com', 'x', 'Ada') RETURNING id")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        let team_id: Uuid = sqlx::query_scalar(
+            r#"INSERT INTO teams (name, slug, owner_id, settings) VALUES ('Sales', 'sales', $1, '{"data_retention_days": 30}') RETURNING id"#
+        )
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        let mut files = Vec::new();
+        for age_days in [40, 10] {
+            let file_id: Uuid = sqlx::query_scalar(
+                r#"
+                INSERT INTO files (user_id, team_id, name, original_name, mime_type, size_bytes, storage_path, created_at)
+                VALUES ($1, $2, 'sales.csv', 'sales.csv', 'text/csv', 10, '/nonexistent/sales.csv', NOW() - make_interval(days => $3))
+                RETURNING id
+                "#
+            )
+            .bind(user_id)
+            .bind(team_id)
+            .bind(age_days)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+            files.push(file_id);
+        }
+
+        assert_eq!(TeamSettingsService::purge_expired_files(pool).await.unwrap(), 1);
+
+        let remaining: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM files").fetch_all(pool).await.unwrap();
+        assert_eq!(remaining, [files[1]]);
+        let audited: Vec<(Uuid, Option<Uuid>, Value)> =
+            sqlx::query_as("SELECT resource_id, team_id, details FROM audit_log WHERE action = 'file.retention_purge'")
+                .fetch_all(pool)
+                .await
+                .unwrap();
+        assert_eq!(audited.len(), 1);
+        assert_eq!(audited[0].0, files[0]);
+        assert_eq!(audited[0].1, Some(team_id));
+        assert_eq!(audited[0].2["retention_days"], 30);
+
+        db.drop().await;
+    }
+}
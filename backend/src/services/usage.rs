@@ -0,0 +1,635 @@
+//! Usage accounting and storage quotas
+//!
+//! Storage is summed from `files` when asked for, so it cannot drift from
+//! what is on disk; queries are counted with their compute time in
+//! `query_usage`, one row per user, team and day.
+//!
+//! Personal files count against their uploader's quota: the user's own
+//! (`users.storage_quota_bytes`, `users.file_quota`) or else the server's
+//! [`UsageLimits`]. Team files count against the team's
+//! `storage_quota_bytes` setting instead, or the server's team quota when
+//! the team has none; only system administrators may set a team's above it.
+
+use chrono::{Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::services::team_settings::TeamSettings;
+
+/// Personal storage allowed when `USER_STORAGE_QUOTA_BYTES` is unset (10GB)
+pub const DEFAULT_USER_STORAGE_QUOTA: u64 = 10 * 1024 * 1024 * 1024;
+
+/// Storage per team when `TEAM_STORAGE_QUOTA_BYTES` is unset (50GB)
+pub const DEFAULT_TEAM_STORAGE_QUOTA: u64 = 50 * 1024 * 1024 * 1024;
+
+/// Days of query usage reported by default
+pub const DEFAULT_USAGE_DAYS: u32 = 30;
+
+/// Longest period query usage can be reported for
+pub const MAX_USAGE_DAYS: u32 = 365;
+
+/// Usage configuration error
+#[derive(Debug, Error)]
+pub enum UsageError {
+    #[error("Usage configuration error: {0}")]
+    Config(String),
+}
+
+/// Server-wide quotas for users and teams without their own
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageLimits {
+    pub user_storage_quota_bytes: Option<u64>,
+    pub user_file_quota: Option<u64>,
+    pub team_storage_quota_bytes: Option<u64>,
+}
+
+impl Default for UsageLimits {
+    fn default() -> Self {
+        UsageLimits {
+            user_storage_quota_bytes: Some(DEFAULT_USER_STORAGE_QUOTA),
+            user_file_quota: None,
+            team_storage_quota_bytes: Some(DEFAULT_TEAM_STORAGE_QUOTA),
+        }
+    }
+}
+
+impl UsageLimits {
+    /// Build from the environment, where `unlimited` lifts a quota:
+    /// - `USER_STORAGE_QUOTA_BYTES`: personal storage per user (default 10GB)
+    /// - `USER_FILE_QUOTA`: personal files per user (default unlimited)
+    /// - `TEAM_STORAGE_QUOTA_BYTES`: storage per team (default 50GB)
+    pub fn from_env() -> Result<Self, UsageError> {
+        let defaults = Self::default();
+        Ok(UsageLimits {
+            user_storage_quota_bytes: env_quota("USER_STORAGE_QUOTA_BYTES", defaults.user_storage_quota_bytes)?,
+            user_file_quota: env_quota("USER_FILE_QUOTA", defaults.user_file_quota)?,
+            team_storage_quota_bytes: env_quota("TEAM_STORAGE_QUOTA_BYTES", defaults.team_storage_quota_bytes)?,
+        })
+    }
+
+    /// Whether a team storage quota is above the server's, which only system
+    /// administrators may grant
+    pub fn exceeds_team_quota(&self, storage_quota_bytes: Option<u64>) -> bool {
+        matches!(
+            (storage_quota_bytes, self.team_storage_quota_bytes),
+            (Some(quota), Some(limit)) if quota > limit
+        )
+    }
+}
+
+fn env_quota(name: &str, default: Option<u64>) -> Result<Option<u64>, UsageError> {
+    match std::env::var(name) {
+        Ok(value) => parse_quota(&value).ok_or_else(|| UsageError::Config(format!("invalid {}: {}", name, value))),
+        Err(_) => Ok(default),
+    }
+}
+
+/// A positive number, or `unlimited` for no quota
+fn parse_quota(value: &str) -> Option<Option<u64>> {
+    match value.trim() {
+        "unlimited" => Some(None),
+        number => number.parse().ok().filter(|n| *n > 0).map(Some),
+    }
+}
+
+/// Whose usage is meant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "id", rename_all = "lowercase")]
+pub enum UsageOwner {
+    User(Uuid),
+    Team(Uuid),
+}
+
+impl UsageOwner {
+    fn name(&self) -> &'static str {
+        match self {
+            UsageOwner::User(_) => "personal",
+            UsageOwner::Team(_) => "team",
+        }
+    }
+
+    fn id(&self) -> Uuid {
+        match *self {
+            UsageOwner::User(id) | UsageOwner::Team(id) => id,
+        }
+    }
+}
+
+/// What a quota limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaResource {
+    Storage,
+    Files,
+}
+
+impl QuotaResource {
+    fn name(&self) -> &'static str {
+        match self {
+            QuotaResource::Storage => "storage",
+            QuotaResource::Files => "file",
+        }
+    }
+
+    fn unit(&self) -> &'static str {
+        match self {
+            QuotaResource::Storage => "bytes",
+            QuotaResource::Files => "files",
+        }
+    }
+}
+
+/// An upload refused for exceeding a quota
+#[derive(Debug, Clone, PartialEq, Serialize, Error)]
+#[error(
+    "Upload exceeds the {} {} quota: {remaining} of {limit} {} remaining",
+    .owner.name(),
+    .resource.name(),
+    .resource.unit()
+)]
+pub struct QuotaExceeded {
+    pub owner: UsageOwner,
+    pub resource: QuotaResource,
+    pub limit: u64,
+    pub used: u64,
+    pub remaining: u64,
+}
+
+/// Quotas that apply to an owner; `None` for no limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Quota {
+    pub storage_bytes: Option<u64>,
+    pub files: Option<u64>,
+}
+
+/// Stored files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, sqlx::FromRow)]
+pub struct StorageUsage {
+    pub storage_bytes: i64,
+    pub file_count: i64,
+}
+
+/// Queries run over a period
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, sqlx::FromRow)]
+pub struct QueryUsage {
+    pub query_count: i64,
+    pub compute_ms: i64,
+}
+
+/// An owner's usage against their quota
+#[derive(Debug, Clone, Serialize)]
+pub struct Usage {
+    #[serde(flatten)]
+    pub storage: StorageUsage,
+    #[serde(flatten)]
+    pub queries: QueryUsage,
+    pub quota: Quota,
+    /// What is left of each quota
+    pub remaining: Quota,
+}
+
+impl Usage {
+    pub fn new(storage: StorageUsage, queries: QueryUsage, quota: Quota) -> Self {
+        Usage {
+            storage,
+            queries,
+            quota,
+            remaining: Quota {
+                storage_bytes: quota.storage_bytes.map(|limit| limit.saturating_sub(storage.storage_bytes.max(0) as u64)),
+                files: quota.files.map(|limit| limit.saturating_sub(storage.file_count.max(0) as u64)),
+            },
+        }
+    }
+
+    /// The quota an upload of `size` bytes would exceed, if any
+    pub fn exceeded_by(&self, owner: UsageOwner, size: u64) -> Option<QuotaExceeded> {
+        let exceeded = |resource, limit: Option<u64>, used: i64, remaining: Option<u64>, needed: u64| {
+            match (limit, remaining) {
+                (Some(limit), Some(remaining)) if needed > remaining => Some(QuotaExceeded {
+                    owner,
+                    resource,
+                    limit,
+                    used: used.max(0) as u64,
+                    remaining,
+                }),
+                _ => None,
+            }
+        };
+
+        exceeded(QuotaResource::Files, self.quota.files, self.storage.file_count, self.remaining.files, 1).or_else(|| {
+            exceeded(
+                QuotaResource::Storage,
+                self.quota.storage_bytes,
+                self.storage.storage_bytes,
+                self.remaining.storage_bytes,
+                size,
+            )
+        })
+    }
+}
+
+/// Usage per user or team, as listed to administrators
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct UsageRanking {
+    pub id: Uuid,
+    pub name: String,
+    /// The user's email; `None` for teams
+    pub email: Option<String>,
+    pub storage_bytes: i64,
+    pub file_count: i64,
+    pub query_count: i64,
+    pub compute_ms: i64,
+}
+
+/// A team member's share of the team's usage
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct MemberUsage {
+    pub user_id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub storage_bytes: i64,
+    pub file_count: i64,
+    pub query_count: i64,
+    pub compute_ms: i64,
+}
+
+/// First day of a reporting period of `days` days ending today
+pub fn period_start(days: Option<u32>) -> NaiveDate {
+    let days = days.unwrap_or(DEFAULT_USAGE_DAYS).clamp(1, MAX_USAGE_DAYS);
+    (Utc::now() - Duration::days(i64::from(days) - 1)).date_naive()
+}
+
+/// Usage accounting service
+pub struct UsageService;
+
+impl UsageService {
+    /// Count a query against the user and, for a team file, the team
+    pub async fn record_query(
+        pool: &PgPool,
+        user_id: Uuid,
+        team_id: Option<Uuid>,
+        elapsed: std::time::Duration,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO query_usage (user_id, team_id, day, query_count, compute_ms)
+            VALUES ($1, $2, $3, 1, $4)
+            ON CONFLICT (user_id, COALESCE(team_id, '00000000-0000-0000-0000-000000000000'::uuid), day)
+            DO UPDATE SET query_count = query_usage.query_count + 1,
+                          compute_ms = query_usage.compute_ms + EXCLUDED.compute_ms
+            "#
+        )
+        .bind(user_id)
+        .bind(team_id)
+        .bind(Utc::now().date_naive())
+        .bind(i64::try_from(elapsed.as_millis()).unwrap_or(i64::MAX))
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// A user's personal files, and the queries they ran anywhere since `since`
+    pub async fn user_usage(
+        pool: &PgPool,
+        limits: &UsageLimits,
+        user_id: Uuid,
+        since: NaiveDate,
+    ) -> Result<Usage, sqlx::Error> {
+        let storage = Self::storage(pool, UsageOwner::User(user_id)).await?;
+        let queries: QueryUsage = sqlx::query_as(
+            r#"
+            SELECT COALESCE(SUM(query_count), 0)::BIGINT AS query_count, COALESCE(SUM(compute_ms), 0)::BIGINT AS compute_ms
+            FROM query_usage WHERE user_id = $1 AND day >= $2
+            "#
+        )
+        .bind(user_id)
+        .bind(since)
+        .fetch_one(pool)
+        .await?;
+
+        let quota = Self::user_quota(pool, limits, user_id).await?;
+        Ok(Usage::new(storage, queries, quota))
+    }
+
+    /// A team's files, and the queries run on them since `since`; `None`
+    /// if there is no such team
+    pub async fn team_usage(
+        pool: &PgPool,
+        limits: &UsageLimits,
+        team_id: Uuid,
+        since: NaiveDate,
+    ) -> Result<Option<Usage>, sqlx::Error> {
+        let Some(quota) = Self::team_quota(pool, limits, team_id).await? else {
+            return Ok(None);
+        };
+
+        let storage = Self::storage(pool, UsageOwner::Team(team_id)).await?;
+        let queries: QueryUsage = sqlx::query_as(
+            r#"
+            SELECT COALESCE(SUM(query_count), 0)::BIGINT AS query_count, COALESCE(SUM(compute_ms), 0)::BIGINT AS compute_ms
+            FROM query_usage WHERE team_id = $1 AND day >= $2
+            "#
+        )
+        .bind(team_id)
+        .bind(since)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(Some(Usage::new(storage, queries, quota)))
+    }
+
+    /// Each current member's share of a team's files and queries
+    pub async fn team_members(pool: &PgPool, team_id: Uuid, since: NaiveDate) -> Result<Vec<MemberUsage>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT u.id AS user_id, u.name, u.email,
+                   COALESCE(f.storage_bytes, 0)::BIGINT AS storage_bytes,
+                   COALESCE(f.file_count, 0)::BIGINT AS file_count,
+                   COALESCE(q.query_count, 0)::BIGINT AS query_count,
+                   COALESCE(q.compute_ms, 0)::BIGINT AS compute_ms
+            FROM team_members tm
+            JOIN users u ON u.id = tm.user_id
+            LEFT JOIN (
+                SELECT user_id, SUM(size_bytes) AS storage_bytes, COUNT(*) AS file_count
+                FROM files WHERE team_id = $1 GROUP BY user_id
+            ) f ON f.user_id = u.id
+            LEFT JOIN (
+                SELECT user_id, SUM(query_count) AS query_count, SUM(compute_ms) AS compute_ms
+                FROM query_usage WHERE team_id = $1 AND day >= $2 GROUP BY user_id
+            ) q ON q.user_id = u.id
+            WHERE tm.team_id = $1
+            ORDER BY storage_bytes DESC, u.name
+            "#
+        )
+        .bind(team_id)
+        .bind(since)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Quotas for a user's personal files
+    pub async fn user_quota(
+        conn: impl PgExecutor<'_>,
+        limits: &UsageLimits,
+        user_id: Uuid,
+    ) -> Result<Quota, sqlx::Error> {
+        let own: Option<(Option<i64>, Option<i32>)> =
+            sqlx::query_as("SELECT storage_quota_bytes, file_quota FROM users WHERE id = $1")
+                .bind(user_id)
+                .fetch_optional(conn)
+                .await?;
+        let (storage_bytes, files) = own.unwrap_or_default();
+
+        Ok(Quota {
+            storage_bytes: storage_bytes.map(|q| q as u64).or(limits.user_storage_quota_bytes),
+            files: files.map(|q| q as u64).or(limits.user_file_quota),
+        })
+    }
+
+    /// The quota an upload of `size` bytes would exceed, if any
+    ///
+    /// Locks the owner's quota until the transaction ends, so concurrent
+    /// uploads are checked one at a time; insert the file in the same
+    /// transaction for the next check to count it.
+    pub async fn check_upload(
+        conn: &mut PgConnection,
+        limits: &UsageLimits,
+        owner: UsageOwner,
+        size: u64,
+    ) -> Result<Option<QuotaExceeded>, sqlx::Error> {
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1::text, 0))")
+            .bind(owner.id())
+            .execute(&mut *conn)
+            .await?;
+
+        let quota = match owner {
+            UsageOwner::User(user_id) => Self::user_quota(&mut *conn, limits, user_id).await?,
+            UsageOwner::Team(team_id) => match Self::team_quota(&mut *conn, limits, team_id).await? {
+                Some(quota) => quota,
+                None => return Ok(None),
+            },
+        };
+        if quota == Quota::default() {
+            return Ok(None);
+        }
+
+        let storage = Self::storage(&mut *conn, owner).await?;
+        Ok(Usage::new(storage, QueryUsage::default(), quota).exceeded_by(owner, size))
+    }
+
+    /// Quotas for a team's files, from its settings or else the server's;
+    /// `None` if there is no such team
+    async fn team_quota(
+        conn: impl PgExecutor<'_>,
+        limits: &UsageLimits,
+        team_id: Uuid,
+    ) -> Result<Option<Quota>, sqlx::Error> {
+        let settings: Option<(serde_json::Value,)> = sqlx::query_as("SELECT settings FROM teams WHERE id = $1")
+            .bind(team_id)
+            .fetch_optional(conn)
+            .await?;
+
+        Ok(settings.map(|(settings,)| Quota {
+            storage_bytes: TeamSettings::from_value(&settings)
+                .storage_quota_bytes
+                .or(limits.team_storage_quota_bytes),
+            files: None,
+        }))
+    }
+
+    /// Files counting against an owner's quota
+    async fn storage(conn: impl PgExecutor<'_>, owner: UsageOwner) -> Result<StorageUsage, sqlx::Error> {
+        let (filter, id) = match owner {
+            UsageOwner::User(user_id) => ("user_id = $1 AND team_id IS NULL", user_id),
+            UsageOwner::Team(team_id) => ("team_id = $1", team_id),
+        };
+        sqlx::query_as(&format!(
+            "SELECT COALESCE(SUM(size_bytes), 0)::BIGINT AS storage_bytes, COUNT(*) AS file_count FROM files WHERE {}",
+            filter
+        ))
+        .bind(id)
+        .fetch_one(conn)
+        .await
+    }
+
+    /// Users with the most personal storage, with their queries since `since`
+    pub async fn top_users(pool: &PgPool, since: NaiveDate, limit: i64) -> Result<Vec<UsageRanking>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT u.id, u.name, u.email,
+                   COALESCE(f.storage_bytes, 0)::BIGINT AS storage_bytes,
+                   COALESCE(f.file_count, 0)::BIGINT AS file_count,
+                   COALESCE(q.query_count, 0)::BIGINT AS query_count,
+                   COALESCE(q.compute_ms, 0)::BIGINT AS compute_ms
+            FROM users u
+            LEFT JOIN (
+                SELECT user_id, SUM(size_bytes) AS storage_bytes, COUNT(*) AS file_count
+                FROM files WHERE team_id IS NULL GROUP BY user_id
+            ) f ON f.user_id = u.id
+            LEFT JOIN (
+                SELECT user_id, SUM(query_count) AS query_count, SUM(compute_ms) AS compute_ms
+                FROM query_usage WHERE day >= $1 GROUP BY user_id
+            ) q ON q.user_id = u.id
+            ORDER BY storage_bytes DESC, compute_ms DESC, u.email
+            LIMIT $2
+            "#
+        )
+        .bind(since)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Teams with the most storage, with the queries on their files since `since`
+    pub async fn top_teams(pool: &PgPool, since: NaiveDate, limit: i64) -> Result<Vec<UsageRanking>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT t.id, t.name, NULL::VARCHAR AS email,
+                   COALESCE(f.storage_bytes, 0)::BIGINT AS storage_bytes,
+                   COALESCE(f.file_count, 0)::BIGINT AS file_count,
+                   COALESCE(q.query_count, 0)::BIGINT AS query_count,
+                   COALESCE(q.compute_ms, 0)::BIGINT AS compute_ms
+            FROM teams t
+            LEFT JOIN (
+                SELECT team_id, SUM(size_bytes) AS storage_bytes, COUNT(*) AS file_count
+                FROM files WHERE team_id IS NOT NULL GROUP BY team_id
+            ) f ON f.team_id = t.id
+            LEFT JOIN (
+                SELECT team_id, SUM(query_count) AS query_count, SUM(compute_ms) AS compute_ms
+                FROM query_usage WHERE team_id IS NOT NULL AND day >= $1 GROUP BY team_id
+            ) q ON q.team_id = t.id
+            ORDER BY storage_bytes DESC, compute_ms DESC, t.name
+            LIMIT $2
+            "#
+        )
+        .bind(since)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Everything stored and queried on the server since `since`
+    pub async fn totals(pool: &PgPool, since: NaiveDate) -> Result<(StorageUsage, QueryUsage), sqlx::Error> {
+        let storage: StorageUsage = sqlx::query_as(
+            "SELECT COALESCE(SUM(size_bytes), 0)::BIGINT AS storage_bytes, COUNT(*) AS file_count FROM files"
+        )
+        .fetch_one(pool)
+        .await?;
+
+        let queries: QueryUsage = sqlx::query_as(
+            r#"
+            SELECT COALESCE(SUM(query_count), 0)::BIGINT AS query_count, COALESCE(SUM(compute_ms), 0)::BIGINT AS compute_ms
+            FROM query_usage WHERE day >= $1
+            "#
+        )
+        .bind(since)
+        .fetch_one(pool)
+        .await?;
+
+        Ok((storage, queries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db::TestDb;
+
+    fn usage(storage_bytes: i64, file_count: i64, quota: Quota) -> Usage {
+        Usage::new(StorageUsage { storage_bytes, file_count }, QueryUsage::default(), quota)
+    }
+
+    #[test]
+    fn test_parse_quota() {
+        assert_eq!(parse_quota("1048576"), Some(Some(1_048_576)));
+        assert_eq!(parse_quota("unlimited"), Some(None));
+        assert_eq!(parse_quota("0"), None);
+        assert_eq!(parse_quota("lots"), None);
+    }
+
+    #[test]
+    fn test_exceeded_by() {
+        let owner = UsageOwner::User(Uuid::new_v4());
+        let quota = Quota { storage_bytes: Some(1000), files: Some(3) };
+
+        let within = usage(400, 2, quota);
+        assert_eq!(within.remaining, Quota { storage_bytes: Some(600), files: Some(1) });
+        assert_eq!(within.exceeded_by(owner, 600), None);
+
+        let exceeded = within.exceeded_by(owner, 601).unwrap();
+        assert_eq!(exceeded.resource, QuotaResource::Storage);
+        assert_eq!((exceeded.limit, exceeded.used, exceeded.remaining), (1000, 400, 600));
+        assert_eq!(exceeded.to_string(), "Upload exceeds the personal storage quota: 600 of 1000 bytes remaining");
+
+        let full = usage(400, 3, quota).exceeded_by(owner, 1).unwrap();
+        assert_eq!(full.resource, QuotaResource::Files);
+        assert_eq!(full.remaining, 0);
+
+        // Usage above a lowered quota leaves nothing rather than underflowing
+        let over = usage(5000, 0, quota);
+        assert_eq!(over.remaining.storage_bytes, Some(0));
+        assert!(usage(0, 100, Quota::default()).exceeded_by(owner, u64::MAX).is_none());
+    }
+
+    #[test]
+    fn test_exceeds_team_quota() {
+        let limits = UsageLimits { team_storage_quota_bytes: Some(1000), ..Default::default() };
+        assert!(!limits.exceeds_team_quota(None));
+        assert!(!limits.exceeds_team_quota(Some(1000)));
+        assert!(limits.exceeds_team_quota(Some(1001)));
+
+        let unlimited = UsageLimits { team_storage_quota_bytes: None, ..Default::default() };
+        assert!(!unlimited.exceeds_team_quota(Some(u64::MAX)));
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_concurrent_upload_checks_wait_for_each_other() {
+        let db = TestDb::create().await;
+        let pool = &db.pool;
+        let limits = UsageLimits { user_storage_quota_bytes: Some(100), ..Default::default() };
+
+        let user_id: Uuid =
+            sqlx::query_scalar("INSERT INTO users (email, password_hash, name) VALUES ('ada@example.com', 'x', 'Ada') RETURNING id")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        let owner = UsageOwner::User(user_id);
+
+        let mut first = pool.begin().await.unwrap();
+        assert_eq!(UsageService::check_upload(&mut first, &limits, owner, 60).await.unwrap(), None);
+
+        // The second check waits for the first upload's transaction...
+        let second = tokio::spawn({
+            let pool = pool.clone();
+            let limits = limits.clone();
+            async move {
+                let mut tx = pool.begin().await.unwrap();
+                UsageService::check_upload(&mut tx, &limits, owner, 60).await.unwrap()
+            }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(!second.is_finished());
+
+        sqlx::query(
+            r#"
+            INSERT INTO files (user_id, name, original_name, mime_type, size_bytes, storage_path)
+            VALUES ($1, 'sales.csv', 'sales.csv', 'text/csv', 60, '/tmp/sales.csv')
+            "#
+        )
+        .bind(user_id)
+        .execute(&mut *first)
+        .await
+        .unwrap();
+        first.commit().await.unwrap();
+
+        // ...and then counts its file
+        let exceeded = second.await.unwrap().unwrap();
+        assert_eq!(exceeded.resource, QuotaResource::Storage);
+        assert_eq!(exceeded.remaining, 40);
+
+        db.drop().await;
+    }
+}
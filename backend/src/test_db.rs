@@ -0,0 +1,74 @@
+//! Scratch databases for tests that need Postgres
+//!
+//! [`TestDb::create`] makes a fresh database on the server named by
+//! `TEST_DATABASE_URL` and applies every migration to it. Tests using it are
+//! marked `#[ignore = "requires TEST_DATABASE_URL"]` and run with
+//! `cargo test -- --include-ignored`, so a run without a database reports
+//! them as ignored rather than passed.
+
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{ConnectOptions, Executor, PgPool};
+use std::path::PathBuf;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// A migrated database, dropped by [`TestDb::drop`]
+pub struct TestDb {
+    pub pool: PgPool,
+    name: String,
+    server: PgConnectOptions,
+}
+
+impl TestDb {
+    /// Create and migrate a database; panics without `TEST_DATABASE_URL`
+    pub async fn create() -> Self {
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must name a Postgres server");
+        let server = PgConnectOptions::from_str(&url).expect("invalid TEST_DATABASE_URL");
+        let name = format!("pilotba_test_{}", Uuid::new_v4().simple());
+
+        let mut connection = server.connect().await.expect("failed to connect to TEST_DATABASE_URL");
+        connection
+            .execute(format!("CREATE DATABASE {}", name).as_str())
+            .await
+            .expect("failed to create test database");
+
+        let pool = PgPoolOptions::new()
+            .max_connections(4)
+            .connect_with(server.clone().database(&name))
+            .await
+            .expect("failed to connect to test database");
+
+        let mut migrations: Vec<PathBuf> = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations"))
+            .expect("migrations directory")
+            .map(|entry| entry.expect("migration entry").path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "sql"))
+            .collect();
+        migrations.sort();
+
+        for path in migrations {
+            let sql = std::fs::read_to_string(&path).expect("readable migration");
+            // Without bind arguments the whole file runs as one simple query
+            pool.execute(sql.as_str())
+                .await
+                .unwrap_or_else(|e| panic!("migration {} failed: {}", path.display(), e));
+        }
+
+        TestDb { pool, name, server }
+    }
+
+    /// Close the pool and drop the database
+    pub async fn drop(self) {
+        self.pool.close().await;
+        match self.server.connect().await {
+            Ok(mut connection) => {
+                if let Err(e) = connection
+                    .execute(format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", self.name).as_str())
+                    .await
+                {
+                    log::warn!("Failed to drop test database {}: {}", self.name, e);
+                }
+            }
+            Err(e) => log::warn!("Failed to drop test database {}: {}", self.name, e),
+        }
+    }
+}